@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Embeds the commit, build date and compiler version, so that a running
+/// server can say exactly what it is.
+fn main() {
+    watch_git_head();
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"])
+        .unwrap_or_else(|| "unknown".to_string());
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    // Reproducible builds pin the date through SOURCE_DATE_EPOCH.
+    let build_secs = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs())
+        });
+
+    println!("cargo:rustc-env=BUILD_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=BUILD_DATE={}", civil_date(build_secs));
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={}", rustc_version);
+
+    check_locales();
+}
+
+/// Reruns the build script when the commit changes: when HEAD moves to
+/// another branch, when the branch it is on moves, and when `git pack-refs`
+/// moves the branch into `packed-refs`. A branch only found in
+/// `packed-refs` gets its own file again when it moves, so the directory it
+/// would go in is watched instead. Nothing that does not exist is watched,
+/// as cargo would rerun the script every time.
+fn watch_git_head() {
+    let git_path = |name: &str| command_output("git", &["rev-parse", "--git-path", name]);
+    let mut watched = vec![git_path("HEAD"), git_path("packed-refs")];
+    if let Some(branch) = command_output("git", &["symbolic-ref", "-q", "HEAD"]) {
+        watched.push(git_path(&branch).map(|path| {
+            if fs::metadata(&path).is_ok() {
+                path
+            } else {
+                path.rsplit_once('/')
+                    .map_or(path.clone(), |(dir, _)| dir.to_string())
+            }
+        }));
+    }
+    for path in watched.into_iter().flatten() {
+        if fs::metadata(&path).is_ok() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+/// Fails the build when a locale has a key the base locale lacks, which
+/// would be a typo or a text the server no longer shows.
+fn check_locales() {
+    println!("cargo:rerun-if-changed=locales");
+    let read = |name: &str| -> HashMap<String, String> {
+        let path = format!("locales/{}", name);
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    };
+    let base = read("en.json");
+    let entries = fs::read_dir("locales").expect("locales directory");
+    for entry in entries {
+        let name = entry.expect("locale file").file_name();
+        let name = name.to_string_lossy();
+        for key in read(&name).keys() {
+            if !base.contains_key(key) {
+                panic!("locales/{}: key {:?} is missing from en.json", name, key);
+            }
+        }
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// Formats seconds since the Unix epoch as a UTC date, e.g. "2024-03-01".
+fn civil_date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
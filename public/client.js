@@ -0,0 +1,159 @@
+// Shared connection handling for the touchpad pages.
+// Expects /config.js to have been loaded first.
+
+// Devices have to be paired before they can connect when the server requires it.
+if (window.TOUCHPAD.pairingRequired && !localStorage.getItem('touchpadToken')
+        && window.location.pathname !== '/pair') {
+    window.location.replace('/pair?next=' + encodeURIComponent(window.location.pathname));
+}
+
+// Opens the WebSocket. When the server asks for signed events in its Hello,
+// `send` signs the events it lists from then on, see `signed_events`;
+// everything sent goes through one queue so that it stays in order. Until
+// the Hello tells whether to sign, events wait, so that none goes unsigned.
+function openTouchpadSocket() {
+    const ws = new WebSocket(touchpadUrl());
+    const sendRaw = ws.send.bind(ws);
+    let hello = false;
+    let waiting = [];
+    let signing = null;
+    let queue = Promise.resolve();
+    let seq = 0;
+
+    const send = (data) => {
+        if (!signing) {
+            sendRaw(data);
+            return;
+        }
+        queue = queue.then(async () => {
+            let message = data;
+            if (signing.events.has(JSON.parse(data).type)) {
+                seq++;
+                const sig = hex(await hmac(await signing.key, `${seq}.${data}`));
+                message = JSON.stringify({ type: 'Signed', seq, event: data, sig });
+            }
+            if (ws.readyState === WebSocket.OPEN) {
+                sendRaw(message);
+            }
+        }).catch((error) => console.log('Signing failed:', error));
+    };
+
+    ws.addEventListener('message', (event) => {
+        const message = JSON.parse(event.data);
+        if (message.type !== 'Hello') {
+            return;
+        }
+        if (message.signing) {
+            signing = {
+                events: new Set(message.signing.events),
+                key: hmacKey(localStorage.getItem('touchpadToken'))
+                    .then((token) => hmac(token, 'web_touchpad events ' + message.signing.nonce))
+                    .then(hmacKey),
+            };
+        }
+        hello = true;
+        waiting.forEach(send);
+        waiting = [];
+    });
+    ws.addEventListener('close', () => {
+        waiting = [];
+    });
+
+    ws.send = (data) => {
+        if (hello) {
+            send(data);
+        } else {
+            waiting.push(data);
+        }
+    };
+
+    return ws;
+}
+
+// HMAC-SHA1 through WebCrypto, which browsers only offer over HTTPS and on
+// localhost.
+function hmacKey(bytes) {
+    const raw = typeof bytes === 'string' ? new TextEncoder().encode(bytes) : bytes;
+    return crypto.subtle.importKey('raw', raw, { name: 'HMAC', hash: 'SHA-1' }, false, ['sign']);
+}
+
+async function hmac(key, text) {
+    return new Uint8Array(await crypto.subtle.sign('HMAC', key, new TextEncoder().encode(text)));
+}
+
+function hex(bytes) {
+    return Array.from(bytes, (byte) => byte.toString(16).padStart(2, '0')).join('');
+}
+
+// The page text for `key` in the language the server picked for this browser.
+function t(key) {
+    return window.TOUCHPAD.strings[key] || key;
+}
+
+// Fills every element with a data-i18n attribute with that text.
+function translatePage() {
+    document.documentElement.lang = window.TOUCHPAD.locale;
+    document.querySelectorAll('[data-i18n]').forEach((element) => {
+        element.textContent = t(element.dataset.i18n);
+    });
+}
+document.addEventListener('DOMContentLoaded', translatePage);
+
+// `onMessage`, if given, receives every other message from the server.
+function connectTouchpad(onMessage) {
+    const ws = openTouchpadSocket();
+
+    ws.onopen = () => console.log('WebSocket connected');
+    ws.onclose = (event) => console.log('WebSocket disconnected', event.reason);
+    ws.onerror = (error) => console.log('WebSocket error:', error);
+    ws.onmessage = (event) => {
+        const message = JSON.parse(event.data);
+        if (message.type === 'Error') {
+            console.log('Server error:', message.code, message.message);
+        } else if (onMessage) {
+            onMessage(message);
+        }
+    };
+
+    window.addEventListener('beforeunload', () => ws.close());
+
+    return {
+        send: (event) => {
+            if (ws.readyState === WebSocket.OPEN) {
+                ws.send(JSON.stringify(event));
+            }
+        },
+    };
+}
+
+// A random id remembered by this browser.
+function deviceId() {
+    let device = localStorage.getItem('touchpadDevice');
+    if (!device) {
+        device = Math.random().toString(36).slice(2) + Date.now().toString(36);
+        localStorage.setItem('touchpadDevice', device);
+    }
+    return device;
+}
+
+// WebSocket URL identifying this browser, so the server restores its saved
+// profile, along with its pairing credential.
+function touchpadUrl() {
+    const scheme = window.location.protocol === 'https:' ? 'wss://' : 'ws://';
+    let url = scheme + window.location.host + window.TOUCHPAD.wsPath + '?device=' + encodeURIComponent(deviceId())
+        + '&locale=' + encodeURIComponent(window.TOUCHPAD.locale);
+    const token = localStorage.getItem('touchpadToken');
+    if (token) {
+        url += '&token=' + encodeURIComponent(token);
+    }
+    return url;
+}
+
+// Wires every element with a data-event attribute to send that event when tapped.
+function bindButtons(touchpad) {
+    document.querySelectorAll('[data-event]').forEach((button) => {
+        button.addEventListener('click', () => {
+            touchpad.send(JSON.parse(button.dataset.event));
+        });
+    });
+}
@@ -0,0 +1,49 @@
+use crate::input::Input;
+use crate::keys::KeyCombo;
+use serde::Deserialize;
+use std::fmt;
+
+/// Something a gesture can be configured to do. Written in the config either
+/// as a key combination, e.g. `"Ctrl+r"`, or as a wheel turn with keys held
+/// down, e.g. `{"wheel": -1, "modifiers": "Ctrl"}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Action {
+    Keys(KeyCombo),
+    Wheel {
+        wheel: i32,
+        modifiers: Option<KeyCombo>,
+    },
+}
+
+impl Action {
+    pub fn perform(&self, input: &mut Input) {
+        match self {
+            Action::Keys(combo) => combo.press(input),
+            Action::Wheel {
+                wheel,
+                modifiers: Some(modifiers),
+            } => modifiers.hold(input, |input| input.mouse_scroll_y(*wheel)),
+            Action::Wheel {
+                wheel,
+                modifiers: None,
+            } => input.mouse_scroll_y(*wheel),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Keys(combo) => write!(f, "{}", combo),
+            Action::Wheel {
+                wheel,
+                modifiers: Some(modifiers),
+            } => write!(f, "{}+wheel {}", modifiers, wheel),
+            Action::Wheel {
+                wheel,
+                modifiers: None,
+            } => write!(f, "wheel {}", wheel),
+        }
+    }
+}
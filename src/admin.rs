@@ -0,0 +1,410 @@
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+const ADMIN_FILE: &str = "admin.json";
+
+/// Wrong secrets a peer may try before it is locked out, as for pairing.
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(60);
+
+/// The credential for the admin endpoints. Kept apart from the device
+/// credentials handed out by pairing, which never satisfy it, and never
+/// shown in logs.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(transparent)]
+pub struct AdminSecret(String);
+
+impl fmt::Debug for AdminSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl From<String> for AdminSecret {
+    fn from(secret: String) -> Self {
+        AdminSecret(secret)
+    }
+}
+
+impl AdminSecret {
+    /// Compares in time that only depends on the lengths, so that timing
+    /// does not give away how much of a guess was right.
+    fn matches(&self, presented: &str) -> bool {
+        let (expected, presented) = (self.0.as_bytes(), presented.as_bytes());
+        expected.len() == presented.len()
+            && expected
+                .iter()
+                .zip(presented)
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct AdminFile {
+    secret: AdminSecret,
+}
+
+/// What a request or connection may do: what every allowed device may, or
+/// also look after the server.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    Device,
+    Admin,
+}
+
+/// The admin secret, and the failed attempts at it per peer.
+#[derive(Clone)]
+pub struct Admin {
+    secret: Arc<AdminSecret>,
+    /// Whether the host itself is an admin without the secret, see
+    /// `admin_from_host`.
+    trust_host: bool,
+    failures: Arc<Mutex<HashMap<IpAddr, (u32, Instant)>>>,
+}
+
+impl Admin {
+    /// Uses the secret from the config or command line, or else the one
+    /// kept in `admin.json`, generating it on first run.
+    pub fn load(configured: Option<AdminSecret>, trust_host: bool) -> Result<Self, Error> {
+        let secret = match configured {
+            Some(secret) => secret,
+            None => match fs::read_to_string(ADMIN_FILE) {
+                Ok(text) => {
+                    serde_json::from_str::<AdminFile>(&text)
+                        .map_err(|e| Error::Config(format!("{}: {}", ADMIN_FILE, e)))?
+                        .secret
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => generate(),
+                Err(e) => return Err(Error::Config(format!("{}: {}", ADMIN_FILE, e))),
+            },
+        };
+        if secret.0.is_empty() {
+            return Err(Error::Config(
+                "the admin secret must not be empty".to_string(),
+            ));
+        }
+        Ok(Admin {
+            secret: Arc::new(secret),
+            trust_host,
+            failures: Arc::default(),
+        })
+    }
+
+    /// The `Authorization` header value carrying the secret, for this
+    /// server's own requests to another instance, see `--replace`.
+    pub fn authorization(&self) -> String {
+        format!("Bearer {}", self.secret.0)
+    }
+
+    /// The permission of a peer presenting `presented`, if anything. A wrong
+    /// secret is an error rather than the device permission, so that it
+    /// counts towards the peer's lockout and its owner learns of it.
+    fn authorize(
+        &self,
+        peer: Option<IpAddr>,
+        presented: Option<&str>,
+    ) -> Result<Permission, Error> {
+        if self.trust_host && peer.is_some_and(|peer| peer.is_loopback()) {
+            return Ok(Permission::Admin);
+        }
+        match presented {
+            Some(presented) => self.check(peer, presented).map(|()| Permission::Admin),
+            None => Ok(Permission::Device),
+        }
+    }
+
+    /// Checks a presented secret; failures count towards the peer's lockout.
+    fn check(&self, peer: Option<IpAddr>, presented: &str) -> Result<(), Error> {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        if let Some((count, last)) = peer.and_then(|peer| failures.get(&peer)) {
+            if *count >= MAX_FAILURES && now.duration_since(*last) < LOCKOUT {
+                return Err(Error::TooManyAttempts);
+            }
+        }
+        if self.secret.matches(presented) {
+            if let Some(peer) = peer {
+                failures.remove(&peer);
+            }
+            return Ok(());
+        }
+        if let Some(peer) = peer {
+            let entry = failures.entry(peer).or_insert((0, now));
+            if now.duration_since(entry.1) >= LOCKOUT {
+                entry.0 = 0;
+            }
+            entry.0 += 1;
+            entry.1 = now;
+        }
+        Err(Error::Unauthorized("wrong admin secret"))
+    }
+}
+
+/// Makes up a secret and keeps it in `admin.json`, readable by its owner
+/// only. It is printed this once, so that whoever started the server can
+/// note it down. When it cannot be kept, it lasts until the server stops.
+fn generate() -> AdminSecret {
+    let secret = AdminSecret(
+        (0..32)
+            .map(|_| format!("{:02x}", rand::thread_rng().gen::<u8>()))
+            .collect(),
+    );
+    match save(ADMIN_FILE, &secret) {
+        Ok(()) => println!(
+            "Admin secret: {} (kept in {}, not shown again)",
+            secret.0, ADMIN_FILE
+        ),
+        Err(e) => {
+            eprintln!(
+                "Warning: cannot keep the admin secret in {}: {}",
+                ADMIN_FILE, e
+            );
+            println!("Admin secret: {} (until the server stops)", secret.0);
+        }
+    }
+    secret
+}
+
+fn save(path: &str, secret: &AdminSecret) -> io::Result<()> {
+    let text = serde_json::to_string_pretty(&AdminFile {
+        secret: secret.clone(),
+    })?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(text.as_bytes())
+}
+
+/// Only lets through requests carrying the admin secret as
+/// `Authorization: Bearer <secret>`, or from the host itself with
+/// `admin_from_host`. Device credentials do not count, so a paired device
+/// gets 403 like any other peer.
+pub fn admin_only(admin: Admin) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(
+            move |addr: Option<SocketAddr>, authorization: Option<String>| {
+                let admin = admin.clone();
+                async move {
+                    let peer = addr.map(|addr| addr.ip().to_canonical());
+                    let presented = authorization
+                        .as_deref()
+                        .and_then(|value| value.strip_prefix("Bearer "));
+                    match admin.authorize(peer, presented) {
+                        Ok(Permission::Admin) => Ok(()),
+                        Ok(Permission::Device) => Err(warp::reject::custom(Error::Forbidden(
+                            peer.map_or("unknown peer".to_string(), |peer| peer.to_string()),
+                        ))),
+                        Err(e) => Err(warp::reject::custom(e)),
+                    }
+                }
+            },
+        )
+        .untuple_one()
+}
+
+/// The permission a WebSocket connection is opened with. Browsers cannot
+/// set headers on it, so besides `Authorization: Bearer <secret>` the
+/// secret may come as the `admin` query parameter, like the device
+/// credential. A wrong one refuses the connection.
+pub fn permission(admin: Admin) -> impl Filter<Extract = (Permission,), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<AdminQuery>())
+        .and_then(
+            move |addr: Option<SocketAddr>, authorization: Option<String>, query: AdminQuery| {
+                let admin = admin.clone();
+                async move {
+                    let peer = addr.map(|addr| addr.ip().to_canonical());
+                    let presented = authorization
+                        .as_deref()
+                        .and_then(|value| value.strip_prefix("Bearer "))
+                        .or(query.admin.as_deref());
+                    admin
+                        .authorize(peer, presented)
+                        .map_err(warp::reject::custom)
+                }
+            },
+        )
+}
+
+#[derive(Deserialize)]
+struct AdminQuery {
+    admin: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn admin() -> Admin {
+        Admin::load(Some(AdminSecret::from(SECRET.to_string())), false).unwrap()
+    }
+
+    /// The status the admin filter answers with for a request from `peer`.
+    async fn status(admin: &Admin, peer: &str, authorization: Option<&str>) -> StatusCode {
+        let mut request = warp::test::request()
+            .path("/admin/clients?token=device-credential")
+            .remote_addr(peer.parse().unwrap());
+        if let Some(authorization) = authorization {
+            request = request.header("authorization", authorization);
+        }
+        match request.filter(&admin_only(admin.clone())).await {
+            Ok(()) => StatusCode::OK,
+            Err(rejection) => rejection.find::<Error>().unwrap().status(),
+        }
+    }
+
+    #[tokio::test]
+    async fn other_peers_without_the_secret_are_forbidden() {
+        let admin = admin();
+        // A device credential in the query, as the touchpad sends it, is no
+        // admin credential.
+        assert_eq!(
+            status(&admin, "192.168.1.20:5000", None).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&admin, "192.168.1.20:5000", Some("Basic abc")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(&admin, "[fe80::1]:5000", None).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn the_secret_is_let_through() {
+        let admin = admin();
+        let bearer = format!("Bearer {}", SECRET);
+        for peer in ["192.168.1.20:5000", "127.0.0.1:5000"] {
+            assert_eq!(status(&admin, peer, Some(&bearer)).await, StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_host_needs_the_secret_unless_trusted() {
+        // Tunnels and every other program on the computer come from there too.
+        let admin = admin();
+        for peer in ["127.0.0.1:5000", "[::1]:5000"] {
+            assert_eq!(status(&admin, peer, None).await, StatusCode::FORBIDDEN);
+        }
+
+        let trusting = Admin::load(Some(AdminSecret::from(SECRET.to_string())), true).unwrap();
+        assert_eq!(
+            status(&trusting, "127.0.0.1:5000", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(status(&trusting, "[::1]:5000", None).await, StatusCode::OK);
+        // An IPv4-mapped loopback address is still the host.
+        assert_eq!(
+            status(&trusting, "[::ffff:127.0.0.1]:5000", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&trusting, "192.168.1.20:5000", None).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    /// The permission a WebSocket request from another peer gets.
+    async fn permission_of(
+        path: &str,
+        authorization: Option<&str>,
+    ) -> Result<Permission, StatusCode> {
+        let mut request = warp::test::request()
+            .path(path)
+            .remote_addr("192.168.1.20:5000".parse().unwrap());
+        if let Some(authorization) = authorization {
+            request = request.header("authorization", authorization);
+        }
+        request
+            .filter(&permission(admin()))
+            .await
+            .map_err(|rejection| rejection.find::<Error>().unwrap().status())
+    }
+
+    #[tokio::test]
+    async fn websocket_connections_get_the_permission_of_their_secret() {
+        assert_eq!(
+            permission_of("/ws?device=phone", None).await,
+            Ok(Permission::Device)
+        );
+        // A device credential is no admin credential.
+        assert_eq!(
+            permission_of("/ws?token=device-credential", None).await,
+            Ok(Permission::Device)
+        );
+        let query = format!("/ws?device=laptop&admin={}", SECRET);
+        assert_eq!(permission_of(&query, None).await, Ok(Permission::Admin));
+        let bearer = format!("Bearer {}", SECRET);
+        assert_eq!(
+            permission_of("/ws", Some(&bearer)).await,
+            Ok(Permission::Admin)
+        );
+        assert_eq!(
+            permission_of("/ws?admin=device-credential", None).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[tokio::test]
+    async fn wrong_secrets_lock_the_peer_out() {
+        let admin = admin();
+        let peer = "192.168.1.20:5000";
+        for _ in 0..MAX_FAILURES {
+            assert_eq!(
+                status(&admin, peer, Some("Bearer device-credential")).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        let bearer = format!("Bearer {}", SECRET);
+        assert_eq!(
+            status(&admin, peer, Some(&bearer)).await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // Other peers are not held up by it.
+        assert_eq!(
+            status(&admin, "192.168.1.21:5000", Some(&bearer)).await,
+            StatusCode::OK
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_secret_is_kept_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("admin-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        save(path, &AdminSecret::from(SECRET.to_string())).unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        // An existing file is never overwritten.
+        assert!(save(path, &AdminSecret::from("other".to_string())).is_err());
+        let kept: AdminFile = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(kept.secret.matches(SECRET));
+    }
+
+    #[test]
+    fn an_empty_secret_is_refused() {
+        assert!(Admin::load(Some(AdminSecret::from(String::new())), false).is_err());
+    }
+}
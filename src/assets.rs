@@ -0,0 +1,352 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use httpdate::HttpDate;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use warp::filters::BoxedFilter;
+use warp::http::header::{
+    CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, LAST_MODIFIED, VARY,
+};
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::path::Tail;
+use warp::reply::Response;
+use warp::{Filter, Rejection};
+
+const PUBLIC_DIR: &str = "public";
+
+/// Files smaller than this are sent as they are; compressing them saves less
+/// than the encoding overhead.
+const MIN_COMPRESS_LEN: usize = 1024;
+
+/// A directory served under a URL path prefix.
+struct Mount {
+    prefix: Vec<String>,
+    dir: PathBuf,
+}
+
+/// Serves the files in each of `dirs` under its route prefix, and those in
+/// `public` under every path no prefix covers. `/` maps to `index.html` and
+/// `/<name>` to `<name>.html`, so every page can be opened without its
+/// extension.
+pub fn static_files(dirs: &BTreeMap<String, PathBuf>) -> BoxedFilter<(Response,)> {
+    let mut mounts: Vec<Mount> = dirs
+        .iter()
+        .map(|(prefix, dir)| Mount {
+            prefix: segments(prefix).map(str::to_string).collect(),
+            dir: dir.clone(),
+        })
+        .collect();
+    if !mounts.iter().any(|mount| mount.prefix.is_empty()) {
+        mounts.push(Mount {
+            prefix: Vec::new(),
+            dir: PathBuf::from(PUBLIC_DIR),
+        });
+    }
+    // The longest prefix matching a request wins.
+    mounts.sort_by_key(|mount| std::cmp::Reverse(mount.prefix.len()));
+    let mounts = Arc::new(mounts);
+
+    warp::get()
+        .and(warp::any().map(move || mounts.clone()))
+        .and(warp::path::tail())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .and_then(serve)
+        .boxed()
+}
+
+async fn serve(
+    mounts: Arc<Vec<Mount>>,
+    tail: Tail,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+    accept_encoding: Option<String>,
+) -> Result<Response, Rejection> {
+    let path = resolve(&mounts, tail.as_str())
+        .await
+        .ok_or_else(warp::reject::not_found)?;
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|_| warp::reject::not_found())?;
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let etag = entity_tag(modified, metadata.len());
+
+    let mut builder = warp::http::Response::builder()
+        .header(ETAG, &etag)
+        .header(LAST_MODIFIED, httpdate::fmt_http_date(modified))
+        .header(CACHE_CONTROL, cache_control(&path));
+
+    if is_not_modified(&etag, modified, if_none_match, if_modified_since) {
+        return Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let contents = tokio::fs::read(&path)
+        .await
+        .map_err(|_| warp::reject::not_found())?;
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    builder = builder.header(CONTENT_TYPE, mime.as_ref());
+
+    let mut body = contents;
+    if is_compressible(&mime) {
+        builder = builder.header(VARY, "accept-encoding");
+        if body.len() >= MIN_COMPRESS_LEN && accepts_gzip(accept_encoding.as_deref()) {
+            if let Ok(compressed) = gzip(&body) {
+                builder = builder.header(CONTENT_ENCODING, "gzip");
+                body = compressed;
+            }
+        }
+    }
+
+    Ok(builder.body(Body::from(body)).unwrap())
+}
+
+/// The non-empty segments of a URL path.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Maps a request path to a file inside the directory mounted at its
+/// longest matching prefix, refusing anything that could escape it.
+async fn resolve(mounts: &[Mount], tail: &str) -> Option<PathBuf> {
+    let requested: Vec<&str> = segments(tail).collect();
+    let mount = mounts.iter().find(|mount| {
+        mount.prefix.len() <= requested.len()
+            && mount.prefix.iter().zip(&requested).all(|(a, b)| a == b)
+    })?;
+    let rest = &requested[mount.prefix.len()..];
+    let mut path = mount.dir.clone();
+    for segment in rest {
+        if segment.starts_with('.') || segment.contains(['\\', ':']) {
+            return None;
+        }
+        path.push(segment);
+    }
+
+    if rest.is_empty() {
+        path.push("index.html");
+    } else if path.extension().is_none() {
+        path.set_extension("html");
+    }
+    match tokio::fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_file() => Some(path),
+        _ => None,
+    }
+}
+
+fn entity_tag(modified: SystemTime, len: u64) -> String {
+    let secs = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", secs, len)
+}
+
+fn is_not_modified(
+    etag: &str,
+    modified: SystemTime,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since when both are sent.
+    if let Some(if_none_match) = if_none_match {
+        return if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+    // HttpDate truncates to the one second resolution the client saw.
+    match if_modified_since.and_then(|date| date.parse::<HttpDate>().ok()) {
+        Some(since) => HttpDate::from(modified) <= since,
+        None => false,
+    }
+}
+
+/// Files whose name carries a content hash (e.g. `app.3f2a9c1b.js`) never
+/// change and may be cached forever; everything else is revalidated.
+fn cache_control(path: &Path) -> &'static str {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("");
+    let fingerprinted = stem
+        .rsplit_once('.')
+        .is_some_and(|(_, hash)| hash.len() >= 8 && hash.chars().all(|c| c.is_ascii_hexdigit()));
+    if fingerprinted {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+fn is_compressible(mime: &mime_guess::Mime) -> bool {
+    mime.type_() == mime_guess::mime::TEXT
+        || matches!(mime.subtype().as_str(), "javascript" | "json" | "svg")
+}
+
+fn accepts_gzip(accept_encoding: Option<&str>) -> bool {
+    accept_encoding.is_some_and(|accept_encoding| {
+        accept_encoding.split(',').any(|coding| {
+            let mut parts = coding.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let refused =
+                parts.any(|param| matches!(param.trim(), "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+            (name == "gzip" || name == "*") && !refused
+        })
+    })
+}
+
+fn gzip(contents: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(contents)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::io::Read;
+
+    const SCRIPT: &str = "console.log('touchpad');\n";
+
+    /// A directory of client files for one test, served at the root.
+    fn files(name: &str) -> (PathBuf, BoxedFilter<(Response,)>) {
+        let dir = std::env::temp_dir().join(format!("assets-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "<p>touchpad</p>").unwrap();
+        fs::write(dir.join("app.js"), SCRIPT.repeat(100)).unwrap();
+        fs::write(dir.join("app.3f2a9c1b.js"), SCRIPT).unwrap();
+        fs::write(dir.join("icon.png"), vec![0u8; 4096]).unwrap();
+        let routes = static_files(&BTreeMap::from([(String::new(), dir.clone())]));
+        (dir, routes)
+    }
+
+    async fn get(
+        routes: &BoxedFilter<(Response,)>,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> warp::http::Response<warp::hyper::body::Bytes> {
+        let mut request = warp::test::request().path(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.reply(routes).await
+    }
+
+    fn header<'a>(
+        response: &'a warp::http::Response<warp::hyper::body::Bytes>,
+        name: &str,
+    ) -> Option<&'a str> {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn unchanged_files_are_not_sent_again() {
+        let (dir, routes) = files("not-modified");
+        let first = get(&routes, "/", &[]).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = header(&first, "etag").unwrap().to_string();
+        let modified = header(&first, "last-modified").unwrap().to_string();
+
+        let by_tag = get(&routes, "/", &[("if-none-match", &etag)]).await;
+        assert_eq!(by_tag.status(), StatusCode::NOT_MODIFIED);
+        assert!(by_tag.body().is_empty());
+        let weak = format!("\"other\", W/{}", etag);
+        let by_weak_tag = get(&routes, "/", &[("if-none-match", &weak)]).await;
+        assert_eq!(by_weak_tag.status(), StatusCode::NOT_MODIFIED);
+        let by_date = get(&routes, "/", &[("if-modified-since", &modified)]).await;
+        assert_eq!(by_date.status(), StatusCode::NOT_MODIFIED);
+
+        // A tag that does not match wins over a date that does.
+        let changed = get(
+            &routes,
+            "/",
+            &[
+                ("if-none-match", "\"other\""),
+                ("if-modified-since", &modified),
+            ],
+        )
+        .await;
+        assert_eq!(changed.status(), StatusCode::OK);
+        assert_eq!(changed.body().as_ref(), b"<p>touchpad</p>");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn text_is_compressed_for_clients_accepting_gzip() {
+        let (dir, routes) = files("gzip");
+        let compressed = get(&routes, "/app.js", &[("accept-encoding", "br, gzip;q=0.8")]).await;
+        assert_eq!(header(&compressed, "content-encoding"), Some("gzip"));
+        assert_eq!(header(&compressed, "vary"), Some("accept-encoding"));
+        let mut text = String::new();
+        GzDecoder::new(compressed.body().as_ref())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, SCRIPT.repeat(100));
+
+        for accept_encoding in [None, Some("br"), Some("gzip;q=0")] {
+            let headers: Vec<_> = accept_encoding
+                .map(|value| ("accept-encoding", value))
+                .into_iter()
+                .collect();
+            let plain = get(&routes, "/app.js", &headers).await;
+            assert_eq!(header(&plain, "content-encoding"), None);
+            assert_eq!(plain.body().len(), SCRIPT.len() * 100);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tiny_and_binary_files_are_sent_as_they_are() {
+        let (dir, routes) = files("uncompressed");
+        let gzip = [("accept-encoding", "gzip")];
+        let tiny = get(&routes, "/", &gzip).await;
+        assert_eq!(header(&tiny, "content-encoding"), None);
+        assert_eq!(header(&tiny, "vary"), Some("accept-encoding"));
+        let image = get(&routes, "/icon.png", &gzip).await;
+        assert_eq!(header(&image, "content-encoding"), None);
+        assert_eq!(header(&image, "vary"), None);
+        assert_eq!(image.body().len(), 4096);
+        // The WebSocket path is no file, so it is left to its own route.
+        let ws = warp::test::request()
+            .path("/ws")
+            .header("accept-encoding", "gzip")
+            .filter(&routes)
+            .await;
+        assert!(ws.is_err_and(|rejection| rejection.is_not_found()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_fingerprinted_files_are_cached_for_good() {
+        let (dir, routes) = files("cache-control");
+        let fingerprinted = get(&routes, "/app.3f2a9c1b.js", &[]).await;
+        assert_eq!(
+            header(&fingerprinted, "cache-control"),
+            Some("public, max-age=31536000, immutable")
+        );
+        for path in ["/", "/app.js", "/icon.png"] {
+            let response = get(&routes, path, &[]).await;
+            assert_eq!(
+                header(&response, "cache-control"),
+                Some("no-cache"),
+                "{}",
+                path
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
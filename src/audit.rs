@@ -0,0 +1,171 @@
+use crate::config::Config;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const AUDIT_FILE: &str = "audit.jsonl";
+
+const DEFAULT_PAGE_LEN: usize = 100;
+const MAX_PAGE_LEN: usize = 1000;
+
+/// Something worth knowing about who controlled the machine. Individual
+/// input events are deliberately never recorded.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    ConnectionOpened {
+        connection: u64,
+        device: Option<String>,
+        peer: Option<String>,
+    },
+    ConnectionClosed {
+        connection: u64,
+    },
+    DevicePaired {
+        device: String,
+        peer: Option<String>,
+    },
+    PairingFailed {
+        device: String,
+        peer: Option<String>,
+        reason: String,
+    },
+    SettingChanged {
+        connection: Option<u64>,
+        setting: &'static str,
+        value: serde_json::Value,
+    },
+}
+
+#[derive(Serialize)]
+struct Entry {
+    time: u64,
+    #[serde(flatten)]
+    event: AuditEvent,
+}
+
+/// Handle to the optional audit log. Entries are handed to a dedicated thread
+/// for writing, so recording one never waits for the disk.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: Option<Sender<Entry>>,
+}
+
+impl AuditLog {
+    /// Prunes entries older than the retention period and starts the writer
+    /// thread, when the audit log is enabled.
+    pub fn start(config: &Config) -> Result<Self, Error> {
+        if !config.audit_log {
+            return Ok(AuditLog { sender: None });
+        }
+        if let Some(days) = config.audit_retention_days {
+            prune(now().saturating_sub(days * 24 * 60 * 60))
+                .map_err(|e| Error::Config(format!("{}: {}", AUDIT_FILE, e)))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(AUDIT_FILE)
+            .map_err(|e| Error::Config(format!("{}: {}", AUDIT_FILE, e)))?;
+
+        let (sender, receiver) = mpsc::channel::<Entry>();
+        thread::spawn(move || {
+            while let Ok(entry) = receiver.recv() {
+                let result = serde_json::to_string(&entry)
+                    .map_err(|e| e.to_string())
+                    .and_then(|line| writeln!(file, "{}", line).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Failed to write {}: {}", AUDIT_FILE, e);
+                }
+            }
+        });
+        Ok(AuditLog {
+            sender: Some(sender),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Entry { time: now(), event });
+        }
+    }
+}
+
+/// Which part of the audit log to read back, times in seconds since the epoch.
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct AuditPage {
+    total: usize,
+    entries: Vec<serde_json::Value>,
+}
+
+/// Reads the entries matching `query`, oldest first.
+pub fn read(query: &AuditQuery) -> Result<AuditPage, Error> {
+    let text = match fs::read_to_string(AUDIT_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Config(format!("{}: {}", AUDIT_FILE, e))),
+    };
+    let matching: Vec<serde_json::Value> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|entry| {
+            let time = entry_time(entry);
+            query.from.is_none_or(|from| time >= from) && query.to.is_none_or(|to| time <= to)
+        })
+        .collect();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LEN).min(MAX_PAGE_LEN);
+    Ok(AuditPage {
+        total: matching.len(),
+        entries: matching
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .collect(),
+    })
+}
+
+/// Drops the entries written before `cutoff`.
+fn prune(cutoff: u64) -> std::io::Result<()> {
+    let text = match fs::read_to_string(AUDIT_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let kept: String = text
+        .lines()
+        .filter(|line| {
+            serde_json::from_str::<serde_json::Value>(line)
+                .is_ok_and(|entry| entry_time(&entry) >= cutoff)
+        })
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(AUDIT_FILE, kept)
+}
+
+fn entry_time(entry: &serde_json::Value) -> u64 {
+    entry["time"].as_u64().unwrap_or(0)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
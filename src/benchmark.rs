@@ -0,0 +1,95 @@
+use crate::error::Error;
+use crate::signing::EventSigner;
+use crate::{parse_message, unwrap_signed};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use warp::ws::Message;
+
+/// Messages read for each case by `--benchmark`.
+pub const MESSAGES: usize = 200_000;
+
+const MOVE: &str = r#"{"type":"MouseMove","dx":3.5,"dy":-1.25,"sx":0,"sy":0,"touches":1}"#;
+const CLICK: &str = r#"{"type":"MouseClick","button":"Left"}"#;
+
+/// How long reading one client message took on average in a case.
+pub struct Timing {
+    pub case: &'static str,
+    pub per_message: Duration,
+}
+
+/// Reads `messages` client messages the way the connection handler does,
+/// once per case: parsing alone, then with `signed_events` off and on, so
+/// that what signing costs can be checked on the host it runs on. Moves are
+/// never signed; clicks are, and are verified.
+pub fn run(messages: usize) -> Result<Vec<Timing>, Error> {
+    let moves = vec![Message::text(MOVE); messages];
+    let nonce = "benchmark";
+    let mut signer = EventSigner::new("credential", nonce);
+    let sender = EventSigner::new("credential", nonce);
+    let clicks: Vec<Message> = (1..=messages as u64)
+        .map(|seq| {
+            Message::text(
+                serde_json::json!({
+                    "type": "Signed",
+                    "seq": seq,
+                    "event": CLICK,
+                    "sig": sender.signature(seq, CLICK),
+                })
+                .to_string(),
+            )
+        })
+        .collect();
+
+    Ok(vec![
+        time("move, parsing only", &moves, |message| {
+            parse_message(message).map(|_| ())
+        })?,
+        time("move, signed_events off", &moves, |message| {
+            unwrap_signed(parse_message(message), None).map(|_| ())
+        })?,
+        time("move, signed_events on", &moves, |message| {
+            unwrap_signed(parse_message(message), Some(&mut signer)).map(|_| ())
+        })?,
+        time("click, signed and verified", &clicks, |message| {
+            unwrap_signed(parse_message(message), Some(&mut signer)).map(|_| ())
+        })?,
+    ])
+}
+
+fn time(
+    case: &'static str,
+    messages: &[Message],
+    mut read: impl FnMut(&Message) -> Result<(), Error>,
+) -> Result<Timing, Error> {
+    let start = Instant::now();
+    for message in messages {
+        black_box(read(black_box(message)))?;
+    }
+    Ok(Timing {
+        case,
+        per_message: start.elapsed() / messages.len().max(1) as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_case_reads_its_messages() {
+        let timings = run(50).unwrap();
+        let cases: Vec<_> = timings.iter().map(|timing| timing.case).collect();
+        assert_eq!(
+            cases,
+            [
+                "move, parsing only",
+                "move, signed_events off",
+                "move, signed_events on",
+                "click, signed and verified",
+            ]
+        );
+        assert!(timings
+            .iter()
+            .all(|timing| timing.per_message > Duration::ZERO));
+    }
+}
@@ -0,0 +1,571 @@
+use crate::profiles::ProfileUpdate;
+use crate::server_info::DisplaySize;
+use crate::ClientEvent;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// The labels the suggestions are worked out from. Clients may mark other
+/// segments too; they are counted but not used.
+pub const LABELS: &[&str] = &["slow_drag", "fast_flick", "tap", "scroll"];
+
+/// How long a calibration may go on. One never finished would otherwise
+/// leave its connection measuring instead of moving the pointer for good.
+pub const MAX_DURATION: Duration = Duration::from_secs(300);
+
+/// Samples kept per recording, so that a calibration never finished cannot
+/// grow without bound. Later ones are dropped.
+const MAX_SAMPLES: usize = 20_000;
+
+/// Moves further apart than this belong to different strokes, even without
+/// a lift in between.
+const STROKE_GAP_MS: f64 = 150.0;
+
+/// Strokes with fewer moves are taken for stray touches.
+const MIN_STROKE_MOVES: usize = 5;
+
+/// Share of the primary display's width a typical slow drag should carry
+/// the pointer across, before acceleration.
+const SLOW_DRAG_SHARE: f64 = 0.5;
+
+/// The two-finger stroke, in CSS pixels, that scrolls as much as the
+/// default `scroll_factor` does on the phones the defaults were tuned on.
+const REFERENCE_SCROLL_STROKE_PX: f64 = 200.0;
+
+/// Taps further apart than this are separate attempts rather than a series.
+const MAX_TAP_GAP_MS: f64 = 1500.0;
+
+/// A pointer event recorded for calibration, in milliseconds since the
+/// recording started.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at_ms: f64,
+    motion: Motion,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Motion {
+    Move {
+        touches: i32,
+        dx: f64,
+        dy: f64,
+    },
+    Click,
+    /// A finger touching down or lifting, which ends a stroke.
+    Touch,
+}
+
+/// What a connection sent while calibrating, split into segments by the
+/// labels of `CalibrationMark`. Samples before the first mark are kept
+/// under an empty label and not used.
+#[derive(Debug)]
+pub struct Recording {
+    started: Instant,
+    segments: Vec<(String, Vec<Sample>)>,
+    samples: usize,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Recording {
+            started: Instant::now(),
+            segments: vec![(String::new(), Vec::new())],
+            samples: 0,
+        }
+    }
+
+    /// Whether the recording has gone on longer than `MAX_DURATION` at `now`.
+    pub fn expired(&self, now: Instant) -> bool {
+        now.duration_since(self.started) > MAX_DURATION
+    }
+
+    /// Starts a segment; the samples from here on belong to `label`.
+    pub fn mark(&mut self, label: String) {
+        self.segments.push((label, Vec::new()));
+    }
+
+    /// Records `event` if it is pointer input, returning whether it was.
+    /// Other events, such as keys, are not part of calibrating.
+    pub fn record(&mut self, event: &ClientEvent) -> bool {
+        let motion = match *event {
+            ClientEvent::MouseMove {
+                touches, dx, dy, ..
+            } => Motion::Move { touches, dx, dy },
+            ClientEvent::MouseClick { .. } => Motion::Click,
+            ClientEvent::TouchDown { .. } | ClientEvent::TouchUp => Motion::Touch,
+            _ => return false,
+        };
+        if self.samples < MAX_SAMPLES {
+            self.samples += 1;
+            let at_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+            let (_, samples) = self.segments.last_mut().expect("always one segment");
+            samples.push(Sample { at_ms, motion });
+        }
+        true
+    }
+
+    /// The samples of every segment marked `label`.
+    fn labelled(&self, label: &str) -> impl Iterator<Item = &[Sample]> + '_ {
+        let label = label.to_string();
+        self.segments
+            .iter()
+            .filter(move |(name, _)| *name == label)
+            .map(|(_, samples)| samples.as_slice())
+    }
+}
+
+/// An unbroken run of moves with the same number of fingers.
+struct Stroke {
+    touches: i32,
+    /// Each move's delta, and the time since the one before; none for the
+    /// first.
+    moves: Vec<(f64, f64, Option<f64>)>,
+}
+
+impl Stroke {
+    fn distance(&self) -> f64 {
+        self.moves.iter().map(|(dx, dy, _)| dx.hypot(*dy)).sum()
+    }
+}
+
+fn strokes(samples: &[Sample]) -> Vec<Stroke> {
+    let mut strokes: Vec<Stroke> = Vec::new();
+    let mut last_move: Option<(i32, f64)> = None;
+    for sample in samples {
+        let Motion::Move { touches, dx, dy } = sample.motion else {
+            last_move = None;
+            continue;
+        };
+        let gap = last_move
+            .filter(|(last_touches, _)| *last_touches == touches)
+            .map(|(_, at_ms)| sample.at_ms - at_ms)
+            .filter(|gap| *gap <= STROKE_GAP_MS);
+        match (gap, strokes.last_mut()) {
+            (Some(gap), Some(stroke)) => stroke.moves.push((dx, dy, Some(gap))),
+            _ => strokes.push(Stroke {
+                touches,
+                moves: vec![(dx, dy, None)],
+            }),
+        }
+        last_move = Some((touches, sample.at_ms));
+    }
+    strokes.retain(|stroke| stroke.moves.len() >= MIN_STROKE_MOVES);
+    strokes
+}
+
+/// The value `share` of the way through `values`, by nearest rank.
+fn percentile(values: &mut [f64], share: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = (share * values.len() as f64).ceil() as usize;
+    Some(values[rank.clamp(1, values.len()) - 1])
+}
+
+fn round_to(value: f64, places: i32) -> f64 {
+    let scale = 10f64.powi(places);
+    (value * scale).round() / scale
+}
+
+/// How much a recorded segment holds, so that clients can tell which
+/// suggestions are missing for want of samples.
+#[derive(Serialize, Debug, Default)]
+pub struct SegmentSummary {
+    pub moves: usize,
+    pub strokes: usize,
+    pub clicks: usize,
+}
+
+/// Settings for the device's profile, applied as a `SetConfig` update.
+#[derive(Serialize, Debug, Default)]
+pub struct ProfileSuggestion {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensitivity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll_factor: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snap_degrees: Option<f64>,
+}
+
+impl ProfileSuggestion {
+    pub fn is_empty(&self) -> bool {
+        self.sensitivity.is_none() && self.scroll_factor.is_none() && self.snap_degrees.is_none()
+    }
+
+    pub fn update(&self) -> ProfileUpdate {
+        ProfileUpdate {
+            sensitivity: self.sensitivity,
+            scroll_factor: self.scroll_factor,
+            snap_degrees: self.snap_degrees,
+            ..ProfileUpdate::default()
+        }
+    }
+}
+
+/// Settings for `config.json`, which affect every device and so are only
+/// ever suggested.
+#[derive(Serialize, Debug, Default)]
+pub struct ConfigSuggestion {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub velocity_reference_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_acceleration_px: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tap_drag: Option<TapDragSuggestion>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TapDragSuggestion {
+    pub tap_window_ms: u64,
+}
+
+/// The outcome of a calibration, returned by `/api/calibrate/finish`.
+#[derive(Serialize, Debug)]
+pub struct Calibration {
+    pub segments: BTreeMap<String, SegmentSummary>,
+    pub profile: ProfileSuggestion,
+    pub config: ConfigSuggestion,
+    /// Whether `profile` was applied to the device.
+    pub applied: bool,
+}
+
+impl Calibration {
+    /// Works out suggestions from `recording`. Each needs enough samples of
+    /// its own segment and is left out otherwise.
+    pub fn suggest(recording: &Recording, display: Option<DisplaySize>) -> Self {
+        let mut segments: BTreeMap<String, SegmentSummary> = BTreeMap::new();
+        for (label, samples) in recording
+            .segments
+            .iter()
+            .filter(|(label, _)| !label.is_empty())
+        {
+            let summary = segments.entry(label.clone()).or_default();
+            summary.strokes += strokes(samples).len();
+            for sample in samples {
+                match sample.motion {
+                    Motion::Move { .. } => summary.moves += 1,
+                    Motion::Click => summary.clicks += 1,
+                    Motion::Touch => {}
+                }
+            }
+        }
+        let strokes_of = |label: &str, touches: i32| -> Vec<Stroke> {
+            recording
+                .labelled(label)
+                .flat_map(strokes)
+                .filter(|stroke| stroke.touches == touches)
+                .collect()
+        };
+        let slow_drags = strokes_of("slow_drag", 1);
+        let flicks = strokes_of("fast_flick", 1);
+        let scrolls = strokes_of("scroll", 2);
+        let every_stroke = LABELS
+            .iter()
+            .flat_map(|label| recording.labelled(label).flat_map(strokes))
+            .collect::<Vec<_>>();
+
+        let profile = ProfileSuggestion {
+            sensitivity: display.and_then(|display| sensitivity(&slow_drags, display)),
+            scroll_factor: scroll_factor(&scrolls),
+            snap_degrees: snap_degrees(&slow_drags),
+        };
+        let config = ConfigSuggestion {
+            velocity_reference_ms: velocity_reference_ms(&every_stroke),
+            max_acceleration_px: max_acceleration_px(&flicks),
+            tap_drag: tap_window_ms(recording.labelled("tap"))
+                .map(|tap_window_ms| TapDragSuggestion { tap_window_ms }),
+        };
+        Calibration {
+            segments,
+            profile,
+            config,
+            applied: false,
+        }
+    }
+}
+
+/// So that the typical slow drag carries the pointer `SLOW_DRAG_SHARE` of
+/// the way across the display: slow drags are the careful ones, and should
+/// neither need several strokes nor overshoot.
+fn sensitivity(slow_drags: &[Stroke], display: DisplaySize) -> Option<f64> {
+    if slow_drags.len() < 2 {
+        return None;
+    }
+    let mut distances: Vec<f64> = slow_drags.iter().map(Stroke::distance).collect();
+    let typical = percentile(&mut distances, 0.5)?;
+    (typical > 0.0).then(|| {
+        round_to(
+            (display.width as f64 * SLOW_DRAG_SHARE / typical).clamp(0.2, 5.0),
+            2,
+        )
+    })
+}
+
+/// So that the typical scroll stroke scrolls as far as the reference one
+/// does by default: short strokes, from small screens or little reach,
+/// scroll more each.
+fn scroll_factor(scrolls: &[Stroke]) -> Option<f64> {
+    if scrolls.len() < 2 {
+        return None;
+    }
+    let mut distances: Vec<f64> = scrolls.iter().map(Stroke::distance).collect();
+    let typical = percentile(&mut distances, 0.5)?;
+    (typical > 0.0).then(|| round_to((REFERENCE_SCROLL_STROKE_PX / typical).clamp(0.25, 4.0), 2))
+}
+
+/// From how far the moves of slow drags along an axis stray from it. A
+/// steady hand gets no snapping; a shaky one gets enough to straighten
+/// three moves in four, up to 15 degrees so that diagonals stay possible.
+fn snap_degrees(slow_drags: &[Stroke]) -> Option<f64> {
+    let mut deviations = Vec::new();
+    for stroke in slow_drags {
+        let (x, y) = stroke
+            .moves
+            .iter()
+            .fold((0.0, 0.0), |(x, y), (dx, dy, _)| (x + dx, y + dy));
+        let heading = y.atan2(x).to_degrees();
+        // Only strokes close to an axis say anything about wobble; a
+        // diagonal one is meant to be diagonal.
+        if axis_offset(heading) > 20.0 {
+            continue;
+        }
+        deviations.extend(
+            stroke
+                .moves
+                .iter()
+                .filter(|(dx, dy, _)| dx.hypot(*dy) >= 1.0)
+                .map(|(dx, dy, _)| axis_offset(dy.atan2(*dx).to_degrees())),
+        );
+    }
+    if deviations.len() < 20 {
+        return None;
+    }
+    let wobble = percentile(&mut deviations, 0.75)?;
+    Some(if wobble < 2.0 {
+        0.0
+    } else {
+        wobble.min(15.0).round()
+    })
+}
+
+/// How far, in degrees, `heading` is from the nearest axis.
+fn axis_offset(heading: f64) -> f64 {
+    let offset = heading.abs() % 90.0;
+    offset.min(90.0 - offset)
+}
+
+/// The device's typical time between moves, so that acceleration judges
+/// every move as if it took that long.
+fn velocity_reference_ms(strokes: &[Stroke]) -> Option<f64> {
+    let mut gaps: Vec<f64> = strokes
+        .iter()
+        .flat_map(|stroke| stroke.moves.iter().filter_map(|(_, _, gap)| *gap))
+        .filter(|gap| *gap > 0.0)
+        .collect();
+    if gaps.len() < 20 {
+        return None;
+    }
+    percentile(&mut gaps, 0.5).map(|gap| round_to(gap, 1))
+}
+
+/// The finger's own fastest moves, so that acceleration never adds more to
+/// a move than the finger itself does.
+fn max_acceleration_px(flicks: &[Stroke]) -> Option<f64> {
+    let mut distances: Vec<f64> = flicks
+        .iter()
+        .flat_map(|stroke| stroke.moves.iter().map(|(dx, dy, _)| dx.hypot(*dy)))
+        .collect();
+    if distances.len() < 10 {
+        return None;
+    }
+    percentile(&mut distances, 0.95).map(|distance| round_to(distance, 1))
+}
+
+/// A quarter more than nearly all of the gaps between the taps of a series,
+/// so that a second touch as slow as the user's usual one still counts.
+fn tap_window_ms<'a>(segments: impl Iterator<Item = &'a [Sample]>) -> Option<u64> {
+    let mut gaps = Vec::new();
+    for samples in segments {
+        let clicks: Vec<f64> = samples
+            .iter()
+            .filter(|sample| matches!(sample.motion, Motion::Click))
+            .map(|sample| sample.at_ms)
+            .collect();
+        gaps.extend(
+            clicks
+                .windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .filter(|gap| *gap <= MAX_TAP_GAP_MS),
+        );
+    }
+    if gaps.len() < 3 {
+        return None;
+    }
+    let slow = percentile(&mut gaps, 0.9)?;
+    Some((slow * 1.25).round().clamp(150.0, 800.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: DisplaySize = DisplaySize {
+        width: 1920,
+        height: 1080,
+    };
+
+    /// A recording written sample by sample, with the times a device with
+    /// the given touch rate would have sent them at.
+    struct Trace {
+        recording: Recording,
+        at_ms: f64,
+        frame_ms: f64,
+    }
+
+    impl Trace {
+        fn new(frame_ms: f64) -> Self {
+            Trace {
+                recording: Recording::new(),
+                at_ms: 0.0,
+                frame_ms,
+            }
+        }
+
+        fn push(&mut self, motion: Motion) {
+            let (_, samples) = self.recording.segments.last_mut().unwrap();
+            samples.push(Sample {
+                at_ms: self.at_ms,
+                motion,
+            });
+        }
+
+        fn mark(&mut self, label: &str) {
+            self.recording.mark(label.to_string());
+            self.at_ms += 2000.0;
+        }
+
+        /// One touch moving by `moves`, one per frame, then a pause.
+        fn stroke(&mut self, touches: i32, moves: impl IntoIterator<Item = (f64, f64)>) {
+            self.push(Motion::Touch);
+            for (dx, dy) in moves {
+                self.at_ms += self.frame_ms;
+                self.push(Motion::Move { touches, dx, dy });
+            }
+            self.push(Motion::Touch);
+            self.at_ms += 500.0;
+        }
+
+        fn taps(&mut self, count: usize, gap_ms: f64) {
+            for _ in 0..count {
+                self.push(Motion::Click);
+                self.at_ms += gap_ms;
+            }
+        }
+    }
+
+    /// A small phone at 60 Hz: short, slightly wobbly strokes and quick taps.
+    fn phone() -> Recording {
+        let mut trace = Trace::new(50.0 / 3.0);
+        trace.mark("slow_drag");
+        for _ in 0..3 {
+            trace.stroke(
+                1,
+                (0..30).map(|i| (8.0, if i % 2 == 0 { 1.0 } else { -1.0 })),
+            );
+        }
+        trace.mark("fast_flick");
+        for _ in 0..3 {
+            trace.stroke(1, [10.0, 25.0, 40.0, 30.0, 15.0, 5.0].map(|dx| (dx, 0.0)));
+        }
+        trace.mark("tap");
+        trace.taps(6, 240.0);
+        trace.mark("scroll");
+        for _ in 0..3 {
+            trace.stroke(2, [(0.0, 10.0); 10]);
+        }
+        trace.recording
+    }
+
+    /// A large tablet at 120 Hz: long, straight strokes and slower taps.
+    fn tablet() -> Recording {
+        let mut trace = Trace::new(25.0 / 3.0);
+        trace.mark("slow_drag");
+        for _ in 0..3 {
+            trace.stroke(1, [(0.0, 10.0); 60]);
+        }
+        trace.mark("fast_flick");
+        for _ in 0..3 {
+            trace.stroke(1, [20.0, 60.0, 90.0, 70.0, 30.0, 10.0].map(|dx| (dx, 0.0)));
+        }
+        trace.mark("tap");
+        trace.taps(6, 400.0);
+        trace.mark("scroll");
+        for _ in 0..3 {
+            trace.stroke(2, [(0.0, -20.0); 20]);
+        }
+        trace.recording
+    }
+
+    #[test]
+    fn phone_suggestions() {
+        let calibration = Calibration::suggest(&phone(), Some(DISPLAY));
+        let profile = &calibration.profile;
+        // 240px drags need four times the speed to cross half the display.
+        assert_eq!(profile.sensitivity, Some(3.97));
+        assert_eq!(profile.scroll_factor, Some(2.0));
+        // Moves 7 degrees off the axis get straightened.
+        assert_eq!(profile.snap_degrees, Some(7.0));
+        let config = &calibration.config;
+        assert_eq!(config.velocity_reference_ms, Some(16.7));
+        assert_eq!(config.max_acceleration_px, Some(40.0));
+        assert_eq!(config.tap_drag.as_ref().unwrap().tap_window_ms, 300);
+        assert_eq!(calibration.segments["slow_drag"].strokes, 3);
+        assert_eq!(calibration.segments["tap"].clicks, 6);
+    }
+
+    #[test]
+    fn tablet_suggestions() {
+        let calibration = Calibration::suggest(&tablet(), Some(DISPLAY));
+        let profile = &calibration.profile;
+        assert_eq!(profile.sensitivity, Some(1.6));
+        assert_eq!(profile.scroll_factor, Some(0.5));
+        assert_eq!(profile.snap_degrees, Some(0.0));
+        let config = &calibration.config;
+        assert_eq!(config.velocity_reference_ms, Some(8.3));
+        assert_eq!(config.max_acceleration_px, Some(90.0));
+        assert_eq!(config.tap_drag.as_ref().unwrap().tap_window_ms, 500);
+        assert_eq!(calibration.segments["scroll"].moves, 60);
+    }
+
+    #[test]
+    fn missing_samples_leave_suggestions_out() {
+        let calibration = Calibration::suggest(&phone(), None);
+        assert_eq!(calibration.profile.sensitivity, None);
+        assert_eq!(calibration.profile.scroll_factor, Some(2.0));
+
+        let mut trace = Trace::new(10.0);
+        trace.mark("slow_drag");
+        trace.stroke(1, [(10.0, 0.0); 40]);
+        // Too short to be a stroke.
+        trace.stroke(1, [(10.0, 0.0); 4]);
+        trace.mark("tap");
+        trace.taps(3, 200.0);
+        let calibration = Calibration::suggest(&trace.recording, Some(DISPLAY));
+        // One drag is not enough to judge speed by, though its moves are
+        // enough to judge wobble by.
+        assert_eq!(calibration.profile.sensitivity, None);
+        assert_eq!(calibration.profile.snap_degrees, Some(0.0));
+        assert_eq!(calibration.profile.scroll_factor, None);
+        assert_eq!(calibration.segments["slow_drag"].strokes, 1);
+        assert!(calibration.config.tap_drag.is_none());
+        assert!(calibration.config.velocity_reference_ms.is_some());
+    }
+
+    #[test]
+    fn recordings_expire() {
+        let recording = Recording::new();
+        assert!(!recording.expired(recording.started + MAX_DURATION));
+        assert!(recording.expired(recording.started + MAX_DURATION + Duration::from_secs(1)));
+    }
+}
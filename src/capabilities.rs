@@ -0,0 +1,153 @@
+use crate::config::{Config, ScrollAxes, ScrollMode};
+use crate::contact::PalmRejection;
+use crate::gestures::{Gesture, GestureSwitches};
+use crate::input::{InputAbilities, InputReport};
+use crate::keys::{MediaKey, SpecialKey};
+use crate::media::MediaController;
+use crate::wake;
+use serde::Serialize;
+
+/// What this server can do on the current platform, so that clients only
+/// show the controls that will actually work.
+#[derive(Serialize, Debug, Clone)]
+pub struct Capabilities {
+    pub platform: &'static str,
+    /// What the input backend passed its startup self-check for; features
+    /// needing the rest are reported as unavailable.
+    pub input: InputAbilities,
+    pub media_keys: bool,
+    /// Media players can be controlled and followed directly, see `Media`.
+    pub mpris: bool,
+    pub special_keys: bool,
+    /// `SpecialKey`s beyond the portable ones, such as the browser keys on
+    /// Windows. Left out where there are none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub platform_keys: Vec<SpecialKey>,
+    pub typing: bool,
+    /// Text beyond ASCII can be typed; clients should offer only ASCII
+    /// characters otherwise.
+    pub unicode_typing: bool,
+    pub screenshot: bool,
+    pub screenshot_region: bool,
+    pub undo: bool,
+    /// Clients should report two-finger rotation with `Rotate`.
+    pub rotate: bool,
+    /// Clients should report the `pressure` and `radius` of touches.
+    pub touch_scaling: bool,
+    /// Clients should report finger positions with `MouseMove`.
+    pub edge_swipe: bool,
+    /// Clients should recognize four- and five-finger gestures and report
+    /// them with `FingerGesture`.
+    pub finger_gestures: bool,
+    /// Clients should report two-finger taps as a `FingerGesture` with two
+    /// fingers, rather than as clicks.
+    pub double_tap_zoom: bool,
+    /// Clients can ask for `Feedback` cues with `SetFeedback`.
+    pub feedback: bool,
+    /// Rules for clients to ignore accidental touches by, reporting each one
+    /// with `PalmRejected`.
+    pub palm_rejection: Option<PalmRejection>,
+    /// Clients should report `TouchDown` and `TouchUp`, and expect a touch
+    /// this soon after a tap to drag rather than click.
+    pub tap_drag_window_ms: Option<u64>,
+    /// `SetDrawingMode` can map the touchpad onto the display, which needs
+    /// its size.
+    pub absolute_drawing: bool,
+    /// `WakeDisplay` can switch the display on.
+    pub wake_display: bool,
+    /// Every gesture by name, and whether the server has it on; devices can
+    /// switch those that are on off with `SetConfig`.
+    pub gestures: GestureSwitches,
+    pub default_scroll_mode: ScrollMode,
+    /// Whether two-finger scrolling also goes sideways, see `scroll_axes`.
+    pub scroll_axes: ScrollAxes,
+    pub confirm_actions: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn detect(config: &Config, media: &MediaController, report: InputReport) -> Self {
+        let input = report.abilities;
+        Capabilities {
+            platform: std::env::consts::OS,
+            input,
+            media_keys: input.keys && MediaKey::is_supported(),
+            mpris: media.has_mpris(),
+            special_keys: input.keys,
+            platform_keys: SpecialKey::platform_keys(),
+            typing: input.keys,
+            unicode_typing: input.keys && report.backend.types_unicode(),
+            screenshot: config.screenshot_combo.is_some(),
+            screenshot_region: config.screenshot_region_combo.is_some(),
+            undo: config.undo_combo.is_some(),
+            rotate: config.has_gesture(Gesture::Rotate),
+            touch_scaling: config.touch_scaling.is_some(),
+            edge_swipe: config.has_gesture(Gesture::EdgeSwipe),
+            finger_gestures: config.has_gesture(Gesture::FingerGestures),
+            double_tap_zoom: config.has_gesture(Gesture::DoubleTapZoom),
+            feedback: config.feedback,
+            palm_rejection: config.palm_rejection.clone(),
+            tap_drag_window_ms: config
+                .tap_drag
+                .as_ref()
+                .filter(|_| config.has_gesture(Gesture::TapDrag))
+                .map(|tap_drag| tap_drag.tap_window_ms),
+            absolute_drawing: report.display.is_some(),
+            wake_display: wake::is_supported(report.backend, input),
+            gestures: Gesture::ALL.into_iter().fold(
+                GestureSwitches::default(),
+                |mut switches, gesture| {
+                    switches.set(gesture, config.has_gesture(gesture));
+                    switches
+                },
+            ),
+            default_scroll_mode: config.scroll_mode,
+            scroll_axes: config.scroll_axes,
+            confirm_actions: config.confirm_actions.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{BackendKind, Input};
+
+    fn report(backend: BackendKind, keys: bool) -> InputReport {
+        let (input, _) = Input::mock();
+        InputReport {
+            backend,
+            abilities: InputAbilities {
+                pointer: true,
+                keys,
+                scroll: true,
+            },
+            display: input.display_size(),
+        }
+    }
+
+    fn detect(report: InputReport) -> Capabilities {
+        Capabilities::detect(&Config::default(), &MediaController::default(), report)
+    }
+
+    #[test]
+    fn drawing_needs_the_display_size() {
+        let known = report(BackendKind::Enigo, true);
+        assert!(detect(known).absolute_drawing);
+        let unknown = InputReport {
+            display: None,
+            ..known
+        };
+        assert!(!detect(unknown).absolute_drawing);
+    }
+
+    #[test]
+    fn typing_follows_the_backend() {
+        let enigo = detect(report(BackendKind::Enigo, true));
+        assert!(enigo.typing && enigo.special_keys && enigo.unicode_typing);
+        let ydotool = detect(report(BackendKind::Ydotool, true));
+        assert!(ydotool.typing && !ydotool.unicode_typing);
+        let no_keys = detect(report(BackendKind::Enigo, false));
+        assert!(!no_keys.typing && !no_keys.special_keys && !no_keys.unicode_typing);
+        assert!(!no_keys.media_keys);
+    }
+}
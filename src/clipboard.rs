@@ -0,0 +1,86 @@
+use crate::input::Input;
+use crate::keys::KeyCombo;
+#[cfg(not(test))]
+use arboard::Clipboard;
+#[cfg(test)]
+use fake::Clipboard;
+use std::time::Duration;
+
+/// Time for the focused application to fetch the pasted text before the
+/// clipboard is put back. On X11 the text is only handed over on request.
+pub const PASTE_SETTLE: Duration = Duration::from_millis(200);
+
+/// Text put on the clipboard to be pasted. Dropping it puts back the text
+/// that was on the clipboard before; other clipboard contents, such as
+/// images, cannot be restored and are lost.
+pub struct Paste {
+    clipboard: Clipboard,
+    previous: Option<String>,
+    combo: KeyCombo,
+}
+
+impl Paste {
+    pub fn new(text: &str) -> Result<Self, String> {
+        let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
+        let previous = clipboard.get_text().ok();
+        clipboard.set_text(text).map_err(|e| e.to_string())?;
+        Ok(Paste {
+            clipboard,
+            previous,
+            combo: paste_combo(),
+        })
+    }
+
+    /// Pastes the text once.
+    pub fn press(&self, input: &mut Input) {
+        self.combo.press(input);
+    }
+}
+
+impl Drop for Paste {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            if let Err(e) = self.clipboard.set_text(previous) {
+                eprintln!("Failed to restore the clipboard: {}", e);
+            }
+        }
+    }
+}
+
+fn paste_combo() -> KeyCombo {
+    let combo = if cfg!(target_os = "macos") {
+        "Meta+v"
+    } else {
+        "Ctrl+v"
+    };
+    combo.parse().expect("valid paste combination")
+}
+
+/// A clipboard for tests, shared by the whole process like the real one.
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    static TEXT: Mutex<Option<String>> = Mutex::new(None);
+
+    pub struct Clipboard;
+
+    impl Clipboard {
+        pub fn new() -> Result<Self, String> {
+            Ok(Clipboard)
+        }
+
+        pub fn get_text(&mut self) -> Result<String, String> {
+            text().ok_or_else(|| "the clipboard is empty".to_string())
+        }
+
+        pub fn set_text(&mut self, text: impl Into<String>) -> Result<(), String> {
+            *TEXT.lock().unwrap() = Some(text.into());
+            Ok(())
+        }
+    }
+
+    pub fn text() -> Option<String> {
+        TEXT.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,844 @@
+use crate::admin::AdminSecret;
+use crate::contact::{PalmRejection, TouchScaling};
+use crate::dwell::DwellClick;
+use crate::error::Error;
+use crate::fence::FenceArea;
+use crate::gestures::{
+    DoubleTapZoom, EdgeSwipe, FingerGestureBinding, Gesture, GestureConfig, HeldButtonTimeout,
+    LauncherGesture, RotateGesture, SwipeGesture, TapDrag, GESTURE_FINGERS,
+};
+use crate::input::BackendKind;
+use crate::keys::KeyCombo;
+use crate::momentum::Momentum;
+use crate::panels::{self, Panel};
+use crate::scroll::ScrollDeceleration;
+use crate::transforms::EventTransform;
+use crate::ClientEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const CONFIG_FILE: &str = "config.json";
+
+/// Longest `key_debounce_ms` allowed: well below the gap between two
+/// letters typed by the fastest typists, so real double letters survive.
+const MAX_KEY_DEBOUNCE_MS: u64 = 50;
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Networks allowed to connect, in CIDR notation (e.g. "192.168.1.0/24").
+    /// An empty list allows every peer.
+    pub allowed_subnets: Vec<Subnet>,
+    /// Listen on the loopback address only, so that other devices have to
+    /// come through a tunnel. `--local-only` turns it on too.
+    pub local_only: bool,
+    /// Only let devices that were paired with a one-time code connect.
+    pub require_pairing: bool,
+    /// The secret for the admin endpoints from other computers. Generated
+    /// and kept in `admin.json` when unset.
+    pub admin_secret: Option<AdminSecret>,
+    /// Let the computer running the server use the admin endpoints without
+    /// the secret. Off by default, as tunnels and other local programs come
+    /// from the computer itself too.
+    pub admin_from_host: bool,
+    /// Require clicks, keys and other sensitive events to be signed with a
+    /// key derived from the device's pairing credential, so that captured
+    /// messages cannot be replayed. Needs `require_pairing`.
+    pub signed_events: bool,
+    /// Origins other than the server's own (e.g. "https://touchpad.example")
+    /// whose pages may open `/ws` and call the `/api` routes.
+    pub allowed_origins: Vec<String>,
+    /// Host names the server is opened under besides its IP addresses and
+    /// `localhost`, e.g. "desktop.local". A page is only the server's own
+    /// when its address names one of those, so that a website pointing its
+    /// own name at this computer (DNS rebinding) does not pass for it.
+    pub server_names: Vec<String>,
+    /// Close connections this many seconds after they opened, so that no one
+    /// holds on to the touchpad indefinitely. Unlimited when unset.
+    pub max_session_secs: Option<u64>,
+    /// The largest WebSocket message accepted, in bytes. A client sending a
+    /// larger one is told so and disconnected.
+    pub max_message_bytes: usize,
+    /// Drop the first movement after fingers are added or lifted, so a new
+    /// gesture never inherits motion from the previous one.
+    pub reset_on_finger_change: bool,
+    /// Take a connection's first move after this many milliseconds without
+    /// one, after it connects and after a `TouchDown`, for the finger's new
+    /// position rather than movement: its delta is dropped and the gesture
+    /// starts afresh. Disabled when unset.
+    pub resync_after_ms: Option<u64>,
+    /// Log how every gesture was classified and the thresholds that decided it.
+    pub trace_gestures: bool,
+    /// Initial two-finger scroll mode, see [`ScrollMode`]. The default depends
+    /// on the host platform.
+    pub scroll_mode: ScrollMode,
+    /// Which directions two-finger scrolling moves in, see [`ScrollAxes`].
+    pub scroll_axes: ScrollAxes,
+    /// Upper bound on the characters a single `TypeString` may produce,
+    /// repetitions included.
+    pub max_type_len: usize,
+    /// Upper bound on how long a `KeyHold` keeps its key down; longer holds
+    /// are cut short.
+    pub max_key_hold_ms: u64,
+    /// Characters rewritten in `KeyPress` and `TypeString` before they are
+    /// typed, for characters the host layout cannot type (e.g. smart quotes
+    /// to straight ones). A replacement may be longer or empty.
+    pub char_substitutions: BTreeMap<char, String>,
+    /// Pause between repetitions of a repeated `TypeString`.
+    pub type_delay_ms: u64,
+    /// Enter `TypeString` text by pasting it through the clipboard instead
+    /// of typing each key, for remote desktops and input methods that drop
+    /// synthetic key events.
+    pub prefer_clipboard_typing: bool,
+    /// Drop a `KeyPress` or `SpecialKey` repeating the previous one on the
+    /// same connection within this many milliseconds, for browsers that
+    /// report one tap twice. Disabled when unset.
+    pub key_debounce_ms: Option<u64>,
+    /// Event types (e.g. "TypeString") that only run when the same event is
+    /// sent twice within `confirm_window_ms`.
+    pub confirm_actions: Vec<String>,
+    pub confirm_window_ms: u64,
+    /// Rules rewriting or dropping client events before anything acts on
+    /// them, applied in order.
+    pub event_transforms: Vec<EventTransform>,
+    /// How often to tell clients how many events are waiting to be processed,
+    /// so they can slow down. Disabled when unset.
+    pub queue_depth_report_ms: Option<u64>,
+    /// Let key presses overtake the pointer moves queued ahead of them, so
+    /// that typing stays responsive while the pointer is busy.
+    pub key_priority: bool,
+    /// Base pointer acceleration on the average speed of this many recent
+    /// moves instead of the latest one alone.
+    pub acceleration_window: Option<usize>,
+    /// Estimate the speed of a touch's first move from its distance, since
+    /// clients measure it from when the finger landed and so make fast
+    /// flicks start slow.
+    pub acceleration_warm_start: bool,
+    /// Forget the speeds carried over by `acceleration_window` and
+    /// `acceleration_warm_start` once a touch has not moved for this long, so
+    /// that resuming after a pause does not lurch. Disabled when unset.
+    pub inertia_reset_ms: Option<u64>,
+    /// Most that acceleration may add to a move along each axis, in pixels
+    /// before sensitivity, so that carried-over speeds smooth movement
+    /// without ever outweighing the finger's own. Unlimited when unset.
+    pub max_acceleration_px: Option<f64>,
+    /// Judge each one-finger move as if it took this many milliseconds, so
+    /// that acceleration does not depend on how often the client reports
+    /// moves. Moves are judged one by one when unset.
+    pub velocity_reference_ms: Option<f64>,
+    /// Accelerate horizontal and vertical movement each by its own speed
+    /// instead of by the overall speed. Disabled when unset.
+    pub axis_acceleration: Option<AxisAcceleration>,
+    /// How often two-finger scrolling gathered in `Fast` mode is sent as one
+    /// wheel event; 0 sends it with every move.
+    pub scroll_interval_ms: u64,
+    /// Every this many one-finger moves, move the pointer to where the moves
+    /// of the touch so far should have put it, for backends whose relative
+    /// moves drift. Disabled when unset.
+    pub reconcile_every: Option<u32>,
+    /// Keep the pointer inside this part of the desktop, e.g. the display
+    /// being presented from. Clients can also set it to the display the
+    /// pointer is on with `SetPointerFence`. Disabled when unset.
+    pub pointer_fence: Option<FenceArea>,
+    /// Upper bound on the wheel amount a single two-finger move may scroll.
+    pub max_scroll_per_event: Option<u16>,
+    /// Kinetic scrolling in `Fast` mode. Disabled when unset.
+    pub momentum: Option<Momentum>,
+    /// Fast two-finger scrolling scrolls less per pixel than slow, rather
+    /// than as much. Disabled when unset.
+    pub scroll_deceleration: Option<ScrollDeceleration>,
+    /// Shortcut sent by the `Screenshot` event for a full screen capture.
+    /// Defaults to the platform shortcut; Linux has none since enigo cannot
+    /// press Print Screen there.
+    pub screenshot_combo: Option<KeyCombo>,
+    /// Same as `screenshot_combo`, for capturing a region.
+    pub screenshot_region_combo: Option<KeyCombo>,
+    /// Shortcut sent by the `UndoLast` event. Defaults to the platform's
+    /// undo shortcut; null turns the event off.
+    pub undo_combo: Option<KeyCombo>,
+    /// Let clients ask for `Feedback` cues when their clicks, drags and
+    /// gestures take effect.
+    pub feedback: bool,
+    /// Scales pointer sensitivity by touch pressure and ignores palm-sized
+    /// contacts, for clients that report them. Disabled when unset.
+    pub touch_scaling: Option<TouchScaling>,
+    /// Ignores touches that look accidental. Disabled when unset, since it
+    /// can get in the way of stylus use.
+    pub palm_rejection: Option<PalmRejection>,
+    /// Sensitivity multiplier while a client holds precision mode with
+    /// `SetPrecisionHold`. Acceleration is skipped meanwhile.
+    pub precision_multiplier: f64,
+    /// Gestures switched on and off by name, see [`Gesture`]. All are on,
+    /// though the ones with a section of their own below, such as `rotate`,
+    /// also need that to be set.
+    pub gestures: GestureConfig,
+    /// What a two-finger rotation does. Disabled when unset.
+    pub rotate: Option<RotateGesture>,
+    /// Two-finger horizontal flicks for browser back and forward.
+    pub swipe: SwipeGesture,
+    /// One-finger swipes in from the touchpad's edges. Disabled when unset.
+    pub edge_swipe: Option<EdgeSwipe>,
+    /// Actions for four- and five-finger taps, swipes and pinches. None are
+    /// bound by default.
+    pub finger_gestures: Vec<FingerGestureBinding>,
+    /// Extra buttons for the client pages, served by `/api/panels` and
+    /// pressed with `PanelAction`. None by default.
+    pub panels: Vec<Panel>,
+    /// The whole-hand gesture opening the application launcher. Disabled
+    /// when unset.
+    pub launcher: Option<LauncherGesture>,
+    /// The action for a two-finger double tap. Disabled when unset.
+    pub double_tap_zoom: Option<DoubleTapZoom>,
+    /// Timing of the tap-and-a-half drag. Disabled when unset.
+    pub tap_drag: Option<TapDrag>,
+    /// Releases a tap drag or drawing left held without movement, e.g.
+    /// because the client went quiet before the finger lifted.
+    pub held_button_timeout: HeldButtonTimeout,
+    /// Snap the pointer to a grid of this many pixels while drawing with
+    /// absolute positioning. Disabled when unset.
+    pub snap_grid: Option<u32>,
+    /// When set, a click followed by movement within this many milliseconds
+    /// becomes a drag: the button is held down while the pointer moves and
+    /// released once movement pauses for as long. Clicks are delayed by the
+    /// same amount.
+    pub click_drag_window_ms: Option<u64>,
+    /// Whether a click held back by `tap_drag` or `click_drag_window_ms`
+    /// presses the button straight away or only once no drag followed.
+    pub click_on: ClickOn,
+    /// What two-finger drags hold down in pan mode, see `SetPanMode`.
+    pub pan_input: PanInput,
+    /// Timing of dwell clicking, for connections that turn it on with
+    /// `SetDwellClick`.
+    pub dwell_click: DwellClick,
+    /// Treat the first touch after this many milliseconds without input as
+    /// waking the display: it wakes it and is otherwise ignored. Disabled
+    /// when unset.
+    pub wake_after_idle_ms: Option<u64>,
+    /// Keep an append-only record of connections and settings changes in
+    /// `audit.jsonl`.
+    pub audit_log: bool,
+    /// Audit entries older than this many days are dropped at startup.
+    pub audit_retention_days: Option<u64>,
+    /// Input backends to try at startup, in order; the first that passes its
+    /// self-check is used.
+    pub input_backends: Vec<BackendKind>,
+    /// Directories of client files served under URL path prefixes (e.g.
+    /// "minimal" for `/minimal/`), for running several client variants from
+    /// one server. Paths no prefix covers are served from `public`.
+    pub static_dirs: BTreeMap<String, PathBuf>,
+}
+
+/// How two-finger scrolling is turned into wheel events.
+///
+/// Platforms disagree on what one wheel unit means. On Windows every unit is a
+/// full wheel notch and many native applications jump a whole page per notch,
+/// so `Notch` is the default there. X11 and macOS scroll by lines, where the
+/// larger magnitudes of `Fast` feel natural, so it is the default elsewhere.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum ScrollMode {
+    /// Emit single +/-1 steps, paced more rapidly the faster the gesture is.
+    Notch,
+    /// Emit magnitudes scaled with the gesture distance.
+    Fast,
+}
+
+impl Default for ScrollMode {
+    fn default() -> Self {
+        if cfg!(windows) {
+            ScrollMode::Notch
+        } else {
+            ScrollMode::Fast
+        }
+    }
+}
+
+/// Which directions two-finger scrolling moves in.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScrollAxes {
+    /// Only up and down.
+    #[default]
+    Vertical,
+    /// Horizontally or vertically, whichever the gesture starts along,
+    /// until the fingers lift.
+    Locked,
+    /// Both at once, following the fingers.
+    Free,
+}
+
+/// When a click that may turn into a drag reaches the application.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickOn {
+    /// The button goes down with the tap and up once no drag followed, or
+    /// after the drag. A drag is then held from the tap's position on.
+    Press,
+    /// The whole click goes out once no drag followed.
+    #[default]
+    Release,
+}
+
+/// What a two-finger drag holds down to pan in pan mode.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PanInput {
+    /// The middle button, which pans maps, CAD drawings and 3D views.
+    #[default]
+    Middle,
+    /// The space bar, which pans the canvas in image editors and design
+    /// tools.
+    Space,
+}
+
+/// Pointer acceleration worked out for each axis from its own speed, so a
+/// diagonal move is not accelerated like an axis-aligned one of the same
+/// speed.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AxisAcceleration {
+    /// Multipliers for the acceleration of horizontal and vertical movement.
+    pub horizontal: f64,
+    pub vertical: f64,
+}
+
+impl Default for AxisAcceleration {
+    fn default() -> Self {
+        AxisAcceleration {
+            horizontal: 1.0,
+            vertical: 1.0,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let (screenshot_combo, screenshot_region_combo) = default_screenshot_combos();
+        Config {
+            allowed_subnets: Vec::new(),
+            local_only: false,
+            allowed_origins: Vec::new(),
+            server_names: Vec::new(),
+            require_pairing: false,
+            admin_secret: None,
+            admin_from_host: false,
+            signed_events: false,
+            max_session_secs: None,
+            max_message_bytes: 64 * 1024,
+            reset_on_finger_change: true,
+            resync_after_ms: Some(1000),
+            trace_gestures: false,
+            scroll_mode: ScrollMode::default(),
+            scroll_axes: ScrollAxes::default(),
+            max_type_len: 1000,
+            max_key_hold_ms: 5000,
+            char_substitutions: BTreeMap::new(),
+            type_delay_ms: 0,
+            prefer_clipboard_typing: false,
+            key_debounce_ms: None,
+            confirm_actions: Vec::new(),
+            event_transforms: Vec::new(),
+            confirm_window_ms: 2000,
+            queue_depth_report_ms: None,
+            key_priority: true,
+            acceleration_window: None,
+            acceleration_warm_start: false,
+            inertia_reset_ms: Some(300),
+            max_acceleration_px: None,
+            velocity_reference_ms: None,
+            axis_acceleration: None,
+            scroll_interval_ms: 100,
+            reconcile_every: None,
+            pointer_fence: None,
+            max_scroll_per_event: None,
+            momentum: None,
+            scroll_deceleration: None,
+            screenshot_combo,
+            screenshot_region_combo,
+            undo_combo: default_undo_combo(),
+            feedback: true,
+            touch_scaling: None,
+            palm_rejection: None,
+            precision_multiplier: 0.25,
+            gestures: GestureConfig::default(),
+            rotate: None,
+            swipe: SwipeGesture::default(),
+            edge_swipe: None,
+            finger_gestures: Vec::new(),
+            panels: Vec::new(),
+            launcher: None,
+            double_tap_zoom: None,
+            tap_drag: None,
+            held_button_timeout: HeldButtonTimeout::default(),
+            snap_grid: None,
+            click_drag_window_ms: None,
+            click_on: ClickOn::default(),
+            pan_input: PanInput::default(),
+            dwell_click: DwellClick::default(),
+            wake_after_idle_ms: None,
+            audit_log: false,
+            audit_retention_days: None,
+            input_backends: vec![BackendKind::Enigo, BackendKind::Ydotool],
+            static_dirs: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads `config.json` from the working directory, falling back to the
+    /// defaults when the file does not exist.
+    pub fn load() -> Result<Self, Error> {
+        let mut config: Config = match fs::read_to_string(CONFIG_FILE) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| Error::Config(format!("{}: {}", CONFIG_FILE, e)))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(Error::Config(format!("{}: {}", CONFIG_FILE, e))),
+        };
+        config.check_origins()?;
+        config.check_static_dirs()?;
+        config.check_event_transforms()?;
+        if config.max_session_secs == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: max_session_secs must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.max_key_hold_ms == 0 {
+            return Err(Error::Config(format!(
+                "{}: max_key_hold_ms must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.max_message_bytes == 0 {
+            return Err(Error::Config(format!(
+                "{}: max_message_bytes must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(swipe) = &config.edge_swipe {
+            if swipe.edge_swipe_px <= 0.0 || swipe.min_distance_px <= 0.0 {
+                return Err(Error::Config(format!(
+                    "{}: edge_swipe distances must be positive",
+                    CONFIG_FILE
+                )));
+            }
+        }
+        if let Some(binding) = config
+            .finger_gestures
+            .iter()
+            .find(|binding| !GESTURE_FINGERS.contains(&binding.fingers))
+        {
+            return Err(Error::Config(format!(
+                "{}: finger_gestures: {} fingers is not supported, use 4 or 5",
+                CONFIG_FILE, binding.fingers
+            )));
+        }
+        panels::check(&config.panels)
+            .map_err(|e| Error::Config(format!("{}: {}", CONFIG_FILE, e)))?;
+        if let Some(launcher) = &config.launcher {
+            if !GESTURE_FINGERS.contains(&launcher.fingers) {
+                return Err(Error::Config(format!(
+                    "{}: launcher: {} fingers is not supported, use 4 or 5",
+                    CONFIG_FILE, launcher.fingers
+                )));
+            }
+            if config
+                .finger_gestures
+                .iter()
+                .any(|binding| launcher.matches(binding.fingers, binding.gesture))
+            {
+                return Err(Error::Config(format!(
+                    "{}: launcher: {}-finger {:?} is already bound in finger_gestures",
+                    CONFIG_FILE, launcher.fingers, launcher.gesture
+                )));
+            }
+        }
+        if let Some(ms) = config.key_debounce_ms {
+            if ms == 0 || ms > MAX_KEY_DEBOUNCE_MS {
+                return Err(Error::Config(format!(
+                    "{}: key_debounce_ms must be between 1 and {}",
+                    CONFIG_FILE, MAX_KEY_DEBOUNCE_MS
+                )));
+            }
+        }
+        if config.input_backends.is_empty() {
+            return Err(Error::Config(format!(
+                "{}: input_backends must name at least one backend",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(ms) = config.velocity_reference_ms {
+            if !(ms.is_finite() && ms > 0.0) {
+                return Err(Error::Config(format!(
+                    "{}: velocity_reference_ms must be positive",
+                    CONFIG_FILE
+                )));
+            }
+        }
+        if config.resync_after_ms == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: resync_after_ms must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.inertia_reset_ms == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: inertia_reset_ms must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config
+            .max_acceleration_px
+            .is_some_and(|max| !(max.is_finite() && max >= 0.0))
+        {
+            return Err(Error::Config(format!(
+                "{}: max_acceleration_px must be a non-negative number",
+                CONFIG_FILE
+            )));
+        }
+        if config.acceleration_window == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: acceleration_window must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(axes) = &config.axis_acceleration {
+            if !(axes.horizontal >= 0.0 && axes.vertical >= 0.0) {
+                return Err(Error::Config(format!(
+                    "{}: axis_acceleration multipliers must not be negative",
+                    CONFIG_FILE
+                )));
+            }
+        }
+        if config.reconcile_every == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: reconcile_every must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.max_scroll_per_event == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: max_scroll_per_event must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(momentum) = &config.momentum {
+            momentum
+                .check()
+                .map_err(|e| Error::Config(format!("{}: momentum: {}", CONFIG_FILE, e)))?;
+        }
+        if let Some(deceleration) = &config.scroll_deceleration {
+            deceleration.check().map_err(|e| {
+                Error::Config(format!("{}: scroll_deceleration: {}", CONFIG_FILE, e))
+            })?;
+        }
+        config
+            .dwell_click
+            .check()
+            .map_err(|e| Error::Config(format!("{}: dwell_click: {}", CONFIG_FILE, e)))?;
+        if let Some(palm_rejection) = &config.palm_rejection {
+            palm_rejection
+                .check()
+                .map_err(|e| Error::Config(format!("{}: palm_rejection: {}", CONFIG_FILE, e)))?;
+        }
+        if config.signed_events && !config.require_pairing {
+            return Err(Error::Config(format!(
+                "{}: signed_events needs require_pairing, whose credentials the keys come from",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(area) = &config.pointer_fence {
+            area.check()
+                .map_err(|e| Error::Config(format!("{}: pointer_fence: {}", CONFIG_FILE, e)))?;
+        }
+        if let Some(touch_scaling) = &config.touch_scaling {
+            touch_scaling
+                .check()
+                .map_err(|e| Error::Config(format!("{}: touch_scaling: {}", CONFIG_FILE, e)))?;
+        }
+        if config.wake_after_idle_ms == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: wake_after_idle_ms must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.snap_grid == Some(0) {
+            return Err(Error::Config(format!(
+                "{}: snap_grid must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if config.precision_multiplier <= 0.0 {
+            return Err(Error::Config(format!(
+                "{}: precision_multiplier must be positive",
+                CONFIG_FILE
+            )));
+        }
+        if let Some(rotate) = &config.rotate {
+            if rotate.step_degrees <= 0.0 {
+                return Err(Error::Config(format!(
+                    "{}: rotate.step_degrees must be positive",
+                    CONFIG_FILE
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Browsers send origins without a trailing slash, so one in the config
+    /// would never match. A wildcard is refused outright: without
+    /// authentication it would let any website inject input.
+    fn check_origins(&mut self) -> Result<(), Error> {
+        for origin in &mut self.allowed_origins {
+            if origin.contains('*') {
+                return Err(Error::Config(format!(
+                    "{}: wildcard origin {:?} is not allowed without authentication",
+                    CONFIG_FILE, origin
+                )));
+            }
+            let trimmed = origin.trim_end_matches('/').len();
+            origin.truncate(trimmed);
+        }
+        for name in &mut self.server_names {
+            if name.is_empty() || name.contains([':', '/', '*']) {
+                return Err(Error::Config(format!(
+                    "{}: server_names: {:?} is not a host name",
+                    CONFIG_FILE, name
+                )));
+            }
+            name.make_ascii_lowercase();
+        }
+        Ok(())
+    }
+
+    /// Refuses `drop` rules naming event types clients never send, which
+    /// would be typos that silently drop nothing.
+    fn check_event_transforms(&self) -> Result<(), Error> {
+        if let Some(kind) = self
+            .event_transforms
+            .iter()
+            .flat_map(EventTransform::dropped_kinds)
+            .find(|kind| !ClientEvent::KINDS.contains(&kind.as_str()))
+        {
+            return Err(Error::Config(format!(
+                "{}: event_transforms drops unknown event type {:?}",
+                CONFIG_FILE, kind
+            )));
+        }
+        Ok(())
+    }
+
+    /// Route prefixes are kept without their slashes so that "/minimal/"
+    /// and "minimal" are the same route.
+    fn check_static_dirs(&mut self) -> Result<(), Error> {
+        let mut static_dirs = BTreeMap::new();
+        for (prefix, dir) in std::mem::take(&mut self.static_dirs) {
+            let segments: Vec<&str> = prefix.split('/').filter(|s| !s.is_empty()).collect();
+            if segments.iter().any(|segment| segment.starts_with('.')) {
+                return Err(Error::Config(format!(
+                    "{}: static_dirs: invalid route prefix {:?}",
+                    CONFIG_FILE, prefix
+                )));
+            }
+            if !dir.is_dir() {
+                return Err(Error::Config(format!(
+                    "{}: static_dirs: {:?} is not a directory",
+                    CONFIG_FILE,
+                    dir.display().to_string()
+                )));
+            }
+            let route = segments.join("/");
+            if static_dirs.insert(route, dir).is_some() {
+                return Err(Error::Config(format!(
+                    "{}: static_dirs: route prefix {:?} is given twice",
+                    CONFIG_FILE, prefix
+                )));
+            }
+        }
+        self.static_dirs = static_dirs;
+        Ok(())
+    }
+
+    /// Whether `gesture` is switched on and, where it needs one, has its
+    /// section.
+    pub fn has_gesture(&self, gesture: Gesture) -> bool {
+        self.gestures.enabled.is_on(gesture)
+            && match gesture {
+                Gesture::EdgeSwipe => self.edge_swipe.is_some(),
+                Gesture::Rotate => self.rotate.is_some(),
+                Gesture::TapDrag => self.tap_drag.is_some(),
+                Gesture::FingerGestures => {
+                    !self.finger_gestures.is_empty() || self.launcher.is_some()
+                }
+                Gesture::DoubleTapZoom => self.double_tap_zoom.is_some(),
+                Gesture::Swipe => self.swipe.enabled,
+                Gesture::Tap | Gesture::LongPress | Gesture::Scroll => true,
+            }
+    }
+
+    /// Whether `allowed_subnets` lets `ip` in. The computer itself always
+    /// gets in, so that a second instance can check on the first.
+    pub fn is_peer_allowed(&self, ip: IpAddr) -> bool {
+        self.allowed_subnets.is_empty()
+            || ip.to_canonical().is_loopback()
+            || self
+                .allowed_subnets
+                .iter()
+                .any(|subnet| subnet.contains(ip))
+    }
+}
+
+/// The platform's full screen and region screenshot shortcuts.
+fn default_screenshot_combos() -> (Option<KeyCombo>, Option<KeyCombo>) {
+    let (full, region) = if cfg!(windows) {
+        (Some("PrintScreen"), Some("Meta+Shift+s"))
+    } else if cfg!(target_os = "macos") {
+        (Some("Meta+Shift+3"), Some("Meta+Shift+4"))
+    } else {
+        (None, None)
+    };
+    (
+        full.and_then(|combo| combo.parse().ok()),
+        region.and_then(|combo| combo.parse().ok()),
+    )
+}
+
+/// The platform's undo shortcut.
+fn default_undo_combo() -> Option<KeyCombo> {
+    let combo = if cfg!(target_os = "macos") {
+        "Meta+z"
+    } else {
+        "Control+z"
+    };
+    combo.parse().ok()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "String")]
+pub struct Subnet {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid subnet address: {}", s))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid subnet prefix length: {}", s))?,
+            None => max_len,
+        };
+        Ok(Subnet {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_gets_past_allowed_subnets() {
+        let config = Config {
+            allowed_subnets: serde_json::from_str(r#"["192.168.1.0/24"]"#).unwrap(),
+            ..Config::default()
+        };
+        for (ip, allowed) in [
+            ("192.168.1.7", true),
+            ("192.168.2.7", false),
+            ("127.0.0.1", true),
+            ("::1", true),
+            ("::ffff:127.0.0.1", true),
+            ("::ffff:10.0.0.1", false),
+        ] {
+            assert_eq!(
+                config.is_peer_allowed(ip.parse().unwrap()),
+                allowed,
+                "{}",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn event_transforms_must_drop_known_types() {
+        let config = |rules: &str| Config {
+            event_transforms: serde_json::from_str(rules).unwrap(),
+            ..Config::default()
+        };
+        let known = config(r#"["swap_axes", {"drop": ["Screenshot", "TypeString"]}]"#);
+        assert!(known.check_event_transforms().is_ok());
+        for rules in [
+            r#"[{"drop": ["Screenshots"]}]"#,
+            r#"["swap_buttons", {"drop": ["MouseClick", "screenshot"]}]"#,
+            // Only the server itself sends it.
+            r#"[{"drop": ["Closed"]}]"#,
+        ] {
+            assert!(matches!(
+                config(rules).check_event_transforms(),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn server_names_are_bare_host_names() {
+        let config = |names: &[&str]| Config {
+            server_names: names.iter().map(|name| name.to_string()).collect(),
+            ..Config::default()
+        };
+        let mut names = config(&["Desktop.local"]);
+        assert!(names.check_origins().is_ok());
+        assert_eq!(names.server_names, ["desktop.local"]);
+        for name in ["", "desktop.local:8080", "http://desktop.local", "*.local"] {
+            assert!(config(&[name]).check_origins().is_err(), "{:?}", name);
+        }
+    }
+}
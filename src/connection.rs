@@ -0,0 +1,759 @@
+use crate::calibration::Recording;
+use crate::error::Error;
+use crate::event_log::{EventLog, Logged, Outcome};
+use crate::profiles::Profile;
+use crate::{ClientEvent, ServerEvent};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// A connected WebSocket client, shared between its handler and the mouse event thread.
+#[derive(Debug)]
+pub struct Connection {
+    pub id: u64,
+    /// Stable id the client identified itself with, used to look up its profile.
+    pub device: Option<String>,
+    /// The locale messages to the client are in, see `i18n::negotiate`.
+    pub locale: &'static str,
+    explain: AtomicBool,
+    /// Whether the client asked for `Feedback` cues, and when it was last
+    /// sent a scroll cue.
+    feedback: AtomicBool,
+    scroll_feedback: Mutex<Option<Instant>>,
+    precision: AtomicBool,
+    pan: AtomicBool,
+    dwell_click: AtomicBool,
+    drawing: Mutex<Option<Drawing>>,
+    /// Pointer input being measured, see `/api/calibrate/start`.
+    calibration: Mutex<Option<Recording>>,
+    palm_rejections: AtomicU64,
+    ghost_keys: AtomicU64,
+    counters: EventCounters,
+    profile: Mutex<Profile>,
+    notices: UnboundedSender<ServerEvent>,
+    events: EventLog,
+    /// Event log entry of the event the mouse event thread is handling, 0
+    /// when there is none.
+    handling: AtomicU64,
+}
+
+/// What became of a connection's messages, counted by its handler and the
+/// mouse event thread.
+#[derive(Debug, Default)]
+struct EventCounters {
+    received: AtomicU64,
+    parsed: AtomicU64,
+    forwarded: AtomicU64,
+    /// Events the mouse event thread picked up, whether applied or dropped.
+    handled: AtomicU64,
+    /// Of those, the ones it dropped.
+    dropped_by_worker: AtomicU64,
+    dropped: Mutex<BTreeMap<&'static str, u64>>,
+    /// Time from the latest handled event arriving to being picked up.
+    delay_us: AtomicU64,
+    /// Latest WebSocket ping round trip; 0 until one has been measured.
+    rtt_us: AtomicU64,
+}
+
+/// How often at most a connection is sent a scroll cue; scrolling produces
+/// many events a second.
+const SCROLL_FEEDBACK_INTERVAL: Duration = Duration::from_millis(150);
+
+/// What a `Feedback` cue tells the client happened, so that it can vibrate
+/// or play a tick in place of the travel of a physical button.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackKind {
+    Click,
+    RightClick,
+    DragStart,
+    DragEnd,
+    Gesture,
+    Scroll,
+    Error,
+    /// A dwell click is coming, in `click_in_ms`.
+    Dwell,
+    /// The dwell click announced last is off, as the pointer moved away.
+    DwellCancel,
+}
+
+/// A snapshot of a connection's counters, served by `/api/clients` and sent
+/// in `Stats` reports.
+#[derive(Serialize, Debug, Clone)]
+pub struct ConnectionStats {
+    pub id: u64,
+    pub locale: &'static str,
+    pub received: u64,
+    pub parsed: u64,
+    pub forwarded: u64,
+    pub applied: u64,
+    /// Dropped messages by reason: an error code for those refused on
+    /// arrival, otherwise what the mouse event thread dropped them for.
+    pub dropped: BTreeMap<&'static str, u64>,
+    pub palm_rejections: u64,
+    pub ghost_keys: u64,
+    pub processing_delay_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f64>,
+}
+
+impl Connection {
+    /// Whether the mouse event thread should log why it handled this
+    /// connection's events the way it did.
+    pub fn explain(&self) -> bool {
+        self.explain.load(Ordering::Relaxed)
+    }
+
+    pub fn set_explain(&self, enabled: bool) {
+        self.explain.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Turns `Feedback` cues on or off, see `SetFeedback`.
+    pub fn set_feedback(&self, active: bool) {
+        self.feedback.store(active, Ordering::Relaxed);
+    }
+
+    /// Cues the client that its input took effect, if it asked for cues.
+    /// Scroll cues are sent at most every `SCROLL_FEEDBACK_INTERVAL`.
+    pub fn feedback(&self, kind: FeedbackKind) {
+        if !self.feedback.load(Ordering::Relaxed) {
+            return;
+        }
+        if kind == FeedbackKind::Scroll {
+            let mut last = self.scroll_feedback.lock().unwrap();
+            let now = Instant::now();
+            if last.is_some_and(|last| now - last < SCROLL_FEEDBACK_INTERVAL) {
+                return;
+            }
+            *last = Some(now);
+        }
+        self.notify(ServerEvent::Feedback {
+            kind,
+            click_in_ms: None,
+        });
+    }
+
+    /// Tells the client that a dwell click comes in `click_in`, or with
+    /// `None` that it is off, so that it can show the countdown. Sent whether
+    /// or not the client asked for `Feedback` cues, as it asked for dwell
+    /// clicks, which are refused when `feedback` is off.
+    pub fn dwell_cue(&self, click_in: Option<Duration>) {
+        self.notify(ServerEvent::Feedback {
+            kind: if click_in.is_some() {
+                FeedbackKind::Dwell
+            } else {
+                FeedbackKind::DwellCancel
+            },
+            click_in_ms: click_in.map(|click_in| click_in.as_millis() as u64),
+        });
+    }
+
+    /// Whether the client is holding precision mode, see `SetPrecisionHold`.
+    pub fn precision(&self) -> bool {
+        self.precision.load(Ordering::Relaxed)
+    }
+
+    pub fn set_precision(&self, active: bool) {
+        self.precision.store(active, Ordering::Relaxed);
+    }
+
+    /// Whether two-finger drags pan rather than scroll, see `SetPanMode`.
+    pub fn pan(&self) -> bool {
+        self.pan.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pan(&self, active: bool) {
+        self.pan.store(active, Ordering::Relaxed);
+    }
+
+    /// Whether resting the pointer clicks, see `SetDwellClick`.
+    pub fn dwell_click(&self) -> bool {
+        self.dwell_click.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dwell_click(&self, active: bool) {
+        self.dwell_click.store(active, Ordering::Relaxed);
+    }
+
+    /// Starts measuring the client's pointer input in place of acting on it,
+    /// dropping any calibration already under way.
+    pub fn start_calibration(&self) {
+        *self.calibration.lock().unwrap() = Some(Recording::new());
+    }
+
+    /// The calibration under way, ended once it has gone on longer than
+    /// `calibration::MAX_DURATION`, so that the pointer works again.
+    fn calibration(&self) -> MutexGuard<'_, Option<Recording>> {
+        let mut calibration = self.calibration.lock().unwrap();
+        if calibration
+            .as_ref()
+            .is_some_and(|recording| recording.expired(Instant::now()))
+        {
+            println!(
+                "Calibration on connection {} ran out of time and was dropped.",
+                self.id
+            );
+            *calibration = None;
+        }
+        calibration
+    }
+
+    /// Starts a labelled segment of the calibration under way.
+    pub fn mark_calibration(&self, label: String) -> Result<(), Error> {
+        match self.calibration().as_mut() {
+            Some(recording) => {
+                recording.mark(label);
+                Ok(())
+            }
+            None => Err(Error::NotCalibrating),
+        }
+    }
+
+    /// Records `event` if a calibration is under way and it is pointer
+    /// input; returns whether it was, in which case it is not to be acted on.
+    pub fn record_calibration(&self, event: &ClientEvent) -> bool {
+        let recorded = self
+            .calibration()
+            .as_mut()
+            .is_some_and(|recording| recording.record(event));
+        if recorded {
+            self.count_refused("calibrating");
+        }
+        recorded
+    }
+
+    /// Ends the calibration under way and hands over what was recorded.
+    pub fn finish_calibration(&self) -> Option<Recording> {
+        self.calibration().take()
+    }
+
+    /// How the client is drawing, if it is in drawing mode, see `SetDrawingMode`.
+    pub fn drawing(&self) -> Option<Drawing> {
+        *self.drawing.lock().unwrap()
+    }
+
+    pub fn set_drawing(&self, drawing: Option<Drawing>) {
+        *self.drawing.lock().unwrap() = drawing;
+    }
+
+    /// Counts a touch rejected as a palm, returning the total so far.
+    pub fn count_palm_rejection(&self) -> u64 {
+        self.palm_rejections.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn palm_rejections(&self) -> u64 {
+        self.palm_rejections.load(Ordering::Relaxed)
+    }
+
+    /// Counts a key press dropped by `key_debounce_ms`, returning the total so far.
+    pub fn count_ghost_key(&self) -> u64 {
+        self.ghost_keys.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn ghost_keys(&self) -> u64 {
+        self.ghost_keys.load(Ordering::Relaxed)
+    }
+
+    /// Counts a message arriving from the client.
+    pub fn count_received(&self) {
+        self.counters.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_parsed(&self) {
+        self.counters.parsed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an event passed to the mouse event thread.
+    pub fn count_forwarded(&self) {
+        self.counters.forwarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message refused before it reached the mouse event thread.
+    pub fn count_refused(&self, reason: &'static str) {
+        *self
+            .counters
+            .dropped
+            .lock()
+            .unwrap()
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    /// Counts an event picked up by the mouse event thread, `delay` after it
+    /// arrived, and notes its event log entry in case it is dropped.
+    pub fn count_handled(&self, delay: Duration, logged: Option<u64>) {
+        self.handling.store(logged.unwrap_or(0), Ordering::Relaxed);
+        self.counters.handled.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .delay_us
+            .store(delay.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Counts an event the mouse event thread picked up but did not apply.
+    pub fn count_dropped(&self, reason: &'static str) {
+        self.counters
+            .dropped_by_worker
+            .fetch_add(1, Ordering::Relaxed);
+        self.count_refused(reason);
+        let logged = self.handling.swap(0, Ordering::Relaxed);
+        if logged != 0 {
+            self.events.set_outcome(logged, Outcome::Dropped { reason });
+        }
+    }
+
+    /// Adds a message that arrived on this connection to the event log,
+    /// returning the entry's id.
+    pub fn log_event(&self, logged: Logged, outcome: Outcome) -> u64 {
+        self.events.record(self.id, logged, outcome)
+    }
+
+    pub fn set_rtt(&self, rtt: Duration) {
+        self.counters
+            .rtt_us
+            .store(rtt.as_micros().max(1) as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let counters = &self.counters;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let ms = |us: u64| us as f64 / 1000.0;
+        ConnectionStats {
+            id: self.id,
+            locale: self.locale,
+            received: load(&counters.received),
+            parsed: load(&counters.parsed),
+            forwarded: load(&counters.forwarded),
+            applied: load(&counters.handled).saturating_sub(load(&counters.dropped_by_worker)),
+            dropped: counters.dropped.lock().unwrap().clone(),
+            palm_rejections: self.palm_rejections(),
+            ghost_keys: self.ghost_keys(),
+            processing_delay_ms: ms(load(&counters.delay_us)),
+            rtt_ms: Some(load(&counters.rtt_us)).filter(|us| *us > 0).map(ms),
+        }
+    }
+
+    pub fn profile(&self) -> Profile {
+        *self.profile.lock().unwrap()
+    }
+
+    pub fn set_profile(&self, profile: Profile) {
+        *self.profile.lock().unwrap() = profile;
+    }
+
+    /// Queues an event for the client from outside its handler, e.g. from
+    /// the mouse event thread. Dropped once the connection has closed.
+    pub fn notify(&self, event: ServerEvent) {
+        let _ = self.notices.send(event);
+    }
+}
+
+/// How the pointer follows the finger in drawing mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Drawing {
+    /// Moved by the finger's movement, without acceleration.
+    Relative,
+    /// Placed where the finger is, with the touchpad mapped onto the
+    /// primary display.
+    Absolute,
+}
+
+/// Registry of the currently open connections.
+#[derive(Clone, Default)]
+pub struct Connections {
+    next_id: Arc<AtomicU64>,
+    connections: Arc<Mutex<HashMap<u64, Arc<Connection>>>>,
+    events: EventLog,
+    /// Whether the mouse event thread may have work; cleared when it parks
+    /// after the last connection closed, set again by the next one.
+    active: Arc<AtomicBool>,
+}
+
+/// Whether the mouse event thread is handling input or parked for want of
+/// connections, reported by `/api/status`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerState {
+    Active,
+    Parked,
+}
+
+impl Connections {
+    /// Adds a connection, returning it with the receiver of its notices.
+    pub fn register(
+        &self,
+        device: Option<String>,
+        profile: Profile,
+        locale: &'static str,
+    ) -> (Arc<Connection>, UnboundedReceiver<ServerEvent>) {
+        let (notices, notice_receiver) = unbounded_channel();
+        let connection = Arc::new(Connection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            device,
+            locale,
+            explain: AtomicBool::new(false),
+            feedback: AtomicBool::new(false),
+            scroll_feedback: Mutex::new(None),
+            precision: AtomicBool::new(false),
+            pan: AtomicBool::new(false),
+            dwell_click: AtomicBool::new(false),
+            drawing: Mutex::new(None),
+            calibration: Mutex::new(None),
+            palm_rejections: AtomicU64::new(0),
+            ghost_keys: AtomicU64::new(0),
+            counters: EventCounters::default(),
+            profile: Mutex::new(profile),
+            notices,
+            events: self.events.clone(),
+            handling: AtomicU64::new(0),
+        });
+        let mut connections = self.connections.lock().unwrap();
+        connections.insert(connection.id, connection.clone());
+        self.active.store(true, Ordering::Relaxed);
+        (connection, notice_receiver)
+    }
+
+    /// Marks the mouse event thread parked if `closed` was the last open
+    /// connection, whether or not its handler has removed it yet. Returns
+    /// whether it was.
+    pub fn park(&self, closed: u64) -> bool {
+        let connections = self.connections.lock().unwrap();
+        let last = connections.keys().all(|id| *id == closed);
+        if last {
+            self.active.store(false, Ordering::Relaxed);
+        }
+        last
+    }
+
+    pub fn power(&self) -> PowerState {
+        if self.active.load(Ordering::Relaxed) {
+            PowerState::Active
+        } else {
+            PowerState::Parked
+        }
+    }
+
+    pub fn remove(&self, id: u64) {
+        self.connections.lock().unwrap().remove(&id);
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Connection>> {
+        self.connections.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Cues a connection by id, for input the mouse event thread finishes
+    /// later, e.g. a held click.
+    pub fn feedback(&self, id: u64, kind: FeedbackKind) {
+        if let Some(connection) = self.get(id) {
+            connection.feedback(kind);
+        }
+    }
+
+    /// The open connections, oldest first.
+    pub fn all(&self) -> Vec<Arc<Connection>> {
+        let mut all: Vec<_> = self.connections.lock().unwrap().values().cloned().collect();
+        all.sort_by_key(|connection| connection.id);
+        all
+    }
+
+    /// The events received on all connections, see `/api/events`.
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    /// The newest open connection of the client that identified itself as
+    /// `device`.
+    pub fn latest_for_device(&self, device: &str) -> Option<Arc<Connection>> {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|connection| connection.device.as_deref() == Some(device))
+            .max_by_key(|connection| connection.id)
+            .cloned()
+    }
+}
+
+/// A client event tagged with the connection it arrived on and when.
+pub struct Envelope {
+    pub connection: Arc<Connection>,
+    pub event: ClientEvent,
+    pub received_at: Instant,
+    /// The event's entry in the event log.
+    pub logged: Option<u64>,
+}
+
+/// Creates the queue feeding the mouse event thread. With `key_priority`,
+/// key events overtake the moves queued ahead of them.
+pub fn event_channel(key_priority: bool) -> (EventSender, EventReceiver) {
+    let (sender, receiver) = mpsc::channel();
+    let depth = Arc::new(AtomicUsize::new(0));
+    (
+        EventSender {
+            sender,
+            depth: depth.clone(),
+        },
+        EventReceiver {
+            receiver,
+            depth,
+            key_priority,
+            moves: VecDeque::new(),
+            pending: VecDeque::new(),
+        },
+    )
+}
+
+/// Sending half of the event queue, which keeps count of the events that
+/// have not been processed yet.
+#[derive(Clone)]
+pub struct EventSender {
+    sender: mpsc::Sender<Envelope>,
+    depth: Arc<AtomicUsize>,
+}
+
+impl EventSender {
+    pub fn send(&self, envelope: Envelope) -> Result<(), Error> {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(envelope).map_err(|_| {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            Error::ChannelClosed
+        })
+    }
+
+    /// Number of events queued but not yet picked up by the mouse event thread.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+pub struct EventReceiver {
+    receiver: mpsc::Receiver<Envelope>,
+    depth: Arc<AtomicUsize>,
+    key_priority: bool,
+    /// Events taken off the channel to look past queued moves, still to be
+    /// handled: the moves a key may overtake, then everything from the
+    /// first event that is not one of them.
+    moves: VecDeque<Envelope>,
+    pending: VecDeque<Envelope>,
+}
+
+/// Whether a key may overtake the event. Only moves of fingers still on the
+/// touchpad; a move without fingers ends a tap-drag, a pen stroke or a pan,
+/// which a key must not come before.
+fn may_overtake(event: &ClientEvent) -> bool {
+    matches!(event, ClientEvent::MouseMove { touches, .. } if *touches > 0)
+}
+
+impl EventReceiver {
+    /// Waits for the next event, giving up at `deadline` when one is given.
+    ///
+    /// With `key_priority`, a key event waiting behind moves comes first, so
+    /// that a backlog of moves does not hold up typing. It never overtakes
+    /// anything else: a key typed after a click still goes to whatever the
+    /// click focused.
+    pub fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Envelope, RecvTimeoutError> {
+        if self.moves.is_empty() && self.pending.is_empty() {
+            let envelope = match deadline {
+                Some(deadline) => self
+                    .receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))?,
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)?,
+            };
+            self.take(envelope);
+        }
+        if self.key_priority {
+            while let Ok(envelope) = self.receiver.try_recv() {
+                self.take(envelope);
+            }
+        }
+        let key_first = self.key_priority
+            && self
+                .pending
+                .front()
+                .is_some_and(|envelope| envelope.event.is_key());
+        let envelope = match self.moves.pop_front() {
+            Some(envelope) if !key_first => envelope,
+            envelope => {
+                if let Some(envelope) = envelope {
+                    self.moves.push_front(envelope);
+                }
+                let envelope = self.pending.pop_front().expect("an event is pending");
+                // The moves behind it may be overtaken now, as those before.
+                while let Some(next) = self.pending.pop_front() {
+                    if !may_overtake(&next.event) {
+                        self.pending.push_front(next);
+                        break;
+                    }
+                    self.moves.push_back(next);
+                }
+                envelope
+            }
+        };
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        Ok(envelope)
+    }
+
+    /// Queues an event taken off the channel, in order.
+    fn take(&mut self, envelope: Envelope) {
+        if self.pending.is_empty() && may_overtake(&envelope.event) {
+            self.moves.push_back(envelope);
+        } else {
+            self.pending.push_back(envelope);
+        }
+    }
+}
+
+/// A log line about one connection's events. The connection is a
+/// `connection=<id>` field up front, so that one connection's lines can be
+/// picked out of the log of many.
+pub fn connection_line(connection: u64, topic: &str, message: fmt::Arguments) -> String {
+    format!("connection={} {}: {}", connection, topic, message)
+}
+
+/// Logs a decision made for an event, only when its connection is in explain mode.
+macro_rules! explain {
+    ($connection:expr, $($arg:tt)*) => {
+        if $connection.explain() {
+            println!(
+                "{}",
+                $crate::connection::connection_line(
+                    $connection.id,
+                    "explain",
+                    format_args!($($arg)*)
+                )
+            );
+        }
+    };
+}
+
+pub(crate) use explain;
+
+/// Logs how a gesture was classified, for every connection, with `trace_gestures`.
+macro_rules! trace_gesture {
+    ($config:expr, $connection:expr, $($arg:tt)*) => {
+        if $config.trace_gestures {
+            println!(
+                "{}",
+                $crate::connection::connection_line(
+                    $connection,
+                    "gesture",
+                    format_args!($($arg)*)
+                )
+            );
+        }
+    };
+}
+
+pub(crate) use trace_gesture;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(connection: &Arc<Connection>, json: &str) -> Envelope {
+        Envelope {
+            connection: connection.clone(),
+            event: serde_json::from_str(json).unwrap(),
+            received_at: Instant::now(),
+            logged: None,
+        }
+    }
+
+    #[test]
+    fn send_to_dead_channel_fails() {
+        let connections = Connections::default();
+        let (connection, _notices) = connections.register(None, Profile::default(), "en");
+        let (sender, receiver) = event_channel(false);
+        drop(receiver);
+        let sent = sender.send(envelope(&connection, r#"{"type":"WakeDisplay"}"#));
+        assert!(matches!(sent, Err(Error::ChannelClosed)));
+        assert_eq!(sender.depth(), 0);
+    }
+
+    /// Sends `events` and returns them in the order the receiver hands
+    /// them out, by their `dx` for moves and their type otherwise.
+    fn received(key_priority: bool, events: &[&str]) -> Vec<String> {
+        let connections = Connections::default();
+        let (connection, _notices) = connections.register(None, Profile::default(), "en");
+        let (sender, mut receiver) = event_channel(key_priority);
+        for event in events {
+            sender.send(envelope(&connection, event)).unwrap();
+        }
+        let order = events
+            .iter()
+            .map(|_| match receiver.recv_until(None).unwrap().event {
+                ClientEvent::MouseMove { dx, touches, .. } => format!("move {} {}", dx, touches),
+                event => event.kind().to_string(),
+            })
+            .collect();
+        assert_eq!(sender.depth(), 0);
+        order
+    }
+
+    fn finger_move(dx: i32, touches: i32) -> String {
+        format!(
+            r#"{{"type":"MouseMove","dx":{},"dy":0,"sx":0,"sy":0,"touches":{}}}"#,
+            dx, touches
+        )
+    }
+
+    const KEY: &str = r#"{"type":"KeyPress","key":"a"}"#;
+    const CLICK: &str = r#"{"type":"MouseClick","button":"Left"}"#;
+
+    #[test]
+    fn key_overtakes_a_move_backlog() {
+        let moves: Vec<String> = (1..=1000).map(|dx| finger_move(dx, 1)).collect();
+        let mut events: Vec<&str> = moves.iter().map(String::as_str).collect();
+        events.push(KEY);
+        events.push(moves[0].as_str());
+        events.push(KEY);
+        let order = received(true, &events);
+        assert_eq!(order[..2], ["KeyPress", "KeyPress"]);
+        let expected: Vec<String> = (1..=1000)
+            .chain([1])
+            .map(|dx| format!("move {} 1", dx))
+            .collect();
+        assert_eq!(order[2..], expected);
+    }
+
+    #[test]
+    fn key_waits_for_anything_but_finger_moves() {
+        let (one, two, lifted) = (finger_move(1, 1), finger_move(2, 1), finger_move(3, 0));
+        // Not past a click, nor past a move ending a drag, nor the moves
+        // before them.
+        assert_eq!(
+            received(true, &[&one, CLICK, &two, KEY]),
+            ["move 1 1", "MouseClick", "KeyPress", "move 2 1"]
+        );
+        assert_eq!(
+            received(true, &[&one, &lifted, &two, KEY]),
+            ["move 1 1", "move 3 0", "KeyPress", "move 2 1"]
+        );
+        assert_eq!(
+            received(true, &[&one, &two, CLICK, KEY]),
+            ["move 1 1", "move 2 1", "MouseClick", "KeyPress"]
+        );
+    }
+
+    #[test]
+    fn without_key_priority_the_order_is_kept() {
+        let (one, two) = (finger_move(1, 1), finger_move(2, 1));
+        assert_eq!(
+            received(false, &[&one, &two, KEY, &one]),
+            ["move 1 1", "move 2 1", "KeyPress", "move 1 1"]
+        );
+    }
+
+    #[test]
+    fn log_lines_name_their_connection_as_a_field() {
+        let line = connection_line(3, "gesture", format_args!("tap, {:?} click", "Left"));
+        assert_eq!(line, r#"connection=3 gesture: tap, "Left" click"#);
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// How the pressure and size of a touch, for clients that report them,
+/// change pointer movement.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TouchScaling {
+    /// Pressure range mapped linearly onto the multipliers below; pressure
+    /// outside it gets the nearest one.
+    pub min_pressure: f64,
+    pub max_pressure: f64,
+    /// Multipliers for pointer sensitivity at the lightest and firmest touch.
+    pub min_multiplier: f64,
+    pub max_multiplier: f64,
+    /// Contacts with a larger radius, in CSS pixels, are taken for a palm
+    /// and ignored.
+    pub palm_radius: Option<f64>,
+}
+
+impl Default for TouchScaling {
+    fn default() -> Self {
+        TouchScaling {
+            min_pressure: 0.0,
+            max_pressure: 1.0,
+            min_multiplier: 0.5,
+            max_multiplier: 2.0,
+            palm_radius: None,
+        }
+    }
+}
+
+impl TouchScaling {
+    pub fn check(&self) -> Result<(), String> {
+        if self.min_pressure >= self.max_pressure {
+            return Err("min_pressure must be below max_pressure".to_string());
+        }
+        if self.min_multiplier <= 0.0 || self.max_multiplier <= 0.0 {
+            return Err("multipliers must be positive".to_string());
+        }
+        if self.palm_radius.is_some_and(|radius| radius <= 0.0) {
+            return Err("palm_radius must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Sensitivity multiplier for a touch, 1 when the client reports no pressure.
+    pub fn multiplier(&self, pressure: Option<f64>) -> f64 {
+        let Some(pressure) = pressure.filter(|pressure| pressure.is_finite()) else {
+            return 1.0;
+        };
+        let t = ((pressure - self.min_pressure) / (self.max_pressure - self.min_pressure))
+            .clamp(0.0, 1.0);
+        self.min_multiplier + t * (self.max_multiplier - self.min_multiplier)
+    }
+
+    pub fn is_palm(&self, radius: Option<f64>) -> bool {
+        matches!((radius, self.palm_radius), (Some(radius), Some(palm)) if radius > palm)
+    }
+}
+
+/// Settings for telling accidental touches, such as the base of the thumb
+/// brushing the edge, from deliberate ones. The touchpad page applies the
+/// rules that need touch positions; the server drops sudden jumps.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct PalmRejection {
+    /// Touches starting this close to the touchpad's edge, in CSS pixels,
+    /// with a radius above `palm_radius` are ignored.
+    pub edge_margin_px: f64,
+    pub palm_radius: f64,
+    /// A touch added this long or longer after the first one is ignored,
+    /// rather than turning the movement into a two-finger gesture.
+    pub extra_touch_delay_ms: u64,
+    /// Moves right at the start of a one-finger touch covering more than
+    /// this distance are dropped.
+    pub max_start_delta_px: f64,
+}
+
+impl Default for PalmRejection {
+    fn default() -> Self {
+        PalmRejection {
+            edge_margin_px: 24.0,
+            palm_radius: 20.0,
+            extra_touch_delay_ms: 150,
+            max_start_delta_px: 60.0,
+        }
+    }
+}
+
+impl PalmRejection {
+    pub fn check(&self) -> Result<(), String> {
+        if self.edge_margin_px < 0.0 {
+            return Err("edge_margin_px must not be negative".to_string());
+        }
+        if self.palm_radius <= 0.0 || self.max_start_delta_px <= 0.0 {
+            return Err("palm_radius and max_start_delta_px must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Why a touch was taken for a palm.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PalmReason {
+    Edge,
+    ExtraTouch,
+    StartJump,
+}
@@ -0,0 +1,247 @@
+use crate::config::Config;
+use crate::error::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
+use warp::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, VARY,
+};
+use warp::http::{HeaderValue, Method, StatusCode};
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Checks the `Origin` of a browser request. Requests without one (not sent
+/// by a browser) and requests from the server's own pages pass and extract
+/// `None`; requests
+/// from a configured external origin extract `Some(origin)`; any other
+/// origin is rejected, so that arbitrary websites cannot drive the host's input.
+pub fn allowed_origin(
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("origin")
+        .and(warp::header::optional::<String>("host"))
+        .and_then(move |origin: Option<String>, host: Option<String>| {
+            let config = config.clone();
+            async move {
+                let origin = match origin {
+                    Some(origin) => origin,
+                    None => return Ok(None),
+                };
+                if is_own_origin(&origin, host.as_deref(), &config.server_names) {
+                    Ok(None)
+                } else if config.allowed_origins.contains(&origin) {
+                    Ok(Some(origin))
+                } else {
+                    Err(warp::reject::custom(Error::Forbidden(origin)))
+                }
+            }
+        })
+}
+
+/// Whether a page comes from this server. Both the `Origin` and the `Host`
+/// are the requester's to choose: under DNS rebinding a hostile page's own
+/// name resolves to this computer and the two agree. So the name must also
+/// be one the server goes by: an IP address, `localhost` or one of
+/// `server_names`.
+fn is_own_origin(origin: &str, host: Option<&str>, server_names: &[String]) -> bool {
+    let origin_host = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"));
+    match (origin_host, host) {
+        (Some(origin_host), Some(host)) if origin_host.eq_ignore_ascii_case(host) => {
+            let name = host_name(host).to_ascii_lowercase();
+            name.parse::<IpAddr>().is_ok() || name == "localhost" || server_names.contains(&name)
+        }
+        _ => false,
+    }
+}
+
+/// The host of a `Host` header, without its port or IPv6 brackets.
+fn host_name(host: &str) -> &str {
+    if let Some(bracketed) = host.strip_prefix('[') {
+        return bracketed.split(']').next().unwrap_or(bracketed);
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+/// Adds the CORS response headers for requests from an allowed external origin.
+pub fn with_cors_headers(reply: impl Reply, origin: Option<String>) -> Response {
+    let mut response = reply.into_response();
+    if let Some(origin) = origin.and_then(|origin| HeaderValue::from_str(&origin).ok()) {
+        let headers = response.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+        headers.insert(VARY, HeaderValue::from_static("origin"));
+    }
+    response
+}
+
+/// Answers CORS preflight requests for the API routes. Other methods are
+/// passed over as not found rather than `warp::options()`'s 405, which would
+/// otherwise win over the 404 for unknown paths.
+pub fn preflight(
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::method()
+        .and_then(|method: Method| async move {
+            if method == Method::OPTIONS {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(allowed_origin(config))
+        .and(warp::header::optional::<String>(
+            "access-control-request-headers",
+        ))
+        .map(|origin: Option<String>, request_headers: Option<String>| {
+            let mut response = with_cors_headers(StatusCode::NO_CONTENT, origin);
+            let headers = response.headers_mut();
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static("GET, POST, OPTIONS"),
+            );
+            let allow_headers = request_headers
+                .and_then(|request_headers| HeaderValue::from_str(&request_headers).ok())
+                .unwrap_or_else(|| HeaderValue::from_static("content-type"));
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("600"));
+            response
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALLOWED: &str = "https://touchpad.example";
+
+    fn config() -> Arc<Config> {
+        Arc::new(Config {
+            allowed_origins: vec![ALLOWED.to_string()],
+            server_names: vec!["desktop.local".to_string()],
+            ..Config::default()
+        })
+    }
+
+    /// What `allowed_origin` makes of a request: the external origin to send
+    /// CORS headers for, or the status it is refused with.
+    async fn check(origin: Option<&str>, host: &str) -> Result<Option<String>, StatusCode> {
+        let mut request = warp::test::request().path("/api/info").header("host", host);
+        if let Some(origin) = origin {
+            request = request.header("origin", origin);
+        }
+        request
+            .filter(&allowed_origin(config()))
+            .await
+            .map_err(|rejection| rejection.find::<Error>().unwrap().status())
+    }
+
+    #[tokio::test]
+    async fn pages_from_the_server_itself_pass_without_cors() {
+        assert_eq!(check(None, "192.168.1.2:8080").await, Ok(None));
+        for (origin, host) in [
+            ("http://192.168.1.2:8080", "192.168.1.2:8080"),
+            ("http://[fe80::1]:8080", "[fe80::1]:8080"),
+            ("http://localhost:8080", "localhost:8080"),
+            ("https://Desktop.local", "desktop.local"),
+        ] {
+            assert_eq!(check(Some(origin), host).await, Ok(None), "{}", origin);
+        }
+    }
+
+    #[tokio::test]
+    async fn rebound_names_do_not_pass_for_the_server() {
+        // The hostile page's name resolves to this computer, so its origin and
+        // the Host header agree; the name is still not one of the server's.
+        for (origin, host) in [
+            ("http://evil.example:8080", "evil.example:8080"),
+            ("http://127.0.0.1.evil.example", "127.0.0.1.evil.example"),
+        ] {
+            assert_eq!(
+                check(Some(origin), host).await,
+                Err(StatusCode::FORBIDDEN),
+                "{}",
+                origin
+            );
+        }
+        // Nor does an origin that merely differs from the Host.
+        assert_eq!(
+            check(Some("http://192.168.1.3:8080"), "192.168.1.2:8080").await,
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[tokio::test]
+    async fn allowed_origins_get_cors_headers_with_credentials() {
+        assert_eq!(
+            check(Some(ALLOWED), "192.168.1.2:8080").await,
+            Ok(Some(ALLOWED.to_string()))
+        );
+        let routes = warp::any()
+            .and(allowed_origin(config()))
+            .map(|origin| with_cors_headers(StatusCode::OK, origin));
+        // A page sending its session cookie along gets its own origin back,
+        // never a wildcard, which browsers refuse for credentialed requests.
+        let response = warp::test::request()
+            .header("host", "192.168.1.2:8080")
+            .header("origin", ALLOWED)
+            .header("cookie", "session=abc")
+            .reply(&routes)
+            .await;
+        let headers = response.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], ALLOWED);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[VARY], "origin");
+
+        // The server's own pages need none of it.
+        let response = warp::test::request()
+            .header("host", "192.168.1.2:8080")
+            .header("origin", "http://192.168.1.2:8080")
+            .header("cookie", "session=abc")
+            .reply(&routes)
+            .await;
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(!response
+            .headers()
+            .contains_key(ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
+
+    #[tokio::test]
+    async fn preflight_answers_allowed_origins_only() {
+        let preflight = preflight(config());
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .header("host", "192.168.1.2:8080")
+            .header("origin", ALLOWED)
+            .header("access-control-request-headers", "authorization")
+            .reply(&preflight)
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], ALLOWED);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST, OPTIONS");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "authorization");
+
+        let foreign = warp::test::request()
+            .method("OPTIONS")
+            .header("host", "evil.example:8080")
+            .header("origin", "http://evil.example:8080")
+            .filter(&preflight)
+            .await;
+        assert!(foreign.is_err_and(|rejection| {
+            rejection.find::<Error>().unwrap().status() == StatusCode::FORBIDDEN
+        }));
+        // Other methods are left to the routes.
+        let get = warp::test::request()
+            .header("origin", ALLOWED)
+            .filter(&preflight)
+            .await;
+        assert!(get.is_err_and(|rejection| rejection.is_not_found()));
+    }
+}
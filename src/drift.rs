@@ -0,0 +1,109 @@
+use crate::input::Input;
+use crate::locate;
+use crate::server_info::DisplaySize;
+
+/// Where the relative moves of a touch should have put the pointer, so that
+/// it can be put back there every few moves, see `reconcile_every`. Only
+/// the primary display's size is known, so tracking needs the pointer to be
+/// on it.
+#[derive(Default)]
+pub struct DriftCorrection {
+    position: Option<(i32, i32)>,
+    moves: u32,
+}
+
+impl DriftCorrection {
+    pub fn reset(&mut self) {
+        *self = DriftCorrection::default();
+    }
+
+    /// Records a relative move just made. Every `every` moves, returns the
+    /// position the pointer should be at.
+    pub fn track(
+        &mut self,
+        input: &Input,
+        dx: i32,
+        dy: i32,
+        every: u32,
+        display: Option<DisplaySize>,
+    ) -> Option<(i32, i32)> {
+        let display = display?;
+        let Some((x, y)) = self.position else {
+            // The first move of a touch only tells where tracking starts.
+            let position = input.mouse_location()?;
+            locate::display_index(position, Some(display))?;
+            self.position = Some(position);
+            return None;
+        };
+        // The pointer stops at the display's edges, and so does the tracking.
+        let max_x = i32::try_from(display.width).unwrap_or(i32::MAX) - 1;
+        let max_y = i32::try_from(display.height).unwrap_or(i32::MAX) - 1;
+        let position = (
+            x.saturating_add(dx).clamp(0, max_x),
+            y.saturating_add(dy).clamp(0, max_y),
+        );
+        self.position = Some(position);
+        self.moves += 1;
+        if self.moves < every {
+            return None;
+        }
+        self.moves = 0;
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: DisplaySize = DisplaySize {
+        width: 1920,
+        height: 1080,
+    };
+
+    #[test]
+    fn pointer_is_put_back_every_few_moves() {
+        let (mut input, _) = Input::mock();
+        let mut drift = DriftCorrection::default();
+        input.mouse_move_to(100, 100);
+        assert_eq!(drift.track(&input, 10, 0, 3, Some(DISPLAY)), None);
+        // The desktop moved the pointer further than it was told to.
+        input.mouse_move_to(400, 300);
+        assert_eq!(drift.track(&input, 10, 0, 3, Some(DISPLAY)), None);
+        assert_eq!(drift.track(&input, 10, 5, 3, Some(DISPLAY)), None);
+        assert_eq!(
+            drift.track(&input, 10, 5, 3, Some(DISPLAY)),
+            Some((130, 110))
+        );
+        // And again three moves later.
+        assert_eq!(drift.track(&input, 1, 0, 3, Some(DISPLAY)), None);
+        assert_eq!(drift.track(&input, 1, 0, 3, Some(DISPLAY)), None);
+        assert_eq!(
+            drift.track(&input, 1, 0, 3, Some(DISPLAY)),
+            Some((133, 110))
+        );
+    }
+
+    #[test]
+    fn tracking_stops_at_the_display_edges() {
+        let (mut input, _) = Input::mock();
+        let mut drift = DriftCorrection::default();
+        input.mouse_move_to(1910, 5);
+        drift.track(&input, 0, 0, 1, Some(DISPLAY));
+        assert_eq!(
+            drift.track(&input, 50, -20, 1, Some(DISPLAY)),
+            Some((1919, 0))
+        );
+    }
+
+    #[test]
+    fn no_tracking_off_the_primary_display() {
+        let (mut input, _) = Input::mock();
+        let mut drift = DriftCorrection::default();
+        assert_eq!(drift.track(&input, 10, 0, 1, None), None);
+        assert_eq!(drift.track(&input, 10, 0, 1, None), None);
+        input.mouse_move_to(2500, 100);
+        assert_eq!(drift.track(&input, 10, 0, 1, Some(DISPLAY)), None);
+        assert_eq!(drift.track(&input, 10, 0, 1, Some(DISPLAY)), None);
+    }
+}
@@ -0,0 +1,230 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Clicking by holding the pointer still, for those who can move a finger
+/// but find tapping hard. Connections turn it on with `SetDwellClick`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DwellClick {
+    /// How long the pointer has to rest for a click.
+    pub dwell_ms: u64,
+    /// How far, in pixels, the pointer may wander and still be resting.
+    pub radius_px: f64,
+    /// What a rest clicks: the first entry once the pointer comes to rest,
+    /// the next if it rests as long again, and so on. After the last, the
+    /// pointer has to move away before it clicks again.
+    pub cycle: Vec<DwellAction>,
+}
+
+impl Default for DwellClick {
+    fn default() -> Self {
+        DwellClick {
+            dwell_ms: 1000,
+            radius_px: 8.0,
+            cycle: vec![DwellAction::Left],
+        }
+    }
+}
+
+impl DwellClick {
+    pub fn check(&self) -> Result<(), String> {
+        if self.dwell_ms == 0 {
+            return Err("dwell_ms must be positive".to_string());
+        }
+        if !(self.radius_px.is_finite() && self.radius_px > 0.0) {
+            return Err("radius_px must be positive".to_string());
+        }
+        if self.cycle.is_empty() {
+            return Err("cycle must name at least one click".to_string());
+        }
+        Ok(())
+    }
+
+    /// How long the pointer rests before the client is told a click is
+    /// coming, so that slow movement does not send a cue every few pixels.
+    fn settle(&self) -> Duration {
+        Duration::from_millis(self.dwell_ms / 5)
+    }
+}
+
+/// A click made by resting the pointer.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DwellAction {
+    Left,
+    Right,
+    Double,
+}
+
+/// Where a connection's pointer is resting, counting down to its next dwell
+/// click.
+pub struct Dwell {
+    pub connection: u64,
+    /// Movement since the pointer came to rest.
+    drift: (f64, f64),
+    /// Clicks of the cycle made since then.
+    clicks: usize,
+    /// Whether the client was told the next click is coming.
+    pub cued: bool,
+    /// When the next step is due; none once the cycle is done.
+    pub deadline: Option<Instant>,
+}
+
+/// What a dwell does when its deadline comes.
+pub enum DwellStep {
+    /// Tell the client a click comes in this long.
+    Cue(Duration),
+    /// Click, then tell the client the next one comes in this long, if the
+    /// cycle goes on.
+    Click(DwellAction, Option<Duration>),
+}
+
+impl Dwell {
+    /// Starts counting down for a pointer that just moved.
+    pub fn new(connection: u64, settings: &DwellClick) -> Self {
+        Dwell {
+            connection,
+            drift: (0.0, 0.0),
+            clicks: 0,
+            cued: false,
+            deadline: Some(Instant::now() + settings.settle()),
+        }
+    }
+
+    /// Adds a move of the pointer. Leaving the resting spot starts the rest
+    /// and the cycle over; returns whether a cued click was called off by it.
+    pub fn moved(&mut self, dx: i32, dy: i32, settings: &DwellClick) -> bool {
+        self.drift.0 += f64::from(dx);
+        self.drift.1 += f64::from(dy);
+        if self.drift.0.hypot(self.drift.1) <= settings.radius_px {
+            return false;
+        }
+        let cued = self.cued;
+        *self = Dwell::new(self.connection, settings);
+        cued
+    }
+
+    /// Moves on to the next step, which has just come due.
+    pub fn step(&mut self, settings: &DwellClick) -> DwellStep {
+        let now = Instant::now();
+        let dwell = Duration::from_millis(settings.dwell_ms);
+        if !self.cued {
+            let click_in = dwell.saturating_sub(settings.settle());
+            self.cued = true;
+            self.deadline = Some(now + click_in);
+            return DwellStep::Cue(click_in);
+        }
+        let action = settings.cycle[self.clicks];
+        self.clicks += 1;
+        let more = self.clicks < settings.cycle.len();
+        self.cued = more;
+        self.deadline = more.then(|| now + dwell);
+        DwellStep::Click(action, more.then_some(dwell))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(cycle: Vec<DwellAction>) -> DwellClick {
+        DwellClick {
+            cycle,
+            ..DwellClick::default()
+        }
+    }
+
+    #[test]
+    fn rest_cues_then_clicks() {
+        let settings = settings(vec![DwellAction::Left]);
+        let start = Instant::now();
+        let mut dwell = Dwell::new(1, &settings);
+        assert!(!dwell.cued);
+        assert!(dwell.deadline.unwrap() >= start + Duration::from_millis(200));
+        assert!(matches!(
+            dwell.step(&settings),
+            DwellStep::Cue(click_in) if click_in == Duration::from_millis(800)
+        ));
+        assert!(dwell.cued);
+        assert!(matches!(
+            dwell.step(&settings),
+            DwellStep::Click(DwellAction::Left, None)
+        ));
+        // The cycle is done until the pointer moves away.
+        assert!(!dwell.cued);
+        assert_eq!(dwell.deadline, None);
+    }
+
+    #[test]
+    fn resting_on_goes_through_the_cycle() {
+        let settings = settings(vec![DwellAction::Left, DwellAction::Double]);
+        let mut dwell = Dwell::new(1, &settings);
+        assert!(matches!(dwell.step(&settings), DwellStep::Cue(_)));
+        assert!(matches!(
+            dwell.step(&settings),
+            DwellStep::Click(DwellAction::Left, Some(next)) if next == Duration::from_millis(1000)
+        ));
+        assert!(dwell.cued);
+        assert!(dwell.deadline.is_some());
+        assert!(matches!(
+            dwell.step(&settings),
+            DwellStep::Click(DwellAction::Double, None)
+        ));
+        assert_eq!(dwell.deadline, None);
+    }
+
+    #[test]
+    fn wandering_within_the_radius_keeps_resting() {
+        let settings = settings(vec![DwellAction::Left]);
+        let mut dwell = Dwell::new(1, &settings);
+        dwell.step(&settings);
+        let deadline = dwell.deadline;
+        assert!(!dwell.moved(5, 0, &settings));
+        assert!(!dwell.moved(-5, 6, &settings));
+        assert!(dwell.cued);
+        assert_eq!(dwell.deadline, deadline);
+    }
+
+    #[test]
+    fn leaving_the_spot_starts_over() {
+        let settings = settings(vec![DwellAction::Left]);
+        let mut dwell = Dwell::new(1, &settings);
+        // Small moves add up.
+        assert!(!dwell.moved(5, 0, &settings));
+        assert!(!dwell.moved(3, 0, &settings));
+        assert_eq!(dwell.drift, (8.0, 0.0));
+        assert!(!dwell.moved(1, 0, &settings));
+        assert_eq!(dwell.drift, (0.0, 0.0));
+        assert!(!dwell.cued);
+
+        dwell.step(&settings);
+        assert!(dwell.moved(0, 9, &settings), "the cued click is called off");
+        assert!(!dwell.cued);
+        assert!(matches!(dwell.step(&settings), DwellStep::Cue(_)));
+
+        // Moving away after the cycle is done starts a new one.
+        dwell.step(&settings);
+        assert_eq!(dwell.deadline, None);
+        assert!(!dwell.moved(20, 0, &settings));
+        assert!(dwell.deadline.is_some());
+        assert_eq!(dwell.connection, 1);
+    }
+
+    #[test]
+    fn check_refuses_bad_settings() {
+        assert!(DwellClick::default().check().is_ok());
+        for bad in [
+            DwellClick {
+                dwell_ms: 0,
+                ..DwellClick::default()
+            },
+            DwellClick {
+                radius_px: f64::NAN,
+                ..DwellClick::default()
+            },
+            settings(Vec::new()),
+        ] {
+            assert!(bad.check().is_err());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use thiserror::Error;
+use warp::http::StatusCode;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("failed to parse client event: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("unsupported message: {0}")]
+    Unsupported(&'static str),
+    #[error("mouse event channel closed")]
+    ChannelClosed,
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] warp::Error),
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    #[error("connection from {0} is not allowed")]
+    Forbidden(String),
+    #[error("no client with id {0}")]
+    UnknownClient(u64),
+    #[error("no panel button with id {0}")]
+    UnknownButton(String),
+    #[error("invalid setting: {0}")]
+    InvalidSetting(String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(&'static str),
+    #[error("too many failed attempts, try again later")]
+    TooManyAttempts,
+    #[error("cannot inject input: {0}")]
+    InputBackend(String),
+    #[error("input injection failed: {0}")]
+    Input(String),
+    #[error("session ended after the maximum of {0} seconds")]
+    SessionExpired(u64),
+    #[error("message larger than the limit of {0} bytes")]
+    MessageTooLarge(usize),
+    #[error("replayed event: {0}")]
+    Replayed(&'static str),
+    #[error("media player error: {0}")]
+    #[cfg_attr(not(all(feature = "mpris", target_os = "linux")), allow(dead_code))]
+    MediaPlayer(String),
+    #[error("another instance is already running: {0}")]
+    AlreadyRunning(String),
+    #[error("no open connection for device {0}")]
+    UnknownDevice(String),
+    #[error("no calibration under way")]
+    NotCalibrating,
+}
+
+impl Error {
+    /// Short, stable identifier reported to clients in `ServerEvent::Error`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Parse(_) => "parse_error",
+            Error::Unsupported(_) => "unsupported",
+            Error::ChannelClosed => "channel_closed",
+            Error::WebSocket(_) => "websocket_error",
+            Error::Config(_) => "config_error",
+            Error::Forbidden(_) => "forbidden",
+            Error::UnknownClient(_) => "unknown_client",
+            Error::UnknownButton(_) => "unknown_button",
+            Error::InvalidSetting(_) => "invalid_setting",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::TooManyAttempts => "too_many_attempts",
+            Error::InputBackend(_) => "input_backend_error",
+            Error::Input(_) => "input_failed",
+            Error::SessionExpired(_) => "session_expired",
+            Error::MessageTooLarge(_) => "message_too_large",
+            Error::Replayed(_) => "replayed_event",
+            Error::MediaPlayer(_) => "media_player_error",
+            Error::AlreadyRunning(_) => "already_running",
+            Error::UnknownDevice(_) => "unknown_device",
+            Error::NotCalibrating => "not_calibrating",
+        }
+    }
+
+    /// The part of the message that varies, which translations keep as is.
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Error::Parse(e) => Some(e.to_string()),
+            Error::WebSocket(e) => Some(e.to_string()),
+            Error::Unsupported(detail) | Error::Unauthorized(detail) | Error::Replayed(detail) => {
+                Some(detail.to_string())
+            }
+            Error::Config(detail)
+            | Error::Forbidden(detail)
+            | Error::InvalidSetting(detail)
+            | Error::InputBackend(detail)
+            | Error::Input(detail)
+            | Error::UnknownButton(detail)
+            | Error::MediaPlayer(detail)
+            | Error::AlreadyRunning(detail)
+            | Error::UnknownDevice(detail) => Some(detail.clone()),
+            Error::UnknownClient(id) => Some(id.to_string()),
+            Error::SessionExpired(secs) => Some(secs.to_string()),
+            Error::MessageTooLarge(bytes) => Some(bytes.to_string()),
+            Error::ChannelClosed | Error::TooManyAttempts | Error::NotCalibrating => None,
+        }
+    }
+
+    /// Fatal errors terminate the connection, the others are reported and skipped.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::ChannelClosed
+                | Error::WebSocket(_)
+                | Error::Config(_)
+                | Error::InputBackend(_)
+                | Error::AlreadyRunning(_)
+        )
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::Parse(_) | Error::InvalidSetting(_) => StatusCode::BAD_REQUEST,
+            Error::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            Error::Unauthorized(_) | Error::Replayed(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) | Error::SessionExpired(_) => StatusCode::FORBIDDEN,
+            Error::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            Error::MessageTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnknownClient(_) | Error::UnknownButton(_) | Error::UnknownDevice(_) => {
+                StatusCode::NOT_FOUND
+            }
+            Error::AlreadyRunning(_) | Error::NotCalibrating => StatusCode::CONFLICT,
+            Error::ChannelClosed
+            | Error::WebSocket(_)
+            | Error::Config(_)
+            | Error::InputBackend(_)
+            | Error::Input(_)
+            | Error::MediaPlayer(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Client mistakes are logged as warnings, server-side failures as errors.
+    pub fn log(&self) {
+        if self.is_fatal() {
+            eprintln!("Error: {}", self);
+        } else {
+            eprintln!("Warning: {}", self);
+        }
+    }
+}
+
+impl warp::reject::Reject for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n;
+
+    fn samples() -> Vec<Error> {
+        vec![
+            Error::Parse(serde_json::from_str::<u8>("x").unwrap_err()),
+            Error::Unsupported("binary"),
+            Error::ChannelClosed,
+            Error::Config("bad".to_string()),
+            Error::Forbidden("10.0.0.1".to_string()),
+            Error::UnknownClient(7),
+            Error::UnknownButton("b".to_string()),
+            Error::InvalidSetting("speed".to_string()),
+            Error::Unauthorized("no token"),
+            Error::TooManyAttempts,
+            Error::InputBackend("none".to_string()),
+            Error::Input("click: failed".to_string()),
+            Error::SessionExpired(60),
+            Error::MessageTooLarge(1024),
+            Error::Replayed("old seq"),
+            Error::MediaPlayer("gone".to_string()),
+            Error::AlreadyRunning("pid 1".to_string()),
+            Error::UnknownDevice("phone".to_string()),
+            Error::NotCalibrating,
+        ]
+    }
+
+    #[test]
+    fn codes_and_statuses() {
+        let expected = [
+            ("parse_error", StatusCode::BAD_REQUEST),
+            ("unsupported", StatusCode::NOT_IMPLEMENTED),
+            ("channel_closed", StatusCode::INTERNAL_SERVER_ERROR),
+            ("config_error", StatusCode::INTERNAL_SERVER_ERROR),
+            ("forbidden", StatusCode::FORBIDDEN),
+            ("unknown_client", StatusCode::NOT_FOUND),
+            ("unknown_button", StatusCode::NOT_FOUND),
+            ("invalid_setting", StatusCode::BAD_REQUEST),
+            ("unauthorized", StatusCode::UNAUTHORIZED),
+            ("too_many_attempts", StatusCode::TOO_MANY_REQUESTS),
+            ("input_backend_error", StatusCode::INTERNAL_SERVER_ERROR),
+            ("input_failed", StatusCode::INTERNAL_SERVER_ERROR),
+            ("session_expired", StatusCode::FORBIDDEN),
+            ("message_too_large", StatusCode::PAYLOAD_TOO_LARGE),
+            ("replayed_event", StatusCode::UNAUTHORIZED),
+            ("media_player_error", StatusCode::INTERNAL_SERVER_ERROR),
+            ("already_running", StatusCode::CONFLICT),
+            ("unknown_device", StatusCode::NOT_FOUND),
+            ("not_calibrating", StatusCode::CONFLICT),
+        ];
+        for (error, (code, status)) in samples().iter().zip(expected) {
+            assert_eq!(error.code(), code);
+            assert_eq!(error.status(), status, "{}", code);
+        }
+    }
+
+    #[test]
+    fn only_server_failures_are_fatal() {
+        let fatal: Vec<_> = samples()
+            .iter()
+            .filter(|error| error.is_fatal())
+            .map(Error::code)
+            .collect();
+        assert_eq!(
+            fatal,
+            [
+                "channel_closed",
+                "config_error",
+                "input_backend_error",
+                "already_running"
+            ]
+        );
+    }
+
+    #[test]
+    fn every_code_is_translated() {
+        for error in samples() {
+            let key = format!("error.{}", error.code());
+            assert_ne!(i18n::text("es", &key), key);
+            let message = i18n::error_message("es", &error);
+            if let Some(detail) = error.detail() {
+                assert!(message.contains(&detail), "{}: {}", key, message);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Entries kept; at a touchpad's event rate, a minute or so of use.
+const CAPACITY: usize = 2000;
+
+/// What arrived on a connection, as kept in the event log.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Logged {
+    /// The event's `type`; missing for a message that could not be parsed.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    /// The event's fields, with whatever was typed left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+}
+
+impl Logged {
+    pub fn new(kind: &'static str, event: String) -> Self {
+        Logged {
+            kind: Some(kind),
+            event: Some(event),
+        }
+    }
+}
+
+/// What became of a logged event.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    /// Handled on arrival or passed on to the mouse event thread, which did
+    /// not drop it.
+    Applied,
+    /// Dropped by the handler or the mouse event thread, for the reason the
+    /// connection's counters have it under.
+    Dropped { reason: &'static str },
+    /// Refused with an error sent back to the client.
+    Error { code: &'static str },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Entry {
+    id: u64,
+    /// Milliseconds since the epoch.
+    time_ms: u64,
+    connection: u64,
+    #[serde(flatten)]
+    logged: Logged,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Default)]
+struct Ring {
+    next_id: u64,
+    entries: VecDeque<Entry>,
+}
+
+/// The latest events received on any connection, kept in memory only, so
+/// that a misbehaving touchpad can be looked into after the fact.
+#[derive(Clone, Default)]
+pub struct EventLog {
+    ring: Arc<Mutex<Ring>>,
+}
+
+impl fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLog").finish_non_exhaustive()
+    }
+}
+
+impl EventLog {
+    /// Adds an entry, dropping the oldest once full, and returns its id.
+    pub fn record(&self, connection: u64, logged: Logged, outcome: Outcome) -> u64 {
+        let time_ms = now_ms();
+        let mut ring = self.ring.lock().unwrap();
+        ring.next_id += 1;
+        let id = ring.next_id;
+        if ring.entries.len() == CAPACITY {
+            ring.entries.pop_front();
+        }
+        ring.entries.push_back(Entry {
+            id,
+            time_ms,
+            connection,
+            logged,
+            outcome,
+        });
+        id
+    }
+
+    /// Changes the outcome of the entry with `id`, unless it has already
+    /// been dropped from the log.
+    pub fn set_outcome(&self, id: u64, outcome: Outcome) {
+        let mut ring = self.ring.lock().unwrap();
+        // Ids are consecutive, so the entry's place follows from the oldest.
+        let Some(first) = ring.entries.front().map(|entry| entry.id) else {
+            return;
+        };
+        if let Some(entry) = id
+            .checked_sub(first)
+            .and_then(|index| ring.entries.get_mut(index as usize))
+        {
+            entry.outcome = outcome;
+        }
+    }
+
+    /// The entries matching `query`, oldest first. Clears the log
+    /// afterwards when asked to.
+    pub fn read(&self, query: &EventQuery) -> Vec<Entry> {
+        let mut ring = self.ring.lock().unwrap();
+        let entries = ring
+            .entries
+            .iter()
+            .filter(|entry| query.connection.is_none_or(|id| entry.connection == id))
+            .filter(|entry| {
+                query
+                    .kind
+                    .as_deref()
+                    .is_none_or(|kind| entry.logged.kind == Some(kind))
+            })
+            .cloned()
+            .collect();
+        if query.clear {
+            ring.entries.clear();
+        }
+        entries
+    }
+}
+
+/// Which entries of the event log to read back.
+#[derive(Deserialize)]
+pub struct EventQuery {
+    connection: Option<u64>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    clear: bool,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,150 @@
+use crate::input::Input;
+use crate::server_info::DisplaySize;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How often at most the pointer is checked against the fence while it
+/// moves. Every check asks the backend where the pointer is, so checking on
+/// every move would double the calls.
+const FENCE_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The part of the desktop `pointer_fence` keeps the pointer in, written in
+/// the config either as `{"display": 0}` or as a rectangle in pixels.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum FenceArea {
+    Display {
+        display: usize,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl FenceArea {
+    pub fn check(&self) -> Result<(), String> {
+        match *self {
+            // Only the primary display's size is known, see `locate`.
+            FenceArea::Display { display } if display != 0 => {
+                Err("only the primary display, 0, is known".to_string())
+            }
+            FenceArea::Rect { width, height, .. } if width == 0 || height == 0 => {
+                Err("width and height must be positive".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The rectangle fenced in, unless the display's size is unknown.
+    pub fn rect(self, primary: Option<DisplaySize>) -> Option<Rect> {
+        match self {
+            FenceArea::Display { display: 0 } => {
+                let primary = primary?;
+                Some(Rect {
+                    x: 0,
+                    y: 0,
+                    width: i32::try_from(primary.width).ok()?,
+                    height: i32::try_from(primary.height).ok()?,
+                })
+            }
+            FenceArea::Display { .. } => None,
+            FenceArea::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => Some(Rect {
+                x,
+                y,
+                width: i32::try_from(width).ok()?,
+                height: i32::try_from(height).ok()?,
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    /// The point inside the rectangle nearest to `position`.
+    fn clamp(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (
+            x.clamp(self.x, self.x.saturating_add(self.width - 1)),
+            y.clamp(self.y, self.y.saturating_add(self.height - 1)),
+        )
+    }
+}
+
+/// Keeps the pointer inside a rectangle, e.g. the projected display during
+/// a presentation. Relative moves cannot be stopped at the edge beforehand,
+/// so the pointer is put back inside when a check finds it escaped.
+#[derive(Default)]
+pub struct PointerFence {
+    area: Option<Rect>,
+    /// When the next check is due; unset while the pointer has not moved
+    /// since the last.
+    due: Option<Instant>,
+    last_check: Option<Instant>,
+}
+
+impl PointerFence {
+    pub fn new(area: Option<Rect>) -> Self {
+        PointerFence {
+            area,
+            ..PointerFence::default()
+        }
+    }
+
+    /// Fences the pointer into `area`, or lets it go anywhere at once.
+    pub fn set(&mut self, area: Option<Rect>) {
+        *self = PointerFence::new(area);
+        if area.is_some() {
+            self.due = Some(Instant::now());
+        }
+    }
+
+    /// A relative move was made: checks straight away, unless the last
+    /// check was too recent.
+    pub fn moved(&mut self) {
+        if self.area.is_none() {
+            return;
+        }
+        let earliest = self
+            .last_check
+            .map_or_else(Instant::now, |last| last + FENCE_CHECK_INTERVAL);
+        self.due.get_or_insert(earliest);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.due
+    }
+
+    /// Drops a check still due; the next move schedules one again.
+    pub fn skip_check(&mut self) {
+        self.due = None;
+    }
+
+    /// Puts the pointer back inside if it escaped. Returns where it was
+    /// put.
+    pub fn check(&mut self, input: &mut Input) -> Option<(i32, i32)> {
+        self.due = None;
+        self.last_check = Some(Instant::now());
+        let area = self.area?;
+        let position = input.mouse_location()?;
+        let inside = area.clamp(position);
+        if inside == position {
+            return None;
+        }
+        input.mouse_move_to(inside.0, inside.1);
+        Some(inside)
+    }
+}
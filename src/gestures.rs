@@ -0,0 +1,857 @@
+use crate::actions::Action;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Two-finger rotation, e.g. for image viewers and maps. Clients only report
+/// a rotation when turning clearly dominates panning and pinching, so that
+/// ordinary scrolling is not mistaken for it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RotateGesture {
+    /// Degrees of rotation that trigger the action once. Must be positive.
+    #[serde(default = "default_rotate_step")]
+    pub step_degrees: f64,
+    pub clockwise: Action,
+    pub counter_clockwise: Action,
+}
+
+fn default_rotate_step() -> f64 {
+    15.0
+}
+
+impl RotateGesture {
+    /// Adds `angle_delta` to the rotation accumulated so far and returns the
+    /// actions due, keeping the remainder for the next event.
+    pub fn accumulate(&self, accumulated: &mut f64, angle_delta: f64) -> Vec<&Action> {
+        let mut actions = Vec::new();
+        if !angle_delta.is_finite() {
+            return actions;
+        }
+        *accumulated += angle_delta;
+        while *accumulated >= self.step_degrees {
+            *accumulated -= self.step_degrees;
+            actions.push(&self.clockwise);
+        }
+        while *accumulated <= -self.step_degrees {
+            *accumulated += self.step_degrees;
+            actions.push(&self.counter_clockwise);
+        }
+        actions
+    }
+}
+
+/// Timing of "tap and a half": a tap followed by a touch that comes back
+/// down within `tap_window_ms` and stays down for `hold_ms` (or starts
+/// moving) presses the left button until the finger lifts.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TapDrag {
+    #[serde(default = "default_tap_window_ms")]
+    pub tap_window_ms: u64,
+    #[serde(default = "default_hold_ms")]
+    pub hold_ms: u64,
+}
+
+fn default_tap_window_ms() -> u64 {
+    300
+}
+
+fn default_hold_ms() -> u64 {
+    150
+}
+
+/// Safety net for a button left pressed by a gesture, e.g. when the client
+/// never reports the finger lifting: once the button has been held for
+/// `timeout_ms` and the pointer has not moved for `idle_ms`, it is released.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HeldButtonTimeout {
+    pub enabled: bool,
+    pub timeout_ms: u64,
+    pub idle_ms: u64,
+}
+
+impl Default for HeldButtonTimeout {
+    fn default() -> Self {
+        HeldButtonTimeout {
+            enabled: true,
+            timeout_ms: 30_000,
+            idle_ms: 5_000,
+        }
+    }
+}
+
+impl HeldButtonTimeout {
+    /// When a button pressed at `since` and last moved at `last_move` is due
+    /// to be released.
+    pub fn deadline(&self, since: Instant, last_move: Instant) -> Option<Instant> {
+        self.enabled.then(|| {
+            (since + Duration::from_millis(self.timeout_ms))
+                .max(last_move + Duration::from_millis(self.idle_ms))
+        })
+    }
+}
+
+/// A quick two-finger horizontal flick, which goes back or forward in
+/// browsers. Slower or longer horizontal movement, and anything mostly
+/// vertical, is left alone so deliberate scrolling is not mistaken for it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SwipeGesture {
+    /// Off unless asked for, as it takes over quick horizontal scrolls.
+    pub enabled: bool,
+    /// The flick must cover `min_distance_px` within this time after the
+    /// fingers start moving.
+    pub max_duration_ms: u64,
+    pub min_distance_px: f64,
+    /// Peak horizontal speed, in pixels per millisecond, the flick must reach.
+    pub min_speed: f64,
+    /// Swiping right.
+    pub back: Action,
+    /// Swiping left.
+    pub forward: Action,
+}
+
+impl Default for SwipeGesture {
+    fn default() -> Self {
+        // Option+Left moves by word on macOS, where browsers use Cmd+[ instead.
+        let (back, forward) = if cfg!(target_os = "macos") {
+            ("Meta+[", "Meta+]")
+        } else {
+            ("Alt+Left", "Alt+Right")
+        };
+        SwipeGesture {
+            enabled: false,
+            max_duration_ms: 250,
+            min_distance_px: 60.0,
+            min_speed: 1.0,
+            back: Action::Keys(back.parse().expect("valid default combination")),
+            forward: Action::Keys(forward.parse().expect("valid default combination")),
+        }
+    }
+}
+
+/// Vertical movement allowed for every pixel of horizontal movement.
+const SWIPE_MAX_SLOPE: f64 = 0.5;
+
+/// Progress of the current two-finger gesture towards a swipe.
+#[derive(Default)]
+pub struct SwipeTracker {
+    start_ms: Option<u64>,
+    dx: f64,
+    dy: f64,
+    peak_speed: f64,
+    done: bool,
+}
+
+impl SwipeTracker {
+    /// Forgets the gesture, when the fingers change.
+    pub fn reset(&mut self) {
+        *self = SwipeTracker::default();
+    }
+
+    /// Adds a two-finger movement at `now_ms` and returns the action due, at
+    /// most once per gesture.
+    pub fn track<'a>(
+        &mut self,
+        swipe: &'a SwipeGesture,
+        dx: f64,
+        dy: f64,
+        sx: f64,
+        now_ms: u64,
+    ) -> Option<&'a Action> {
+        if !swipe.enabled || self.done || !(dx.is_finite() && dy.is_finite() && sx.is_finite()) {
+            return None;
+        }
+        let start_ms = *self.start_ms.get_or_insert(now_ms);
+        if now_ms.saturating_sub(start_ms) > swipe.max_duration_ms {
+            self.done = true;
+            return None;
+        }
+        self.dx += dx;
+        self.dy += dy;
+        self.peak_speed = self.peak_speed.max(sx.abs());
+
+        let horizontal = self.dx.abs();
+        if horizontal < swipe.min_distance_px
+            || self.dy.abs() > horizontal * SWIPE_MAX_SLOPE
+            || self.peak_speed < swipe.min_speed
+        {
+            return None;
+        }
+        self.done = true;
+        Some(if self.dx > 0.0 {
+            &swipe.back
+        } else {
+            &swipe.forward
+        })
+    }
+}
+
+/// What four or five fingers did together, as recognized by the client.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FingerGesture {
+    Tap,
+    SwipeUp,
+    SwipeDown,
+    SwipeLeft,
+    SwipeRight,
+    PinchIn,
+    PinchOut,
+}
+
+/// One entry of `finger_gestures`: the action run when `fingers` fingers
+/// perform `gesture`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FingerGestureBinding {
+    pub fingers: u8,
+    pub gesture: FingerGesture,
+    pub action: Action,
+}
+
+/// Fingers the client recognizes whole-hand gestures for. Fewer fingers
+/// keep moving, scrolling and rotating.
+pub const GESTURE_FINGERS: std::ops::RangeInclusive<u8> = 4..=5;
+
+/// The action bound to a gesture, if any.
+pub fn finger_gesture_action(
+    bindings: &[FingerGestureBinding],
+    fingers: u8,
+    gesture: FingerGesture,
+) -> Option<&Action> {
+    bindings
+        .iter()
+        .find(|binding| binding.fingers == fingers && binding.gesture == gesture)
+        .map(|binding| &binding.action)
+}
+
+/// A four- or five-finger gesture opening the desktop's application
+/// launcher. Other `finger_gestures` could bind the same shortcut, but this
+/// one knows the platform's and opens the launcher only once, however often
+/// the client reports the gesture within `debounce_ms`: a second press of
+/// the shortcut would close the launcher again.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LauncherGesture {
+    pub fingers: u8,
+    pub gesture: FingerGesture,
+    pub action: Action,
+    pub debounce_ms: u64,
+}
+
+impl Default for LauncherGesture {
+    fn default() -> Self {
+        // Super alone opens the Start menu, the GNOME overview and the KDE
+        // launcher; macOS has Spotlight on Cmd+Space instead.
+        let action = if cfg!(target_os = "macos") {
+            "Meta+Space"
+        } else {
+            "Meta"
+        };
+        LauncherGesture {
+            fingers: 4,
+            gesture: FingerGesture::Tap,
+            action: Action::Keys(action.parse().expect("valid default combination")),
+            debounce_ms: 1000,
+        }
+    }
+}
+
+impl LauncherGesture {
+    pub fn matches(&self, fingers: u8, gesture: FingerGesture) -> bool {
+        self.fingers == fingers && self.gesture == gesture
+    }
+}
+
+/// Two quick two-finger taps in a row, which toggle zoom in browsers and
+/// maps on touch devices. The client reports each two-finger tap; a single
+/// one does nothing, so it cannot be mistaken for the start of a scroll.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DoubleTapZoom {
+    /// The second tap must come this soon after the first.
+    pub window_ms: u64,
+    pub action: Action,
+}
+
+impl Default for DoubleTapZoom {
+    fn default() -> Self {
+        let action = if cfg!(target_os = "macos") {
+            "Meta+="
+        } else {
+            "Ctrl+="
+        };
+        DoubleTapZoom {
+            window_ms: 300,
+            action: Action::Keys(action.parse().expect("valid default combination")),
+        }
+    }
+}
+
+/// The two-finger tap waiting for a second one, see [`DoubleTapZoom`].
+#[derive(Default)]
+pub struct DoubleTap {
+    first: Option<(u64, Instant)>,
+}
+
+impl DoubleTap {
+    /// Counts a two-finger tap by `connection` at `now`. Returns whether it
+    /// completes a double tap; otherwise it may be the first of one.
+    pub fn tap(&mut self, connection: u64, window: Duration, now: Instant) -> bool {
+        match self.first.take() {
+            Some((id, first)) if id == connection && now.duration_since(first) <= window => true,
+            _ => {
+                self.first = Some((connection, now));
+                false
+            }
+        }
+    }
+}
+
+/// One-finger swipes in from an edge of the touchpad, like the bezel swipes
+/// of phones. Only touches starting within `edge_swipe_px` of an edge with
+/// an action count, so ordinary movement near the edge is left alone.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EdgeSwipe {
+    #[serde(default = "default_edge_swipe_px")]
+    pub edge_swipe_px: f64,
+    /// Distance the finger must travel inwards before the action runs.
+    #[serde(default = "default_edge_distance_px")]
+    pub min_distance_px: f64,
+    pub left: Option<Action>,
+    pub right: Option<Action>,
+    pub top: Option<Action>,
+    pub bottom: Option<Action>,
+}
+
+fn default_edge_swipe_px() -> f64 {
+    12.0
+}
+
+fn default_edge_distance_px() -> f64 {
+    60.0
+}
+
+/// Longest touchpad side, in CSS pixels, taken at the client's word for
+/// edge swipes; anything longer is garbage rather than a real page.
+const MAX_SURFACE_PX: f64 = 16_384.0;
+
+/// Where a finger is on the touchpad, and the touchpad's size, in CSS pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl EdgeSwipe {
+    /// Whether edge swipes make sense on a touchpad of `position`'s size. On
+    /// one no more than twice `edge_swipe_px` across, every touch would start
+    /// at an edge.
+    fn fits(&self, position: Position) -> bool {
+        let usable = |side: f64| side > 2.0 * self.edge_swipe_px && side <= MAX_SURFACE_PX;
+        usable(position.width) && usable(position.height)
+    }
+
+    /// The edge `position` is on, if it has an action.
+    fn edge_of(&self, position: Position) -> Option<(Edge, &Action)> {
+        let near = |distance: f64| distance >= 0.0 && distance <= self.edge_swipe_px;
+        [
+            (Edge::Left, position.x, &self.left),
+            (Edge::Right, position.width - position.x, &self.right),
+            (Edge::Top, position.y, &self.top),
+            (Edge::Bottom, position.height - position.y, &self.bottom),
+        ]
+        .into_iter()
+        .filter(|(_, distance, _)| near(*distance))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .and_then(|(edge, _, action)| action.as_ref().map(|action| (edge, action)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+pub enum EdgeStep<'a> {
+    /// Not an edge swipe; the move is handled as usual.
+    Pass,
+    /// Part of an edge swipe; the pointer stays put.
+    Hold,
+    Fire(&'a Action),
+    /// The touch starts on a touchpad too small or too large for edge
+    /// swipes, so it is handled as usual.
+    BadSize,
+}
+
+/// Progress of the current one-finger touch towards an edge swipe.
+#[derive(Default)]
+pub struct EdgeTracker {
+    started: bool,
+    edge: Option<Edge>,
+    travel: f64,
+    fired: bool,
+}
+
+impl EdgeTracker {
+    pub fn reset(&mut self) {
+        *self = EdgeTracker::default();
+    }
+
+    /// Adds a move by (`dx`, `dy`) that ended at `position`.
+    pub fn track<'a>(
+        &mut self,
+        swipe: &'a EdgeSwipe,
+        position: Position,
+        dx: f64,
+        dy: f64,
+    ) -> EdgeStep<'a> {
+        if !self.started {
+            self.started = true;
+            if !swipe.fits(position) {
+                return EdgeStep::BadSize;
+            }
+            let origin = Position {
+                x: position.x - dx,
+                y: position.y - dy,
+                ..position
+            };
+            self.edge = swipe.edge_of(origin).map(|(edge, _)| edge);
+        }
+        let Some(edge) = self.edge else {
+            return EdgeStep::Pass;
+        };
+        if self.fired {
+            return EdgeStep::Hold;
+        }
+        self.travel += match edge {
+            Edge::Left => dx,
+            Edge::Right => -dx,
+            Edge::Top => dy,
+            Edge::Bottom => -dy,
+        };
+        let action = match edge {
+            Edge::Left => &swipe.left,
+            Edge::Right => &swipe.right,
+            Edge::Top => &swipe.top,
+            Edge::Bottom => &swipe.bottom,
+        };
+        match action {
+            Some(action) if self.travel >= swipe.min_distance_px => {
+                self.fired = true;
+                EdgeStep::Fire(action)
+            }
+            _ => EdgeStep::Hold,
+        }
+    }
+}
+
+/// Gestures that can be switched off one by one, by the names below.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Gesture {
+    /// A one-finger tap, reported as a left click.
+    Tap,
+    /// A one-finger touch held still, reported as a right click.
+    LongPress,
+    /// Two-finger scrolling.
+    Scroll,
+    /// See [`SwipeGesture`].
+    Swipe,
+    /// See [`EdgeSwipe`].
+    EdgeSwipe,
+    /// See [`RotateGesture`].
+    Rotate,
+    /// See [`TapDrag`].
+    TapDrag,
+    /// See [`FingerGestureBinding`].
+    FingerGestures,
+    /// See [`DoubleTapZoom`].
+    DoubleTapZoom,
+}
+
+impl Gesture {
+    pub const ALL: [Gesture; 9] = [
+        Gesture::Tap,
+        Gesture::LongPress,
+        Gesture::Scroll,
+        Gesture::Swipe,
+        Gesture::EdgeSwipe,
+        Gesture::Rotate,
+        Gesture::TapDrag,
+        Gesture::FingerGestures,
+        Gesture::DoubleTapZoom,
+    ];
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// Which gestures are switched on, written as a map from gesture name to
+/// whether it is on. Gestures left out are on.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(from = "BTreeMap<Gesture, bool>", into = "BTreeMap<Gesture, bool>")]
+pub struct GestureSwitches {
+    /// Kept as a bit set so that profiles holding one stay `Copy`.
+    off: u16,
+}
+
+impl GestureSwitches {
+    pub fn is_on(self, gesture: Gesture) -> bool {
+        self.off & gesture.bit() == 0
+    }
+
+    pub fn set(&mut self, gesture: Gesture, on: bool) {
+        if on {
+            self.off &= !gesture.bit();
+        } else {
+            self.off |= gesture.bit();
+        }
+    }
+
+    /// Gestures on in both sets.
+    pub fn and(self, other: GestureSwitches) -> GestureSwitches {
+        GestureSwitches {
+            off: self.off | other.off,
+        }
+    }
+}
+
+impl fmt::Debug for GestureSwitches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(BTreeMap::<Gesture, bool>::from(*self))
+            .finish()
+    }
+}
+
+impl From<BTreeMap<Gesture, bool>> for GestureSwitches {
+    fn from(map: BTreeMap<Gesture, bool>) -> Self {
+        let mut switches = GestureSwitches::default();
+        for (gesture, on) in map {
+            switches.set(gesture, on);
+        }
+        switches
+    }
+}
+
+impl From<GestureSwitches> for BTreeMap<Gesture, bool> {
+    fn from(switches: GestureSwitches) -> Self {
+        Gesture::ALL
+            .into_iter()
+            .map(|gesture| (gesture, switches.is_on(gesture)))
+            .collect()
+    }
+}
+
+/// The `gestures` section of the config.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GestureConfig {
+    /// Gestures on for every device. Devices can switch more off in their
+    /// profile, but not the ones off here back on.
+    pub enabled: GestureSwitches,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_button_deadline() {
+        let timeout = HeldButtonTimeout {
+            enabled: true,
+            timeout_ms: 1000,
+            idle_ms: 200,
+        };
+        let since = Instant::now();
+        let ms = Duration::from_millis;
+        // Still early on, however long ago the last move was.
+        assert_eq!(timeout.deadline(since, since), Some(since + ms(1000)));
+        // A move near the end pushes the release back by `idle_ms`.
+        assert_eq!(
+            timeout.deadline(since, since + ms(900)),
+            Some(since + ms(1100))
+        );
+        let disabled = HeldButtonTimeout {
+            enabled: false,
+            ..timeout
+        };
+        assert_eq!(disabled.deadline(since, since), None);
+    }
+
+    #[test]
+    fn double_tap_within_the_window() {
+        let window = Duration::from_millis(300);
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut double_tap = DoubleTap::default();
+        assert!(!double_tap.tap(1, window, start));
+        assert!(double_tap.tap(1, window, start + ms(300)));
+        // A double tap is not the first tap of the next one.
+        assert!(!double_tap.tap(1, window, start + ms(400)));
+        assert!(double_tap.tap(1, window, start + ms(500)));
+    }
+
+    #[test]
+    fn late_second_tap_starts_over() {
+        let window = Duration::from_millis(300);
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut double_tap = DoubleTap::default();
+        assert!(!double_tap.tap(1, window, start));
+        assert!(!double_tap.tap(1, window, start + ms(301)));
+        // The late tap is the first of a new pair.
+        assert!(double_tap.tap(1, window, start + ms(601)));
+    }
+
+    #[test]
+    fn double_tap_takes_one_connection() {
+        let window = Duration::from_millis(300);
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+        let mut double_tap = DoubleTap::default();
+        assert!(!double_tap.tap(1, window, start));
+        assert!(!double_tap.tap(2, window, start + ms(50)));
+        // The other connection's tap replaced the first one.
+        assert!(!double_tap.tap(1, window, start + ms(100)));
+        assert!(double_tap.tap(1, window, start + ms(150)));
+    }
+
+    #[test]
+    fn swipe_is_off_by_default() {
+        let swipe = SwipeGesture::default();
+        let mut tracker = SwipeTracker::default();
+        assert!(tracker.track(&swipe, 40.0, 0.0, 2.0, 0).is_none());
+        assert!(tracker.track(&swipe, 40.0, 0.0, 2.0, 20).is_none());
+
+        let swipe = SwipeGesture {
+            enabled: true,
+            ..SwipeGesture::default()
+        };
+        let mut tracker = SwipeTracker::default();
+        assert!(tracker.track(&swipe, 40.0, 0.0, 2.0, 0).is_none());
+        let action = tracker.track(&swipe, 40.0, 0.0, 2.0, 20);
+        assert!(std::ptr::eq(action.unwrap(), &swipe.back));
+    }
+
+    fn rotate() -> RotateGesture {
+        RotateGesture {
+            step_degrees: 15.0,
+            clockwise: Action::Wheel {
+                wheel: 1,
+                modifiers: None,
+            },
+            counter_clockwise: Action::Wheel {
+                wheel: -1,
+                modifiers: None,
+            },
+        }
+    }
+
+    #[test]
+    fn rotation_turns_into_one_action_per_step() {
+        let rotate = rotate();
+        let mut accumulated = 0.0;
+        assert!(rotate.accumulate(&mut accumulated, 10.0).is_empty());
+        let actions = rotate.accumulate(&mut accumulated, 25.0);
+        assert_eq!(actions, [&rotate.clockwise, &rotate.clockwise]);
+        assert_eq!(accumulated, 5.0);
+        // Turning back first uses up what is left over.
+        assert!(rotate.accumulate(&mut accumulated, -19.0).is_empty());
+        let actions = rotate.accumulate(&mut accumulated, -1.0);
+        assert_eq!(actions, [&rotate.counter_clockwise]);
+        assert_eq!(accumulated, 0.0);
+    }
+
+    #[test]
+    fn non_finite_rotation_is_ignored() {
+        let rotate = rotate();
+        let mut accumulated = 10.0;
+        assert!(rotate.accumulate(&mut accumulated, f64::NAN).is_empty());
+        assert!(rotate
+            .accumulate(&mut accumulated, f64::INFINITY)
+            .is_empty());
+        assert_eq!(accumulated, 10.0);
+    }
+
+    fn swipe() -> SwipeGesture {
+        SwipeGesture {
+            enabled: true,
+            ..SwipeGesture::default()
+        }
+    }
+
+    #[test]
+    fn swipe_boundaries_in_both_directions() {
+        let swipe = swipe();
+        for (sign, expected) in [(1.0, &swipe.back), (-1.0, &swipe.forward)] {
+            // Exactly the distance, slope and speed asked for, on the deadline.
+            let mut tracker = SwipeTracker::default();
+            assert!(tracker.track(&swipe, sign * 30.0, 0.0, 1.0, 0).is_none());
+            let action = tracker.track(&swipe, sign * 30.0, 30.0, sign, 250);
+            assert!(std::ptr::eq(action.unwrap(), expected));
+            // At most once per gesture, until the fingers change.
+            assert!(tracker.track(&swipe, sign * 60.0, 0.0, 2.0, 250).is_none());
+            tracker.reset();
+            assert!(tracker.track(&swipe, sign * 60.0, 0.0, 2.0, 300).is_some());
+
+            // Just short of the distance.
+            let mut tracker = SwipeTracker::default();
+            assert!(tracker.track(&swipe, sign * 59.9, 0.0, 2.0, 0).is_none());
+
+            // Just too steep.
+            let mut tracker = SwipeTracker::default();
+            assert!(tracker.track(&swipe, sign * 60.0, 30.1, 2.0, 0).is_none());
+
+            // Just too slow.
+            let mut tracker = SwipeTracker::default();
+            assert!(tracker.track(&swipe, sign * 60.0, 0.0, 0.99, 0).is_none());
+
+            // Just too late, and the gesture stays over.
+            let mut tracker = SwipeTracker::default();
+            assert!(tracker.track(&swipe, sign * 30.0, 0.0, 2.0, 0).is_none());
+            assert!(tracker.track(&swipe, sign * 30.0, 0.0, 2.0, 251).is_none());
+            assert!(tracker.track(&swipe, sign * 60.0, 0.0, 2.0, 252).is_none());
+        }
+    }
+
+    #[test]
+    fn finger_gestures_are_told_apart_by_finger_count() {
+        let binding = |fingers, combo: &str| FingerGestureBinding {
+            fingers,
+            gesture: FingerGesture::SwipeUp,
+            action: Action::Keys(combo.parse().unwrap()),
+        };
+        let bindings = [binding(4, "Ctrl+Up"), binding(5, "Ctrl+Down")];
+        let action = |fingers, gesture| finger_gesture_action(&bindings, fingers, gesture);
+        assert_eq!(action(4, FingerGesture::SwipeUp), Some(&bindings[0].action));
+        assert_eq!(action(5, FingerGesture::SwipeUp), Some(&bindings[1].action));
+        assert_eq!(action(5, FingerGesture::SwipeDown), None);
+        assert_eq!(action(3, FingerGesture::SwipeUp), None);
+    }
+
+    fn edge_swipe() -> EdgeSwipe {
+        EdgeSwipe {
+            edge_swipe_px: 12.0,
+            min_distance_px: 60.0,
+            left: Some(Action::Keys("Alt+Left".parse().unwrap())),
+            right: None,
+            top: Some(Action::Keys("Alt+Up".parse().unwrap())),
+            bottom: None,
+        }
+    }
+
+    /// A move by (`dx`, `dy`) ending at (`x`, `y`) on a 400x800 touchpad.
+    fn edge_step<'a>(
+        tracker: &mut EdgeTracker,
+        swipe: &'a EdgeSwipe,
+        (x, y): (f64, f64),
+        (dx, dy): (f64, f64),
+    ) -> EdgeStep<'a> {
+        let position = Position {
+            x,
+            y,
+            width: 400.0,
+            height: 800.0,
+        };
+        tracker.track(swipe, position, dx, dy)
+    }
+
+    #[test]
+    fn edge_swipes_start_where_the_first_move_started() {
+        let swipe = edge_swipe();
+        // The first move ends well inside, but started 5px from the left.
+        let mut tracker = EdgeTracker::default();
+        let step = edge_step(&mut tracker, &swipe, (35.0, 400.0), (30.0, 0.0));
+        assert!(matches!(step, EdgeStep::Hold));
+        let step = edge_step(&mut tracker, &swipe, (65.0, 400.0), (30.0, 0.0));
+        assert!(
+            matches!(step, EdgeStep::Fire(action) if std::ptr::eq(action, swipe.left.as_ref().unwrap()))
+        );
+        // Once per touch.
+        let step = edge_step(&mut tracker, &swipe, (95.0, 400.0), (30.0, 0.0));
+        assert!(matches!(step, EdgeStep::Hold));
+
+        // Just within the edge, and just outside it.
+        let mut tracker = EdgeTracker::default();
+        let step = edge_step(&mut tracker, &swipe, (200.0, 42.0), (0.0, 30.0));
+        assert!(matches!(step, EdgeStep::Hold));
+        let mut tracker = EdgeTracker::default();
+        let step = edge_step(&mut tracker, &swipe, (200.0, 42.1), (0.0, 30.0));
+        assert!(matches!(step, EdgeStep::Pass));
+    }
+
+    #[test]
+    fn edge_swipes_only_count_movement_inwards() {
+        let swipe = edge_swipe();
+        let mut tracker = EdgeTracker::default();
+        edge_step(&mut tracker, &swipe, (10.0, 400.0), (0.0, 0.0));
+        let step = edge_step(&mut tracker, &swipe, (10.0, 700.0), (0.0, 300.0));
+        assert!(matches!(step, EdgeStep::Hold));
+        let step = edge_step(&mut tracker, &swipe, (5.0, 700.0), (-5.0, 0.0));
+        assert!(matches!(step, EdgeStep::Hold));
+        // 5px back out have to be made up.
+        let step = edge_step(&mut tracker, &swipe, (65.0, 700.0), (60.0, 0.0));
+        assert!(matches!(step, EdgeStep::Hold));
+        let step = edge_step(&mut tracker, &swipe, (70.0, 700.0), (5.0, 0.0));
+        assert!(matches!(step, EdgeStep::Fire(_)));
+    }
+
+    #[test]
+    fn edges_without_an_action_are_left_alone() {
+        let swipe = edge_swipe();
+        // Started 5px from the right edge, which does nothing.
+        let mut tracker = EdgeTracker::default();
+        let step = edge_step(&mut tracker, &swipe, (300.0, 400.0), (-95.0, 0.0));
+        assert!(matches!(step, EdgeStep::Pass));
+        // In the corner, the nearer edge is the one swiped from.
+        let mut tracker = EdgeTracker::default();
+        edge_step(&mut tracker, &swipe, (8.0, 3.0), (0.0, 0.0));
+        let step = edge_step(&mut tracker, &swipe, (8.0, 63.0), (0.0, 60.0));
+        assert!(
+            matches!(step, EdgeStep::Fire(action) if std::ptr::eq(action, swipe.top.as_ref().unwrap()))
+        );
+    }
+
+    #[test]
+    fn edge_swipes_need_a_plausible_touchpad() {
+        let swipe = edge_swipe();
+        let step = |width: f64, height: f64| {
+            let mut tracker = EdgeTracker::default();
+            let position = Position {
+                x: 5.0,
+                y: 5.0,
+                width,
+                height,
+            };
+            let first = tracker.track(&swipe, position, 0.0, 0.0);
+            let then = tracker.track(&swipe, position, 30.0, 0.0);
+            (first, then)
+        };
+        for (width, height) in [
+            (0.0, 800.0),
+            (-400.0, 800.0),
+            (400.0, 24.0),
+            (16_385.0, 800.0),
+            (f64::NAN, 800.0),
+            (400.0, f64::INFINITY),
+        ] {
+            let (first, then) = step(width, height);
+            assert!(matches!(first, EdgeStep::BadSize), "{}x{}", width, height);
+            // Handled as usual for the rest of the touch, warned about once.
+            assert!(matches!(then, EdgeStep::Pass), "{}x{}", width, height);
+        }
+        for (width, height) in [(400.0, 24.1), (16_384.0, 16_384.0)] {
+            let (first, _) = step(width, height);
+            assert!(matches!(first, EdgeStep::Hold), "{}x{}", width, height);
+        }
+    }
+}
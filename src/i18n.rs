@@ -0,0 +1,94 @@
+use crate::error::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+/// The locale every other falls back to, and the one logs are written in.
+pub const BASE_LOCALE: &str = "en";
+
+/// Translations of the text the server shows users, by locale. The build
+/// checks that every key exists in the base locale.
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("es", include_str!("../locales/es.json")),
+];
+
+type Table = HashMap<String, String>;
+
+fn tables() -> &'static HashMap<&'static str, Table> {
+    static TABLES: OnceLock<HashMap<&'static str, Table>> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|(locale, text)| {
+                let table = serde_json::from_str(text)
+                    .unwrap_or_else(|e| panic!("locales/{}.json: {}", locale, e));
+                (*locale, table)
+            })
+            .collect()
+    })
+}
+
+/// Picks the locale for a client: the one it asked for, else the best match
+/// from its `Accept-Language` header, else the base locale. Only the
+/// language is matched, so "es-MX" gets "es".
+pub fn negotiate(requested: Option<&str>, accept_language: Option<&str>) -> &'static str {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse().ok())?;
+            Some((tag, quality))
+        })
+        .filter(|(tag, quality)| !tag.is_empty() && *quality > 0.0)
+        .collect();
+    // Stable, so that equally preferred ranges keep the client's order.
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    requested
+        .into_iter()
+        .chain(ranges.into_iter().map(|(tag, _)| tag))
+        .find_map(supported)
+        .unwrap_or(BASE_LOCALE)
+}
+
+fn supported(tag: &str) -> Option<&'static str> {
+    let language = tag.split(['-', '_']).next()?;
+    LOCALES
+        .iter()
+        .map(|(locale, _)| *locale)
+        .find(|locale| locale.eq_ignore_ascii_case(language))
+}
+
+/// The text for `key` in `locale`, or in the base locale when it has not
+/// been translated yet.
+pub fn text(locale: &str, key: &str) -> String {
+    let tables = tables();
+    tables
+        .get(locale)
+        .and_then(|table| table.get(key))
+        .or_else(|| tables[BASE_LOCALE].get(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// The texts the pages show, for `/config.js`.
+pub fn page_strings(locale: &str) -> BTreeMap<&'static str, String> {
+    tables()[BASE_LOCALE]
+        .keys()
+        .filter(|key| !key.starts_with("error."))
+        .map(|key| (key.as_str(), text(locale, key)))
+        .collect()
+}
+
+/// An error's message for the client, in `locale`. The details are not
+/// translated, as they come from the config, the OS or the code.
+pub fn error_message(locale: &str, error: &Error) -> String {
+    if locale == BASE_LOCALE {
+        return error.to_string();
+    }
+    text(locale, &format!("error.{}", error.code()))
+        .replace("{detail}", &error.detail().unwrap_or_default())
+}
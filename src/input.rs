@@ -0,0 +1,817 @@
+use crate::error::Error;
+use crate::server_info::DisplaySize;
+use crate::wake;
+#[cfg(target_os = "linux")]
+use crate::ydotool;
+use enigo::{
+    Axis, Button, Coordinate, Direction, Enigo, InputError, Key, Keyboard, Mouse, Settings,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::{atomic::AtomicBool, atomic::AtomicUsize, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often a click, key or button change that failed in a way that may
+/// pass is tried again, and the wait before the first retry, doubled for
+/// each one after it.
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(5);
+/// Calls that failed in a row, retries and all, after which the backend is
+/// set up again.
+const RESTART_AFTER_FAILURES: u32 = 5;
+
+/// The ways input can be injected, tried in the order of `input_backends`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// SendInput on Windows, CoreGraphics on macOS and xdo (X11) on Linux.
+    Enigo,
+    /// The `ydotool` command on Linux, which also works under Wayland.
+    Ydotool,
+}
+
+impl BackendKind {
+    /// What the backend injects input through on this platform.
+    pub fn describe(self) -> &'static str {
+        match self {
+            BackendKind::Enigo if cfg!(windows) => "SendInput",
+            BackendKind::Enigo if cfg!(target_os = "macos") => "CoreGraphics",
+            BackendKind::Enigo => "xdo",
+            BackendKind::Ydotool => "ydotool",
+        }
+    }
+
+    /// Whether text beyond ASCII can be typed. `ydotool type` works through
+    /// a US keyboard layout and skips the characters it has no key for.
+    pub fn types_unicode(self) -> bool {
+        match self {
+            BackendKind::Enigo => true,
+            BackendKind::Ydotool => false,
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackendKind::Enigo => "enigo",
+            BackendKind::Ydotool => "ydotool",
+        })
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enigo" => Ok(BackendKind::Enigo),
+            "ydotool" => Ok(BackendKind::Ydotool),
+            _ => Err(format!(
+                "unknown input backend {:?}, expected \"enigo\" or \"ydotool\"",
+                s
+            )),
+        }
+    }
+}
+
+/// What a backend was found able to do when it was probed at startup.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct InputAbilities {
+    pub pointer: bool,
+    pub keys: bool,
+    pub scroll: bool,
+}
+
+impl InputAbilities {
+    fn all(self) -> bool {
+        self.pointer && self.keys && self.scroll
+    }
+
+    fn count(self) -> usize {
+        [self.pointer, self.keys, self.scroll]
+            .into_iter()
+            .filter(|able| *able)
+            .count()
+    }
+}
+
+impl fmt::Display for InputAbilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |able| if able { "yes" } else { "no" };
+        write!(
+            f,
+            "pointer {}, keys {}, scroll {}",
+            yes_no(self.pointer),
+            yes_no(self.keys),
+            yes_no(self.scroll)
+        )
+    }
+}
+
+/// A failed backend call, and whether trying it again may help.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallError {
+    message: String,
+    pub transient: bool,
+}
+
+impl CallError {
+    /// A failure that may pass, such as a busy display server or daemon.
+    pub fn transient(message: impl Into<String>) -> Self {
+        CallError {
+            message: message.into(),
+            transient: true,
+        }
+    }
+
+    /// A failure that comes back however often the call is tried, such as
+    /// a key the backend has no code for.
+    pub fn permanent(message: impl Into<String>) -> Self {
+        CallError {
+            message: message.into(),
+            transient: false,
+        }
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Only input enigo cannot express fails for good; the display server
+/// refusing a request or running out of keycodes to map may pass.
+impl From<InputError> for CallError {
+    fn from(e: InputError) -> Self {
+        match e {
+            InputError::InvalidInput(_) => CallError::permanent(e.to_string()),
+            e => CallError::transient(e.to_string()),
+        }
+    }
+}
+
+/// How often backend calls were tried again or failed for good, and how
+/// often the backend was restarted, shared with `/api/status`.
+#[derive(Clone, Default)]
+pub struct InputHealth {
+    retries: Arc<AtomicU64>,
+    failures: Arc<AtomicU64>,
+    restarts: Arc<AtomicU64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct InputCounts {
+    pub retries: u64,
+    pub failures: u64,
+    pub restarts: u64,
+}
+
+impl InputHealth {
+    pub fn counts(&self) -> InputCounts {
+        InputCounts {
+            retries: self.retries.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The backend picked at startup and what it can do.
+#[derive(Debug, Clone, Copy)]
+pub struct InputReport {
+    pub backend: BackendKind,
+    pub abilities: InputAbilities,
+    /// Size of the primary display, where the backend can tell. Asked for
+    /// once, on the thread the backend was set up on.
+    pub display: Option<DisplaySize>,
+}
+
+// There is only ever one, so the size of `Enigo` does not matter.
+#[cfg_attr(test, allow(clippy::large_enum_variant))]
+enum Backend {
+    Enigo(Enigo),
+    #[cfg(target_os = "linux")]
+    Ydotool(ydotool::Ydotool),
+    #[cfg(test)]
+    Mock(Mock),
+}
+
+/// A backend for tests that writes down the input it is given instead of
+/// injecting it, and fails on demand.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Mock {
+    calls: Arc<Mutex<Vec<String>>>,
+    failing: Arc<AtomicBool>,
+    failures_left: Arc<AtomicUsize>,
+    position: Arc<Mutex<(i32, i32)>>,
+}
+
+#[cfg(test)]
+impl Mock {
+    /// The input given so far, oldest first, such as `move Rel 1 0` or
+    /// `button Click Left`.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Makes every call from now on fail, or work again.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+
+    /// Makes only the next `count` calls fail.
+    pub fn fail_next(&self, count: usize) {
+        self.failures_left.store(count, Ordering::Relaxed);
+    }
+
+    fn record(&self, call: String) -> Result<(), CallError> {
+        let failing_once = self
+            .failures_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failing_once || self.failing.load(Ordering::Relaxed) {
+            return Err(CallError::transient("mock backend failure"));
+        }
+        self.calls.lock().unwrap().push(call);
+        Ok(())
+    }
+
+    fn move_mouse(&self, x: i32, y: i32, coordinate: Coordinate) -> Result<(), CallError> {
+        self.record(format!("move {:?} {} {}", coordinate, x, y))?;
+        let mut position = self.position.lock().unwrap();
+        *position = match coordinate {
+            Coordinate::Abs => (x, y),
+            Coordinate::Rel => (position.0 + x, position.1 + y),
+        };
+        Ok(())
+    }
+}
+
+/// Injects input through the backend picked at startup. Every call can fail;
+/// a failure is kept rather than stopping the caller halfway through a
+/// gesture, so that the mouse event thread can report it once the event is
+/// handled.
+pub struct Input {
+    kind: BackendKind,
+    backend: Backend,
+    failure: Option<String>,
+    /// Calls that failed in a row in a way that may pass, see
+    /// `RESTART_AFTER_FAILURES`.
+    failures_in_row: u32,
+    health: InputHealth,
+    /// Where the left or right button last went down, see `last_click`.
+    last_click: Option<(i32, i32)>,
+}
+
+impl Input {
+    pub fn new() -> Result<Self, Error> {
+        Input::open(BackendKind::Enigo).map_err(Error::InputBackend)
+    }
+
+    /// An input that goes to a `Mock`, along with the mock to look at.
+    #[cfg(test)]
+    pub fn mock() -> (Self, Mock) {
+        let mock = Mock::default();
+        let input = Input {
+            kind: BackendKind::Enigo,
+            backend: Backend::Mock(mock.clone()),
+            failure: None,
+            failures_in_row: 0,
+            health: InputHealth::default(),
+            last_click: None,
+        };
+        (input, mock)
+    }
+
+    fn open(kind: BackendKind) -> Result<Self, String> {
+        let backend = match kind {
+            BackendKind::Enigo => {
+                let settings = Settings {
+                    // Relative moves have always followed the pointer speed and
+                    // acceleration set in Windows.
+                    windows_subject_to_mouse_speed_and_acceleration_level: true,
+                    ..Settings::default()
+                };
+                let enigo = Enigo::new(&settings).map_err(|e| e.to_string())?;
+                Backend::Enigo(enigo)
+            }
+            #[cfg(target_os = "linux")]
+            BackendKind::Ydotool => Backend::Ydotool(ydotool::Ydotool::new()),
+            #[cfg(not(target_os = "linux"))]
+            BackendKind::Ydotool => return Err("ydotool is only available on Linux".to_string()),
+        };
+        Ok(Input {
+            kind,
+            backend,
+            failure: None,
+            failures_in_row: 0,
+            health: InputHealth::default(),
+            last_click: None,
+        })
+    }
+
+    /// Probes the backends in `order`, logging what each can do, and keeps
+    /// the first that can do everything. Failing that, the one that can do
+    /// the most is used with the rest left out. A `forced` backend is the
+    /// only one tried, and must be able to do everything.
+    pub fn detect(
+        order: &[BackendKind],
+        forced: Option<BackendKind>,
+    ) -> Result<(Self, InputReport), Error> {
+        let candidates = forced.map_or(order.to_vec(), |kind| vec![kind]);
+        let mut best: Option<(Self, InputReport)> = None;
+        let mut failures = Vec::new();
+        for kind in candidates {
+            let mut input = match Input::open(kind) {
+                Ok(input) => input,
+                Err(e) => {
+                    eprintln!("Input backend {}: unavailable: {}", kind, e);
+                    failures.push(format!("{}: {}", kind, e));
+                    continue;
+                }
+            };
+            let (abilities, failure) = input.probe();
+            input.health = InputHealth::default();
+            match failure {
+                None => println!("Input backend {}: {}", kind, abilities),
+                Some(failure) => eprintln!("Input backend {}: {} ({})", kind, abilities, failure),
+            }
+            let report = InputReport {
+                backend: kind,
+                abilities,
+                display: input.display_size(),
+            };
+            if abilities.all() {
+                return Ok((input, report));
+            }
+            if forced.is_some() {
+                return Err(Error::InputBackend(format!(
+                    "{} does not work fully ({})",
+                    kind, abilities
+                )));
+            }
+            failures.push(format!("{}: {}", kind, abilities));
+            if best
+                .as_ref()
+                .is_none_or(|(_, best)| abilities.count() > best.abilities.count())
+            {
+                best = Some((input, report));
+            }
+        }
+        match best {
+            Some((input, report)) if report.abilities.count() > 0 => {
+                eprintln!(
+                    "No input backend works fully, using {} ({}).",
+                    report.backend, report.abilities
+                );
+                Ok((input, report))
+            }
+            _ => Err(Error::InputBackend(failures.join("; "))),
+        }
+    }
+
+    /// Checks each kind of input with a move, key press and scroll that
+    /// undo themselves. Returns the first failure along with the results.
+    fn probe(&mut self) -> (InputAbilities, Option<String>) {
+        let mut first_failure = None;
+        // Failures while probing are no reason to restart the backend.
+        let mut passed = |input: &mut Input| {
+            input.failures_in_row = 0;
+            match input.take_failure() {
+                Some(failure) => {
+                    first_failure.get_or_insert(failure);
+                    false
+                }
+                None => true,
+            }
+        };
+        self.mouse_move_relative(1, 0);
+        self.mouse_move_relative(-1, 0);
+        let pointer = passed(self);
+        self.key_down(Key::Shift);
+        self.key_up(Key::Shift);
+        let keys = passed(self);
+        self.mouse_scroll_y(1);
+        self.mouse_scroll_y(-1);
+        let scroll = passed(self);
+        let abilities = InputAbilities {
+            pointer,
+            keys,
+            scroll,
+        };
+        (abilities, first_failure)
+    }
+
+    /// The first failure since the last call, if any.
+    pub fn take_failure(&mut self) -> Option<String> {
+        self.failure.take()
+    }
+
+    /// What the backend's calls have come to since startup.
+    pub fn health(&self) -> InputHealth {
+        self.health.clone()
+    }
+
+    /// Runs a backend call. With `retry`, a failure that may pass is tried
+    /// again up to `RETRIES` times, pausing a few milliseconds, as a lost
+    /// click or button release is much worse than the wait. Moves and
+    /// scrolls go without, since the next one makes up for a lost one, and
+    /// so does text, which may have been typed in part. A failure that
+    /// remains is kept for `take_failure`.
+    fn call<T>(
+        &mut self,
+        action: &str,
+        retry: bool,
+        mut f: impl FnMut(&mut Backend) -> Result<T, CallError>,
+    ) -> Option<T> {
+        let mut backoff = RETRY_BACKOFF;
+        let mut retries = 0;
+        let e = loop {
+            match f(&mut self.backend) {
+                Ok(value) => {
+                    self.failures_in_row = 0;
+                    return Some(value);
+                }
+                Err(e) if retry && e.transient && retries < RETRIES => {
+                    retries += 1;
+                    self.health.retries.fetch_add(1, Ordering::Relaxed);
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                Err(e) => break e,
+            }
+        };
+        self.health.failures.fetch_add(1, Ordering::Relaxed);
+        self.failure
+            .get_or_insert_with(|| format!("{}: {}", action, e));
+        if e.transient {
+            self.failures_in_row += 1;
+            if self.failures_in_row >= RESTART_AFTER_FAILURES {
+                self.restart();
+            }
+        }
+        None
+    }
+
+    /// Sets the backend up again once `RESTART_AFTER_FAILURES` calls failed
+    /// in a row, for one that lost its display server or daemon. The old
+    /// one is kept if that fails too.
+    fn restart(&mut self) {
+        self.failures_in_row = 0;
+        self.health.restarts.fetch_add(1, Ordering::Relaxed);
+        eprintln!(
+            "Input backend {}: {} calls in a row failed, restarting it",
+            self.kind, RESTART_AFTER_FAILURES
+        );
+        #[cfg(test)]
+        if let Backend::Mock(mock) = &self.backend {
+            mock.calls.lock().unwrap().push("restart".to_string());
+            return;
+        }
+        match Input::open(self.kind) {
+            Ok(input) => self.backend = input.backend,
+            Err(e) => eprintln!("Input backend {}: restart failed: {}", self.kind, e),
+        }
+    }
+
+    fn move_mouse(&mut self, x: i32, y: i32, coordinate: Coordinate) {
+        self.call("move", false, |backend| match backend {
+            Backend::Enigo(enigo) => Ok(enigo.move_mouse(x, y, coordinate)?),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(ydotool) => ydotool.move_mouse(x, y, coordinate == Coordinate::Abs),
+            #[cfg(test)]
+            Backend::Mock(mock) => mock.move_mouse(x, y, coordinate),
+        });
+    }
+
+    fn button(&mut self, action: &str, button: Button, direction: Direction) {
+        self.call(action, true, |backend| match backend {
+            Backend::Enigo(enigo) => Ok(enigo.button(button, direction)?),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(ydotool) => ydotool.button(button, direction),
+            #[cfg(test)]
+            Backend::Mock(mock) => mock.record(format!("button {:?} {:?}", direction, button)),
+        });
+    }
+
+    fn scroll(&mut self, length: i32, axis: Axis) {
+        self.call("scroll", false, |backend| match backend {
+            Backend::Enigo(enigo) => Ok(enigo.scroll(length, axis)?),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(ydotool) => ydotool.scroll(length, axis),
+            #[cfg(test)]
+            Backend::Mock(mock) => mock.record(format!("scroll {:?} {}", axis, length)),
+        });
+    }
+
+    fn key(&mut self, action: &str, key: Key, direction: Direction) {
+        self.call(action, true, |backend| match backend {
+            Backend::Enigo(enigo) => Ok(enigo.key(key, direction)?),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(ydotool) => ydotool.key(key, direction),
+            #[cfg(test)]
+            Backend::Mock(mock) => mock.record(format!("key {:?} {:?}", direction, key)),
+        });
+    }
+
+    pub fn mouse_move_relative(&mut self, dx: i32, dy: i32) {
+        self.move_mouse(dx, dy, Coordinate::Rel);
+    }
+
+    pub fn mouse_move_to(&mut self, x: i32, y: i32) {
+        self.move_mouse(x, y, Coordinate::Abs);
+    }
+
+    pub fn mouse_down(&mut self, button: Button) {
+        self.note_click(button);
+        self.button("press button", button, Direction::Press);
+    }
+
+    pub fn mouse_up(&mut self, button: Button) {
+        self.button("release button", button, Direction::Release);
+    }
+
+    pub fn mouse_click(&mut self, button: Button) {
+        self.note_click(button);
+        self.button("click", button, Direction::Click);
+    }
+
+    /// Where the latest left or right click or press landed, whatever made
+    /// it, for `ReturnToLastClick`. Unknown until one lands, and always with
+    /// backends that cannot tell where the pointer is.
+    pub fn last_click(&self) -> Option<(i32, i32)> {
+        self.last_click
+    }
+
+    fn note_click(&mut self, button: Button) {
+        if matches!(button, Button::Left | Button::Right) {
+            if let Some(position) = self.mouse_location() {
+                self.last_click = Some(position);
+            }
+        }
+    }
+
+    pub fn mouse_scroll_x(&mut self, length: i32) {
+        self.scroll(length, Axis::Horizontal);
+    }
+
+    pub fn mouse_scroll_y(&mut self, length: i32) {
+        self.scroll(length, Axis::Vertical);
+    }
+
+    pub fn key_down(&mut self, key: Key) {
+        self.key("press key", key, Direction::Press);
+    }
+
+    pub fn key_up(&mut self, key: Key) {
+        self.key("release key", key, Direction::Release);
+    }
+
+    pub fn key_click(&mut self, key: Key) {
+        self.key("press key", key, Direction::Click);
+    }
+
+    pub fn key_sequence(&mut self, text: &str) {
+        self.call("type text", false, |backend| match backend {
+            Backend::Enigo(enigo) => Ok(enigo.text(text)?),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(ydotool) => ydotool.text(text),
+            #[cfg(test)]
+            Backend::Mock(mock) => mock.record(format!("text {}", text)),
+        });
+    }
+
+    /// Switches the display on if it is asleep, leaving the pointer where it
+    /// is.
+    pub fn wake_display(&mut self) {
+        match &self.backend {
+            Backend::Enigo(_) => {
+                if let Err(e) = wake::wake_display() {
+                    self.failure
+                        .get_or_insert_with(|| format!("wake display: {}", e));
+                }
+                // Windows only switches a sleeping display back on for
+                // input; a move by nothing is input enough.
+                if cfg!(windows) {
+                    self.mouse_move_relative(0, 0);
+                }
+            }
+            // The kernel drops moves by nothing, so the pointer goes there
+            // and back.
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(_) => {
+                self.mouse_move_relative(1, 0);
+                self.mouse_move_relative(-1, 0);
+            }
+            #[cfg(test)]
+            Backend::Mock(mock) => {
+                if let Err(e) = mock.record("wake".to_string()) {
+                    self.failure
+                        .get_or_insert_with(|| format!("wake display: {}", e));
+                }
+            }
+        }
+    }
+
+    /// Where the pointer is, where the backend can tell.
+    pub fn mouse_location(&self) -> Option<(i32, i32)> {
+        match &self.backend {
+            Backend::Enigo(enigo) => enigo.location().ok(),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(_) => None,
+            #[cfg(test)]
+            Backend::Mock(mock) => Some(*mock.position.lock().unwrap()),
+        }
+    }
+
+    /// Size of the primary display, where the backend can tell.
+    pub fn display_size(&self) -> Option<DisplaySize> {
+        let (width, height) = self.main_display_size()?;
+        Some(DisplaySize {
+            width: usize::try_from(width).ok()?,
+            height: usize::try_from(height).ok()?,
+        })
+    }
+
+    /// Size of the primary display in pixels, where the backend can tell.
+    fn main_display_size(&self) -> Option<(i32, i32)> {
+        match &self.backend {
+            Backend::Enigo(enigo) => enigo.main_display().ok(),
+            #[cfg(target_os = "linux")]
+            Backend::Ydotool(_) => None,
+            #[cfg(test)]
+            Backend::Mock(_) => Some((1920, 1080)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_is_kept_until_taken() {
+        let (mut input, mock) = Input::mock();
+        input.mouse_click(Button::Left);
+        assert_eq!(input.take_failure(), None);
+        assert_eq!(mock.calls(), ["button Click Left"]);
+
+        mock.set_failing(true);
+        input.mouse_move_relative(5, 0);
+        input.key_click(Key::Shift);
+        let failure = input.take_failure().expect("the move failed");
+        assert!(failure.starts_with("move: "), "{}", failure);
+        assert_eq!(input.take_failure(), None);
+    }
+
+    #[test]
+    fn clicks_and_keys_are_tried_again() {
+        let (mut input, mock) = Input::mock();
+        mock.fail_next(2);
+        input.mouse_click(Button::Left);
+        mock.fail_next(3);
+        input.key_up(Key::Shift);
+        assert_eq!(input.take_failure(), None);
+        assert_eq!(mock.calls(), ["button Click Left", "key Release Shift"]);
+        assert_eq!(
+            input.health().counts(),
+            InputCounts {
+                retries: 5,
+                failures: 0,
+                restarts: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn retries_give_up_after_a_few() {
+        let (mut input, mock) = Input::mock();
+        mock.fail_next(RETRIES as usize + 1);
+        input.mouse_up(Button::Left);
+        let failure = input.take_failure().expect("the release failed");
+        assert!(failure.starts_with("release button: "), "{}", failure);
+        assert!(mock.calls().is_empty());
+        assert_eq!(input.health().counts().retries, u64::from(RETRIES));
+        assert_eq!(input.health().counts().failures, 1);
+    }
+
+    #[test]
+    fn moves_scrolls_and_text_are_not_tried_again() {
+        let (mut input, mock) = Input::mock();
+        mock.fail_next(3);
+        input.mouse_move_relative(5, 0);
+        input.mouse_scroll_y(1);
+        input.key_sequence("abc");
+        assert!(input.take_failure().is_some());
+        // The next call works: nothing was retried.
+        input.mouse_move_relative(1, 0);
+        assert_eq!(mock.calls(), ["move Rel 1 0"]);
+        assert_eq!(input.health().counts().retries, 0);
+        assert_eq!(input.health().counts().failures, 3);
+    }
+
+    #[test]
+    fn failures_in_a_row_restart_the_backend() {
+        let (mut input, mock) = Input::mock();
+        mock.set_failing(true);
+        for _ in 1..RESTART_AFTER_FAILURES {
+            input.mouse_move_relative(1, 0);
+        }
+        assert!(mock.calls().is_empty());
+        // A call that works ends the run of failures.
+        mock.set_failing(false);
+        input.mouse_move_relative(1, 0);
+        mock.set_failing(true);
+        for _ in 1..RESTART_AFTER_FAILURES {
+            input.mouse_move_relative(1, 0);
+        }
+        assert_eq!(mock.calls(), ["move Rel 1 0"]);
+        input.mouse_move_relative(1, 0);
+        assert_eq!(mock.calls(), ["move Rel 1 0", "restart"]);
+        assert_eq!(input.health().counts().restarts, 1);
+    }
+
+    #[test]
+    fn only_invalid_input_fails_for_good() {
+        assert!(!CallError::from(InputError::InvalidInput("NUL in text")).transient);
+        assert!(CallError::from(InputError::Simulate("busy")).transient);
+        assert!(CallError::from(InputError::NoEmptyKeycodes).transient);
+        assert!(CallError::from(InputError::Mapping("full".to_string())).transient);
+    }
+
+    #[test]
+    fn permanent_failures_are_not_tried_again() {
+        let (mut input, _) = Input::mock();
+        let mut tries = 0;
+        let result: Option<()> = input.call("click", true, |_| {
+            tries += 1;
+            Err(CallError::permanent("no such button"))
+        });
+        assert_eq!(result, None);
+        assert_eq!(tries, 1);
+        assert_eq!(input.failures_in_row, 0);
+        assert_eq!(
+            input.take_failure().as_deref(),
+            Some("click: no such button")
+        );
+    }
+
+    #[test]
+    fn probe_of_failing_backend_finds_nothing() {
+        let (mut input, mock) = Input::mock();
+        mock.set_failing(true);
+        let (abilities, failure) = input.probe();
+        assert_eq!(
+            abilities,
+            InputAbilities {
+                pointer: false,
+                keys: false,
+                scroll: false,
+            }
+        );
+        assert!(failure.is_some());
+    }
+
+    #[test]
+    fn probe_undoes_itself() {
+        let (mut input, mock) = Input::mock();
+        let (abilities, failure) = input.probe();
+        assert!(abilities.all());
+        assert_eq!(failure, None);
+        assert_eq!(input.mouse_location(), Some((0, 0)));
+        assert_eq!(mock.calls().len(), 6);
+    }
+
+    #[test]
+    fn last_click_is_where_left_or_right_went_down() {
+        let (mut input, _) = Input::mock();
+        assert_eq!(input.last_click(), None);
+        input.mouse_move_to(100, 200);
+        input.mouse_click(Button::Left);
+        assert_eq!(input.last_click(), Some((100, 200)));
+        // Other buttons, and letting go after a drag, do not count.
+        input.mouse_move_relative(50, 0);
+        input.mouse_click(Button::Middle);
+        input.mouse_up(Button::Left);
+        assert_eq!(input.last_click(), Some((100, 200)));
+        input.mouse_down(Button::Right);
+        assert_eq!(input.last_click(), Some((150, 200)));
+    }
+
+    #[test]
+    fn display_size_from_the_backend() {
+        let (input, _) = Input::mock();
+        let display = input.display_size().unwrap();
+        assert_eq!((display.width, display.height), (1920, 1080));
+    }
+}
@@ -0,0 +1,428 @@
+use crate::admin::Admin;
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INSTANCE_FILE: &str = "instance.json";
+
+/// How long the running instance has to answer over loopback before it
+/// counts as gone.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long `--replace` waits for the old instance to stop.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What to do when another instance is already running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstanceMode {
+    /// Refuse to start, the default.
+    Refuse,
+    /// Ask the other instance to shut down and take over, `--replace`.
+    Replace,
+    /// Start anyway without claiming the lock, `--allow-multiple`.
+    AllowMultiple,
+}
+
+impl InstanceMode {
+    pub fn from_args() -> Self {
+        if std::env::args().any(|arg| arg == "--allow-multiple") {
+            InstanceMode::AllowMultiple
+        } else if std::env::args().any(|arg| arg == "--replace") {
+            InstanceMode::Replace
+        } else {
+            InstanceMode::Refuse
+        }
+    }
+}
+
+/// The lock file's contents: which instance holds it, and where to reach it
+/// to check that it still runs.
+#[derive(Deserialize, Serialize, Debug)]
+struct InstanceRecord {
+    id: String,
+    pid: u32,
+    port: u16,
+}
+
+/// A random version 4 UUID, reported as `instance_id` by `/api/info` so that
+/// a lock file can be told apart from a server that merely reuses the port.
+pub fn new_instance_id() -> String {
+    let mut bytes = rand::thread_rng().gen::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Makes sure no other instance runs from this directory before this one,
+/// `id`, takes the lock. A lock whose instance does not answer on its port
+/// with its id was left by one that crashed, and is removed.
+pub fn claim(id: &str, port: u16, mode: InstanceMode, admin: &Admin) -> Result<(), Error> {
+    claim_at(Path::new(INSTANCE_FILE), id, port, mode, admin)
+}
+
+fn claim_at(
+    path: &Path,
+    id: &str,
+    port: u16,
+    mode: InstanceMode,
+    admin: &Admin,
+) -> Result<(), Error> {
+    if mode == InstanceMode::AllowMultiple {
+        println!("Multiple instances allowed: not checking for another one.");
+        return Ok(());
+    }
+    if let Some(existing) = read_record(path)? {
+        match probe(&existing) {
+            Probe::Gone => println!(
+                "Removing the stale {} of process {}, which is no longer running.",
+                INSTANCE_FILE, existing.pid
+            ),
+            probe if mode == InstanceMode::Refuse => {
+                let unsure = match probe {
+                    Probe::Unsure(status) => format!(", answering with status {}", status),
+                    _ => String::new(),
+                };
+                return Err(Error::AlreadyRunning(format!(
+                    "process {} at http://localhost:{}/{} (start with --replace to take over, or --allow-multiple to run both)",
+                    existing.pid, existing.port, unsure
+                )));
+            }
+            _ => replace(&existing, admin)?,
+        }
+        // Unless the instance removed it on the way out, or another one has
+        // taken it over meanwhile.
+        if read_record(path)?.is_some_and(|record| record.id == existing.id) {
+            fs::remove_file(path)
+                .map_err(|e| Error::Config(format!("{}: {}", INSTANCE_FILE, e)))?;
+        }
+    }
+    let record = InstanceRecord {
+        id: id.to_string(),
+        pid: std::process::id(),
+        port,
+    };
+    let text = serde_json::to_string_pretty(&record).map_err(|e| Error::Config(e.to_string()))?;
+    // Created only if it does not exist, so that of two instances starting
+    // at once, one gets the lock.
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    match created {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(Error::AlreadyRunning(format!(
+            "another instance took {} while this one was starting",
+            INSTANCE_FILE
+        ))),
+        Err(e) => Err(Error::Config(format!("{}: {}", INSTANCE_FILE, e))),
+    }
+}
+
+/// Removes the lock on shutdown, unless another instance has taken it over
+/// in the meantime.
+pub fn release(id: &str) {
+    release_at(Path::new(INSTANCE_FILE), id);
+}
+
+fn release_at(path: &Path, id: &str) {
+    match read_record(path) {
+        Ok(Some(record)) if record.id == id => {
+            if let Err(e) = fs::remove_file(path) {
+                eprintln!("Warning: {}: {}", INSTANCE_FILE, e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => e.log(),
+    }
+}
+
+fn read_record(path: &Path) -> Result<Option<InstanceRecord>, Error> {
+    match fs::read_to_string(path) {
+        Ok(text) => match serde_json::from_str(&text) {
+            Ok(record) => Ok(Some(record)),
+            Err(e) => {
+                eprintln!("Warning: {}: {}, replacing it", INSTANCE_FILE, e);
+                Ok(None)
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Config(format!("{}: {}", INSTANCE_FILE, e))),
+    }
+}
+
+/// Asks the instance in `existing` to shut down through its admin route,
+/// with this server's admin secret, and waits until it has. Instances
+/// started from the same working directory share `admin.json`; others
+/// need the same `--admin-secret`.
+fn replace(existing: &InstanceRecord, admin: &Admin) -> Result<(), Error> {
+    println!(
+        "Asking process {} at http://localhost:{}/ to shut down.",
+        existing.pid, existing.port
+    );
+    let request = format!(
+        "POST /api/shutdown HTTP/1.0\r\nHost: localhost\r\nAuthorization: {}\r\nContent-Length: 0\r\n\r\n",
+        admin.authorization()
+    );
+    match send(existing.port, &request).map(|response| status(&response)) {
+        Ok(Some(status)) if !(200..300).contains(&status) => {
+            return Err(Error::AlreadyRunning(format!(
+                "process {} refused to shut down with status {}; is its admin secret the same?",
+                existing.pid, status
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return Err(Error::AlreadyRunning(format!(
+                "process {} did not take the request to shut down: {}",
+                existing.pid, e
+            )));
+        }
+    }
+    let deadline = Instant::now() + REPLACE_TIMEOUT;
+    while probe(existing) != Probe::Gone {
+        if Instant::now() > deadline {
+            return Err(Error::AlreadyRunning(format!(
+                "process {} is still running {} seconds after being asked to shut down",
+                existing.pid,
+                REPLACE_TIMEOUT.as_secs()
+            )));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    println!("Process {} has shut down.", existing.pid);
+    Ok(())
+}
+
+/// What the port of a lock's instance says about it.
+#[derive(Debug, PartialEq)]
+enum Probe {
+    /// It answers `/api/info` with the instance's id.
+    Running,
+    /// Nothing answers, or a server that is not the instance.
+    Gone,
+    /// Something answers with this error status, which may well be the
+    /// instance, e.g. one that does not let this computer in.
+    Unsure(u16),
+}
+
+/// Checks whether the instance in `record` still runs, by asking its port
+/// for `/api/info`.
+fn probe(record: &InstanceRecord) -> Probe {
+    let request = "GET /api/info HTTP/1.0\r\nHost: localhost\r\n\r\n";
+    let Ok(response) = send(record.port, request) else {
+        return Probe::Gone;
+    };
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+    match status(&response) {
+        Some(status) if !(200..300).contains(&status) => return Probe::Unsure(status),
+        _ => {}
+    }
+    let same = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|info| info["instance_id"].as_str().map(|id| id == record.id))
+        .unwrap_or(false);
+    if same {
+        Probe::Running
+    } else {
+        Probe::Gone
+    }
+}
+
+/// The status code of a raw HTTP response.
+fn status(response: &str) -> Option<u16> {
+    response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+}
+
+/// Sends a bare HTTP/1.0 request over loopback and returns the response.
+fn send(port: u16, request: &str) -> std::io::Result<String> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&address, PROBE_TIMEOUT)?;
+    stream.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    stream.set_write_timeout(Some(PROBE_TIMEOUT))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminSecret;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+
+    /// A lock file of the test's own, removed when it ends.
+    struct LockFile(PathBuf);
+
+    impl LockFile {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("instance-{}-{}.json", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            LockFile(path)
+        }
+
+        fn write(&self, id: &str, port: u16) {
+            let record = InstanceRecord {
+                id: id.to_string(),
+                pid: 1,
+                port,
+            };
+            fs::write(&self.0, serde_json::to_string(&record).unwrap()).unwrap();
+        }
+
+        fn id(&self) -> Option<String> {
+            read_record(&self.0).unwrap().map(|record| record.id)
+        }
+    }
+
+    impl Drop for LockFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A port nothing listens on.
+    fn closed_port() -> u16 {
+        TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn admin(secret: &str) -> Admin {
+        Admin::load(Some(AdminSecret::from(secret.to_string())), false).unwrap()
+    }
+
+    /// Serves `/api/info` on a loopback port with `status` and `body`, and
+    /// stops when asked to through `/api/shutdown` with `SECRET`.
+    fn mock_instance(status: &'static str, body: String) -> u16 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).into_owned();
+                let shutdown = request.starts_with("POST /api/shutdown")
+                    && request.contains(&format!("Authorization: Bearer {}\r\n", SECRET));
+                let response = if shutdown {
+                    "HTTP/1.0 200 OK\r\n\r\n".to_string()
+                } else if request.starts_with("POST /api/shutdown") {
+                    "HTTP/1.0 403 Forbidden\r\n\r\n".to_string()
+                } else {
+                    format!("HTTP/1.0 {}\r\n\r\n{}", status, body)
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                if shutdown {
+                    break;
+                }
+            }
+        });
+        port
+    }
+
+    fn info(id: &str) -> String {
+        serde_json::json!({ "instance_id": id }).to_string()
+    }
+
+    #[test]
+    fn stale_lock_is_taken_over() {
+        let lock = LockFile::new("stale");
+        lock.write("old", closed_port());
+        claim_at(&lock.0, "new", 8088, InstanceMode::Refuse, &admin(SECRET)).unwrap();
+        assert_eq!(lock.id().as_deref(), Some("new"));
+        release_at(&lock.0, "new");
+        assert_eq!(lock.id(), None);
+    }
+
+    #[test]
+    fn server_reusing_the_port_leaves_the_lock_stale() {
+        let lock = LockFile::new("reused");
+        lock.write("old", mock_instance("200 OK", info("someone else")));
+        claim_at(&lock.0, "new", 8088, InstanceMode::Refuse, &admin(SECRET)).unwrap();
+        assert_eq!(lock.id().as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn live_instance_is_refused() {
+        let lock = LockFile::new("live");
+        lock.write("old", mock_instance("200 OK", info("old")));
+        let claimed = claim_at(&lock.0, "new", 8088, InstanceMode::Refuse, &admin(SECRET));
+        assert!(matches!(claimed, Err(Error::AlreadyRunning(_))));
+        assert_eq!(lock.id().as_deref(), Some("old"));
+        // Nor does the refused instance remove the lock on its way out.
+        release_at(&lock.0, "new");
+        assert_eq!(lock.id().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn error_status_counts_as_running() {
+        let lock = LockFile::new("forbidden");
+        lock.write("old", mock_instance("403 Forbidden", String::new()));
+        let claimed = claim_at(&lock.0, "new", 8088, InstanceMode::Refuse, &admin(SECRET));
+        assert!(matches!(claimed, Err(Error::AlreadyRunning(e)) if e.contains("403")));
+        assert_eq!(lock.id().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn replace_stops_the_live_instance() {
+        let lock = LockFile::new("replace");
+        lock.write("old", mock_instance("200 OK", info("old")));
+        claim_at(&lock.0, "new", 8088, InstanceMode::Replace, &admin(SECRET)).unwrap();
+        assert_eq!(lock.id().as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn replace_needs_the_same_admin_secret() {
+        let lock = LockFile::new("replace-refused");
+        lock.write("old", mock_instance("200 OK", info("old")));
+        let claimed = claim_at(
+            &lock.0,
+            "new",
+            8088,
+            InstanceMode::Replace,
+            &admin("another secret"),
+        );
+        assert!(matches!(claimed, Err(Error::AlreadyRunning(e)) if e.contains("403")));
+        assert_eq!(lock.id().as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn allow_multiple_leaves_the_lock_alone() {
+        let lock = LockFile::new("multiple");
+        lock.write("old", mock_instance("200 OK", info("old")));
+        claim_at(
+            &lock.0,
+            "new",
+            8088,
+            InstanceMode::AllowMultiple,
+            &admin(SECRET),
+        )
+        .unwrap();
+        assert_eq!(lock.id().as_deref(), Some("old"));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::input::Input;
+use enigo::Key;
+use std::time::{Duration, Instant};
+
+/// A key held down by `KeyHold`.
+struct HeldKey {
+    connection: u64,
+    key: Key,
+    until: Instant,
+}
+
+/// Keys held down for a set time, e.g. for games that tell a tap from a
+/// hold. Other events go on being handled while they are down.
+#[derive(Default)]
+pub struct KeyHolds {
+    held: Vec<HeldKey>,
+}
+
+impl KeyHolds {
+    /// Presses `key` for `duration`. A key already held by a `KeyHold` is
+    /// not pressed again; it is kept down until the later of the two ends.
+    pub fn hold(&mut self, input: &mut Input, connection: u64, key: Key, duration: Duration) {
+        let until = Instant::now() + duration;
+        match self.held.iter_mut().find(|held| held.key == key) {
+            Some(held) => {
+                held.connection = connection;
+                held.until = held.until.max(until);
+            }
+            None => {
+                input.key_down(key);
+                self.held.push(HeldKey {
+                    connection,
+                    key,
+                    until,
+                });
+            }
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held.iter().map(|held| held.until).min()
+    }
+
+    /// Lets go of the keys whose time is up.
+    pub fn release_due(&mut self, input: &mut Input, now: Instant) {
+        self.release_where(input, |held| held.until <= now);
+    }
+
+    /// Lets go of the keys a connection holds, early, e.g. when it closes.
+    pub fn release_connection(&mut self, input: &mut Input, connection: u64) {
+        self.release_where(input, |held| held.connection == connection);
+    }
+
+    fn release_where(&mut self, input: &mut Input, due: impl Fn(&HeldKey) -> bool) {
+        self.held.retain(|held| {
+            if !due(held) {
+                return true;
+            }
+            input.key_up(held.key);
+            println!(
+                "Key released after hold: {:?} (connection {})",
+                held.key, held.connection
+            );
+            false
+        });
+    }
+}
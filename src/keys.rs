@@ -0,0 +1,549 @@
+use crate::input::Input;
+use enigo::Key;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Non-character keys clients can press by name. Any name in `KEY_NAMES`
+/// is accepted; the variant name is the canonical one.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum SpecialKey {
+    Escape,
+    Tab,
+    Backspace,
+    Return,
+    Space,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    UpArrow,
+    DownArrow,
+    LeftArrow,
+    RightArrow,
+    F5,
+    // Keys only Windows can send.
+    BrowserBack,
+    BrowserForward,
+    BrowserRefresh,
+    BrowserHome,
+    LaunchMail,
+    LaunchMediaSelect,
+}
+
+impl SpecialKey {
+    fn entry(self) -> &'static KeyName {
+        KEY_NAMES
+            .iter()
+            .find(|entry| entry.special == Some(self))
+            .expect("every special key is named")
+    }
+
+    /// The key to press, or `None` for a key this platform cannot send.
+    pub fn to_enigo(self) -> Option<Key> {
+        self.entry().sends.to_enigo()
+    }
+
+    /// The special keys that only some platforms can send, and that this one can.
+    pub fn platform_keys() -> Vec<SpecialKey> {
+        KEY_NAMES
+            .iter()
+            .filter(|entry| matches!(entry.sends, Sends::VirtualKey(_)))
+            .filter(|entry| entry.sends.to_enigo().is_some())
+            .filter_map(|entry| entry.special)
+            .collect()
+    }
+}
+
+impl TryFrom<String> for SpecialKey {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let entry = lookup(&name)?;
+        entry
+            .special
+            .ok_or_else(|| format!("{} is not available as a special key", entry.name))
+    }
+}
+
+/// A key for `KeyHold`: a single character, or the name of a special key.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum HoldKey {
+    Char(char),
+    Special(SpecialKey),
+}
+
+impl HoldKey {
+    pub fn to_enigo(self) -> Option<Key> {
+        match self {
+            HoldKey::Char(key) => Some(Key::Unicode(key)),
+            HoldKey::Special(key) => key.to_enigo(),
+        }
+    }
+}
+
+impl TryFrom<String> for HoldKey {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) => Ok(HoldKey::Char(key)),
+            _ => SpecialKey::try_from(name).map(HoldKey::Special),
+        }
+    }
+}
+
+/// How a named key is sent.
+#[derive(Clone, Copy)]
+enum Sends {
+    Key(Key),
+    /// A Windows virtual-key code, for keys enigo has no name for.
+    VirtualKey(u16),
+}
+
+impl Sends {
+    fn to_enigo(self) -> Option<Key> {
+        match self {
+            Sends::Key(key) => Some(key),
+            Sends::VirtualKey(vk) => windows_key(vk),
+        }
+    }
+}
+
+/// enigo sends raw keys by virtual-key code, which only exists on Windows.
+#[cfg(windows)]
+fn windows_key(vk: u16) -> Option<Key> {
+    Some(Key::Other(u32::from(vk)))
+}
+
+#[cfg(not(windows))]
+fn windows_key(_vk: u16) -> Option<Key> {
+    None
+}
+
+/// The key labelled AltGr on European keyboards, which types the third
+/// character on a key: right Alt on Windows, right Option on macOS, and the
+/// ISO Level 3 Shift keysym elsewhere.
+#[cfg(windows)]
+pub const ALT_GR: Key = Key::RMenu;
+#[cfg(target_os = "macos")]
+pub const ALT_GR: Key = Key::ROption;
+#[cfg(not(any(windows, target_os = "macos")))]
+pub const ALT_GR: Key = Key::Other(0xFE03);
+
+/// A key as it may be written in messages and `config.json`.
+struct KeyName {
+    /// Used in logs and responses.
+    name: &'static str,
+    /// Other accepted spellings, including the labels on non-English
+    /// keyboards. Names are compared ignoring case, spaces, '-' and '_'.
+    aliases: &'static [&'static str],
+    sends: Sends,
+    /// The `SpecialKey` clients press it as, if any.
+    special: Option<SpecialKey>,
+}
+
+const fn named(name: &'static str, aliases: &'static [&'static str], key: Key) -> KeyName {
+    KeyName {
+        name,
+        aliases,
+        sends: Sends::Key(key),
+        special: None,
+    }
+}
+
+const fn windows(name: &'static str, aliases: &'static [&'static str], vk: u16) -> KeyName {
+    KeyName {
+        name,
+        aliases,
+        sends: Sends::VirtualKey(vk),
+        special: None,
+    }
+}
+
+impl KeyName {
+    const fn special(self, special: SpecialKey) -> KeyName {
+        KeyName {
+            special: Some(special),
+            ..self
+        }
+    }
+}
+
+/// Every key that can be named, for key combinations and `SpecialKey`s
+/// alike. Single characters are not listed; they name themselves.
+const KEY_NAMES: &[KeyName] = &[
+    named("Control", &["ctrl", "ctl", "strg"], Key::Control),
+    named("Alt", &["option", "opt"], Key::Alt),
+    named(
+        "AltGr",
+        &["altgraph", "ralt", "rightalt", "roption", "rightoption"],
+        ALT_GR,
+    ),
+    named("Shift", &["shft", "umschalt", "maj"], Key::Shift),
+    named(
+        "Meta",
+        &["super", "win", "windows", "cmd", "command", "gui"],
+        Key::Meta,
+    ),
+    named(
+        "CapsLock",
+        &["caps", "capital", "feststell", "verrmaj"],
+        Key::CapsLock,
+    ),
+    named("Escape", &["esc"], Key::Escape).special(SpecialKey::Escape),
+    named("Tab", &["tabulator"], Key::Tab).special(SpecialKey::Tab),
+    named("Backspace", &["bksp", "rücktaste"], Key::Backspace).special(SpecialKey::Backspace),
+    named(
+        "Return",
+        &["enter", "eingabe", "entrée", "intro"],
+        Key::Return,
+    )
+    .special(SpecialKey::Return),
+    named(
+        "Space",
+        &["spacebar", "leertaste", "espace", "espacio"],
+        Key::Space,
+    )
+    .special(SpecialKey::Space),
+    named("Delete", &["del", "entf", "suppr", "supr"], Key::Delete).special(SpecialKey::Delete),
+    named("Home", &["pos1", "inicio"], Key::Home).special(SpecialKey::Home),
+    named("End", &["ende", "fin"], Key::End).special(SpecialKey::End),
+    named("PageUp", &["pgup", "prior", "bildauf"], Key::PageUp).special(SpecialKey::PageUp),
+    named("PageDown", &["pgdn", "pgdown", "bildab"], Key::PageDown).special(SpecialKey::PageDown),
+    named("UpArrow", &["up", "arrowup"], Key::UpArrow).special(SpecialKey::UpArrow),
+    named("DownArrow", &["down", "arrowdown"], Key::DownArrow).special(SpecialKey::DownArrow),
+    named("LeftArrow", &["left", "arrowleft"], Key::LeftArrow).special(SpecialKey::LeftArrow),
+    named("RightArrow", &["right", "arrowright"], Key::RightArrow).special(SpecialKey::RightArrow),
+    named("F1", &[], Key::F1),
+    named("F2", &[], Key::F2),
+    named("F3", &[], Key::F3),
+    named("F4", &[], Key::F4),
+    named("F5", &[], Key::F5).special(SpecialKey::F5),
+    named("F6", &[], Key::F6),
+    named("F7", &[], Key::F7),
+    named("F8", &[], Key::F8),
+    named("F9", &[], Key::F9),
+    named("F10", &[], Key::F10),
+    named("F11", &[], Key::F11),
+    named("F12", &[], Key::F12),
+    windows("PrintScreen", &["prtsc", "prtscn", "print", "druck"], 0x2C),
+    windows("BrowserBack", &[], 0xA6).special(SpecialKey::BrowserBack),
+    windows("BrowserForward", &[], 0xA7).special(SpecialKey::BrowserForward),
+    windows("BrowserRefresh", &[], 0xA8).special(SpecialKey::BrowserRefresh),
+    windows("BrowserHome", &[], 0xAC).special(SpecialKey::BrowserHome),
+    windows("LaunchMail", &["mail"], 0xB4).special(SpecialKey::LaunchMail),
+    windows("LaunchMediaSelect", &["mediaselect"], 0xB5).special(SpecialKey::LaunchMediaSelect),
+];
+
+/// Lowercases a name and drops the separators people put in it.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Finds a key by any of its names, suggesting the closest one for a typo.
+fn lookup(name: &str) -> Result<&'static KeyName, String> {
+    let wanted = normalize(name);
+    if wanted.is_empty() {
+        return Err("empty key name".to_string());
+    }
+    let spellings = || {
+        KEY_NAMES.iter().flat_map(|entry| {
+            [entry.name]
+                .into_iter()
+                .chain(entry.aliases.iter().copied())
+                .map(move |spelling| (entry, normalize(spelling)))
+        })
+    };
+    if let Some((entry, _)) = spellings().find(|(_, spelling)| *spelling == wanted) {
+        return Ok(entry);
+    }
+    let closest = spellings()
+        .map(|(entry, spelling)| (edit_distance(&wanted, &spelling), entry))
+        .min_by_key(|(distance, _)| *distance)
+        .filter(|(distance, _)| *distance <= (wanted.chars().count() / 3).max(2));
+    Err(match closest {
+        Some((_, entry)) => format!("unknown key {:?}, did you mean {:?}?", name, entry.name),
+        None => format!("unknown key {:?}", name),
+    })
+}
+
+/// Levenshtein distance, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Media and volume keys.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MediaKey {
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+impl MediaKey {
+    /// Media keys are sent by virtual-key code on Windows; other platforms
+    /// report them as unsupported.
+    #[cfg(windows)]
+    pub fn to_enigo(self) -> Option<Key> {
+        let vk = match self {
+            MediaKey::PlayPause => 0xB3,
+            MediaKey::Stop => 0xB2,
+            MediaKey::Next => 0xB0,
+            MediaKey::Previous => 0xB1,
+            MediaKey::VolumeUp => 0xAF,
+            MediaKey::VolumeDown => 0xAE,
+            MediaKey::Mute => 0xAD,
+        };
+        Some(Key::Other(vk))
+    }
+
+    #[cfg(not(windows))]
+    pub fn to_enigo(self) -> Option<Key> {
+        None
+    }
+
+    pub fn is_supported() -> bool {
+        cfg!(windows)
+    }
+}
+
+/// A key pressed while holding modifiers, written as e.g. "Meta+Shift+s".
+/// Shown with every key under its canonical name, however it was written.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct KeyCombo {
+    modifiers: Vec<Key>,
+    key: Key,
+    name: String,
+}
+
+impl KeyCombo {
+    pub fn press(&self, input: &mut Input) {
+        for modifier in &self.modifiers {
+            input.key_down(*modifier);
+        }
+        input.key_click(self.key);
+        for modifier in self.modifiers.iter().rev() {
+            input.key_up(*modifier);
+        }
+    }
+
+    /// Holds every key of the combination down while `f` runs.
+    pub fn hold(&self, input: &mut Input, f: impl FnOnce(&mut Input)) {
+        for key in self.modifiers.iter().chain([&self.key]) {
+            input.key_down(*key);
+        }
+        f(input);
+        for key in self.modifiers.iter().chain([&self.key]).rev() {
+            input.key_up(*key);
+        }
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut keys, names): (Vec<Key>, Vec<String>) = s
+            .split('+')
+            .map(|name| key_from_name(name.trim()).map_err(|e| format!("{:?}: {}", s, e)))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        let key = keys
+            .pop()
+            .ok_or_else(|| format!("empty key combination: {:?}", s))?;
+        Ok(KeyCombo {
+            modifiers: keys,
+            key,
+            name: names.join("+"),
+        })
+    }
+}
+
+impl TryFrom<String> for KeyCombo {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The key a name stands for, along with its canonical name.
+fn key_from_name(name: &str) -> Result<(Key, String), String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        let c = c.to_ascii_lowercase();
+        return Ok((Key::Unicode(c), c.to_string()));
+    }
+    let entry = lookup(name)?;
+    let key = entry
+        .sends
+        .to_enigo()
+        .ok_or_else(|| format!("{} cannot be pressed on this platform", entry.name))?;
+    Ok((key, entry.name.to_string()))
+}
+
+/// `text` with every character found in `substitutions` replaced, see
+/// `Config::char_substitutions`.
+pub fn substitute<'a>(text: &'a str, substitutions: &BTreeMap<char, String>) -> Cow<'a, str> {
+    if !text.chars().any(|c| substitutions.contains_key(&c)) {
+        return Cow::Borrowed(text);
+    }
+    let mut substituted = String::with_capacity(text.len());
+    for c in text.chars() {
+        match substitutions.get(&c) {
+            Some(replacement) => substituted.push_str(replacement),
+            None => substituted.push(c),
+        }
+    }
+    Cow::Owned(substituted)
+}
+
+/// The characters of `text` that `substitute` replaces, each once, so that
+/// the mappings used can be logged without the text itself.
+pub fn mapped_chars(text: &str, substitutions: &BTreeMap<char, String>) -> BTreeSet<char> {
+    text.chars()
+        .filter(|c| substitutions.contains_key(c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_finds_its_own_entry() {
+        for entry in KEY_NAMES {
+            for spelling in [entry.name].iter().chain(entry.aliases) {
+                assert_eq!(lookup(spelling).unwrap().name, entry.name);
+                let shouted = spelling.to_uppercase().replace("ARROW", "_ARROW");
+                assert_eq!(lookup(&shouted).unwrap().name, entry.name);
+            }
+        }
+    }
+
+    #[test]
+    fn no_spelling_is_taken_twice() {
+        let mut seen = std::collections::HashMap::new();
+        for entry in KEY_NAMES {
+            for spelling in [entry.name].iter().chain(entry.aliases) {
+                if let Some(other) = seen.insert(normalize(spelling), entry.name) {
+                    panic!("{} is both {} and {}", spelling, other, entry.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn special_keys_round_trip() {
+        for entry in KEY_NAMES {
+            let Some(special) = entry.special else {
+                continue;
+            };
+            assert_eq!(special.entry().name, entry.name);
+            assert_eq!(SpecialKey::try_from(entry.name.to_string()), Ok(special));
+            let json = serde_json::to_string(&special).unwrap();
+            assert_eq!(serde_json::from_str::<SpecialKey>(&json).unwrap(), special);
+        }
+    }
+
+    #[test]
+    fn combinations_show_canonical_names() {
+        for entry in KEY_NAMES {
+            let written = format!("strg+{}", entry.aliases.first().unwrap_or(&entry.name));
+            match written.parse::<KeyCombo>() {
+                Ok(combo) => assert_eq!(combo.to_string(), format!("Control+{}", entry.name)),
+                Err(e) => assert!(entry.sends.to_enigo().is_none(), "{}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn alt_gr() {
+        let combo: KeyCombo = "Alt Gr+e".parse().unwrap();
+        assert_eq!(combo.to_string(), "AltGr+e");
+        assert_eq!(combo.modifiers, [ALT_GR]);
+        assert_eq!(lookup("AltGraph").unwrap().name, "AltGr");
+        assert_ne!(ALT_GR, Key::Alt);
+    }
+
+    fn substitutions() -> BTreeMap<char, String> {
+        [
+            ('“', "\""),
+            ('”', "\""),
+            ('’', "'"),
+            ('…', "..."),
+            ('\u{ad}', ""),
+        ]
+        .into_iter()
+        .map(|(c, replacement)| (c, replacement.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn substitute_rewrites_mapped_characters() {
+        let substitutions = substitutions();
+        assert_eq!(substitute("“Don’t”…", &substitutions), "\"Don't\"...");
+        // An empty replacement drops the character.
+        assert_eq!(substitute("co\u{ad}op", &substitutions), "coop");
+        assert_eq!(
+            mapped_chars("“Don’t” “go”", &substitutions),
+            BTreeSet::from(['“', '”', '’'])
+        );
+    }
+
+    #[test]
+    fn substitute_leaves_other_text_alone() {
+        let substitutions = substitutions();
+        assert!(matches!(
+            substitute("plain \"quotes\"", &substitutions),
+            Cow::Borrowed("plain \"quotes\"")
+        ));
+        assert!(matches!(
+            substitute("“", &BTreeMap::new()),
+            Cow::Borrowed("“")
+        ));
+        assert!(mapped_chars("plain", &substitutions).is_empty());
+    }
+
+    #[test]
+    fn typos_get_a_suggestion() {
+        assert_eq!(
+            lookup("Escpe").err().unwrap(),
+            "unknown key \"Escpe\", did you mean \"Escape\"?"
+        );
+        assert_eq!(lookup("").err().unwrap(), "empty key name");
+    }
+}
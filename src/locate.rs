@@ -0,0 +1,68 @@
+use crate::input::Input;
+use crate::server_info::DisplaySize;
+use std::f64::consts::TAU;
+use std::time::{Duration, Instant};
+
+/// How long the pointer is shaken for, and how far from its start it gets.
+const SHAKE_DURATION_MS: u64 = 400;
+const SHAKE_AMPLITUDE_PX: f64 = 60.0;
+/// Moves making up the shake, and the turns of the spiral they trace.
+const SHAKE_STEPS: u32 = 24;
+const SHAKE_TURNS: f64 = 3.0;
+
+/// The pointer wiggled in an expanding spiral so that it catches the eye,
+/// one step per deadline of the mouse event thread, ending where it started.
+pub struct Shake {
+    steps_taken: u32,
+    /// Where the pointer is, relative to where the shake started.
+    offset: (i32, i32),
+    pub deadline: Instant,
+}
+
+impl Shake {
+    /// Starts shaking straight away.
+    pub fn new() -> Self {
+        Shake {
+            steps_taken: 0,
+            offset: (0, 0),
+            deadline: Instant::now(),
+        }
+    }
+
+    /// Moves the pointer on along the spiral, or back to the start after the
+    /// last step. Returns whether any steps are left.
+    pub fn step(&mut self, input: &mut Input) -> bool {
+        self.steps_taken += 1;
+        let target = if self.steps_taken < SHAKE_STEPS {
+            spiral(self.steps_taken)
+        } else {
+            (0, 0)
+        };
+        input.mouse_move_relative(target.0 - self.offset.0, target.1 - self.offset.1);
+        self.offset = target;
+        self.deadline = Instant::now() + Duration::from_millis(SHAKE_DURATION_MS) / SHAKE_STEPS;
+        self.steps_taken < SHAKE_STEPS
+    }
+}
+
+/// Point `step` of the spiral, relative to its centre.
+fn spiral(step: u32) -> (i32, i32) {
+    let progress = f64::from(step) / f64::from(SHAKE_STEPS);
+    let radius = SHAKE_AMPLITUDE_PX * progress;
+    let angle = TAU * SHAKE_TURNS * progress;
+    (
+        (radius * angle.cos()).round() as i32,
+        (radius * angle.sin()).round() as i32,
+    )
+}
+
+/// Index of the display `position` is on. Only the primary display's size
+/// is known, so a position anywhere else has no index.
+pub fn display_index(position: (i32, i32), primary: Option<DisplaySize>) -> Option<usize> {
+    let primary = primary?;
+    let (x, y) = (
+        usize::try_from(position.0).ok()?,
+        usize::try_from(position.1).ok()?,
+    );
+    (x < primary.width && y < primary.height).then_some(0)
+}
@@ -0,0 +1,220 @@
+use enigo::Key;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The lock keys whose state clients can ask about and toggle.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LockKey {
+    Caps,
+    Num,
+    Scroll,
+}
+
+impl LockKey {
+    /// Caps Lock can be pressed everywhere; the others only on Windows, by
+    /// virtual-key code.
+    pub fn to_enigo(self) -> Option<Key> {
+        match self {
+            LockKey::Caps => Some(Key::CapsLock),
+            #[cfg(windows)]
+            LockKey::Num => Some(Key::Other(0x90)),
+            #[cfg(windows)]
+            LockKey::Scroll => Some(Key::Other(0x91)),
+            #[cfg(not(windows))]
+            LockKey::Num | LockKey::Scroll => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LockState {
+    On,
+    Off,
+    /// The platform offers no way to tell, or the key has not settled.
+    Unknown,
+}
+
+impl From<bool> for LockState {
+    fn from(on: bool) -> Self {
+        if on {
+            LockState::On
+        } else {
+            LockState::Off
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LockStates {
+    pub caps_lock: LockState,
+    pub num_lock: LockState,
+    pub scroll_lock: LockState,
+}
+
+impl LockStates {
+    fn get_mut(&mut self, which: LockKey) -> &mut LockState {
+        match which {
+            LockKey::Caps => &mut self.caps_lock,
+            LockKey::Num => &mut self.num_lock,
+            LockKey::Scroll => &mut self.scroll_lock,
+        }
+    }
+}
+
+/// How long a pressed lock key gets to show its new state, and how often
+/// the state is read meanwhile.
+const SETTLE_TIMEOUT: Duration = Duration::from_millis(250);
+const SETTLE_POLL: Duration = Duration::from_millis(25);
+
+/// The states after `which` was pressed, given `before` it was. The OS
+/// applies an injected key press some time later, so the states are read
+/// until `which` has flipped. If it has not by `SETTLE_TIMEOUT`, it is
+/// reported as unknown rather than as it was.
+pub fn after_toggle(which: LockKey, before: LockStates) -> LockStates {
+    settle(which, before, query, SETTLE_POLL, SETTLE_TIMEOUT)
+}
+
+fn settle(
+    which: LockKey,
+    mut before: LockStates,
+    mut query: impl FnMut() -> LockStates,
+    poll: Duration,
+    timeout: Duration,
+) -> LockStates {
+    let was = *before.get_mut(which);
+    if was == LockState::Unknown {
+        return query();
+    }
+    let deadline = Instant::now() + timeout;
+    loop {
+        thread::sleep(poll);
+        let mut states = query();
+        if *states.get_mut(which) != was {
+            return states;
+        }
+        if Instant::now() >= deadline {
+            *states.get_mut(which) = LockState::Unknown;
+            return states;
+        }
+    }
+}
+
+/// Whether `query` is cheap enough to poll for changes.
+pub const CAN_WATCH: bool = cfg!(any(windows, target_os = "macos"));
+
+#[cfg(windows)]
+pub fn query() -> LockStates {
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetKeyState(virtual_key: i32) -> i16;
+    }
+    // The low bit of the state is set while the key is toggled on.
+    let toggled = |vk| LockState::from(unsafe { GetKeyState(vk) } & 1 != 0);
+    LockStates {
+        caps_lock: toggled(0x14),
+        num_lock: toggled(0x90),
+        scroll_lock: toggled(0x91),
+    }
+}
+
+/// Macs have neither Num Lock nor Scroll Lock.
+#[cfg(target_os = "macos")]
+pub fn query() -> LockStates {
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceFlagsState(state_id: i32) -> u64;
+    }
+    const COMBINED_SESSION_STATE: i32 = 0;
+    const ALPHA_SHIFT_MASK: u64 = 0x0001_0000;
+    let flags = unsafe { CGEventSourceFlagsState(COMBINED_SESSION_STATE) };
+    LockStates {
+        caps_lock: LockState::from(flags & ALPHA_SHIFT_MASK != 0),
+        num_lock: LockState::Unknown,
+        scroll_lock: LockState::Unknown,
+    }
+}
+
+/// Reads the XKB indicators through `xset`, which ships with every X11
+/// desktop, rather than linking Xlib for one call.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn query() -> LockStates {
+    let output = std::process::Command::new("xset")
+        .arg("q")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    LockStates {
+        caps_lock: indicator(&output, "Caps Lock:"),
+        num_lock: indicator(&output, "Num Lock:"),
+        scroll_lock: indicator(&output, "Scroll Lock:"),
+    }
+}
+
+/// Finds e.g. "Caps Lock:   off" in `xset q` output.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn indicator(output: &str, name: &str) -> LockState {
+    let value = output
+        .find(name)
+        .and_then(|start| output[start + name.len()..].split_whitespace().next());
+    match value {
+        Some("on") => LockState::On,
+        Some("off") => LockState::Off,
+        _ => LockState::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(caps_lock: LockState) -> LockStates {
+        LockStates {
+            caps_lock,
+            num_lock: LockState::Off,
+            scroll_lock: LockState::Unknown,
+        }
+    }
+
+    fn settle_with(before: LockStates, answers: &[LockStates]) -> (LockStates, usize) {
+        let mut polls = 0;
+        let settled = settle(
+            LockKey::Caps,
+            before,
+            || {
+                polls += 1;
+                answers[(polls - 1).min(answers.len() - 1)]
+            },
+            Duration::ZERO,
+            Duration::from_millis(20),
+        );
+        (settled, polls)
+    }
+
+    #[test]
+    fn waits_for_the_key_to_flip() {
+        let off = states(LockState::Off);
+        let on = states(LockState::On);
+        let (settled, polls) = settle_with(off, &[off, off, on]);
+        assert_eq!(settled, on);
+        assert_eq!(polls, 3);
+    }
+
+    #[test]
+    fn unknown_when_the_key_never_flips() {
+        let off = states(LockState::Off);
+        let (settled, _) = settle_with(off, &[off]);
+        assert_eq!(settled, states(LockState::Unknown));
+    }
+
+    #[test]
+    fn does_not_wait_when_the_state_cannot_be_read() {
+        let unknown = states(LockState::Unknown);
+        let (settled, polls) = settle_with(unknown, &[unknown]);
+        assert_eq!(settled, unknown);
+        assert_eq!(polls, 1);
+    }
+}
@@ -1,14 +1,106 @@
-use enigo::*;
-use futures_util::stream::StreamExt;
-use serde::Deserialize;
+mod actions;
+mod admin;
+mod assets;
+mod audit;
+mod benchmark;
+mod calibration;
+mod capabilities;
+mod clipboard;
+mod config;
+mod connection;
+mod contact;
+mod cors;
+mod drift;
+mod dwell;
+mod error;
+mod event_log;
+mod fence;
+mod gestures;
+mod i18n;
+mod input;
+mod instance;
+mod key_hold;
+mod keys;
+mod locate;
+mod locks;
+mod media;
+mod momentum;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+mod mpris;
+mod pairing;
+mod panels;
+mod probe_history;
+mod profiles;
+mod scroll;
+mod self_test;
+mod sequence;
+mod server;
+mod server_info;
+mod signing;
+mod snap;
+mod transforms;
+mod version;
+mod wake;
+#[cfg(target_os = "linux")]
+mod ydotool;
+
+use admin::{Admin, AdminSecret, Permission};
+use audit::{AuditEvent, AuditLog};
+use capabilities::Capabilities;
+use clipboard::Paste;
+use config::{ClickOn, Config, PanInput, ScrollAxes, ScrollMode};
+use connection::{
+    event_channel, explain, trace_gesture, Connection, ConnectionStats, Connections, Drawing,
+    Envelope, EventReceiver, EventSender, FeedbackKind,
+};
+use contact::PalmReason;
+use drift::DriftCorrection;
+use dwell::{Dwell, DwellAction, DwellStep};
+use enigo::{Button, Key};
+use error::Error;
+use event_log::{Logged, Outcome};
+use fence::{FenceArea, PointerFence, Rect};
+use futures_util::stream::{SplitSink, StreamExt};
+use futures_util::SinkExt;
+use gestures::{
+    finger_gesture_action, DoubleTap, EdgeStep, EdgeTracker, FingerGesture, Gesture,
+    HeldButtonTimeout, Position, SwipeTracker, TapDrag,
+};
+use input::{BackendKind, Input};
+use instance::InstanceMode;
+use key_hold::KeyHolds;
+use keys::{HoldKey, MediaKey, SpecialKey};
+use locate::Shake;
+use locks::{LockKey, LockStates};
+use media::{MediaAction, MediaController, NowPlaying};
+use momentum::Coast;
+use pairing::Pairing;
+use panels::{ButtonAction, PanelButton};
+use profiles::{ProfileUpdate, Profiles};
+use scroll::ScrollAccumulator;
+use sequence::{PrivateText, Repeat, Sequence};
+use serde::{Deserialize, Serialize};
+use server::AppState;
+use server_info::ServerInfo;
+use signing::{EventSigner, SigningOffer};
+use snap::{Axis, AxisSnap, ScrollLock, SCROLL_LOCK_DISTANCE_PX};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error as _;
+use std::net::Ipv4Addr;
+use std::process;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::Arc;
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
-use warp::Filter;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Notify;
+use tokio::time::Interval;
+use version::{LatestRelease, BUILD};
+use warp::ws::{Message, WebSocket};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 enum ClientEvent {
     MouseMove {
@@ -17,168 +109,4990 @@ enum ClientEvent {
         sx: f64,
         sy: f64,
         touches: i32,
+        /// Touch force from 0 to 1, from clients that report it.
+        #[serde(default)]
+        pressure: Option<f64>,
+        /// Contact radius in CSS pixels, from clients that report it.
+        #[serde(default)]
+        radius: Option<f64>,
+        /// Where the finger is on the touchpad and the touchpad's size, in
+        /// CSS pixels, reported only when `edge_swipe` is configured.
+        #[serde(default)]
+        x: Option<f64>,
+        #[serde(default)]
+        y: Option<f64>,
+        #[serde(default)]
+        width: Option<f64>,
+        #[serde(default)]
+        height: Option<f64>,
     },
     MouseClick {
         button: MouseButton,
     },
+    /// `repeat` marks a press of the same key as the previous one as
+    /// intended, so that `key_debounce_ms` lets it through.
     KeyPress {
         key: char,
+        #[serde(default)]
+        repeat: bool,
+    },
+    SpecialKey {
+        key: SpecialKey,
+        #[serde(default)]
+        repeat: bool,
+    },
+    MediaKey {
+        key: MediaKey,
+    },
+    /// Controls a media player, named as in its MPRIS bus name (e.g.
+    /// "spotify"), or the active one. Falls back to media keys without MPRIS.
+    Media {
+        action: MediaAction,
+        player: Option<String>,
+    },
+    TypeString {
+        text: String,
+        repeat: Option<u32>,
+    },
+    Screenshot {
+        #[serde(default)]
+        region: bool,
+    },
+    /// Holds a key down for `duration_ms`, at most `max_key_hold_ms`,
+    /// while other events go on. The key is a character or a special key's
+    /// name.
+    KeyHold {
+        key: HoldKey,
+        duration_ms: u64,
+    },
+    /// Sends the undo shortcut, see `Config::undo_combo`.
+    UndoLast,
+    /// Asks for the host's lock key states, answered with `LockState`.
+    GetLockState,
+    /// Presses a lock key, answered with the new `LockState`.
+    ToggleLock {
+        which: LockKey,
+    },
+    SetScrollMode {
+        mode: ScrollMode,
+    },
+    /// Two fingers turned by `angle_delta` degrees, clockwise when positive.
+    Rotate {
+        angle_delta: f64,
+    },
+    SetConfig {
+        #[serde(flatten)]
+        update: ProfileUpdate,
+    },
+    /// Four or five fingers tapped, swiped or pinched together.
+    FingerGesture {
+        fingers: u8,
+        gesture: FingerGesture,
+    },
+    /// Presses the button with `id` on one of the config's `panels`.
+    PanelAction {
+        id: String,
+    },
+    /// A finger touched the touchpad, reported only when `tap_drag` is configured.
+    TouchDown {
+        #[serde(default)]
+        radius: Option<f64>,
+    },
+    /// The last finger left the touchpad.
+    TouchUp,
+    /// Types `text` one character at a time, `interval_ms` apart, for
+    /// applications that drop characters typed quickly. Replaces a sequence
+    /// still being typed.
+    TypeSequence {
+        text: PrivateText,
+        #[serde(default)]
+        interval_ms: u64,
+        #[serde(default)]
+        press_enter: bool,
+    },
+    /// Stops this connection's `TypeSequence`, and the copies still to come
+    /// of a repeated `TypeString`.
+    CancelSequence,
+    /// Turns `Stats` reports on this connection on or off.
+    SetStatsReports {
+        active: bool,
+    },
+    /// Turns `Feedback` cues on this connection on or off.
+    SetFeedback {
+        active: bool,
+    },
+    /// Shakes the pointer so that it is easy to spot, and replies with a
+    /// `CursorLocation`. Any movement stops the shake.
+    LocateCursor,
+    /// Moves the pointer back to where the latest left or right click
+    /// landed, if there was one.
+    ReturnToLastClick,
+    /// Wakes the display from power saving without clicking or moving the
+    /// pointer anywhere.
+    WakeDisplay,
+    /// Keeps the pointer on the display it is on now, or lets it go
+    /// anywhere again, see `pointer_fence`. Replied to with `PointerFence`.
+    SetPointerFence {
+        active: bool,
+    },
+    /// Starts a segment of a calibration, see `/api/calibrate/start`: the
+    /// pointer input from here on is what `label` asks for.
+    CalibrationMark {
+        label: String,
+    },
+    /// The connection closed; sent by its handler, never by clients.
+    #[serde(skip_deserializing)]
+    Closed,
+    /// The client ignored a touch it took for a palm, see `palm_rejection`.
+    PalmRejected {
+        reason: PalmReason,
+    },
+    /// While active, moves this connection's pointer at a fixed low
+    /// sensitivity, for nudging it by single pixels.
+    SetPrecisionHold {
+        active: bool,
+    },
+    /// While active, touching presses the left button and one finger draws,
+    /// see [`Drawing`].
+    SetDrawingMode {
+        active: bool,
+        #[serde(default)]
+        absolute: bool,
+    },
+    /// While active, two-finger drags hold `pan_input` down and move the
+    /// pointer instead of scrolling, for maps and canvases. Takes effect
+    /// from the next two-finger gesture.
+    SetPanMode {
+        active: bool,
+    },
+    /// While active, resting this connection's pointer for `dwell_click`'s
+    /// `dwell_ms` clicks, with a `Feedback` cue before each click.
+    SetDwellClick {
+        active: bool,
+    },
+    /// Turns explain mode on or off for `connection`, or this connection
+    /// when left out, as `/api/clients/<id>/trace` does. Only for connections
+    /// opened with the admin secret.
+    SetExplain {
+        active: bool,
+        #[serde(default)]
+        connection: Option<u64>,
+    },
+    /// Another event, as JSON, signed for `signed_events`. `seq` must grow
+    /// with every signed event of the connection.
+    Signed {
+        seq: u64,
+        event: String,
+        sig: String,
     },
 }
 
-#[derive(Deserialize, Debug)]
+/// Events that must be signed when `signed_events` is on: everything that
+/// clicks, types or runs a shortcut. Moves and scrolls are left unsigned,
+/// as they are sent many times a second.
+const SIGNED_EVENTS: &[&str] = &[
+    "MouseClick",
+    "KeyPress",
+    "SpecialKey",
+    "KeyHold",
+    "MediaKey",
+    "Media",
+    "TypeString",
+    "TypeSequence",
+    "Screenshot",
+    "UndoLast",
+    "ToggleLock",
+    "FingerGesture",
+    "PanelAction",
+];
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
 enum MouseButton {
     Left,
     Right,
 }
 
-fn current_time_millis() -> u128 {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => duration.as_millis(),
-        Err(_) => 0,
+impl MouseButton {
+    fn to_enigo(self) -> Button {
+        match self {
+            MouseButton::Left => Button::Left,
+            MouseButton::Right => Button::Right,
+        }
     }
-}
 
-fn should_process_scroll_message(last_processed_time: &Arc<AtomicU64>, time_interval: u64) -> bool {
-    let now = current_time_millis() as u64;
-    let last_time = last_processed_time.load(Ordering::Relaxed);
-    let elapsed = now - last_time;
-    if elapsed >= time_interval {
-        last_processed_time.store(now, Ordering::Relaxed);
-        true
-    } else {
-        false
+    fn click_feedback(self) -> FeedbackKind {
+        match self {
+            MouseButton::Left => FeedbackKind::Click,
+            MouseButton::Right => FeedbackKind::RightClick,
+        }
     }
 }
 
-fn process_mouse_events(
-    receiver: mpsc::Receiver<ClientEvent>,
-    last_processed_time: Arc<AtomicU64>,
-) {
-    let mut enigo = Enigo::new();
+impl ClientEvent {
+    /// Whether the event means a finger is on the touchpad.
+    fn is_touch(&self) -> bool {
+        matches!(
+            self,
+            ClientEvent::MouseMove { .. }
+                | ClientEvent::MouseClick { .. }
+                | ClientEvent::TouchDown { .. }
+        )
+    }
 
-    while let Ok(event) = receiver.recv() {
-        match event {
+    /// The `type` tag of every event clients can send, in the order they
+    /// are declared.
+    const KINDS: &'static [&'static str] = &[
+        "MouseMove",
+        "MouseClick",
+        "KeyPress",
+        "SpecialKey",
+        "MediaKey",
+        "Media",
+        "TypeString",
+        "Screenshot",
+        "KeyHold",
+        "UndoLast",
+        "GetLockState",
+        "ToggleLock",
+        "SetScrollMode",
+        "Rotate",
+        "SetConfig",
+        "FingerGesture",
+        "PanelAction",
+        "TouchDown",
+        "TouchUp",
+        "TypeSequence",
+        "CancelSequence",
+        "SetStatsReports",
+        "SetFeedback",
+        "LocateCursor",
+        "ReturnToLastClick",
+        "WakeDisplay",
+        "SetPointerFence",
+        "CalibrationMark",
+        "PalmRejected",
+        "SetPrecisionHold",
+        "SetDrawingMode",
+        "SetPanMode",
+        "SetDwellClick",
+        "SetExplain",
+        "Signed",
+    ];
+
+    /// The `type` tag the event is sent with.
+    fn kind(&self) -> &'static str {
+        match self {
+            ClientEvent::MouseMove { .. } => "MouseMove",
+            ClientEvent::MouseClick { .. } => "MouseClick",
+            ClientEvent::KeyPress { .. } => "KeyPress",
+            ClientEvent::SpecialKey { .. } => "SpecialKey",
+            ClientEvent::KeyHold { .. } => "KeyHold",
+            ClientEvent::MediaKey { .. } => "MediaKey",
+            ClientEvent::Media { .. } => "Media",
+            ClientEvent::TypeString { .. } => "TypeString",
+            ClientEvent::Screenshot { .. } => "Screenshot",
+            ClientEvent::UndoLast => "UndoLast",
+            ClientEvent::GetLockState => "GetLockState",
+            ClientEvent::ToggleLock { .. } => "ToggleLock",
+            ClientEvent::SetScrollMode { .. } => "SetScrollMode",
+            ClientEvent::Rotate { .. } => "Rotate",
+            ClientEvent::FingerGesture { .. } => "FingerGesture",
+            ClientEvent::PanelAction { .. } => "PanelAction",
+            ClientEvent::SetConfig { .. } => "SetConfig",
+            ClientEvent::SetPrecisionHold { .. } => "SetPrecisionHold",
+            ClientEvent::SetDrawingMode { .. } => "SetDrawingMode",
+            ClientEvent::SetPanMode { .. } => "SetPanMode",
+            ClientEvent::SetDwellClick { .. } => "SetDwellClick",
+            ClientEvent::SetExplain { .. } => "SetExplain",
+            ClientEvent::TouchDown { .. } => "TouchDown",
+            ClientEvent::TouchUp => "TouchUp",
+            ClientEvent::TypeSequence { .. } => "TypeSequence",
+            ClientEvent::CancelSequence => "CancelSequence",
+            ClientEvent::SetStatsReports { .. } => "SetStatsReports",
+            ClientEvent::SetFeedback { .. } => "SetFeedback",
+            ClientEvent::LocateCursor => "LocateCursor",
+            ClientEvent::ReturnToLastClick => "ReturnToLastClick",
+            ClientEvent::WakeDisplay => "WakeDisplay",
+            ClientEvent::SetPointerFence { .. } => "SetPointerFence",
+            ClientEvent::CalibrationMark { .. } => "CalibrationMark",
+            ClientEvent::Closed => "Closed",
+            ClientEvent::PalmRejected { .. } => "PalmRejected",
+            ClientEvent::Signed { .. } => "Signed",
+        }
+    }
+
+    /// The event as logged, in the event log and in explain mode, leaving
+    /// out which keys were pressed and what was typed.
+    fn describe(&self) -> String {
+        match self {
+            ClientEvent::KeyPress { repeat, .. } | ClientEvent::SpecialKey { repeat, .. } => {
+                format!("{} {{ key: <redacted>, repeat: {} }}", self.kind(), repeat)
+            }
+            ClientEvent::TypeString { text, repeat } => format!(
+                "TypeString {{ text: {:?}, repeat: {:?} }}",
+                PrivateText(text.clone()),
+                repeat
+            ),
+            ClientEvent::Signed { seq, .. } => {
+                format!("Signed {{ seq: {}, event: <redacted> }}", seq)
+            }
+            event => format!("{:?}", event),
+        }
+    }
+
+    /// Whether the event has to be signed when `signed_events` is on.
+    fn is_sensitive(&self) -> bool {
+        SIGNED_EVENTS.contains(&self.kind())
+    }
+
+    /// The gesture the event reports as a whole, if it can be switched off.
+    /// Two-finger moves are left out, since they may scroll or swipe.
+    fn gesture(&self) -> Option<Gesture> {
+        match self {
+            ClientEvent::MouseClick {
+                button: MouseButton::Left,
+            } => Some(Gesture::Tap),
+            ClientEvent::MouseClick {
+                button: MouseButton::Right,
+            } => Some(Gesture::LongPress),
+            ClientEvent::Rotate { .. } => Some(Gesture::Rotate),
+            ClientEvent::FingerGesture { fingers: 2, .. } => Some(Gesture::DoubleTapZoom),
+            ClientEvent::FingerGesture { .. } => Some(Gesture::FingerGestures),
+            _ => None,
+        }
+    }
+
+    /// Whether the event only types or presses keys, which does not depend
+    /// on where the pointer is, see `key_priority`.
+    fn is_key(&self) -> bool {
+        matches!(
+            self,
+            ClientEvent::KeyPress { .. }
+                | ClientEvent::SpecialKey { .. }
+                | ClientEvent::KeyHold { .. }
+                | ClientEvent::MediaKey { .. }
+                | ClientEvent::TypeString { .. }
+                | ClientEvent::TypeSequence { .. }
+        )
+    }
+
+    /// The key a key press sends, and whether the client marked it as an
+    /// intended repeat.
+    fn pressed_key(&self) -> Option<(PressedKey, bool)> {
+        match *self {
+            ClientEvent::KeyPress { key, repeat } => Some((PressedKey::Char(key), repeat)),
+            ClientEvent::SpecialKey { key, repeat } => Some((PressedKey::Special(key), repeat)),
+            _ => None,
+        }
+    }
+
+    /// Whether a touch carries NaN or an infinite value, which a buggy
+    /// client could send and which would throw the pointer math off.
+    fn has_non_finite(&self) -> bool {
+        match *self {
             ClientEvent::MouseMove {
                 dx,
                 dy,
                 sx,
                 sy,
-                touches,
-            } => {
-                let mut dx = dx;
-                let mut dy = dy;
+                pressure,
+                radius,
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => [dx, dy, sx, sy]
+                .into_iter()
+                .chain(
+                    [pressure, radius, x, y, width, height]
+                        .into_iter()
+                        .flatten(),
+                )
+                .any(|value| !value.is_finite()),
+            ClientEvent::TouchDown { radius } => radius.is_some_and(|value| !value.is_finite()),
+            _ => false,
+        }
+    }
+}
 
-                if touches == 2 {
-                    let scroll_factor = 10;
-                    let dy_int = dy.round() as i32;
-                    let mut scroll_lines = dy_int / scroll_factor;
+#[derive(Serialize, Debug)]
+#[serde(tag = "type")]
+enum ServerEvent {
+    /// Sent first on every connection.
+    Hello {
+        #[serde(flatten)]
+        info: Box<ServerInfo>,
+        /// The locale the server's messages to this connection are in.
+        locale: &'static str,
+        /// Present when the connection's sensitive events must be signed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signing: Option<SigningOffer>,
+        /// `admin` when the connection was opened with the admin secret.
+        permission: Permission,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+    QueueDepth {
+        depth: usize,
+    },
+    NowPlaying(NowPlaying),
+    /// A button held by a gesture was released by `held_button_timeout`.
+    ButtonReleased {
+        button: MouseButton,
+        held_ms: u64,
+    },
+    /// How far a `TypeSequence` got, every few characters and when done.
+    SequenceProgress {
+        typed: usize,
+        total: usize,
+    },
+    /// The host's lock keys, on request and, where cheap to watch, whenever
+    /// they change.
+    LockState(LockStates),
+    /// What became of the connection's messages so far, every few seconds
+    /// once asked for with `SetStatsReports`.
+    Stats(ConnectionStats),
+    /// Where the pointer is, in reply to `LocateCursor`. The position and
+    /// the index of the display it is on are left out where unknown.
+    CursorLocation {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        x: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        y: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        display: Option<usize>,
+    },
+    /// The rectangle the pointer is kept in, in reply to `SetPointerFence`;
+    /// null when it may go anywhere.
+    PointerFence {
+        area: Option<Rect>,
+    },
+    /// Something the client's input did took effect, for clients that asked
+    /// with `SetFeedback`.
+    Feedback {
+        kind: FeedbackKind,
+        /// For `dwell`, when the click follows.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        click_in_ms: Option<u64>,
+    },
+}
 
-                    if dy_int != 0 && scroll_lines == 0 {
-                        scroll_lines = if dy_int > 0 { 1 } else { -1 };
-                    }
+impl ServerEvent {
+    /// Reports `error` to a client, in the client's locale.
+    fn error(error: &Error, locale: &str) -> Self {
+        ServerEvent::Error {
+            code: error.code(),
+            message: i18n::error_message(locale, error),
+        }
+    }
+}
 
-                    if scroll_lines != 0 && should_process_scroll_message(&last_processed_time, 100)
-                    {
-                        enigo.mouse_scroll_y(scroll_lines);
-                        println!("Mouse scrolled by: dy={}", scroll_lines);
-                    }
+/// Gesture state carried by the mouse event thread from one event to the next.
+#[derive(Default)]
+struct MoveState {
+    last_touches: i32,
+    last_notch_time: u64,
+    /// Rotation, in degrees, not yet turned into a rotate action.
+    rotation: f64,
+    swipe: SwipeTracker,
+    snap: AxisSnap,
+    scroll_lock: ScrollLock,
+    scroll: ScrollAccumulator,
+    /// Moves received since the finger count last changed.
+    moves_in_gesture: u32,
+    /// Speeds of the latest one-finger moves, for `acceleration_window`.
+    recent_speeds: VecDeque<(f64, f64)>,
+    /// The touch's speed estimate has been primed, see `acceleration_warm_start`.
+    warmed_up: bool,
+    /// When the latest move arrived, for `inertia_reset_ms`.
+    last_move: Option<Instant>,
+    edge: EdgeTracker,
+    drift: DriftCorrection,
+    /// The gesture's classification has been logged, see `trace_gestures`.
+    gesture_traced: bool,
+    /// Whether the two-finger gesture pans rather than scrolls, taken from
+    /// the connection's pan mode on its first move so that switching the
+    /// mode never changes a gesture under way.
+    panning: Option<bool>,
+}
 
-                    continue;
-                }
+impl MoveState {
+    /// When the carried-over speeds go stale, if there are any.
+    fn inertia_deadline(&self, reset_ms: Option<u64>) -> Option<Instant> {
+        if self.recent_speeds.is_empty() && !self.warmed_up {
+            return None;
+        }
+        Some(self.last_move? + Duration::from_millis(reset_ms?))
+    }
 
-                // Do not respond to move messages for a period of time after scrolling
-                if should_process_scroll_message(&last_processed_time, 1000) {
-                    continue;
-                }
+    fn reset_inertia(&mut self) {
+        self.recent_speeds.clear();
+        self.warmed_up = false;
+    }
+}
 
-                // Calculate the acceleration based on speed and distance
-                // and adjust the mouse movement accordingly
-                let acceleration_factor = 10.0; // Acceleration factor, adjustable according to actual requirements
-                let distance = (dx.powi(2) + dy.powi(2)).sqrt();
-                if distance > 1.0 {
-                    let acceleration = distance * acceleration_factor;
-                    dx += sx * acceleration;
-                    dy += sy * acceleration;
-                }
+/// A click held back by `click_drag_window_ms`, waiting to see whether
+/// movement follows and turns it into a drag.
+struct HeldClick {
+    connection: u64,
+    button: MouseButton,
+    /// Whether the button is down, and whether movement followed the click.
+    dragging: bool,
+    moved: bool,
+    deadline: Instant,
+}
 
-                let dx_int = dx.round() as i32;
-                let dy_int = dy.round() as i32;
+impl HeldClick {
+    /// Holds back a click; with `ClickOn::Press` the button goes down now,
+    /// as if a drag had already started.
+    fn new(
+        input: &mut Input,
+        connection: u64,
+        button: MouseButton,
+        click_on: ClickOn,
+        window: Duration,
+    ) -> Self {
+        let pressed = click_on == ClickOn::Press;
+        if pressed {
+            input.mouse_down(button.to_enigo());
+            println!("Mouse button pressed for click: {:?}", button);
+        }
+        HeldClick {
+            connection,
+            button,
+            dragging: pressed,
+            moved: false,
+            deadline: Instant::now() + window,
+        }
+    }
 
-                // Discard abnormal movement distances
-                if dx_int >= 1000 || dy_int >= 1000 {
-                    continue;
-                }
+    /// Presses the button on the first movement and pushes the release back.
+    fn drag(mut self, input: &mut Input, connections: &Connections, window: Duration) -> Self {
+        if !self.dragging {
+            input.mouse_down(self.button.to_enigo());
+            println!("Mouse button held for drag: {:?}", self.button);
+            self.dragging = true;
+        }
+        if !self.moved {
+            connections.feedback(self.connection, FeedbackKind::DragStart);
+        }
+        self.moved = true;
+        self.deadline = Instant::now() + window;
+        self
+    }
 
-                enigo.mouse_move_relative(dx_int, dy_int);
-                println!("Mouse moved by: dx={}, dy={}", dx_int, dy_int);
+    /// Ends the drag, or performs the click if no movement followed it.
+    fn release(self, input: &mut Input, connections: &Connections) {
+        let feedback = if self.moved {
+            FeedbackKind::DragEnd
+        } else {
+            self.button.click_feedback()
+        };
+        connections.feedback(self.connection, feedback);
+        if self.dragging && !self.moved {
+            input.mouse_up(self.button.to_enigo());
+            println!("Mouse button released after click: {:?}", self.button);
+        } else if self.dragging {
+            input.mouse_up(self.button.to_enigo());
+            println!("Mouse button released after drag: {:?}", self.button);
+        } else {
+            input.mouse_click(self.button.to_enigo());
+            println!("Mouse button clicked: {:?}", self.button);
+        }
+    }
+}
+
+/// The left button held by a connection in drawing mode.
+struct PenDown {
+    connection: u64,
+    since: Instant,
+    last_move: Instant,
+}
+
+impl PenDown {
+    fn press(input: &mut Input, connection: u64) -> Self {
+        input.mouse_down(Button::Left);
+        println!("Mouse button held for drawing: {:?}", MouseButton::Left);
+        let now = Instant::now();
+        PenDown {
+            connection,
+            since: now,
+            last_move: now,
+        }
+    }
+
+    fn lift(self, input: &mut Input) {
+        input.mouse_up(Button::Left);
+        println!(
+            "Mouse button released after drawing: {:?}",
+            MouseButton::Left
+        );
+    }
+}
+
+/// The button or key held by a connection's two-finger drag in pan mode.
+struct PanGrab {
+    connection: u64,
+    input: PanInput,
+    since: Instant,
+    last_move: Instant,
+}
+
+impl PanGrab {
+    fn press(input: &mut Input, connection: u64, with: PanInput) -> Self {
+        match with {
+            PanInput::Middle => input.mouse_down(Button::Middle),
+            PanInput::Space => input.key_down(Key::Space),
+        }
+        println!("Held for panning: {:?}", with);
+        let now = Instant::now();
+        PanGrab {
+            connection,
+            input: with,
+            since: now,
+            last_move: now,
+        }
+    }
+
+    fn release(self, input: &mut Input) {
+        match self.input {
+            PanInput::Middle => input.mouse_up(Button::Middle),
+            PanInput::Space => input.key_up(Key::Space),
+        }
+        println!("Released after panning: {:?}", self.input);
+    }
+}
+
+/// The pan of `connection`, holding the input for it when needed. Another
+/// connection's pan is let go first.
+fn hold_pan<'a>(
+    pan: &'a mut Option<PanGrab>,
+    input: &mut Input,
+    connection: u64,
+    with: PanInput,
+) -> &'a mut PanGrab {
+    if let Some(grab) = pan.take_if(|grab| grab.connection != connection) {
+        grab.release(input);
+    }
+    pan.get_or_insert_with(|| PanGrab::press(input, connection, with))
+}
+
+/// The pen of `connection`, pressing the button for it when needed. There is
+/// only one button, so another connection's drawing is lifted first.
+fn hold_pen<'a>(
+    pen: &'a mut Option<PenDown>,
+    input: &mut Input,
+    connection: u64,
+) -> &'a mut PenDown {
+    if let Some(down) = pen.take_if(|down| down.connection != connection) {
+        down.lift(input);
+    }
+    pen.get_or_insert_with(|| PenDown::press(input, connection))
+}
+
+/// Releases the left button after `held_button_timeout` ran out, telling the
+/// connection holding it.
+fn force_release(input: &mut Input, connections: &Connections, connection: u64, since: Instant) {
+    let held_ms = since.elapsed().as_millis() as u64;
+    input.mouse_up(Button::Left);
+    println!(
+        "Mouse button released after being held {}ms without movement: {:?}",
+        held_ms,
+        MouseButton::Left
+    );
+    if let Some(connection) = connections.get(connection) {
+        connection.feedback(FeedbackKind::DragEnd);
+        connection.notify(ServerEvent::ButtonReleased {
+            button: MouseButton::Left,
+            held_ms,
+        });
+    }
+}
+
+/// Progress of a tap-and-a-half drag, see `Config::tap_drag`.
+#[derive(Default)]
+enum TapDragState {
+    #[default]
+    Idle,
+    /// A tap whose click is held back in case the finger comes back down.
+    /// `pressed` when the button already went down with it, see `click_on`.
+    Tapped {
+        connection: u64,
+        deadline: Instant,
+        pressed: bool,
+    },
+    /// The finger came back down after a tap; held long enough, it drags.
+    Touching {
+        connection: u64,
+        deadline: Instant,
+        pressed: bool,
+    },
+    Dragging {
+        connection: u64,
+        since: Instant,
+        last_move: Instant,
+    },
+    /// The second touch lifted before `hold_ms` without moving and was sent
+    /// as a double click; the client's click for it is still to come.
+    DoubleTapped { connection: u64, deadline: Instant },
+}
+
+impl TapDragState {
+    fn deadline(&self) -> Option<Instant> {
+        match self {
+            TapDragState::Tapped { deadline, .. }
+            | TapDragState::Touching { deadline, .. }
+            | TapDragState::DoubleTapped { deadline, .. } => Some(*deadline),
+            _ => None,
+        }
+    }
+
+    /// When a drag left without movement is due to be released.
+    fn release_deadline(&self, timeout: &HeldButtonTimeout) -> Option<Instant> {
+        match self {
+            TapDragState::Dragging {
+                since, last_move, ..
+            } => timeout.deadline(*since, *last_move),
+            _ => None,
+        }
+    }
+
+    /// The deadline passed: a lone tap clicks, a held touch starts dragging.
+    fn expire(self, input: &mut Input, connections: &Connections) -> Self {
+        match self {
+            TapDragState::DoubleTapped { .. } => TapDragState::Idle,
+            TapDragState::Tapped {
+                connection,
+                pressed,
+                ..
+            } => {
+                finish_tap(input, connections, connection, pressed);
+                TapDragState::Idle
             }
-            ClientEvent::MouseClick { button } => {
-                match button {
-                    MouseButton::Left => enigo.mouse_click(enigo::MouseButton::Left),
-                    MouseButton::Right => enigo.mouse_click(enigo::MouseButton::Right),
+            TapDragState::Touching {
+                connection,
+                pressed,
+                ..
+            } => {
+                if !pressed {
+                    input.mouse_down(Button::Left);
                 }
-                println!("Mouse button clicked: {:?}", button);
+                println!("Mouse button held for tap drag: {:?}", MouseButton::Left);
+                connections.feedback(connection, FeedbackKind::DragStart);
+                let now = Instant::now();
+                TapDragState::Dragging {
+                    connection,
+                    since: now,
+                    last_move: now,
+                }
+            }
+            state => state,
+        }
+    }
+
+    /// Advances the gesture with `event`. Returns the next state and whether
+    /// the event was used up by the gesture.
+    fn handle(
+        self,
+        connection: &Connection,
+        event: &ClientEvent,
+        tap_drag: &TapDrag,
+        click_on: ClickOn,
+        input: &mut Input,
+        connections: &Connections,
+    ) -> (Self, bool) {
+        let now = Instant::now();
+        let same = |id: u64| id == connection.id;
+        match (self, event) {
+            (
+                TapDragState::Idle,
+                ClientEvent::MouseClick {
+                    button: MouseButton::Left,
+                },
+            ) => {
+                explain!(
+                    connection,
+                    "tap held for {}ms in case a drag follows",
+                    tap_drag.tap_window_ms
+                );
+                let deadline = now + Duration::from_millis(tap_drag.tap_window_ms);
+                let connection = connection.id;
+                let pressed = click_on == ClickOn::Press;
+                if pressed {
+                    input.mouse_down(Button::Left);
+                    println!("Mouse button pressed for tap: {:?}", MouseButton::Left);
+                }
+                (
+                    TapDragState::Tapped {
+                        connection,
+                        deadline,
+                        pressed,
+                    },
+                    true,
+                )
+            }
+            (
+                TapDragState::Tapped {
+                    connection: id,
+                    pressed,
+                    ..
+                },
+                ClientEvent::TouchDown { .. },
+            ) if same(id) => {
+                explain!(
+                    connection,
+                    "touch after tap, drag in {}ms",
+                    tap_drag.hold_ms
+                );
+                let deadline = now + Duration::from_millis(tap_drag.hold_ms);
+                (
+                    TapDragState::Touching {
+                        connection: id,
+                        deadline,
+                        pressed,
+                    },
+                    true,
+                )
+            }
+            (
+                state @ TapDragState::Touching { connection: id, .. },
+                ClientEvent::MouseMove { touches: 1, .. },
+            ) if same(id) => (state.expire(input, connections), false),
+            (
+                TapDragState::Dragging {
+                    connection: id,
+                    since,
+                    ..
+                },
+                ClientEvent::MouseMove { touches: 1, .. },
+            ) if same(id) => (
+                TapDragState::Dragging {
+                    connection: id,
+                    since,
+                    last_move: now,
+                },
+                false,
+            ),
+            (
+                TapDragState::Touching {
+                    connection: id,
+                    pressed,
+                    ..
+                },
+                ClientEvent::TouchUp,
+            ) if same(id) => {
+                explain!(connection, "second tap, double click");
+                finish_tap(input, connections, id, pressed);
+                input.mouse_click(Button::Left);
+                println!("Mouse button clicked: {:?}", MouseButton::Left);
+                connection.feedback(FeedbackKind::Click);
+                // The client follows up with the second tap's own click.
+                let deadline = now + Duration::from_millis(tap_drag.tap_window_ms);
+                (
+                    TapDragState::DoubleTapped {
+                        connection: id,
+                        deadline,
+                    },
+                    true,
+                )
             }
-            ClientEvent::KeyPress { key } => {
-                enigo.key_click(Key::Layout(key));
-                println!("Key pressed: {}", key);
+            (
+                TapDragState::DoubleTapped { connection: id, .. },
+                ClientEvent::MouseClick {
+                    button: MouseButton::Left,
+                },
+            ) if same(id) => (TapDragState::Idle, true),
+            (TapDragState::Dragging { connection: id, .. }, ClientEvent::TouchUp) if same(id) => {
+                input.mouse_up(Button::Left);
+                connection.feedback(FeedbackKind::DragEnd);
+                println!(
+                    "Mouse button released after tap drag: {:?}",
+                    MouseButton::Left
+                );
+                (TapDragState::Idle, true)
+            }
+            (state, ClientEvent::TouchDown { .. } | ClientEvent::TouchUp)
+                if !state.involves(connection) =>
+            {
+                (state, true)
+            }
+            (state, _) => {
+                state.settle(input, connections);
+                (TapDragState::Idle, false)
             }
         }
     }
-    println!("Mouse event thread is terminating due to the closing of the channel.");
-}
 
-async fn handle_websocket(
-    socket: warp::ws::WebSocket,
-    mouse_event_sender: mpsc::Sender<ClientEvent>,
-) {
-    let (_ws_tx, mut ws_rx) = socket.split();
+    fn involves(&self, connection: &Connection) -> bool {
+        match self {
+            TapDragState::Idle => false,
+            TapDragState::Tapped { connection: id, .. }
+            | TapDragState::Touching { connection: id, .. }
+            | TapDragState::Dragging { connection: id, .. }
+            | TapDragState::DoubleTapped { connection: id, .. } => *id == connection.id,
+        }
+    }
 
-    while let Some(message_result) = ws_rx.next().await {
-        match message_result {
-            Ok(msg) => {
-                if let Ok(text) = msg.to_str() {
-                    if let Ok(mouse_move) = serde_json::from_str::<ClientEvent>(text) {
-                        if mouse_event_sender.send(mouse_move).is_err() {
-                            eprintln!("Failed to send mouse event; terminating connection.");
-                            break;
-                        }
-                    } else {
-                        eprintln!("Failed to parse mouse movement data. {}", text);
-                    }
-                }
+    /// Finishes whatever the gesture was doing: the held tap clicks, a drag ends.
+    fn settle(self, input: &mut Input, connections: &Connections) {
+        match self {
+            TapDragState::Tapped {
+                connection,
+                pressed,
+                ..
             }
-            Err(e) => {
-                eprintln!("WebSocket receive error: {}", e);
-                break;
+            | TapDragState::Touching {
+                connection,
+                pressed,
+                ..
+            } => finish_tap(input, connections, connection, pressed),
+            TapDragState::Dragging { connection, .. } => {
+                input.mouse_up(Button::Left);
+                connections.feedback(connection, FeedbackKind::DragEnd);
+                println!(
+                    "Mouse button released after tap drag: {:?}",
+                    MouseButton::Left
+                );
             }
+            TapDragState::DoubleTapped { .. } | TapDragState::Idle => {}
         }
     }
-    println!("WebSocket connection closed.");
 }
 
-#[tokio::main]
-async fn main() {
-    let last_processed_time = Arc::new(AtomicU64::new(0));
+/// Acceleration to add along one axis, limited to `max` either way, see
+/// `max_acceleration_px`.
+fn capped(added: f64, max: Option<f64>) -> f64 {
+    max.map_or(added, |max| added.clamp(-max, max))
+}
+
+/// Clicks for a tap that did not become a drag, or lets the button that
+/// went down with it back up.
+fn finish_tap(input: &mut Input, connections: &Connections, connection: u64, pressed: bool) {
+    connections.feedback(connection, FeedbackKind::Click);
+    if pressed {
+        input.mouse_up(Button::Left);
+        println!("Mouse button released after tap: {:?}", MouseButton::Left);
+    } else {
+        input.mouse_click(Button::Left);
+        println!("Mouse button clicked: {:?}", MouseButton::Left);
+    }
+}
 
-    let (mouse_event_sender, mouse_event_receiver) = mpsc::channel::<ClientEvent>();
+/// How often connections with MPRIS check what is playing.
+const NOW_PLAYING_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
-    thread::spawn(move || {
-        process_mouse_events(mouse_event_receiver, last_processed_time);
-    });
+/// How often connections check the lock keys, where that is cheap.
+const LOCK_STATE_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-    let static_files = warp::fs::dir("public");
+/// Finger travel, in pixels, that corresponds to one wheel notch in `Notch` mode.
+const NOTCH_DISTANCE_PX: f64 = 20.0;
+const NOTCH_MIN_INTERVAL_MS: f64 = 15.0;
+const NOTCH_MAX_INTERVAL_MS: f64 = 250.0;
+/// Finger travel, in pixels, that scrolls one line in `Fast` mode.
+const FAST_SCROLL_DIVISOR: i32 = 10;
 
-    let mouse_event_sender_filter = warp::any().map(move || mouse_event_sender.clone());
-    let websocket_route = warp::path("ws")
-        .and(warp::ws())
-        .and(mouse_event_sender_filter)
-        .map(|ws: warp::ws::Ws, sender| {
-            ws.on_upgrade(move |socket| handle_websocket(socket, sender))
-        });
+/// Sends the whole lines `scroll` has gathered in `Fast` mode, no more than
+/// `max_scroll_per_event` at a time.
+fn flush_scroll(
+    input: &mut Input,
+    scroll: &mut ScrollAccumulator,
+    interval: Duration,
+    max_lines: Option<u16>,
+    last_processed_time: &AtomicU64,
+) {
+    let (lines_x, lines_y) = scroll.flush(interval, max_lines);
+    if lines_x != 0 {
+        input.mouse_scroll_x(lines_x);
+        println!("Mouse scrolled by: dx={}", lines_x);
+    }
+    if lines_y != 0 {
+        input.mouse_scroll_y(lines_y);
+        println!("Mouse scrolled by: dy={}", lines_y);
+    }
+    if lines_x != 0 || lines_y != 0 {
+        last_processed_time.store(current_time_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+fn current_time_millis() -> u128 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis(),
+        Err(_) => 0,
+    }
+}
+
+/// Pause in touch events that ends a touch waking the display, long enough
+/// to cover a finger held still for a right click.
+const WAKE_TOUCH_GAP: Duration = Duration::from_millis(1000);
+
+/// Interval assumed between touch moves when priming the speed of the first
+/// one: a frame at 60 Hz.
+const WARM_START_FRAME_MS: f64 = 16.0;
+
+/// Bounds on how far `velocity_reference_ms` rescales a move, so that a stray
+/// interval, such as a stalled client's, stays harmless.
+const MIN_TIME_SCALE: f64 = 0.25;
+const MAX_TIME_SCALE: f64 = 4.0;
+
+/// How long a move took relative to `reference_ms`, or 1 where its duration
+/// is unknown.
+fn time_scale(interval_ms: f64, reference_ms: f64) -> f64 {
+    if interval_ms.is_finite() && interval_ms > 0.0 {
+        (interval_ms / reference_ms).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+    } else {
+        1.0
+    }
+}
+
+/// Moves at the start of a touch checked for implausible jumps.
+const PALM_START_MOVES: u32 = 2;
+
+/// Counts and logs a touch taken for a palm, so that the thresholds can be
+/// tuned from the log.
+fn reject_palm(connection: &Connection, reason: PalmReason) {
+    let total = connection.count_palm_rejection();
+    println!(
+        "Palm rejected ({:?}) on connection {}, {} so far",
+        reason, connection.id, total
+    );
+}
+
+fn should_process_scroll_message(last_processed_time: &Arc<AtomicU64>, time_interval: u64) -> bool {
+    let now = current_time_millis() as u64;
+    let last_time = last_processed_time.load(Ordering::Relaxed);
+    let elapsed = now - last_time;
+    if elapsed >= time_interval {
+        last_processed_time.store(now, Ordering::Relaxed);
+        true
+    } else {
+        false
+    }
+}
 
-    let routes = static_files.or(websocket_route);
+fn process_mouse_events(
+    mut input: Input,
+    mut receiver: EventReceiver,
+    last_processed_time: Arc<AtomicU64>,
+    config: Arc<Config>,
+    audit: AuditLog,
+    connections: Connections,
+) {
+    let mut state = MoveState::default();
+    let mut scroll_mode = config.scroll_mode;
+    // Dangerous events waiting for their confirming repeat, per connection.
+    let mut armed: HashMap<u64, (ClientEvent, u64)> = HashMap::new();
+    // When each connection last moved, for `resync_after_ms`.
+    let mut last_moved: HashMap<u64, Instant> = HashMap::new();
+    let drag_window = config
+        .click_drag_window_ms
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis);
+    let mut held_click: Option<HeldClick> = None;
+    let mut double_tap = DoubleTap::default();
+    // When the launcher was last opened, for its `debounce_ms`.
+    let mut launched: Option<Instant> = None;
+    let scroll_interval = Duration::from_millis(config.scroll_interval_ms);
+    let mut tap_drag = TapDragState::default();
+    let mut coast: Option<Coast> = None;
+    let mut pen: Option<PenDown> = None;
+    let mut pan: Option<PanGrab> = None;
+    let mut dwell: Option<Dwell> = None;
+    let mut sequence: Option<Sequence> = None;
+    let mut repeating: Option<Repeat> = None;
+    let mut shake: Option<Shake> = None;
+    let mut key_holds = KeyHolds::default();
+    let display = input.display_size();
+    let mut fence = PointerFence::new(config.pointer_fence.and_then(|area| {
+        let rect = area.rect(display);
+        if rect.is_none() {
+            eprintln!("pointer_fence: the primary display's size is unknown, not fencing");
+        }
+        rect
+    }));
+    // When the latest event arrived, and until when touch events still
+    // belong to a touch that woke the display, see `wake_after_idle_ms`.
+    let mut last_input = Instant::now();
+    let mut waking_until: Option<Instant> = None;
 
-    warp::serve(routes).run(([0, 0, 0, 0], 8088)).await;
+    // The connection whose event was handled last, told if injecting it failed.
+    let mut handled_for: Option<Arc<Connection>> = None;
+    // The connection whose `Closed` was handled last, checked for being the
+    // last one open once everything it started has been let go.
+    let mut closed: Option<u64> = None;
+    loop {
+        if let Some(failure) = input.take_failure() {
+            let e = Error::Input(failure);
+            e.log();
+            if let Some(connection) = handled_for.take() {
+                connection.notify(ServerEvent::error(&e, connection.locale));
+                connection.feedback(FeedbackKind::Error);
+            }
+        }
+        // With no connection left nothing pending is wanted any more: what is
+        // still held goes up and every timer is dropped, so that the thread
+        // blocks until the next connection sends something and makes no
+        // backend calls meanwhile.
+        if closed.take().is_some_and(|id| connections.park(id)) {
+            if let Some(held) = held_click.take() {
+                held.release(&mut input, &connections);
+            }
+            std::mem::take(&mut tap_drag).settle(&mut input, &connections);
+            if let Some(down) = pen.take() {
+                down.lift(&mut input);
+            }
+            if let Some(grab) = pan.take() {
+                grab.release(&mut input);
+            }
+            coast = None;
+            dwell = None;
+            sequence = None;
+            repeating = None;
+            shake = None;
+            state.scroll = ScrollAccumulator::default();
+            state.reset_inertia();
+            fence.skip_check();
+            println!("No connections left, mouse event thread parked.");
+        }
+        // Rather than blocking until the next event, wait only until something
+        // is due without one: a held click to settle, a tap to resolve, a
+        // forgotten drag to release, a coasting scroll step or a dwell click.
+        let deadline = [
+            held_click.as_ref().map(|held| held.deadline),
+            tap_drag.deadline(),
+            tap_drag.release_deadline(&config.held_button_timeout),
+            pen.as_ref().and_then(|down| {
+                config
+                    .held_button_timeout
+                    .deadline(down.since, down.last_move)
+            }),
+            pan.as_ref().and_then(|grab| {
+                config
+                    .held_button_timeout
+                    .deadline(grab.since, grab.last_move)
+            }),
+            coast.as_ref().map(|coast| coast.deadline),
+            dwell.as_ref().and_then(|dwell| dwell.deadline),
+            state.scroll.deadline(),
+            sequence.as_ref().map(|sequence| sequence.deadline),
+            repeating.as_ref().map(|repeat| repeat.deadline),
+            shake.as_ref().map(|shake| shake.deadline),
+            key_holds.deadline(),
+            fence.deadline(),
+            state.inertia_deadline(config.inertia_reset_ms),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let Envelope {
+            connection,
+            event,
+            received_at,
+            logged,
+        } = match receiver.recv_until(deadline) {
+            Ok(envelope) => envelope,
+            Err(RecvTimeoutError::Timeout) => {
+                handled_for = None;
+                let now = Instant::now();
+                if let Some(held) = held_click.take_if(|held| held.deadline <= now) {
+                    held.release(&mut input, &connections);
+                }
+                if tap_drag.deadline().is_some_and(|deadline| deadline <= now) {
+                    if let Some(timing) = &config.tap_drag {
+                        match tap_drag {
+                            TapDragState::Tapped { connection, .. } => trace_gesture!(
+                                config,
+                                connection,
+                                "tap, no second touch within {}ms",
+                                timing.tap_window_ms
+                            ),
+                            TapDragState::Touching { connection, .. } => trace_gesture!(
+                                config,
+                                connection,
+                                "tap drag, touched again after a tap and held for {}ms",
+                                timing.hold_ms
+                            ),
+                            _ => {}
+                        }
+                    }
+                    tap_drag = tap_drag.expire(&mut input, &connections);
+                }
+                if let TapDragState::Dragging {
+                    connection, since, ..
+                } = tap_drag
+                {
+                    if tap_drag
+                        .release_deadline(&config.held_button_timeout)
+                        .is_some_and(|deadline| deadline <= now)
+                    {
+                        force_release(&mut input, &connections, connection, since);
+                        tap_drag = TapDragState::Idle;
+                    }
+                }
+                if let Some(down) = pen.take_if(|down| {
+                    config
+                        .held_button_timeout
+                        .deadline(down.since, down.last_move)
+                        .is_some_and(|deadline| deadline <= now)
+                }) {
+                    force_release(&mut input, &connections, down.connection, down.since);
+                }
+                if let Some(grab) = pan.take_if(|grab| {
+                    config
+                        .held_button_timeout
+                        .deadline(grab.since, grab.last_move)
+                        .is_some_and(|deadline| deadline <= now)
+                }) {
+                    println!(
+                        "Pan held {}ms without movement, letting go",
+                        grab.since.elapsed().as_millis()
+                    );
+                    grab.release(&mut input);
+                }
+                if let (Some(momentum), Some(current)) = (&config.momentum, &mut coast) {
+                    if current.deadline <= now {
+                        let (amount, coasting) = current.step(momentum);
+                        let max = config.max_scroll_per_event.map_or(i32::MAX, i32::from);
+                        let amount = amount.clamp(-max, max);
+                        if amount != 0 {
+                            input.mouse_scroll_y(amount);
+                            last_processed_time
+                                .store(current_time_millis() as u64, Ordering::Relaxed);
+                            println!("Mouse coasted by: dy={}", amount);
+                        }
+                        if !coasting {
+                            coast = None;
+                        }
+                    }
+                }
+                if let Some(current) = dwell
+                    .as_mut()
+                    .filter(|current| current.deadline.is_some_and(|deadline| deadline <= now))
+                {
+                    // Resting does not click while a button is held or a
+                    // scroll is under way; moving again starts over.
+                    let busy = held_click.is_some()
+                        || matches!(tap_drag, TapDragState::Dragging { .. })
+                        || pen.is_some()
+                        || pan.is_some()
+                        || coast.is_some()
+                        || state.scroll.deadline().is_some();
+                    match connections
+                        .get(current.connection)
+                        .filter(|connection| connection.dwell_click())
+                    {
+                        Some(connection) if !busy => match current.step(&config.dwell_click) {
+                            DwellStep::Cue(click_in) => connection.dwell_cue(Some(click_in)),
+                            DwellStep::Click(action, next) => {
+                                let button = match action {
+                                    DwellAction::Right => MouseButton::Right,
+                                    DwellAction::Left | DwellAction::Double => MouseButton::Left,
+                                };
+                                input.mouse_click(button.to_enigo());
+                                if action == DwellAction::Double {
+                                    input.mouse_click(button.to_enigo());
+                                }
+                                println!("Dwell click: {:?}", action);
+                                connection.feedback(button.click_feedback());
+                                if next.is_some() {
+                                    connection.dwell_cue(next);
+                                }
+                            }
+                        },
+                        Some(connection) => {
+                            if current.cued {
+                                connection.dwell_cue(None);
+                            }
+                            explain!(connection, "dwell click skipped for a drag or scroll");
+                            dwell = None;
+                        }
+                        None => dwell = None,
+                    }
+                }
+                if state
+                    .scroll
+                    .deadline()
+                    .is_some_and(|deadline| deadline <= now)
+                {
+                    flush_scroll(
+                        &mut input,
+                        &mut state.scroll,
+                        scroll_interval,
+                        config.max_scroll_per_event,
+                        &last_processed_time,
+                    );
+                }
+                if state
+                    .inertia_deadline(config.inertia_reset_ms)
+                    .is_some_and(|deadline| deadline <= now)
+                {
+                    state.reset_inertia();
+                }
+                if let Some(current) = sequence.as_mut().filter(|current| current.deadline <= now) {
+                    let more = current.step(&mut input);
+                    let (typed, total) = (current.typed(), current.total());
+                    if !more || typed % sequence::PROGRESS_EVERY == 0 {
+                        if let Some(connection) = connections.get(current.connection) {
+                            connection.notify(ServerEvent::SequenceProgress { typed, total });
+                        }
+                    }
+                    if !more {
+                        println!("Sequence typed: {} characters", total);
+                        sequence = None;
+                    }
+                }
+                if let Some(current) = repeating.as_mut().filter(|current| current.deadline <= now)
+                {
+                    if !current.step(&mut input) {
+                        repeating = None;
+                    }
+                }
+                if let Some(current) = shake.as_mut().filter(|current| current.deadline <= now) {
+                    if !current.step(&mut input) {
+                        shake = None;
+                    }
+                }
+                key_holds.release_due(&mut input, now);
+                if fence.deadline().is_some_and(|deadline| deadline <= now) {
+                    if let Some((x, y)) = fence.check(&mut input) {
+                        println!("Pointer put back inside the fence at ({}, {})", x, y);
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        explain!(connection, "received {}", event.describe());
+        connection.count_handled(received_at.elapsed(), logged);
+        handled_for = Some(connection.clone());
+        if matches!(event, ClientEvent::Closed) {
+            closed = Some(connection.id);
+        }
+        if event.has_non_finite() {
+            connection.count_dropped("non_finite");
+            eprintln!(
+                "{} with non-finite values dropped on connection {}",
+                event.kind(),
+                connection.id
+            );
+            continue;
+        }
+        // The first touch after `wake_after_idle_ms` only wakes the display;
+        // the rest of it goes with it, until its click or lift or until
+        // touch events pause.
+        let now = Instant::now();
+        let idle = now - last_input;
+        if !matches!(event, ClientEvent::Closed) {
+            last_input = now;
+        }
+        if let Some(wake_after) = config.wake_after_idle_ms.map(Duration::from_millis) {
+            let woke = event.is_touch() && idle >= wake_after;
+            if woke {
+                input.wake_display();
+                println!(
+                    "Display woken by a touch after {}s without input",
+                    idle.as_secs()
+                );
+            }
+            let waking = waking_until.is_some_and(|until| now < until);
+            if woke || (waking && (event.is_touch() || matches!(event, ClientEvent::TouchUp))) {
+                waking_until =
+                    (!matches!(event, ClientEvent::MouseClick { .. } | ClientEvent::TouchUp))
+                        .then(|| now + WAKE_TOUCH_GAP);
+                connection.count_dropped("wake_touch");
+                explain!(connection, "dropped: touch waking the display");
+                continue;
+            }
+        }
+        // A new touch or a new connection may start anywhere; its first move
+        // only tells where.
+        if matches!(event, ClientEvent::TouchDown { .. } | ClientEvent::Closed) {
+            last_moved.remove(&connection.id);
+        }
+        // Keys a closed connection held go up however long was left.
+        if matches!(event, ClientEvent::Closed) {
+            key_holds.release_connection(&mut input, connection.id);
+        }
+        // Lifting the fingers ends the gesture, whatever else takes the
+        // event; a pan lets go.
+        if matches!(event, ClientEvent::TouchUp | ClientEvent::Closed) {
+            state.panning = None;
+            state.scroll.end_gesture();
+            if let Some(grab) = pan.take_if(|grab| grab.connection == connection.id) {
+                grab.release(&mut input);
+            }
+        }
+        // New input stops a coasting scroll, touches only with `cancel_on_touch`;
+        // a scroll that goes on re-arms it. Lifting the fingers is what sets
+        // it coasting, so that leaves it be.
+        let grabs_coast = match event {
+            ClientEvent::TouchUp => false,
+            _ if event.is_touch() => config
+                .momentum
+                .as_ref()
+                .is_some_and(|momentum| momentum.cancel_on_touch),
+            _ => true,
+        };
+        if coast.take_if(|_| grabs_coast).is_some() {
+            explain!(connection, "coasting stopped by {}", event.kind());
+        }
+        // Real movement stops a shake where it is rather than pulling the
+        // pointer back.
+        if matches!(event, ClientEvent::MouseMove { .. }) && shake.take().is_some() {
+            println!("Pointer shake stopped by movement");
+        }
+        // A dwell click waits for its own connection's pointer to rest; any
+        // other input, e.g. a click, a scroll or another device, calls it off.
+        if let Some(current) = dwell.take_if(|current| {
+            current.connection != connection.id
+                || !matches!(
+                    event,
+                    ClientEvent::MouseMove { touches: 1, .. }
+                        | ClientEvent::TouchDown { .. }
+                        | ClientEvent::TouchUp
+                )
+        }) {
+            if let Some(owner) = connections.get(current.connection).filter(|_| current.cued) {
+                owner.dwell_cue(None);
+            }
+            explain!(connection, "dwell click called off by {}", event.kind());
+        }
+
+        if let Some(scaling) = &config.touch_scaling {
+            let radius = match &event {
+                ClientEvent::MouseMove { radius, .. } | ClientEvent::TouchDown { radius } => {
+                    *radius
+                }
+                _ => None,
+            };
+            if scaling.is_palm(radius) {
+                connection.count_dropped("palm_contact");
+                explain!(
+                    connection,
+                    "dropped: palm-sized contact, radius {:?}",
+                    radius
+                );
+                continue;
+            }
+        }
+
+        let gestures = config.gestures.enabled.and(connection.profile().gestures);
+        if let Some(gesture) = event.gesture().filter(|gesture| !gestures.is_on(*gesture)) {
+            connection.count_dropped("gesture_off");
+            explain!(connection, "dropped: {:?} is switched off", gesture);
+            continue;
+        }
+
+        if config
+            .confirm_actions
+            .iter()
+            .any(|kind| kind == event.kind())
+        {
+            let now = current_time_millis() as u64;
+            match armed.remove(&connection.id) {
+                Some((armed_event, armed_at))
+                    if armed_event == event && now - armed_at <= config.confirm_window_ms =>
+                {
+                    explain!(connection, "{} confirmed", event.kind());
+                }
+                _ => {
+                    connection.count_dropped("unconfirmed");
+                    println!(
+                        "{} armed; send it again within {}ms to confirm.",
+                        event.kind(),
+                        config.confirm_window_ms
+                    );
+                    armed.insert(connection.id, (event, now));
+                    continue;
+                }
+            }
+        }
+
+        // Drawing mode takes one-finger touches away from every gesture: the
+        // button follows the finger. More fingers lift it and scroll as usual.
+        if let Some(drawing) = connection.drawing() {
+            match event {
+                ClientEvent::TouchDown { .. } => {
+                    hold_pen(&mut pen, &mut input, connection.id);
+                    continue;
+                }
+                ClientEvent::TouchUp | ClientEvent::Closed => {
+                    if let Some(down) = pen.take_if(|down| down.connection == connection.id) {
+                        down.lift(&mut input);
+                    }
+                    continue;
+                }
+                ClientEvent::MouseMove {
+                    touches: 1,
+                    dx,
+                    dy,
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                } => {
+                    hold_pen(&mut pen, &mut input, connection.id).last_move = Instant::now();
+                    match (drawing, display, x, y, width, height) {
+                        (
+                            Drawing::Absolute,
+                            Some(display),
+                            Some(x),
+                            Some(y),
+                            Some(width),
+                            Some(height),
+                        ) if width > 0.0 && height > 0.0 => {
+                            let to_x = x / width * display.width as f64;
+                            let to_y = y / height * display.height as f64;
+                            let (to_x, to_y) = match config.snap_grid {
+                                Some(grid) => (
+                                    snap::to_grid(to_x, grid, display.width),
+                                    snap::to_grid(to_y, grid, display.height),
+                                ),
+                                None => (to_x.round() as i32, to_y.round() as i32),
+                            };
+                            input.mouse_move_to(to_x, to_y);
+                            println!("Mouse drawn to: x={}, y={}", to_x, to_y);
+                        }
+                        (Drawing::Absolute, ..) => {
+                            connection.count_dropped("no_position");
+                            explain!(connection, "dropped: no position to draw at");
+                        }
+                        (Drawing::Relative, ..) => {
+                            let profile = connection.profile();
+                            let (dx, dy) = profile.orientation.rotate(dx, dy);
+                            let (dx, dy) = profile.scale_axes(dx, dy);
+                            let dx_int = (dx * profile.sensitivity).round() as i32;
+                            let dy_int = (dy * profile.sensitivity).round() as i32;
+                            input.mouse_move_relative(dx_int, dy_int);
+                            println!("Mouse drawn by: dx={}, dy={}", dx_int, dy_int);
+                        }
+                    }
+                    continue;
+                }
+                ClientEvent::MouseMove { .. } => {
+                    if let Some(down) = pen.take_if(|down| down.connection == connection.id) {
+                        explain!(connection, "drawing paused for more fingers");
+                        down.lift(&mut input);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(timing) = config
+            .tap_drag
+            .as_ref()
+            .filter(|_| gestures.is_on(Gesture::TapDrag))
+        {
+            let consumed;
+            let touching = matches!(tap_drag, TapDragState::Touching { .. });
+            (tap_drag, consumed) = std::mem::take(&mut tap_drag).handle(
+                &connection,
+                &event,
+                timing,
+                config.click_on,
+                &mut input,
+                &connections,
+            );
+            if touching && matches!(tap_drag, TapDragState::Dragging { .. }) {
+                trace_gesture!(
+                    config,
+                    connection.id,
+                    "tap drag, touched again after a tap and moved within {}ms",
+                    timing.hold_ms
+                );
+            }
+            if consumed {
+                continue;
+            }
+        }
+
+        if let (Some(held), Some(window)) = (held_click.take(), drag_window) {
+            // Only one-finger movement on the same connection drags; anything
+            // else settles the held click first.
+            let drags = held.connection == connection.id
+                && matches!(event, ClientEvent::MouseMove { touches: 1, .. });
+            if drags {
+                explain!(connection, "drag continues, release in {:?}", window);
+                held_click = Some(held.drag(&mut input, &connections, window));
+            } else {
+                held.release(&mut input, &connections);
+            }
+        }
+
+        match event {
+            ClientEvent::MouseMove {
+                dx,
+                dy,
+                sx,
+                sy,
+                touches,
+                pressure,
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => {
+                let profile = connection.profile();
+                let (mut dx, mut dy) = profile.orientation.rotate(dx, dy);
+                let (mut sx, mut sy) = profile.orientation.rotate(sx, sy);
+                // How long the move took on the client, which measured its speed
+                // over it.
+                let interval_ms = dx.hypot(dy) / sx.hypot(sy);
+
+                // A move long after the previous one on this connection, e.g.
+                // after the phone slept, is measured from a stale position.
+                let now = Instant::now();
+                let resync = config.resync_after_ms.is_some_and(|ms| {
+                    last_moved
+                        .get(&connection.id)
+                        .is_none_or(|last| now - *last > Duration::from_millis(ms))
+                });
+                last_moved.insert(connection.id, now);
+
+                // The first delta after the finger count changes is measured across two
+                // different gestures (often between two different fingers), so drop it
+                // and let the new gesture start cleanly. A resync starts a new gesture
+                // the same way.
+                let finger_count_changed = touches != state.last_touches || resync;
+                if touches != 2 {
+                    state.rotation = 0.0;
+                }
+                if touches != 2 || finger_count_changed {
+                    state.swipe.reset();
+                    state.scroll_lock.reset();
+                    state.scroll.end_gesture();
+                    state.panning = None;
+                    if let Some(grab) = pan.take_if(|grab| grab.connection == connection.id) {
+                        grab.release(&mut input);
+                    }
+                }
+                if touches != 1 {
+                    state.snap.reset();
+                }
+                if touches != 1 || finger_count_changed {
+                    state.reset_inertia();
+                    state.edge.reset();
+                    state.drift.reset();
+                }
+                if finger_count_changed {
+                    state.gesture_traced = false;
+                }
+                state.last_touches = touches;
+                state.last_move = Some(Instant::now());
+                state.moves_in_gesture = if finger_count_changed {
+                    0
+                } else {
+                    state.moves_in_gesture.saturating_add(1)
+                };
+                // Some clients report the end of a gesture as a move without
+                // fingers; it only ends the gesture, as reset above.
+                if touches <= 0 {
+                    connection.count_dropped("no_fingers");
+                    explain!(connection, "dropped: no fingers, gesture ended");
+                    continue;
+                }
+
+                // Edge swipes are recognized from where the touch started, so
+                // this comes before the first move of a touch is dropped.
+                let position = match (x, y, width, height) {
+                    (Some(x), Some(y), Some(width), Some(height)) => Some(Position {
+                        x,
+                        y,
+                        width,
+                        height,
+                    }),
+                    _ => None,
+                };
+                let edge_swipe = config
+                    .edge_swipe
+                    .as_ref()
+                    .filter(|_| gestures.is_on(Gesture::EdgeSwipe));
+                if let (Some(swipe), Some(position), 1) = (edge_swipe, position, touches) {
+                    match state.edge.track(swipe, position, dx, dy) {
+                        EdgeStep::Pass => {}
+                        EdgeStep::BadSize => eprintln!(
+                            "Warning: connection {} reported a {}x{} touchpad, no edge swipes for this touch",
+                            connection.id, position.width, position.height
+                        ),
+                        EdgeStep::Hold => {
+                            explain!(connection, "held: edge swipe in progress");
+                            continue;
+                        }
+                        EdgeStep::Fire(action) => {
+                            trace_gesture!(
+                                config,
+                                connection.id,
+                                "edge swipe, started within {}px of the edge and moved {}px inwards: {}",
+                                swipe.edge_swipe_px,
+                                swipe.min_distance_px,
+                                action
+                            );
+                            action.perform(&mut input);
+                            println!("Edge swipe: {}", action);
+                            connection.feedback(FeedbackKind::Gesture);
+                            continue;
+                        }
+                    }
+                }
+                if resync {
+                    connection.count_dropped("resync");
+                    explain!(
+                        connection,
+                        "dropped: first move in a while, taken as the finger's position"
+                    );
+                    continue;
+                }
+                if finger_count_changed && config.reset_on_finger_change {
+                    connection.count_dropped("finger_change");
+                    explain!(connection, "dropped: finger count changed to {}", touches);
+                    continue;
+                }
+
+                if touches == 2 && *state.panning.get_or_insert_with(|| connection.pan()) {
+                    if config.trace_gestures && !state.gesture_traced {
+                        state.gesture_traced = true;
+                        trace_gesture!(
+                            config,
+                            connection.id,
+                            "pan, 2 fingers, holding {:?}",
+                            config.pan_input
+                        );
+                    }
+                    // Scrolling left from the previous gesture goes out
+                    // before anything is held, where it would mean something
+                    // else, in as many capped steps as it takes.
+                    if pan.is_none() {
+                        loop {
+                            flush_scroll(
+                                &mut input,
+                                &mut state.scroll,
+                                scroll_interval,
+                                config.max_scroll_per_event,
+                                &last_processed_time,
+                            );
+                            if !state.scroll.has_lines() {
+                                break;
+                            }
+                        }
+                    }
+                    // Held from where the fingers start, and moved with no
+                    // acceleration so that the canvas follows them.
+                    hold_pan(&mut pan, &mut input, connection.id, config.pan_input).last_move =
+                        Instant::now();
+                    let (dx, dy) = profile.scale_axes(dx, dy);
+                    let dx_int = (dx * profile.sensitivity).round() as i32;
+                    let dy_int = (dy * profile.sensitivity).round() as i32;
+                    input.mouse_move_relative(dx_int, dy_int);
+                    println!("Mouse panned by: dx={}, dy={}", dx_int, dy_int);
+                    continue;
+                }
+
+                if touches == 2 && gestures.is_on(Gesture::Swipe) {
+                    let now = current_time_millis() as u64;
+                    if let Some(action) = state.swipe.track(&config.swipe, dx, dy, sx, now) {
+                        trace_gesture!(
+                            config,
+                            connection.id,
+                            "swipe, 2 fingers, at least {}px sideways within {}ms at {}px/ms or faster: {}",
+                            config.swipe.min_distance_px,
+                            config.swipe.max_duration_ms,
+                            config.swipe.min_speed,
+                            action
+                        );
+                        action.perform(&mut input);
+                        println!("Swiped: {}", action);
+                        connection.feedback(FeedbackKind::Gesture);
+                        continue;
+                    }
+                }
+
+                if touches == 2 && !gestures.is_on(Gesture::Scroll) {
+                    connection.count_dropped("gesture_off");
+                    explain!(connection, "dropped: scrolling is switched off");
+                    continue;
+                }
+
+                if touches == 2 {
+                    // Movement along an axis that does not scroll is dropped here.
+                    let (scroll_x, scroll_y) = match config.scroll_axes {
+                        ScrollAxes::Vertical => (false, true),
+                        ScrollAxes::Free => (true, true),
+                        ScrollAxes::Locked => match state.scroll_lock.track(dx, dy) {
+                            Some(Axis::Horizontal) => (true, false),
+                            Some(Axis::Vertical) => (false, true),
+                            None => {
+                                explain!(connection, "scroll: axis not decided yet");
+                                continue;
+                            }
+                        },
+                    };
+                    if !scroll_x {
+                        (dx, sx) = (0.0, 0.0);
+                    }
+                    if !scroll_y {
+                        (dy, sy) = (0.0, 0.0);
+                    }
+                    if config.trace_gestures && !state.gesture_traced {
+                        state.gesture_traced = true;
+                        let axes = match config.scroll_axes {
+                            ScrollAxes::Locked => format!(
+                                "locked to {} after {}px",
+                                if scroll_x { "Horizontal" } else { "Vertical" },
+                                SCROLL_LOCK_DISTANCE_PX
+                            ),
+                            axes => format!("{:?} axes", axes),
+                        };
+                        trace_gesture!(
+                            config,
+                            connection.id,
+                            "scroll, 2 fingers, {:?} mode, {}",
+                            scroll_mode,
+                            axes
+                        );
+                    }
+                }
+
+                // With `scroll_deceleration`, fast gestures scroll less per pixel.
+                let finger_speed = sx.abs().max(sy.abs());
+                let deceleration = config
+                    .scroll_deceleration
+                    .as_ref()
+                    .filter(|_| touches == 2)
+                    .map_or(1.0, |deceleration| deceleration.factor(finger_speed));
+                if deceleration < 1.0 {
+                    explain!(
+                        connection,
+                        "scroll: {:.2}px/ms, decelerated to {:.2}",
+                        finger_speed,
+                        deceleration
+                    );
+                }
+                let scroll_factor = profile.scroll_factor * deceleration;
+
+                if touches == 2 && scroll_mode == ScrollMode::Notch {
+                    // Single steps only; faster gestures shorten the pause between them
+                    // so the page keeps up without any one event scrolling too far.
+                    let speed = finger_speed * scroll_factor;
+                    if (dx.round() != 0.0 || dy.round() != 0.0) && speed > 0.0 {
+                        let interval = (NOTCH_DISTANCE_PX / speed)
+                            .clamp(NOTCH_MIN_INTERVAL_MS, NOTCH_MAX_INTERVAL_MS)
+                            as u64;
+                        let now = current_time_millis() as u64;
+                        if now - state.last_notch_time >= interval {
+                            state.last_notch_time = now;
+                            connection.feedback(FeedbackKind::Scroll);
+                            if dx.round() != 0.0 {
+                                let step = dx.signum() as i32;
+                                input.mouse_scroll_x(step);
+                                println!("Mouse scrolled by: dx={}", step);
+                            }
+                            if dy.round() != 0.0 {
+                                let step = dy.signum() as i32;
+                                input.mouse_scroll_y(step);
+                                println!("Mouse scrolled by: dy={}", step);
+                            }
+                        } else {
+                            explain!(
+                                connection,
+                                "scroll (notch): throttled, interval {}ms",
+                                interval
+                            );
+                        }
+                    } else {
+                        explain!(connection, "scroll (notch): no motion");
+                    }
+
+                    continue;
+                }
+
+                if touches == 2 {
+                    let divisor = f64::from(FAST_SCROLL_DIVISOR);
+                    let mut lines_x = dx * scroll_factor / divisor;
+                    let mut lines_y = dy * scroll_factor / divisor;
+                    if let Some(max) = config.max_scroll_per_event {
+                        let max = f64::from(max);
+                        if lines_x.abs() > max || lines_y.abs() > max {
+                            explain!(
+                                connection,
+                                "scroll (fast): {:.1},{:.1} lines capped to {}",
+                                lines_x,
+                                lines_y,
+                                max
+                            );
+                            lines_x = lines_x.clamp(-max, max);
+                            lines_y = lines_y.clamp(-max, max);
+                        }
+                    }
+
+                    if let Some(momentum) = &config.momentum {
+                        let speed = sy * scroll_factor / divisor;
+                        coast = Coast::new(momentum, speed);
+                    }
+
+                    // Gathered and sent every `scroll_interval_ms`, so slow
+                    // gestures scroll evenly and nothing in between is lost.
+                    state.scroll.add(lines_x, lines_y);
+                    connection.feedback(FeedbackKind::Scroll);
+                    explain!(
+                        connection,
+                        "scroll (fast): {:.2},{:.2} lines gathered",
+                        lines_x,
+                        lines_y
+                    );
+                    if scroll_interval.is_zero()
+                        || state
+                            .scroll
+                            .deadline()
+                            .is_some_and(|deadline| deadline <= Instant::now())
+                    {
+                        flush_scroll(
+                            &mut input,
+                            &mut state.scroll,
+                            scroll_interval,
+                            config.max_scroll_per_event,
+                            &last_processed_time,
+                        );
+                    }
+
+                    continue;
+                }
+
+                if let Some(palm) = &config.palm_rejection {
+                    let distance = dx.hypot(dy);
+                    if state.moves_in_gesture <= PALM_START_MOVES
+                        && distance > palm.max_start_delta_px
+                    {
+                        reject_palm(&connection, PalmReason::StartJump);
+                        connection.count_dropped("palm_jump");
+                        explain!(
+                            connection,
+                            "dropped: jump of {:.1} at touch start",
+                            distance
+                        );
+                        continue;
+                    }
+                }
+
+                // Do not respond to move messages for a period of time after scrolling
+                if should_process_scroll_message(&last_processed_time, 1000) {
+                    connection.count_dropped("after_scroll");
+                    explain!(connection, "dropped: move suppressed after scrolling");
+                    continue;
+                }
+                if config.trace_gestures && !state.gesture_traced {
+                    state.gesture_traced = true;
+                    trace_gesture!(
+                        config,
+                        connection.id,
+                        "move, {} finger{}{}",
+                        touches,
+                        if touches == 1 { "" } else { "s" },
+                        if connection.precision() {
+                            ", precision hold"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+
+                match state.snap.apply(profile.snap_degrees, dx, dy) {
+                    Some(Axis::Horizontal) => {
+                        explain!(
+                            connection,
+                            "move (snapped): horizontal, dropped dy {:.1}",
+                            dy
+                        );
+                        (dy, sy) = (0.0, 0.0);
+                    }
+                    Some(Axis::Vertical) => {
+                        explain!(connection, "move (snapped): vertical, dropped dx {:.1}", dx);
+                        (dx, sx) = (0.0, 0.0);
+                    }
+                    None => {}
+                }
+
+                // Per-axis gain comes before acceleration, so the speed it is
+                // based on matches the distance actually moved.
+                (dx, dy) = profile.scale_axes(dx, dy);
+                (sx, sy) = profile.scale_axes(sx, sy);
+
+                if config.acceleration_warm_start && !state.warmed_up {
+                    state.warmed_up = true;
+                    let (primed_x, primed_y) = (dx / WARM_START_FRAME_MS, dy / WARM_START_FRAME_MS);
+                    if primed_x.hypot(primed_y) > sx.hypot(sy) {
+                        explain!(
+                            connection,
+                            "speed ({:.2}, {:.2}) primed to ({:.2}, {:.2}) from the first move",
+                            sx,
+                            sy,
+                            primed_x,
+                            primed_y
+                        );
+                        (sx, sy) = (primed_x, primed_y);
+                    }
+                }
+
+                if let Some(window) = config.acceleration_window {
+                    state.recent_speeds.push_back((sx, sy));
+                    while state.recent_speeds.len() > window {
+                        state.recent_speeds.pop_front();
+                    }
+                    let n = state.recent_speeds.len() as f64;
+                    let (sum_x, sum_y) = state
+                        .recent_speeds
+                        .iter()
+                        .fold((0.0, 0.0), |(x, y), (sx, sy)| (x + sx, y + sy));
+                    explain!(
+                        connection,
+                        "speed ({:.2}, {:.2}) averaged to ({:.2}, {:.2})",
+                        sx,
+                        sy,
+                        sum_x / n,
+                        sum_y / n
+                    );
+                    (sx, sy) = (sum_x / n, sum_y / n);
+                }
+
+                // Calculate the acceleration based on speed and distance
+                // and adjust the mouse movement accordingly
+                let acceleration_factor = 10.0; // Acceleration factor, adjustable according to actual requirements
+
+                // With `velocity_reference_ms`, the distance is what the move would
+                // have covered in the reference time, and the acceleration added
+                // is scaled back to the time it really took.
+                let time_scale = config
+                    .velocity_reference_ms
+                    .map_or(1.0, |reference| time_scale(interval_ms, reference));
+                if time_scale != 1.0 {
+                    explain!(
+                        connection,
+                        "move took {:.1}ms, judged at {:.2}x",
+                        interval_ms,
+                        1.0 / time_scale
+                    );
+                }
+                let distance = (dx.powi(2) + dy.powi(2)).sqrt() / time_scale;
+                let mut sensitivity = profile.sensitivity;
+                if let Some(scaling) = &config.touch_scaling {
+                    sensitivity *= scaling.multiplier(pressure);
+                }
+                // A drag this connection started holds the button down.
+                let held = held_click
+                    .as_ref()
+                    .is_some_and(|held| held.connection == connection.id && held.dragging);
+                let tap_dragging = matches!(
+                    tap_drag,
+                    TapDragState::Dragging { connection: id, .. } if id == connection.id
+                );
+                let dragging = held || tap_dragging;
+                if dragging && profile.drag_sensitivity != 1.0 {
+                    sensitivity *= profile.drag_sensitivity;
+                    explain!(connection, "move (drag): {}x sensitivity", sensitivity);
+                }
+                if connection.precision() {
+                    sensitivity *= config.precision_multiplier;
+                    explain!(
+                        connection,
+                        "move (precision): unaccelerated at {}x sensitivity",
+                        sensitivity
+                    );
+                } else if let Some(axes) = &config.axis_acceleration {
+                    // Each axis by its own distance, which is zero along the
+                    // other axis for a straight move.
+                    let (raw_dx, raw_dy) = (dx, dy);
+                    let (distance_x, distance_y) = (dx.abs() / time_scale, dy.abs() / time_scale);
+                    if distance_x > 1.0 {
+                        dx += capped(
+                            sx * distance_x * acceleration_factor * axes.horizontal * time_scale,
+                            config.max_acceleration_px,
+                        );
+                    }
+                    if distance_y > 1.0 {
+                        dy += capped(
+                            sy * distance_y * acceleration_factor * axes.vertical * time_scale,
+                            config.max_acceleration_px,
+                        );
+                    }
+                    explain!(
+                        connection,
+                        "move (accelerated per axis): ({:.1}, {:.1}) -> ({:.1}, {:.1})",
+                        raw_dx,
+                        raw_dy,
+                        dx,
+                        dy
+                    );
+                } else if distance > 1.0 {
+                    let (raw_dx, raw_dy) = (dx, dy);
+                    let acceleration = distance * acceleration_factor * time_scale;
+                    dx += capped(sx * acceleration, config.max_acceleration_px);
+                    dy += capped(sy * acceleration, config.max_acceleration_px);
+                    explain!(
+                        connection,
+                        "move (accelerated): ({:.1}, {:.1}) -> ({:.1}, {:.1})",
+                        raw_dx,
+                        raw_dy,
+                        dx,
+                        dy
+                    );
+                } else {
+                    explain!(connection, "move (unaccelerated): distance {:.1}", distance);
+                }
+
+                let dx_int = (dx * sensitivity).round() as i32;
+                let dy_int = (dy * sensitivity).round() as i32;
+
+                // Discard abnormal movement distances
+                if dx_int.abs() >= 1000 || dy_int.abs() >= 1000 {
+                    connection.count_dropped("abnormal_distance");
+                    explain!(
+                        connection,
+                        "dropped: abnormal distance ({}, {})",
+                        dx_int,
+                        dy_int
+                    );
+                    continue;
+                }
+
+                input.mouse_move_relative(dx_int, dy_int);
+                println!("Mouse moved by: dx={}, dy={}", dx_int, dy_int);
+
+                fence.moved();
+                if let Some(every) = config.reconcile_every {
+                    if let Some((x, y)) = state.drift.track(&input, dx_int, dy_int, every, display)
+                    {
+                        input.mouse_move_to(x, y);
+                        explain!(
+                            connection,
+                            "pointer put back at ({}, {}) after {} moves",
+                            x,
+                            y,
+                            every
+                        );
+                    }
+                }
+                // Dwell clicking counts from where the pointer comes to rest,
+                // but never drops a dragged button.
+                if dragging {
+                    if dwell.take().is_some_and(|current| current.cued) {
+                        connection.dwell_cue(None);
+                    }
+                } else if connection.dwell_click() {
+                    match &mut dwell {
+                        Some(current) => {
+                            if current.moved(dx_int, dy_int, &config.dwell_click) {
+                                explain!(connection, "dwell click off, the pointer moved away");
+                                connection.dwell_cue(None);
+                            }
+                        }
+                        None => dwell = Some(Dwell::new(connection.id, &config.dwell_click)),
+                    }
+                }
+            }
+            ClientEvent::MouseClick { button } => {
+                trace_gesture!(config, connection.id, "tap, {:?} click", button);
+                if let Some(window) = drag_window {
+                    explain!(
+                        connection,
+                        "click held for {:?} in case a drag follows",
+                        window
+                    );
+                    held_click = Some(HeldClick::new(
+                        &mut input,
+                        connection.id,
+                        button,
+                        config.click_on,
+                        window,
+                    ));
+                    continue;
+                }
+                input.mouse_click(button.to_enigo());
+                println!("Mouse button clicked: {:?}", button);
+                connection.feedback(button.click_feedback());
+            }
+            ClientEvent::KeyPress { key, .. } => match config.char_substitutions.get(&key) {
+                Some(replacement) => {
+                    explain!(connection, "substituted {:?}", key);
+                    input.key_sequence(replacement);
+                    println!("Key pressed: {:?}", PrivateText(replacement.clone()));
+                }
+                None => {
+                    input.key_click(Key::Unicode(key));
+                    println!("Key pressed: {:?}", PrivateText(key.to_string()));
+                }
+            },
+            ClientEvent::SpecialKey { key, .. } => match key.to_enigo() {
+                Some(enigo_key) => {
+                    input.key_click(enigo_key);
+                    println!("Special key pressed: {:?}", key);
+                }
+                None => eprintln!("Special key {:?} is not supported on this platform.", key),
+            },
+            ClientEvent::KeyHold { key, duration_ms } => {
+                let Some(enigo_key) = key.to_enigo() else {
+                    eprintln!("Key {:?} is not supported on this platform.", key);
+                    continue;
+                };
+                let held_ms = duration_ms.min(config.max_key_hold_ms);
+                if held_ms < duration_ms {
+                    explain!(
+                        connection,
+                        "hold of {}ms cut to max_key_hold_ms",
+                        duration_ms
+                    );
+                }
+                key_holds.hold(
+                    &mut input,
+                    connection.id,
+                    enigo_key,
+                    Duration::from_millis(held_ms),
+                );
+                println!("Key held: {:?} for {}ms", key, held_ms);
+            }
+            ClientEvent::MediaKey { key } => match key.to_enigo() {
+                Some(enigo_key) => {
+                    input.key_click(enigo_key);
+                    println!("Media key pressed: {:?}", key);
+                }
+                None => eprintln!("Media key {:?} is not supported on this platform.", key),
+            },
+            ClientEvent::TypeString {
+                text: original,
+                repeat,
+            } => {
+                let text = keys::substitute(&original, &config.char_substitutions);
+                if let Cow::Owned(_) = &text {
+                    explain!(
+                        connection,
+                        "substituted {:?}",
+                        keys::mapped_chars(&original, &config.char_substitutions)
+                    );
+                }
+                let len = text.chars().count();
+                let repeat = repeat.unwrap_or(1);
+                if len == 0 || repeat == 0 {
+                    connection.count_dropped("nothing_to_type");
+                    explain!(connection, "dropped: nothing to type");
+                    continue;
+                }
+                if len > config.max_type_len {
+                    connection.count_dropped("too_long");
+                    eprintln!(
+                        "Refusing to type {} characters (max_type_len is {}).",
+                        len, config.max_type_len
+                    );
+                    continue;
+                }
+
+                let max_repeat = (config.max_type_len / len) as u32;
+                if repeat > max_repeat {
+                    eprintln!(
+                        "String repeat count {} exceeds max_type_len, capped to {}.",
+                        repeat, max_repeat
+                    );
+                }
+                let repeat = repeat.min(max_repeat);
+                // Copies still to come from an earlier string go first.
+                if let Some(mut pending) = repeating.take() {
+                    pending.finish(&mut input);
+                }
+                let paste = match config.prefer_clipboard_typing.then(|| Paste::new(&text)) {
+                    Some(Ok(paste)) => Some(paste),
+                    Some(Err(e)) => {
+                        eprintln!("Clipboard unavailable, typing instead: {}", e);
+                        None
+                    }
+                    None => None,
+                };
+                let how = if paste.is_some() { "pasted" } else { "typed" };
+                repeating = Repeat::start(
+                    &mut input,
+                    connection.id,
+                    &text,
+                    repeat,
+                    Duration::from_millis(config.type_delay_ms),
+                    paste,
+                );
+                println!(
+                    "String {}: {:?} x{}",
+                    how,
+                    PrivateText(text.into_owned()),
+                    repeat
+                );
+            }
+            ClientEvent::Screenshot { region } => {
+                let combo = if region {
+                    &config.screenshot_region_combo
+                } else {
+                    &config.screenshot_combo
+                };
+                match combo {
+                    Some(combo) => {
+                        combo.press(&mut input);
+                        println!("Screenshot taken (region: {}) with {}", region, combo);
+                    }
+                    None => eprintln!("No screenshot shortcut configured."),
+                }
+            }
+            ClientEvent::UndoLast => match &config.undo_combo {
+                Some(combo) => {
+                    combo.press(&mut input);
+                    println!("Undo sent with {}", combo);
+                }
+                None => eprintln!("No undo shortcut configured."),
+            },
+            ClientEvent::ReturnToLastClick => match input.last_click() {
+                Some((x, y)) => {
+                    input.mouse_move_to(x, y);
+                    fence.moved();
+                    println!("Pointer returned to the last click at ({}, {})", x, y);
+                }
+                None => {
+                    connection.count_dropped("no_click");
+                    explain!(connection, "dropped: no click to return to yet");
+                }
+            },
+            ClientEvent::LocateCursor => {
+                let position = input.mouse_location();
+                connection.notify(ServerEvent::CursorLocation {
+                    x: position.map(|(x, _)| x),
+                    y: position.map(|(_, y)| y),
+                    display: position.and_then(|position| locate::display_index(position, display)),
+                });
+                // A shake under way keeps going, so that it still ends
+                // where it started.
+                if shake.is_none() {
+                    shake = Some(Shake::new());
+                }
+                println!("Locating cursor at {:?}", position);
+            }
+            ClientEvent::WakeDisplay => {
+                input.wake_display();
+                println!("Display woken");
+            }
+            ClientEvent::SetPointerFence { active: false } => {
+                fence.set(None);
+                connection.notify(ServerEvent::PointerFence { area: None });
+                println!("Pointer fence removed");
+                audit.record(AuditEvent::SettingChanged {
+                    connection: Some(connection.id),
+                    setting: "pointer_fence",
+                    value: serde_json::Value::Null,
+                });
+            }
+            ClientEvent::SetPointerFence { active: true } => {
+                let area = input
+                    .mouse_location()
+                    .and_then(|position| locate::display_index(position, display))
+                    .and_then(|index| FenceArea::Display { display: index }.rect(display));
+                let Some(area) = area else {
+                    connection.notify(ServerEvent::error(
+                        &Error::Unsupported("the pointer is not on a display of known size"),
+                        connection.locale,
+                    ));
+                    continue;
+                };
+                fence.set(Some(area));
+                connection.notify(ServerEvent::PointerFence { area: Some(area) });
+                println!("Pointer fenced into {:?}", area);
+                audit.record(AuditEvent::SettingChanged {
+                    connection: Some(connection.id),
+                    setting: "pointer_fence",
+                    value: serde_json::json!(area),
+                });
+            }
+            ClientEvent::GetLockState => {
+                connection.notify(ServerEvent::LockState(locks::query()));
+            }
+            ClientEvent::ToggleLock { which } => match which.to_enigo() {
+                Some(enigo_key) => {
+                    let before = locks::query();
+                    input.key_click(enigo_key);
+                    let states = locks::after_toggle(which, before);
+                    println!("Lock key toggled: {:?} ({:?})", which, states);
+                    connection.notify(ServerEvent::LockState(states));
+                }
+                None => eprintln!("Lock key {:?} is not supported on this platform.", which),
+            },
+            ClientEvent::SetScrollMode { mode } => {
+                scroll_mode = mode;
+                println!("Scroll mode set to: {:?}", mode);
+                audit.record(AuditEvent::SettingChanged {
+                    connection: Some(connection.id),
+                    setting: "scroll_mode",
+                    value: serde_json::json!(mode),
+                });
+            }
+            ClientEvent::FingerGesture {
+                fingers: 2,
+                gesture: FingerGesture::Tap,
+            } => {
+                let Some(zoom) = &config.double_tap_zoom else {
+                    explain!(connection, "two-finger tap, double_tap_zoom is not set");
+                    continue;
+                };
+                if !double_tap.tap(
+                    connection.id,
+                    Duration::from_millis(zoom.window_ms),
+                    Instant::now(),
+                ) {
+                    explain!(
+                        connection,
+                        "two-finger tap, waiting {}ms for a second",
+                        zoom.window_ms
+                    );
+                    continue;
+                }
+                trace_gesture!(
+                    config,
+                    connection.id,
+                    "two-finger double tap within {}ms: {}",
+                    zoom.window_ms,
+                    zoom.action
+                );
+                zoom.action.perform(&mut input);
+                println!("Two-finger double tap: {}", zoom.action);
+                connection.feedback(FeedbackKind::Gesture);
+            }
+            ClientEvent::FingerGesture { fingers, gesture }
+                if config
+                    .launcher
+                    .as_ref()
+                    .is_some_and(|launcher| launcher.matches(fingers, gesture)) =>
+            {
+                let launcher = config.launcher.as_ref().expect("matched above");
+                let debounce = Duration::from_millis(launcher.debounce_ms);
+                if launched.is_some_and(|at| at.elapsed() < debounce) {
+                    explain!(connection, "launcher opened less than {:?} ago", debounce);
+                    connection.count_dropped("launcher_debounce");
+                    continue;
+                }
+                trace_gesture!(
+                    config,
+                    connection.id,
+                    "{}-finger {:?}, recognized by the client: launcher",
+                    fingers,
+                    gesture
+                );
+                launcher.action.perform(&mut input);
+                launched = Some(Instant::now());
+                println!("Launcher opened: {}", launcher.action);
+                connection.feedback(FeedbackKind::Gesture);
+            }
+            ClientEvent::FingerGesture { fingers, gesture } => {
+                match finger_gesture_action(&config.finger_gestures, fingers, gesture) {
+                    Some(action) => {
+                        trace_gesture!(
+                            config,
+                            connection.id,
+                            "{}-finger {:?}, recognized by the client: {}",
+                            fingers,
+                            gesture,
+                            action
+                        );
+                        action.perform(&mut input);
+                        println!("{}-finger {:?}: {}", fingers, gesture, action);
+                        connection.feedback(FeedbackKind::Gesture);
+                    }
+                    None => {
+                        trace_gesture!(
+                            config,
+                            connection.id,
+                            "{}-finger {:?}, recognized by the client: nothing bound",
+                            fingers,
+                            gesture
+                        );
+                        explain!(
+                            connection,
+                            "nothing bound to {}-finger {:?}",
+                            fingers,
+                            gesture
+                        );
+                    }
+                }
+            }
+            ClientEvent::PanelAction { id } => {
+                // Media buttons were turned into `Media` by the handler.
+                if let Some(ButtonAction::Input(action)) =
+                    panels::find(&config.panels, &id).map(|button| &button.action)
+                {
+                    action.perform(&mut input);
+                    println!("Panel button {:?}: {}", id, action);
+                }
+            }
+            ClientEvent::PalmRejected { reason } => reject_palm(&connection, reason),
+            ClientEvent::SetDrawingMode { active, absolute } => {
+                let drawing = match (active, absolute) {
+                    (false, _) => None,
+                    (true, false) => Some(Drawing::Relative),
+                    (true, true) => Some(Drawing::Absolute),
+                };
+                connection.set_drawing(drawing);
+                if let Some(down) = pen.take_if(|down| down.connection == connection.id) {
+                    down.lift(&mut input);
+                }
+                println!(
+                    "Drawing mode {:?} for connection {}",
+                    drawing, connection.id
+                );
+            }
+            ClientEvent::SetPanMode { active } => {
+                connection.set_pan(active);
+                println!(
+                    "Pan mode {} for connection {}",
+                    if active { "on" } else { "off" },
+                    connection.id
+                );
+            }
+            ClientEvent::SetDwellClick { active } => {
+                connection.set_dwell_click(active);
+                println!(
+                    "Dwell click {} for connection {}",
+                    if active { "on" } else { "off" },
+                    connection.id
+                );
+            }
+            ClientEvent::SetPrecisionHold { active } => {
+                connection.set_precision(active);
+                println!(
+                    "Precision hold {} for connection {}",
+                    if active { "on" } else { "off" },
+                    connection.id
+                );
+            }
+            ClientEvent::Rotate { angle_delta } => {
+                let Some(rotate) = &config.rotate else {
+                    continue;
+                };
+                let actions = rotate.accumulate(&mut state.rotation, angle_delta);
+                if actions.is_empty() {
+                    explain!(
+                        connection,
+                        "rotate: {:.1} degrees accumulated",
+                        state.rotation
+                    );
+                }
+                for action in actions {
+                    trace_gesture!(
+                        config,
+                        connection.id,
+                        "rotate, 2 fingers, {} degrees turned: {}",
+                        rotate.step_degrees,
+                        action
+                    );
+                    action.perform(&mut input);
+                    println!("Rotated: {}", action);
+                    connection.feedback(FeedbackKind::Gesture);
+                }
+            }
+            ClientEvent::TypeSequence {
+                text,
+                interval_ms,
+                press_enter,
+            } => {
+                let len = text.0.chars().count();
+                if len == 0 && !press_enter {
+                    connection.count_dropped("nothing_to_type");
+                    explain!(connection, "dropped: nothing to type");
+                    continue;
+                }
+                if len > config.max_type_len {
+                    connection.count_dropped("too_long");
+                    eprintln!(
+                        "Refusing to type {} characters (max_type_len is {}).",
+                        len, config.max_type_len
+                    );
+                    continue;
+                }
+                if let Some(previous) = sequence.take() {
+                    println!(
+                        "Sequence replaced after {} of {} characters",
+                        previous.typed(),
+                        previous.total()
+                    );
+                }
+                sequence = Some(Sequence::new(
+                    connection.id,
+                    &text.0,
+                    interval_ms,
+                    press_enter,
+                ));
+                println!("Sequence of {} characters started", len);
+            }
+            ClientEvent::CancelSequence | ClientEvent::Closed => {
+                if let Some(current) =
+                    sequence.take_if(|current| current.connection == connection.id)
+                {
+                    println!(
+                        "Sequence canceled after {} of {} characters",
+                        current.typed(),
+                        current.total()
+                    );
+                }
+                if let Some(current) =
+                    repeating.take_if(|current| current.connection == connection.id)
+                {
+                    println!("String repeat canceled with {} copies left", current.left());
+                }
+            }
+            // Handled by the connection handler, they never reach this thread.
+            ClientEvent::SetConfig { .. }
+            | ClientEvent::SetStatsReports { .. }
+            | ClientEvent::SetFeedback { .. }
+            | ClientEvent::SetExplain { .. }
+            | ClientEvent::CalibrationMark { .. }
+            | ClientEvent::Media { .. }
+            | ClientEvent::TouchDown { .. }
+            | ClientEvent::TouchUp
+            | ClientEvent::Signed { .. } => {}
+        }
+    }
+    println!("Mouse event thread is terminating due to the closing of the channel.");
+}
+
+fn parse_message(msg: &Message) -> Result<Option<ClientEvent>, Error> {
+    if msg.is_ping() || msg.is_pong() {
+        return Ok(None);
+    }
+    let text = msg
+        .to_str()
+        .map_err(|_| Error::Unsupported("only text messages are accepted"))?;
+    Ok(Some(serde_json::from_str(text)?))
+}
+
+/// Checks a message against `signed_events`: a signed event is verified and
+/// unwrapped, and a sensitive one that is not signed is refused.
+fn unwrap_signed(
+    parsed: Result<Option<ClientEvent>, Error>,
+    signer: Option<&mut EventSigner>,
+) -> Result<Option<ClientEvent>, Error> {
+    match (parsed?, signer) {
+        (Some(ClientEvent::Signed { seq, event, sig }), Some(signer)) => {
+            signer.verify(seq, &event, &sig)?;
+            match serde_json::from_str(&event)? {
+                ClientEvent::Signed { .. } => {
+                    Err(Error::Unsupported("signed events cannot be nested"))
+                }
+                event => Ok(Some(event)),
+            }
+        }
+        (Some(ClientEvent::Signed { .. }), None) => {
+            Err(Error::Unsupported("signed events are not enabled"))
+        }
+        (Some(event), Some(_)) if event.is_sensitive() => {
+            Err(Error::Unauthorized("this event must be signed"))
+        }
+        (event, _) => Ok(event),
+    }
+}
+
+async fn send_event(
+    ws_tx: &mut SplitSink<WebSocket, Message>,
+    event: &ServerEvent,
+) -> Result<(), Error> {
+    let text = serde_json::to_string(event)?;
+    ws_tx.send(Message::text(text)).await?;
+    Ok(())
+}
+
+fn check_supported(event: &ClientEvent, capabilities: &Capabilities) -> Result<(), Error> {
+    match event {
+        ClientEvent::KeyPress { .. }
+        | ClientEvent::TypeString { .. }
+        | ClientEvent::TypeSequence { .. }
+        | ClientEvent::SpecialKey { .. }
+        | ClientEvent::KeyHold { .. }
+            if !capabilities.input.keys =>
+        {
+            Err(Error::Unsupported("the input backend cannot press keys"))
+        }
+        ClientEvent::LocateCursor if !capabilities.input.pointer => Err(Error::Unsupported(
+            "the input backend cannot move the pointer",
+        )),
+        ClientEvent::SetPointerFence { .. } if !capabilities.input.pointer => Err(
+            Error::Unsupported("the input backend cannot move the pointer"),
+        ),
+        ClientEvent::WakeDisplay if !capabilities.wake_display => Err(Error::Unsupported(
+            "the input backend cannot wake the display",
+        )),
+        ClientEvent::MediaKey { .. } if !capabilities.media_keys => Err(Error::Unsupported(
+            "media keys are not available on this platform",
+        )),
+        ClientEvent::SpecialKey { key, .. } if key.to_enigo().is_none() => Err(Error::Unsupported(
+            "this key is not available on this platform",
+        )),
+        ClientEvent::KeyHold { key, .. } if key.to_enigo().is_none() => Err(Error::Unsupported(
+            "this key is not available on this platform",
+        )),
+        ClientEvent::Screenshot { region: false } if !capabilities.screenshot => {
+            Err(Error::Unsupported("no screenshot shortcut is configured"))
+        }
+        ClientEvent::Screenshot { region: true } if !capabilities.screenshot_region => Err(
+            Error::Unsupported("no region screenshot shortcut is configured"),
+        ),
+        ClientEvent::SetDrawingMode {
+            active: true,
+            absolute: true,
+        } if !capabilities.absolute_drawing => Err(Error::Unsupported(
+            "absolute drawing needs the display size, which is unknown on this platform",
+        )),
+        ClientEvent::SetDwellClick { active: true } if !capabilities.feedback => Err(
+            Error::Unsupported("dwell clicking needs the feedback cues that count down to a click"),
+        ),
+        ClientEvent::UndoLast if !capabilities.undo => {
+            Err(Error::Unsupported("no undo shortcut is configured"))
+        }
+        ClientEvent::Rotate { .. } if !capabilities.rotate => {
+            Err(Error::Unsupported("no rotate gesture is configured"))
+        }
+        ClientEvent::PanelAction { .. } if !capabilities.input.keys => {
+            Err(Error::Unsupported("the input backend cannot press keys"))
+        }
+        ClientEvent::ToggleLock { which } if which.to_enigo().is_none() => Err(Error::Unsupported(
+            "this lock key cannot be pressed on this platform",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Applies a `SetConfig` message to the connection and, when the client sent
+/// a device id, remembers the result for its next connection.
+fn update_profile(
+    connection: &Connection,
+    profiles: &Profiles,
+    audit: &AuditLog,
+    update: &ProfileUpdate,
+) -> Result<(), Error> {
+    let mut profile = connection.profile();
+    update.apply(&mut profile)?;
+    connection.set_profile(profile);
+    println!(
+        "Profile of connection {} set to: {:?}",
+        connection.id, profile
+    );
+    if let Some(device) = &connection.device {
+        profiles.save(device, profile);
+    }
+    audit.record(AuditEvent::SettingChanged {
+        connection: Some(connection.id),
+        setting: "profile",
+        value: serde_json::json!(profile),
+    });
+    Ok(())
+}
+
+/// Applies a `SetExplain` message from an admin connection to the
+/// connection it names, or to itself.
+fn set_explain(
+    connection: &Arc<Connection>,
+    connections: &Connections,
+    audit: &AuditLog,
+    target: Option<u64>,
+    active: bool,
+) -> Result<(), Error> {
+    let target = match target {
+        Some(id) => connections.get(id).ok_or(Error::UnknownClient(id))?,
+        None => connection.clone(),
+    };
+    target.set_explain(active);
+    println!(
+        "Explain mode for connection {} set to: {} (by connection {})",
+        target.id, active, connection.id
+    );
+    audit.record(AuditEvent::SettingChanged {
+        connection: Some(target.id),
+        setting: "explain",
+        value: serde_json::json!(active),
+    });
+    Ok(())
+}
+
+/// A key as far as `key_debounce_ms` is concerned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PressedKey {
+    Char(char),
+    Special(SpecialKey),
+}
+
+/// Whether a key press repeats the previous one within `window`, as when a
+/// browser reports one tap twice. The first of a pair is remembered, so a
+/// held-down run of ghosts cannot stretch the window.
+fn is_ghost_key(
+    last_key: &mut Option<(PressedKey, Instant)>,
+    key: PressedKey,
+    window: Duration,
+) -> bool {
+    let now = Instant::now();
+    if let Some((last, at)) = *last_key {
+        if last == key && now.duration_since(at) < window {
+            return true;
+        }
+    }
+    *last_key = Some((key, now));
+    false
+}
+
+/// How often a client that asked for them is sent `Stats`.
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Waits for the next tick, or forever when the timer is disabled.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits until `deadline`, or forever when there is none.
+async fn until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Close code for a session ended by `max_session_secs`: policy violation.
+const SESSION_EXPIRED_CLOSE_CODE: u16 = 1008;
+
+/// Close code for a message over `max_message_bytes`: message too big.
+const MESSAGE_TOO_LARGE_CLOSE_CODE: u16 = 1009;
+
+/// Whether reading failed because a message was over `max_message_bytes`.
+/// The rest of it is still unread, so the connection cannot go on.
+fn is_too_large(e: &warp::Error) -> bool {
+    e.source()
+        .and_then(|source| source.downcast_ref::<tungstenite::Error>())
+        .is_some_and(|e| matches!(e, tungstenite::Error::Capacity(_)))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_websocket(
+    socket: WebSocket,
+    connection: Arc<Connection>,
+    config: Arc<Config>,
+    info: Arc<ServerInfo>,
+    profiles: Profiles,
+    audit: AuditLog,
+    media: MediaController,
+    mouse_event_sender: EventSender,
+    mut notices: UnboundedReceiver<ServerEvent>,
+    token: Option<String>,
+    permission: Permission,
+    connections: Connections,
+) -> Result<(), Error> {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    // Pairing is required along with `signed_events`, so the token is there.
+    let mut signer = None;
+    let signing = token.filter(|_| config.signed_events).map(|token| {
+        let offer = SigningOffer::new(SIGNED_EVENTS);
+        signer = Some(EventSigner::new(&token, &offer.nonce));
+        offer
+    });
+    send_event(
+        &mut ws_tx,
+        &ServerEvent::Hello {
+            info: Box::new((*info).clone()),
+            locale: connection.locale,
+            signing,
+            permission,
+        },
+    )
+    .await?;
+
+    let mut depth_report = config
+        .queue_depth_report_ms
+        .filter(|ms| *ms > 0)
+        .map(|ms| tokio::time::interval(Duration::from_millis(ms)));
+    let mut reported_depth = 0;
+
+    let mut now_playing_poll = media
+        .has_mpris()
+        .then(|| tokio::time::interval(NOW_PLAYING_POLL_INTERVAL));
+    let mut reported_now_playing: Option<NowPlaying> = None;
+
+    let mut lock_state_poll =
+        locks::CAN_WATCH.then(|| tokio::time::interval(LOCK_STATE_POLL_INTERVAL));
+    let mut reported_lock_state: Option<LockStates> = None;
+
+    let key_debounce = config.key_debounce_ms.map(Duration::from_millis);
+    let mut last_key: Option<(PressedKey, Instant)> = None;
+
+    // Off until the client asks for them with `SetStatsReports`; each report
+    // also pings the client to measure the round trip.
+    let mut stats_report: Option<Interval> = None;
+    let mut ping_sent: Option<Instant> = None;
+
+    let session_end = config
+        .max_session_secs
+        .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
+
+    loop {
+        tokio::select! {
+            message_result = ws_rx.next() => {
+                let msg = match message_result {
+                    Some(Err(e)) if is_too_large(&e) => {
+                        let e = Error::MessageTooLarge(config.max_message_bytes);
+                        connection.count_refused(e.code());
+                        e.log();
+                        send_event(&mut ws_tx, &ServerEvent::error(&e, connection.locale)).await?;
+                        ws_tx
+                            .send(Message::close_with(MESSAGE_TOO_LARGE_CLOSE_CODE, "message too big"))
+                            .await?;
+                        break;
+                    }
+                    Some(message_result) => message_result?,
+                    None => break,
+                };
+                let received_at = Instant::now();
+                if msg.is_close() {
+                    break;
+                }
+                if msg.is_pong() {
+                    if let Some(sent) = ping_sent.take() {
+                        connection.set_rtt(sent.elapsed());
+                    }
+                    continue;
+                }
+                if !msg.is_ping() {
+                    connection.count_received();
+                }
+
+                // Media actions run here; only a fallback media key goes on to
+                // the mouse event thread.
+                let parsed = parse_message(&msg);
+                if let Ok(Some(_)) = parsed {
+                    connection.count_parsed();
+                }
+                let parsed = unwrap_signed(parsed, signer.as_mut());
+                // Pings and pongs are left out of the event log.
+                let mut logged = match &parsed {
+                    Ok(Some(event)) => Some(Logged::new(event.kind(), event.describe())),
+                    Ok(None) => None,
+                    Err(_) => Some(Logged::default()),
+                };
+                // Rewritten first, so that every later step sees the event as
+                // `event_transforms` left it.
+                let parsed = parsed.map(|event| {
+                    let event = event?;
+                    let kind = event.kind();
+                    let transformed = transforms::apply(&config.event_transforms, event);
+                    if transformed.is_none() {
+                        connection.count_refused("transform_drop");
+                        if let Some(logged) = logged.take() {
+                            connection.log_event(logged, Outcome::Dropped { reason: "transform_drop" });
+                        }
+                        explain!(connection, "dropped: {} by event_transforms", kind);
+                    }
+                    transformed
+                });
+                // A panel's media button goes the way of the media page's.
+                let parsed = parsed.and_then(|event| match event {
+                    Some(ClientEvent::PanelAction { id }) => match panels::find(&config.panels, &id) {
+                        Some(PanelButton {
+                            action: ButtonAction::Media(action),
+                            ..
+                        }) => Ok(Some(ClientEvent::Media {
+                            action: *action,
+                            player: None,
+                        })),
+                        Some(_) => Ok(Some(ClientEvent::PanelAction { id })),
+                        None => Err(Error::UnknownButton(id)),
+                    },
+                    event => Ok(event),
+                });
+                let parsed = match parsed {
+                    Ok(Some(ClientEvent::Media { action, player })) => media
+                        .control(action, player.as_deref())
+                        .await
+                        .map(|key| key.map(|key| ClientEvent::MediaKey { key })),
+                    parsed => parsed,
+                };
+                let parsed = parsed.and_then(|event| match event {
+                    Some(ClientEvent::SetConfig { update }) => {
+                        update_profile(&connection, &profiles, &audit, &update).map(|_| None)
+                    }
+                    Some(ClientEvent::SetStatsReports { active }) => {
+                        stats_report = active.then(|| tokio::time::interval(STATS_REPORT_INTERVAL));
+                        Ok(None)
+                    }
+                    Some(ClientEvent::SetFeedback { .. }) if !info.capabilities.feedback => {
+                        Err(Error::Unsupported("feedback cues are disabled"))
+                    }
+                    Some(ClientEvent::SetFeedback { active }) => {
+                        connection.set_feedback(active);
+                        Ok(None)
+                    }
+                    Some(ClientEvent::SetExplain { .. }) if permission != Permission::Admin => {
+                        Err(Error::Unauthorized("needs the admin secret"))
+                    }
+                    Some(ClientEvent::SetExplain {
+                        active,
+                        connection: id,
+                    }) => set_explain(&connection, &connections, &audit, id, active).map(|_| None),
+                    Some(ClientEvent::CalibrationMark { label }) => {
+                        connection.mark_calibration(label).map(|_| None)
+                    }
+                    // Pointer input while calibrating is measured, not acted on.
+                    Some(event) if connection.record_calibration(&event) => {
+                        if let Some(logged) = logged.take() {
+                            connection.log_event(logged, Outcome::Dropped { reason: "calibrating" });
+                        }
+                        Ok(None)
+                    }
+                    Some(event) => check_supported(&event, &info.capabilities).map(|_| Some(event)),
+                    None => Ok(None),
+                });
+                match parsed {
+                    Ok(Some(event))
+                        if key_debounce.zip(event.pressed_key()).is_some_and(
+                            |(window, (key, repeat))| {
+                                is_ghost_key(&mut last_key, key, window) && !repeat
+                            },
+                        ) =>
+                    {
+                        connection.count_refused("debounced");
+                        if let Some(logged) = logged {
+                            connection.log_event(logged, Outcome::Dropped { reason: "debounced" });
+                        }
+                        let total = connection.count_ghost_key();
+                        println!(
+                            "Duplicate {} dropped on connection {}, {} so far",
+                            event.kind(),
+                            connection.id,
+                            total
+                        );
+                    }
+                    Ok(Some(event)) => {
+                        mouse_event_sender.send(Envelope {
+                            connection: connection.clone(),
+                            event,
+                            received_at,
+                            logged: logged.map(|logged| connection.log_event(logged, Outcome::Applied)),
+                        })?;
+                        connection.count_forwarded();
+                    }
+                    Ok(None) => {
+                        if let Some(logged) = logged {
+                            connection.log_event(logged, Outcome::Applied);
+                        }
+                    }
+                    Err(e) => {
+                        connection.count_refused(e.code());
+                        if let Some(logged) = logged {
+                            connection.log_event(logged, Outcome::Error { code: e.code() });
+                        }
+                        e.log();
+                        send_event(&mut ws_tx, &ServerEvent::error(&e, connection.locale)).await?;
+                        connection.feedback(FeedbackKind::Error);
+                    }
+                }
+            }
+            Some(notice) = notices.recv() => {
+                send_event(&mut ws_tx, &notice).await?;
+            }
+            _ = tick(&mut depth_report) => {
+                let depth = mouse_event_sender.depth();
+                if depth != reported_depth {
+                    reported_depth = depth;
+                    send_event(&mut ws_tx, &ServerEvent::QueueDepth { depth }).await?;
+                }
+            }
+            _ = tick(&mut now_playing_poll) => {
+                let now_playing = media.now_playing().await;
+                let elapsed_ms = NOW_PLAYING_POLL_INTERVAL.as_millis() as u64;
+                let changed = match (&now_playing, &reported_now_playing) {
+                    (Some(now_playing), Some(reported)) => now_playing.differs_from(reported, elapsed_ms),
+                    (now_playing, reported) => now_playing.is_some() != reported.is_some(),
+                };
+                if changed {
+                    if let Some(now_playing) = &now_playing {
+                        send_event(&mut ws_tx, &ServerEvent::NowPlaying(now_playing.clone())).await?;
+                    }
+                }
+                reported_now_playing = now_playing;
+            }
+            _ = tick(&mut stats_report) => {
+                send_event(&mut ws_tx, &ServerEvent::Stats(connection.stats())).await?;
+                ping_sent = Some(Instant::now());
+                ws_tx.send(Message::ping(Vec::new())).await?;
+            }
+            _ = until(session_end) => {
+                // The input thread lets go of anything still held once the
+                // handler returns.
+                let e = Error::SessionExpired(config.max_session_secs.unwrap_or_default());
+                println!("Connection {}: {}", connection.id, e);
+                send_event(&mut ws_tx, &ServerEvent::error(&e, connection.locale)).await?;
+                ws_tx
+                    .send(Message::close_with(SESSION_EXPIRED_CLOSE_CODE, "session expired"))
+                    .await?;
+                break;
+            }
+            _ = tick(&mut lock_state_poll) => {
+                let states = locks::query();
+                if reported_lock_state != Some(states) {
+                    reported_lock_state = Some(states);
+                    send_event(&mut ws_tx, &ServerEvent::LockState(states)).await?;
+                }
+            }
+        }
+    }
+    println!("WebSocket connection closed.");
+    Ok(())
+}
+
+/// The port the server listens on, on every address or only loopback.
+const PORT: u16 = 8088;
+
+/// The admin secret given by `--admin-secret <secret>`, which takes
+/// precedence over `admin_secret` in the config.
+fn admin_secret_arg() -> Result<Option<AdminSecret>, Error> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--admin-secret");
+    if args.next().is_none() {
+        return Ok(None);
+    }
+    args.next()
+        .map(|secret| Some(AdminSecret::from(secret)))
+        .ok_or_else(|| Error::Config("--admin-secret needs a secret".to_string()))
+}
+
+/// The input backend named by `--backend <name>`, which is then the only one
+/// tried.
+fn backend_arg() -> Result<Option<BackendKind>, Error> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--backend");
+    if args.next().is_none() {
+        return Ok(None);
+    }
+    let name = args
+        .next()
+        .ok_or_else(|| Error::InputBackend("--backend needs a backend name".to_string()))?;
+    name.parse().map(Some).map_err(Error::InputBackend)
+}
+
+#[tokio::main]
+async fn main() {
+    if std::env::args().any(|arg| arg == "--self-test") {
+        match self_test::run() {
+            Ok(()) => {
+                println!("Self-test passed: the pointer should have traced a small square.");
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Self-test failed: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    if std::env::args().any(|arg| arg == "--benchmark") {
+        match benchmark::run(benchmark::MESSAGES) {
+            Ok(timings) => {
+                println!(
+                    "Reading {} client messages per case, on average:",
+                    benchmark::MESSAGES
+                );
+                let baseline = timings[0].per_message.as_nanos() as i128;
+                for timing in &timings {
+                    let nanos = timing.per_message.as_nanos() as i128;
+                    let overhead = if timing.case == timings[0].case {
+                        String::new()
+                    } else {
+                        format!(" ({:+} ns over parsing only)", nanos - baseline)
+                    };
+                    println!("  {:<28} {:>6} ns{}", timing.case, nanos, overhead);
+                }
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Benchmark failed: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    println!("{}", BUILD.banner());
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+    if std::env::args().any(|arg| arg == "--local-only") {
+        config.local_only = true;
+    }
+    // Every peer is this computer then, which the subnets might not allow.
+    if config.local_only && !config.allowed_subnets.is_empty() {
+        println!("Local only: allowed_subnets is ignored, as only this computer can connect.");
+        config.allowed_subnets.clear();
+    }
+    let config = Arc::new(config);
+
+    let admin = match admin_secret_arg().and_then(|secret| {
+        Admin::load(
+            secret.or_else(|| config.admin_secret.clone()),
+            config.admin_from_host,
+        )
+    }) {
+        Ok(admin) => admin,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+
+    let instance_id = instance::new_instance_id();
+    if let Err(e) = instance::claim(&instance_id, PORT, InstanceMode::from_args(), &admin) {
+        e.log();
+        process::exit(1);
+    }
+
+    let profiles = match Profiles::load() {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+
+    let pairing = match Pairing::load(config.require_pairing) {
+        Ok(pairing) => pairing,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+
+    let audit = match AuditLog::start(&config) {
+        Ok(audit) => audit,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+
+    let last_processed_time = Arc::new(AtomicU64::new(0));
+
+    let (mouse_event_sender, mouse_event_receiver) = event_channel(config.key_priority);
+    let connections = Connections::default();
+
+    let forced_backend = match backend_arg() {
+        Ok(forced) => forced,
+        Err(e) => {
+            e.log();
+            process::exit(1);
+        }
+    };
+
+    let worker_config = config.clone();
+    let worker_audit = audit.clone();
+    let worker_connections = connections.clone();
+    // The input backend is set up on the thread that uses it, which some
+    // platforms require; the server only starts once it is ready.
+    let (ready_sender, ready) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let input = match Input::detect(&worker_config.input_backends, forced_backend) {
+            Ok((input, report)) => {
+                let _ = ready_sender.send(Ok((report, input.health())));
+                input
+            }
+            Err(e) => {
+                let _ = ready_sender.send(Err(e));
+                return;
+            }
+        };
+        process_mouse_events(
+            input,
+            mouse_event_receiver,
+            last_processed_time,
+            worker_config,
+            worker_audit,
+            worker_connections,
+        );
+    });
+    let (input_report, input_health) = match ready.recv() {
+        Ok(Ok(ready)) => ready,
+        Ok(Err(e)) => {
+            e.log();
+            process::exit(1);
+        }
+        Err(_) => process::exit(1),
+    };
+
+    let media = MediaController::detect().await;
+    let latest_release = LatestRelease::default();
+    if std::env::args().any(|arg| arg == "--check-updates") {
+        latest_release.check_in_background();
+    }
+    let mut info = ServerInfo::detect(
+        Capabilities::detect(&config, &media, input_report),
+        input_report,
+        latest_release,
+    );
+    info.capability_changes = probe_history::compare_with_previous(
+        &info,
+        std::env::args().any(|arg| arg == "--reset-probe-history"),
+    );
+    info.instance_id = instance_id.clone();
+    let info = Arc::new(info);
+
+    let address = if config.local_only {
+        println!(
+            "Listening on 127.0.0.1:{} only; other devices have to connect through a tunnel.",
+            PORT
+        );
+        Ipv4Addr::LOCALHOST
+    } else {
+        Ipv4Addr::UNSPECIFIED
+    };
+    let shutdown = Arc::new(Notify::new());
+    let state = AppState {
+        config,
+        info,
+        connections,
+        events: mouse_event_sender,
+        profiles,
+        audit,
+        media,
+        pairing,
+        admin,
+        input_health,
+        shutdown: shutdown.clone(),
+    };
+    // Stops on `/api/shutdown`, e.g. from `--replace`, or Ctrl-C. The lock is
+    // released before the port closes, as an instance replacing this one
+    // takes over as soon as the port stops answering.
+    let lock_id = instance_id.clone();
+    let bound = warp::serve(server::router::build_routes(state)).try_bind_with_graceful_shutdown(
+        (address, PORT),
+        async move {
+            tokio::select! {
+                _ = shutdown.notified() => {}
+                _ = tokio::signal::ctrl_c() => println!("Shutting down."),
+            }
+            instance::release(&lock_id);
+        },
+    );
+    match bound {
+        Ok((_, server)) => server.await,
+        Err(e) => {
+            eprintln!(
+                "Error: cannot listen on port {}: {}",
+                PORT,
+                e.source().unwrap_or(&e)
+            );
+            instance::release(&instance_id);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actions::Action;
+    use connection::PowerState;
+    use gestures::DoubleTapZoom;
+    use input::{InputAbilities, InputReport, Mock};
+    use profiles::Profile;
+    use std::thread::JoinHandle;
+    use warp::Filter;
+
+    fn event(json: &str) -> ClientEvent {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// A mouse event thread fed from one connection, injecting into a mock.
+    struct Worker {
+        mock: Mock,
+        sender: EventSender,
+        connection: Arc<Connection>,
+        connections: Connections,
+        notices: UnboundedReceiver<ServerEvent>,
+        thread: JoinHandle<()>,
+    }
+
+    impl Worker {
+        fn start(config: Config) -> Self {
+            let (input, mock) = Input::mock();
+            let (sender, receiver) = event_channel(config.key_priority);
+            let connections = Connections::default();
+            let (connection, notices) =
+                connections.register(None, Default::default(), i18n::BASE_LOCALE);
+            let config = Arc::new(config);
+            let audit = AuditLog::start(&config).unwrap();
+            // As if the last move was just now, so that the first one sent
+            // is not taken for the start of a new touch.
+            let last_processed_time = Arc::new(AtomicU64::new(current_time_millis() as u64));
+            let worker_connections = connections.clone();
+            let thread = thread::spawn(move || {
+                process_mouse_events(
+                    input,
+                    receiver,
+                    last_processed_time,
+                    config,
+                    audit,
+                    worker_connections,
+                )
+            });
+            Worker {
+                mock,
+                sender,
+                connection,
+                connections,
+                notices,
+                thread,
+            }
+        }
+
+        fn send(&self, json: &str) {
+            self.send_event(event(json));
+        }
+
+        fn send_event(&self, event: ClientEvent) {
+            self.sender
+                .send(Envelope {
+                    connection: self.connection.clone(),
+                    event,
+                    received_at: Instant::now(),
+                    logged: None,
+                })
+                .unwrap();
+        }
+
+        /// Waits until the thread has handled everything sent so far.
+        fn settle(&self) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.sender.depth() > 0 {
+                assert!(Instant::now() < deadline, "the mouse event thread is stuck");
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        /// Closes the queue and returns the notices the connection got, as
+        /// JSON.
+        fn finish(mut self) -> Vec<serde_json::Value> {
+            drop(self.sender);
+            self.thread.join().unwrap();
+            let mut notices = Vec::new();
+            while let Ok(notice) = self.notices.try_recv() {
+                notices.push(serde_json::to_value(notice).unwrap());
+            }
+            notices
+        }
+    }
+
+    #[test]
+    fn describe_leaves_out_keys_and_text() {
+        for json in [
+            r#"{"type":"KeyPress","key":"x"}"#,
+            r#"{"type":"SpecialKey","key":"Enter"}"#,
+            r#"{"type":"TypeString","text":"hunter2 x","repeat":2}"#,
+            r#"{"type":"TypeSequence","text":"hunter2 x","interval_ms":50}"#,
+            r#"{"type":"Signed","seq":3,"event":"{\"type\":\"TypeString\",\"text\":\"hunter2 x\"}","sig":"00"}"#,
+        ] {
+            let described = event(json).describe();
+            assert!(
+                !described.contains("hunter2") && !described.contains("\"x\""),
+                "{}",
+                described
+            );
+            assert!(!described.contains("Enter"), "{}", described);
+        }
+        assert_eq!(
+            event(r#"{"type":"TypeString","text":"hunter2 x","repeat":2}"#).describe(),
+            "TypeString { text: <9 characters>, repeat: Some(2) }"
+        );
+    }
+
+    #[test]
+    fn parse_message_refuses_bad_json() {
+        let parsed = parse_message(&Message::text(r#"{"type":"MouseClick""#));
+        assert!(matches!(parsed, Err(Error::Parse(_))));
+        let parsed = parse_message(&Message::text(r#"{"type":"NoSuchEvent"}"#));
+        assert!(matches!(parsed, Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn kinds_are_the_types_clients_send() {
+        // Serde lists every type it accepts when given one it does not.
+        let e = serde_json::from_str::<ClientEvent>(r#"{"type":"NoSuchEvent"}"#).unwrap_err();
+        let e = e.to_string();
+        let (_, expected) = e.split_once("expected one of ").unwrap();
+        let accepted: Vec<&str> = expected.split('`').skip(1).step_by(2).collect();
+        assert_eq!(accepted, ClientEvent::KINDS);
+    }
+
+    #[test]
+    fn dwell_clicking_needs_feedback() {
+        let report = InputReport {
+            backend: BackendKind::Enigo,
+            abilities: InputAbilities {
+                pointer: true,
+                keys: true,
+                scroll: true,
+            },
+            display: None,
+        };
+        let capabilities = |feedback| {
+            let config = Config {
+                feedback,
+                ..Config::default()
+            };
+            Capabilities::detect(&config, &MediaController::default(), report)
+        };
+        let on = event(r#"{"type":"SetDwellClick","active":true}"#);
+        let off = event(r#"{"type":"SetDwellClick","active":false}"#);
+        assert!(check_supported(&on, &capabilities(true)).is_ok());
+        assert!(matches!(
+            check_supported(&on, &capabilities(false)),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(check_supported(&off, &capabilities(false)).is_ok());
+    }
+
+    #[test]
+    fn parse_message_refuses_binary() {
+        let parsed = parse_message(&Message::binary(vec![1, 2, 3]));
+        assert!(matches!(parsed, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn parse_message_skips_pings() {
+        assert!(matches!(parse_message(&Message::ping(vec![])), Ok(None)));
+        assert!(matches!(parse_message(&Message::pong(vec![])), Ok(None)));
+    }
+
+    #[test]
+    fn parse_message_reads_events() {
+        let parsed = parse_message(&Message::text(r#"{"type":"MouseClick","button":"Left"}"#));
+        assert_eq!(
+            parsed.unwrap(),
+            Some(ClientEvent::MouseClick {
+                button: MouseButton::Left,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_frames_are_too_large() {
+        let route = warp::ws().map(|ws: warp::ws::Ws| {
+            ws.max_message_size(16)
+                .max_frame_size(16)
+                .on_upgrade(|socket| async move {
+                    let (mut tx, mut rx) = socket.split();
+                    let mut verdicts = Vec::new();
+                    while let Some(result) = rx.next().await {
+                        match result {
+                            Ok(msg) => verdicts.push(format!("read {}", msg.to_str().unwrap())),
+                            Err(e) => {
+                                verdicts.push(format!("too large: {}", is_too_large(&e)));
+                                break;
+                            }
+                        }
+                    }
+                    tx.send(Message::text(verdicts.join(", "))).await.unwrap();
+                })
+        });
+        let mut client = warp::test::ws().handshake(route).await.unwrap();
+        client.send_text("small").await;
+        client.send_text("x".repeat(17)).await;
+        let reply = client.recv().await.unwrap();
+        assert_eq!(reply.to_str(), Ok("read small, too large: true"));
+    }
+
+    fn signed(signer: &EventSigner, seq: u64, event: &str) -> Result<Option<ClientEvent>, Error> {
+        let sig = signer.signature(seq, event);
+        Ok(Some(ClientEvent::Signed {
+            seq,
+            event: event.to_string(),
+            sig,
+        }))
+    }
+
+    #[test]
+    fn unwrap_signed_verifies_and_unwraps() {
+        let mut signer = EventSigner::new("credential", "nonce");
+        let click = r#"{"type":"MouseClick","button":"Left"}"#;
+        let unwrapped = unwrap_signed(signed(&signer, 1, click), Some(&mut signer));
+        assert_eq!(unwrapped.unwrap(), Some(event(click)));
+        // Moves are sent as they are.
+        let unwrapped = unwrap_signed(Ok(Some(mouse_move(1.0, 2.0))), Some(&mut signer));
+        assert_eq!(unwrapped.unwrap(), Some(mouse_move(1.0, 2.0)));
+        let unsigned = unwrap_signed(Ok(Some(event(click))), Some(&mut signer));
+        assert!(matches!(unsigned, Err(Error::Unauthorized(_))));
+    }
+
+    #[test]
+    fn unwrap_signed_refuses_nested_events() {
+        let mut signer = EventSigner::new("credential", "nonce");
+        let click = r#"{"type":"MouseClick","button":"Left"}"#;
+        let inner = serde_json::json!({
+            "type": "Signed",
+            "seq": 1,
+            "event": click,
+            "sig": signer.signature(1, click),
+        })
+        .to_string();
+        let nested = unwrap_signed(signed(&signer, 2, &inner), Some(&mut signer));
+        assert!(matches!(nested, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn unwrap_signed_needs_signing_on() {
+        let signer = EventSigner::new("credential", "nonce");
+        let click = r#"{"type":"MouseClick","button":"Left"}"#;
+        let parsed = unwrap_signed(signed(&signer, 1, click), None);
+        assert!(matches!(parsed, Err(Error::Unsupported(_))));
+        let parsed = unwrap_signed(Ok(Some(event(click))), None);
+        assert_eq!(parsed.unwrap(), Some(event(click)));
+    }
+
+    fn released(notices: &[serde_json::Value]) -> usize {
+        notices
+            .iter()
+            .filter(|notice| notice["type"] == "ButtonReleased")
+            .count()
+    }
+
+    #[test]
+    fn abandoned_tap_drag_is_released() {
+        let worker = Worker::start(Config {
+            tap_drag: Some(TapDrag {
+                tap_window_ms: 200,
+                hold_ms: 20,
+            }),
+            held_button_timeout: HeldButtonTimeout {
+                enabled: true,
+                timeout_ms: 100,
+                idle_ms: 50,
+            },
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"MouseClick","button":"Left"}"#);
+        worker.send(r#"{"type":"TouchDown"}"#);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(worker.mock.calls(), ["button Press Left"]);
+        // The finger never lifts.
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            worker.mock.calls(),
+            ["button Press Left", "button Release Left"]
+        );
+        assert_eq!(released(&worker.finish()), 1);
+    }
+
+    #[test]
+    fn click_drag_ends_when_movement_pauses() {
+        let worker = Worker::start(Config {
+            click_drag_window_ms: Some(50),
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"MouseClick","button":"Left"}"#);
+        worker.send(r#"{"type":"MouseMove","dx":5,"dy":0,"sx":0,"sy":0,"touches":1}"#);
+        worker.settle();
+        assert_eq!(worker.mock.calls()[0], "button Press Left");
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            worker.mock.calls().last().map(String::as_str),
+            Some("button Release Left")
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn locked_scroll_stays_vertical_despite_wobble() {
+        let worker = Worker::start(Config {
+            scroll_mode: ScrollMode::Fast,
+            scroll_axes: ScrollAxes::Locked,
+            ..Config::default()
+        });
+        for (dx, dy) in [(2.0, 9.0), (-3.0, 12.0), (4.0, 10.0), (-2.0, 11.0)] {
+            worker.send(&format!(
+                r#"{{"type":"MouseMove","dx":{},"dy":{},"sx":0.5,"sy":1,"touches":2}}"#,
+                dx, dy
+            ));
+        }
+        worker.settle();
+        let calls = worker.mock.calls();
+        assert!(!calls.is_empty());
+        assert!(
+            calls.iter().all(|call| call.starts_with("scroll Vertical")),
+            "{:?}",
+            calls
+        );
+        worker.finish();
+    }
+
+    /// Lines a two-finger gesture of five moves scrolls in `Fast` mode.
+    fn lines_scrolled(scroll_deceleration: Option<scroll::ScrollDeceleration>, sy: f64) -> i32 {
+        let worker = Worker::start(Config {
+            scroll_mode: ScrollMode::Fast,
+            scroll_interval_ms: 0,
+            scroll_deceleration,
+            ..Config::default()
+        });
+        for _ in 0..5 {
+            worker.send(&format!(
+                r#"{{"type":"MouseMove","dx":0,"dy":40,"sx":0,"sy":{},"touches":2}}"#,
+                sy
+            ));
+        }
+        worker.settle();
+        let lines = vertical_scrolls(&worker.mock.calls()).iter().sum();
+        worker.finish();
+        lines
+    }
+
+    fn vertical_scrolls(calls: &[String]) -> Vec<i32> {
+        calls
+            .iter()
+            .filter_map(|call| call.strip_prefix("scroll Vertical "))
+            .map(|length| length.parse().unwrap())
+            .collect()
+    }
+
+    /// A worker in the middle of a two-finger gesture, past its first move,
+    /// which only places the fingers.
+    fn scroll_worker(scroll_interval_ms: u64, max_scroll_per_event: Option<u16>) -> Worker {
+        let worker = Worker::start(Config {
+            scroll_mode: ScrollMode::Fast,
+            scroll_interval_ms,
+            max_scroll_per_event,
+            ..Config::default()
+        });
+        scroll_down(&worker, 0.0);
+        worker
+    }
+
+    fn scroll_down(worker: &Worker, dy: f64) {
+        worker.send(&format!(
+            r#"{{"type":"MouseMove","dx":0,"dy":{},"sx":0,"sy":0.5,"touches":2}}"#,
+            dy
+        ));
+    }
+
+    #[test]
+    fn steady_scrolling_sends_everything_whatever_the_timing() {
+        let worker = scroll_worker(40, None);
+        for gap_ms in [0, 0, 5, 70, 0, 30, 0, 100] {
+            thread::sleep(Duration::from_millis(gap_ms));
+            scroll_down(&worker, 15.0);
+        }
+        worker.settle();
+        thread::sleep(Duration::from_millis(100));
+        let sent = vertical_scrolls(&worker.mock.calls());
+        assert_eq!(sent.iter().sum::<i32>(), 12, "{:?}", sent);
+        worker.finish();
+    }
+
+    #[test]
+    fn zero_scroll_interval_scrolls_on_every_event() {
+        let worker = scroll_worker(0, None);
+        for _ in 0..4 {
+            scroll_down(&worker, 20.0);
+        }
+        worker.settle();
+        assert_eq!(vertical_scrolls(&worker.mock.calls()), [2, 2, 2, 2]);
+        worker.finish();
+    }
+
+    #[test]
+    fn steady_scrolling_keeps_to_the_cap() {
+        let worker = scroll_worker(30, Some(3));
+        for _ in 0..4 {
+            scroll_down(&worker, 30.0);
+        }
+        worker.settle();
+        thread::sleep(Duration::from_millis(150));
+        let sent = vertical_scrolls(&worker.mock.calls());
+        assert!(sent.iter().all(|&lines| lines <= 3), "{:?}", sent);
+        assert_eq!(sent.iter().sum::<i32>(), 12, "{:?}", sent);
+        worker.finish();
+    }
+
+    #[test]
+    fn a_fast_flick_scrolls_no_more_than_the_cap() {
+        for (max, expected) in [(None, 20), (Some(3), 3)] {
+            let worker = scroll_worker(0, max);
+            scroll_down(&worker, 200.0);
+            scroll_down(&worker, -200.0);
+            worker.settle();
+            assert_eq!(
+                vertical_scrolls(&worker.mock.calls()),
+                [expected, -expected]
+            );
+            worker.finish();
+        }
+    }
+
+    fn momentum_worker(cancel_on_touch: bool) -> Worker {
+        let worker = Worker::start(Config {
+            scroll_mode: ScrollMode::Fast,
+            scroll_interval_ms: 0,
+            momentum: Some(momentum::Momentum {
+                tick_ms: 10,
+                cancel_on_touch,
+                ..momentum::Momentum::default()
+            }),
+            ..Config::default()
+        });
+        scroll_down(&worker, 0.0);
+        worker
+    }
+
+    /// A two-finger flick down at `sy`, which sets how fast it coasts.
+    fn flick(worker: &Worker, sy: f64) {
+        worker.send(&format!(
+            r#"{{"type":"MouseMove","dx":0,"dy":10,"sx":0,"sy":{},"touches":2}}"#,
+            sy
+        ));
+    }
+
+    #[test]
+    fn scrolling_coasts_on_after_the_fingers_stop() {
+        let worker = momentum_worker(true);
+        flick(&worker, 3.0);
+        worker.settle();
+        thread::sleep(Duration::from_millis(400));
+        let coasted = vertical_scrolls(&worker.mock.calls());
+        // The flick's own line, then steps slowing down from 3 a tick.
+        assert_eq!(coasted[..3], [1, 3, 2]);
+        assert!(coasted[1..].iter().all(|&lines| (0..=3).contains(&lines)));
+        assert!(coasted.len() > 10, "{:?}", coasted);
+        // Until it comes to rest.
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(vertical_scrolls(&worker.mock.calls()), coasted);
+        worker.finish();
+    }
+
+    #[test]
+    fn deceleration_scrolls_fast_flicks_less() {
+        let deceleration = || Some(scroll::ScrollDeceleration::default());
+        // Slow gestures scroll the same either way.
+        assert_eq!(
+            lines_scrolled(None, 0.5),
+            lines_scrolled(deceleration(), 0.5)
+        );
+        // A fast flick scrolls `min_factor` of its usual amount.
+        let usual = lines_scrolled(None, 4.0);
+        assert_eq!(usual, lines_scrolled(None, 0.5));
+        assert!(usual > 0);
+        assert_eq!(lines_scrolled(deceleration(), 4.0), usual * 2 / 5);
+    }
+
+    fn mouse_move(dx: f64, dy: f64) -> ClientEvent {
+        ClientEvent::MouseMove {
+            dx,
+            dy,
+            sx: 0.0,
+            sy: 0.0,
+            touches: 1,
+            pressure: None,
+            radius: None,
+            x: None,
+            y: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    #[test]
+    fn non_finite_values_are_found() {
+        assert!(!mouse_move(3.0, -2.5).has_non_finite());
+        assert!(mouse_move(f64::NAN, 0.0).has_non_finite());
+        assert!(mouse_move(0.0, f64::NEG_INFINITY).has_non_finite());
+        let with_position = ClientEvent::MouseMove {
+            dx: 0.0,
+            dy: 0.0,
+            sx: 0.0,
+            sy: 0.0,
+            touches: 1,
+            pressure: Some(0.5),
+            radius: Some(10.0),
+            x: Some(f64::INFINITY),
+            y: Some(4.0),
+            width: Some(400.0),
+            height: Some(300.0),
+        };
+        assert!(with_position.has_non_finite());
+        assert!(ClientEvent::TouchDown {
+            radius: Some(f64::NAN)
+        }
+        .has_non_finite());
+        assert!(!ClientEvent::TouchDown { radius: None }.has_non_finite());
+        assert!(!ClientEvent::Rotate { angle_delta: 5.0 }.has_non_finite());
+    }
+
+    #[test]
+    fn non_finite_moves_are_dropped() {
+        let worker = Worker::start(Config::default());
+        // The first move in a while only tells where the finger is.
+        worker.send_event(mouse_move(2.0, 0.0));
+        worker.send_event(mouse_move(f64::NAN, 1.0));
+        worker.send_event(mouse_move(2.0, 0.0));
+        worker.settle();
+        let calls = worker.mock.calls();
+        assert_eq!(calls.len(), 1, "{:?}", calls);
+        assert!(calls[0].starts_with("move Rel "), "{:?}", calls);
+        worker.finish();
+    }
+
+    #[test]
+    fn time_scale_normalizes_to_the_reference() {
+        assert_eq!(time_scale(16.0, 16.0), 1.0);
+        assert_eq!(time_scale(8.0, 16.0), 0.5);
+        assert_eq!(time_scale(32.0, 16.0), 2.0);
+        // A stray interval is kept within bounds.
+        assert_eq!(time_scale(1.0, 16.0), MIN_TIME_SCALE);
+        assert_eq!(time_scale(1000.0, 16.0), MAX_TIME_SCALE);
+        // An unknown duration leaves the move as it is.
+        for unknown in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(time_scale(unknown, 16.0), 1.0);
+        }
+    }
+
+    fn tap_drag_worker() -> Worker {
+        Worker::start(Config {
+            tap_drag: Some(TapDrag {
+                tap_window_ms: 150,
+                hold_ms: 60,
+            }),
+            ..Config::default()
+        })
+    }
+
+    const TAP: &str = r#"{"type":"MouseClick","button":"Left"}"#;
+    const TOUCH_DOWN: &str = r#"{"type":"TouchDown"}"#;
+    const TOUCH_UP: &str = r#"{"type":"TouchUp"}"#;
+
+    /// The pointer moves of the mock's calls, as `(dx, dy)`.
+    fn moves(calls: &[String]) -> Vec<(i32, i32)> {
+        calls
+            .iter()
+            .filter_map(|call| {
+                let mut parts = call.strip_prefix("move Rel ")?.split(' ');
+                Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drag_sensitivity_only_applies_while_dragging() {
+        let worker = tap_drag_worker();
+        worker.connection.set_profile(Profile {
+            drag_sensitivity: 0.5,
+            ..Profile::default()
+        });
+        let step = r#"{"type":"MouseMove","dx":20,"dy":-10,"sx":0,"sy":0,"touches":1}"#;
+        // The first move of a touch only gives the finger's position.
+        worker.send(step);
+        worker.send(step);
+        worker.settle();
+        assert_eq!(moves(&worker.mock.calls()), [(20, -10)]);
+        worker.send(TAP);
+        worker.send(TOUCH_DOWN);
+        worker.send(step);
+        worker.send(step);
+        worker.settle();
+        let calls = worker.mock.calls();
+        assert!(calls.contains(&"button Press Left".to_string()));
+        assert_eq!(moves(&calls)[1..], [(10, -5)]);
+        // Once the finger lifts, moves are back to full speed.
+        worker.send(TOUCH_UP);
+        worker.send(step);
+        worker.send(step);
+        worker.settle();
+        assert_eq!(moves(&worker.mock.calls()).last(), Some(&(20, -10)));
+        worker.finish();
+    }
+
+    #[test]
+    fn touch_late_in_the_tap_window_drags() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        // Two thirds of the way through the 150ms window.
+        thread::sleep(Duration::from_millis(100));
+        assert!(
+            worker.mock.calls().is_empty(),
+            "the tap waits for the window"
+        );
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(120));
+        assert_eq!(worker.mock.calls(), ["button Press Left"]);
+        worker.send(TOUCH_UP);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["button Press Left", "button Release Left"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn touch_after_the_tap_window_is_a_new_touch() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        thread::sleep(Duration::from_millis(200));
+        // The window closed: the tap clicked on its own.
+        assert_eq!(worker.mock.calls(), ["button Click Left"]);
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(120));
+        worker.send(TOUCH_UP);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["button Click Left"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn tap_window_is_the_configured_one() {
+        let worker = Worker::start(Config {
+            tap_drag: Some(TapDrag {
+                tap_window_ms: 500,
+                hold_ms: 60,
+            }),
+            ..Config::default()
+        });
+        worker.send(TAP);
+        // Past the default window of 300ms, within this one.
+        thread::sleep(Duration::from_millis(350));
+        assert!(worker.mock.calls().is_empty());
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(120));
+        worker.send(TOUCH_UP);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["button Press Left", "button Release Left"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn quick_second_tap_double_clicks() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(20));
+        worker.send(TOUCH_UP);
+        // The client's own click for the second tap is swallowed.
+        worker.send(TAP);
+        thread::sleep(Duration::from_millis(250));
+        assert_eq!(
+            worker.mock.calls(),
+            ["button Click Left", "button Click Left"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn second_touch_held_past_hold_ms_drags() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(100));
+        worker.send(TOUCH_UP);
+        worker.send(TAP);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls()[..2],
+            ["button Press Left", "button Release Left"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn moving_second_touch_drags_straight_away() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        worker.send(TOUCH_DOWN);
+        worker.send(r#"{"type":"MouseMove","dx":4,"dy":0,"sx":0,"sy":0,"touches":1}"#);
+        worker.settle();
+        assert_eq!(worker.mock.calls()[0], "button Press Left");
+        worker.send(TOUCH_UP);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls().last().map(String::as_str),
+            Some("button Release Left")
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn second_touch_after_the_window_is_a_new_tap() {
+        let worker = tap_drag_worker();
+        worker.send(TAP);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(worker.mock.calls(), ["button Click Left"]);
+        worker.send(TOUCH_DOWN);
+        thread::sleep(Duration::from_millis(100));
+        worker.send(TOUCH_UP);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["button Click Left"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn type_string_repeats_up_to_max_type_len() {
+        let worker = Worker::start(Config {
+            max_type_len: 10,
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"TypeString","text":"ab","repeat":3}"#);
+        worker.send(r#"{"type":"TypeString","text":"c"}"#);
+        worker.send(r#"{"type":"TypeString","text":"xyz","repeat":0}"#);
+        // Capped to the three copies that fit in 10 characters.
+        worker.send(r#"{"type":"TypeString","text":"xyz","repeat":50}"#);
+        worker.settle();
+        let calls = worker.mock.calls();
+        let count = |text: &str| calls.iter().filter(|call| *call == text).count();
+        assert_eq!(
+            (count("text ab"), count("text c"), count("text xyz")),
+            (3, 1, 3),
+            "{:?}",
+            calls
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn type_string_repeats_without_holding_up_other_events() {
+        let worker = Worker::start(Config {
+            type_delay_ms: 50,
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"TypeString","text":"-","repeat":4}"#);
+        worker.send(r#"{"type":"MouseClick","button":"Left"}"#);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["text -", "button Click Left"]);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(worker.mock.calls().len(), 5);
+
+        // A new string comes after the copies of the one before.
+        worker.send(r#"{"type":"TypeString","text":"=","repeat":3}"#);
+        worker.send(r#"{"type":"TypeString","text":"!"}"#);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls()[5..],
+            ["text =", "text =", "text =", "text !"]
+        );
+
+        worker.send(r#"{"type":"TypeString","text":"+","repeat":5}"#);
+        worker.send(r#"{"type":"CancelSequence"}"#);
+        worker.settle();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            worker.mock.calls().last().map(String::as_str),
+            Some("text +")
+        );
+        assert_eq!(worker.mock.calls().len(), 10);
+        worker.finish();
+    }
+
+    /// The kinds of the mock's calls, with moves as their direction.
+    fn call_order(calls: &[String]) -> Vec<String> {
+        calls
+            .iter()
+            .map(|call| match call.split(' ').collect::<Vec<_>>()[..] {
+                ["move", "Rel", dx, _] if dx.starts_with('-') => "left".to_string(),
+                ["move", "Rel", ..] => "right".to_string(),
+                ["button", _, button] => format!("click {}", button),
+                ["key", ..] => "key".to_string(),
+                _ => call.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clicks_land_between_the_moves_around_them() {
+        for key_priority in [true, false] {
+            let worker = Worker::start(Config {
+                key_priority,
+                ..Config::default()
+            });
+            worker.send_event(mouse_move(0.0, 0.0));
+            // Queued all at once, as a backlog builds up behind a slow backend.
+            for event in [
+                mouse_move(10.0, 0.0),
+                event(r#"{"type":"MouseClick","button":"Left"}"#),
+                mouse_move(-10.0, 0.0),
+                mouse_move(-10.0, 0.0),
+                event(r#"{"type":"MouseClick","button":"Right"}"#),
+                mouse_move(10.0, 0.0),
+            ] {
+                worker.send_event(event);
+            }
+            worker.settle();
+            assert_eq!(
+                call_order(&worker.mock.calls()),
+                [
+                    "right",
+                    "click Left",
+                    "left",
+                    "left",
+                    "click Right",
+                    "right"
+                ],
+                "key_priority: {}",
+                key_priority
+            );
+            worker.finish();
+        }
+    }
+
+    #[test]
+    fn keys_overtake_moves_but_not_clicks() {
+        let worker = Worker::start(Config::default());
+        worker.send_event(mouse_move(0.0, 0.0));
+        for event in [
+            mouse_move(10.0, 0.0),
+            event(r#"{"type":"MouseClick","button":"Left"}"#),
+            mouse_move(-10.0, 0.0),
+            mouse_move(-10.0, 0.0),
+            event(r#"{"type":"KeyPress","key":"a"}"#),
+        ] {
+            worker.send_event(event);
+        }
+        worker.settle();
+        let order = call_order(&worker.mock.calls());
+        // The key may come before the moves queued after the click, never
+        // before the click itself.
+        let click = order.iter().position(|call| call == "click Left").unwrap();
+        let key = order.iter().position(|call| call == "key").unwrap();
+        assert!(click < key, "{:?}", order);
+        assert_eq!(order[..click], ["right"]);
+        assert_eq!(order.iter().filter(|call| *call == "left").count(), 2);
+        worker.finish();
+    }
+
+    #[test]
+    fn parked_thread_makes_no_backend_calls() {
+        let worker = Worker::start(Config {
+            type_delay_ms: 50,
+            scroll_mode: ScrollMode::Fast,
+            scroll_interval_ms: 200,
+            tap_drag: Some(TapDrag {
+                tap_window_ms: 150,
+                hold_ms: 60,
+            }),
+            ..Config::default()
+        });
+        // Leave a timer running for each: copies still to type, a tap
+        // waiting for its window and scrolling waiting for its flush.
+        worker.send(r#"{"type":"TypeString","text":"-","repeat":5}"#);
+        worker.send(TAP);
+        scroll_down(&worker, 0.0);
+        scroll_down(&worker, 15.0);
+        scroll_down(&worker, 15.0);
+        worker.send_event(ClientEvent::Closed);
+        worker.settle();
+        assert_eq!(worker.connections.power(), PowerState::Parked);
+        let calls = worker.mock.calls();
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(worker.mock.calls(), calls);
+        worker.finish();
+    }
+
+    #[test]
+    fn prefer_clipboard_typing_pastes() {
+        clipboard::fake::Clipboard::new()
+            .unwrap()
+            .set_text("copied before")
+            .unwrap();
+        let worker = Worker::start(Config {
+            prefer_clipboard_typing: true,
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"TypeString","text":"pasted","repeat":2}"#);
+        worker.settle();
+        assert_eq!(clipboard::fake::text().as_deref(), Some("pasted"));
+        let paste = [
+            "key Press Control",
+            "key Click Unicode('v')",
+            "key Release Control",
+        ];
+        assert_eq!(worker.mock.calls(), paste.repeat(2));
+        thread::sleep(clipboard::PASTE_SETTLE + Duration::from_millis(50));
+        assert_eq!(clipboard::fake::text().as_deref(), Some("copied before"));
+        worker.finish();
+    }
+
+    #[test]
+    fn substitutions_apply_to_keys_and_strings() {
+        let worker = Worker::start(Config {
+            char_substitutions: [('’', "'"), ('…', "...")]
+                .into_iter()
+                .map(|(c, replacement)| (c, replacement.to_string()))
+                .collect(),
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"KeyPress","key":"’"}"#);
+        worker.send(r#"{"type":"KeyPress","key":"a"}"#);
+        worker.send(r#"{"type":"TypeString","text":"Don’t…"}"#);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["text '", "key Click Unicode('a')", "text Don't..."]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn failing_backend_is_reported_to_the_client() {
+        let worker = Worker::start(Config::default());
+        // Failing the click and every retry of it.
+        worker.mock.fail_next(4);
+        worker.send(r#"{"type":"MouseClick","button":"Left"}"#);
+        worker.send(r#"{"type":"MouseClick","button":"Left"}"#);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["button Click Left"]);
+        let notices = worker.finish();
+        let errors: Vec<_> = notices
+            .iter()
+            .filter(|notice| notice["type"] == "Error")
+            .collect();
+        assert_eq!(errors.len(), 1, "{:?}", notices);
+        assert_eq!(errors[0]["code"], "input_failed");
+    }
+
+    #[test]
+    fn two_finger_double_tap_zooms() {
+        let worker = Worker::start(Config {
+            double_tap_zoom: Some(DoubleTapZoom {
+                window_ms: 300,
+                action: Action::Keys("Ctrl+=".parse().unwrap()),
+            }),
+            ..Config::default()
+        });
+        let two_finger_tap = r#"{"type":"FingerGesture","fingers":2,"gesture":"tap"}"#;
+        worker.send(two_finger_tap);
+        worker.settle();
+        assert!(worker.mock.calls().is_empty());
+        worker.send(two_finger_tap);
+        worker.settle();
+        let calls = worker.mock.calls();
+        assert!(!calls.is_empty());
+        // A third tap is the first of the next double tap.
+        worker.send(two_finger_tap);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), calls);
+        worker.finish();
+    }
+
+    #[test]
+    fn return_to_last_click() {
+        let worker = Worker::start(Config::default());
+        worker.send(r#"{"type":"ReturnToLastClick"}"#);
+        worker.settle();
+        // Nothing to return to yet.
+        assert!(worker.mock.calls().is_empty());
+        let step = r#"{"type":"MouseMove","dx":30,"dy":40,"sx":0,"sy":0,"touches":1}"#;
+        worker.send(step);
+        worker.send(step);
+        worker.send(TAP);
+        worker.send(step);
+        worker.send(r#"{"type":"ReturnToLastClick"}"#);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            [
+                "move Rel 30 40",
+                "button Click Left",
+                "move Rel 30 40",
+                "move Abs 30 40"
+            ]
+        );
+        worker.finish();
+    }
+
+    /// A move of `touches` fingers, slow enough not to be accelerated.
+    fn touch_move(touches: i32, dx: f64, dy: f64) -> ClientEvent {
+        event(&format!(
+            r#"{{"type":"MouseMove","dx":{},"dy":{},"sx":0,"sy":0,"touches":{}}}"#,
+            dx, dy, touches
+        ))
+    }
+
+    #[test]
+    fn lifting_a_finger_leaks_no_scrolling_into_the_pointer() {
+        let worker = scroll_worker(0, None);
+        for _ in 0..3 {
+            scroll_down(&worker, 30.0);
+        }
+        worker.settle();
+        let scrolled = worker.mock.calls();
+        assert!(!scrolled.is_empty());
+        assert!(scrolled.iter().all(|call| call.starts_with("scroll ")));
+        // Measured from the finger that lifted to the one left down.
+        worker.send_event(touch_move(1, 80.0, 80.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), scrolled);
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls()[scrolled.len()..], ["move Rel 5 0"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn adding_a_finger_starts_a_clean_scroll() {
+        let worker = Worker::start(Config {
+            scroll_mode: ScrollMode::Fast,
+            scroll_interval_ms: 0,
+            ..Config::default()
+        });
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        scroll_down(&worker, 300.0);
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 5 0"]);
+        scroll_down(&worker, 15.0);
+        worker.settle();
+        assert!(vertical_scrolls(&worker.mock.calls()).iter().sum::<i32>() < 5);
+        worker.finish();
+    }
+
+    #[test]
+    fn finger_changes_can_be_kept() {
+        let worker = Worker::start(Config {
+            reset_on_finger_change: false,
+            ..Config::default()
+        });
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send_event(touch_move(2, 0.0, 0.0));
+        worker.send_event(touch_move(1, 80.0, 80.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 80 80"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn rotation_starts_over_when_a_finger_lifts() {
+        let worker = Worker::start(Config {
+            rotate: Some(gestures::RotateGesture {
+                step_degrees: 15.0,
+                clockwise: Action::Wheel {
+                    wheel: 1,
+                    modifiers: None,
+                },
+                counter_clockwise: Action::Wheel {
+                    wheel: -1,
+                    modifiers: None,
+                },
+            }),
+            ..Config::default()
+        });
+        let rotate = |angle_delta: f64| ClientEvent::Rotate { angle_delta };
+        worker.send_event(touch_move(2, 0.0, 0.0));
+        worker.send_event(rotate(10.0));
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send_event(touch_move(2, 0.0, 0.0));
+        worker.send_event(rotate(10.0));
+        worker.settle();
+        assert!(worker.mock.calls().is_empty());
+        worker.send_event(rotate(-25.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["scroll Vertical -1"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn moves_without_fingers_only_end_the_gesture() {
+        for reset_on_finger_change in [true, false] {
+            let worker = Worker::start(Config {
+                reset_on_finger_change,
+                ..Config::default()
+            });
+            worker.send_event(touch_move(1, 0.0, 0.0));
+            worker.send_event(touch_move(1, 5.0, 0.0));
+            worker.send_event(touch_move(0, 90.0, 90.0));
+            worker.send_event(touch_move(-1, 90.0, 90.0));
+            worker.settle();
+            assert_eq!(worker.mock.calls(), ["move Rel 5 0"]);
+            worker.finish();
+        }
+    }
+
+    #[test]
+    fn per_axis_sensitivity_and_inversion() {
+        let worker = Worker::start(Config::default());
+        worker.connection.set_profile(Profile {
+            x_sensitivity: 2.0,
+            y_sensitivity: 0.5,
+            invert_y: true,
+            ..Profile::default()
+        });
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send_event(touch_move(1, 10.0, 10.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 20 -5"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn abnormal_distances_are_judged_after_axis_scaling() {
+        let worker = Worker::start(Config::default());
+        worker.connection.set_profile(Profile {
+            x_sensitivity: 4.0,
+            invert_y: true,
+            ..Profile::default()
+        });
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        // Fine as sent, too far once scaled.
+        worker.send_event(touch_move(1, 300.0, 0.0));
+        // Too far either way, and inverted.
+        worker.send_event(touch_move(1, 0.0, 1200.0));
+        worker.send_event(touch_move(1, 200.0, 0.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 800 0"]);
+        worker.finish();
+    }
+
+    /// A one-finger move right by `dx` reported at speed `sx`.
+    fn moving_at(dx: f64, sx: f64) -> ClientEvent {
+        event(&format!(
+            r#"{{"type":"MouseMove","dx":{},"dy":0,"sx":{},"sy":0,"touches":1}}"#,
+            dx, sx
+        ))
+    }
+
+    /// Horizontal pointer moves made for `moves` after the placing one.
+    fn moved_x(config: Config, moves: &[ClientEvent]) -> Vec<i32> {
+        let worker = Worker::start(config);
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        for event in moves {
+            worker.send_event(event.clone());
+        }
+        worker.settle();
+        let moved = moves_x(&worker.mock.calls());
+        worker.finish();
+        moved
+    }
+
+    fn moves_x(calls: &[String]) -> Vec<i32> {
+        moves(calls).into_iter().map(|(dx, _)| dx).collect()
+    }
+
+    #[test]
+    fn acceleration_can_follow_the_average_speed() {
+        let shaky = [
+            moving_at(2.0, 1.0),
+            moving_at(2.0, 0.0),
+            moving_at(2.0, 1.0),
+        ];
+        // Each move by its own speed: 2 plus 2px * 10 * speed.
+        assert_eq!(moved_x(Config::default(), &shaky), [22, 2, 22]);
+        let smoothed = Config {
+            acceleration_window: Some(2),
+            ..Config::default()
+        };
+        assert_eq!(moved_x(smoothed, &shaky), [22, 12, 12]);
+    }
+
+    #[test]
+    fn hand_gestures_run_the_binding_for_their_finger_count() {
+        let worker = Worker::start(Config {
+            finger_gestures: vec![
+                gestures::FingerGestureBinding {
+                    fingers: 4,
+                    gesture: FingerGesture::SwipeUp,
+                    action: Action::Wheel {
+                        wheel: 4,
+                        modifiers: None,
+                    },
+                },
+                gestures::FingerGestureBinding {
+                    fingers: 5,
+                    gesture: FingerGesture::SwipeUp,
+                    action: Action::Wheel {
+                        wheel: 5,
+                        modifiers: None,
+                    },
+                },
+            ],
+            ..Config::default()
+        });
+        // The client holds back the moves of fingers landing one after the
+        // other and reports the most that were down once they all lift.
+        worker.send(r#"{"type":"FingerGesture","fingers":5,"gesture":"swipe_up"}"#);
+        worker.send(r#"{"type":"FingerGesture","fingers":4,"gesture":"swipe_up"}"#);
+        worker.send(r#"{"type":"FingerGesture","fingers":4,"gesture":"swipe_down"}"#);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["scroll Vertical 5", "scroll Vertical 4"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn warm_start_primes_the_first_move_of_a_touch() {
+        let warm = || Config {
+            acceleration_warm_start: true,
+            ..Config::default()
+        };
+        let flick = [moving_at(16.0, 0.1), moving_at(16.0, 0.1)];
+        assert_eq!(moved_x(Config::default(), &flick), [32, 32]);
+        // 16px over one 16ms frame is 1px/ms, for the first move only.
+        assert_eq!(moved_x(warm(), &flick), [176, 32]);
+        // Never slower than reported.
+        assert_eq!(moved_x(warm(), &[moving_at(16.0, 2.0)]), [336]);
+        // Each touch starts warm again.
+        let again = [
+            moving_at(16.0, 0.1),
+            touch_move(0, 0.0, 0.0),
+            touch_move(1, 0.0, 0.0),
+            moving_at(16.0, 0.1),
+        ];
+        assert_eq!(moved_x(warm(), &again), [176, 176]);
+    }
+
+    #[test]
+    fn key_debounce_drops_only_quick_repeats_of_the_same_key() {
+        let window = Duration::from_millis(200);
+        let mut last_key = None;
+        let mut ghost = |key| is_ghost_key(&mut last_key, key, window);
+        let backspace = PressedKey::Special(SpecialKey::Backspace);
+        assert!(!ghost(PressedKey::Char('a')));
+        assert!(ghost(PressedKey::Char('a')));
+        assert!(!ghost(PressedKey::Char('b')));
+        assert!(!ghost(backspace));
+        assert!(ghost(backspace));
+        thread::sleep(Duration::from_millis(100));
+        // Still timed from the first of the pair, not from the ghost.
+        assert!(ghost(backspace));
+        thread::sleep(Duration::from_millis(120));
+        assert!(!ghost(backspace));
+    }
+
+    #[test]
+    fn key_presses_can_be_marked_as_repeats() {
+        let press = event(r#"{"type":"KeyPress","key":"a"}"#);
+        assert_eq!(press.pressed_key(), Some((PressedKey::Char('a'), false)));
+        let repeat = event(r#"{"type":"SpecialKey","key":"Return","repeat":true}"#);
+        assert_eq!(
+            repeat.pressed_key(),
+            Some((PressedKey::Special(SpecialKey::Return), true))
+        );
+        assert_eq!(mouse_move(1.0, 1.0).pressed_key(), None);
+    }
+
+    #[test]
+    fn a_pause_forgets_the_speeds_carried_over() {
+        let paused = |inertia_reset_ms| {
+            let worker = Worker::start(Config {
+                acceleration_window: Some(2),
+                acceleration_warm_start: true,
+                inertia_reset_ms,
+                ..Config::default()
+            });
+            worker.send_event(touch_move(1, 0.0, 0.0));
+            worker.send_event(moving_at(16.0, 0.1));
+            worker.settle();
+            thread::sleep(Duration::from_millis(100));
+            worker.send_event(moving_at(16.0, 0.1));
+            worker.settle();
+            let moved = moves_x(&worker.mock.calls());
+            worker.finish();
+            moved
+        };
+        // Averaged with the primed first move, 1px/ms, or primed afresh.
+        assert_eq!(paused(None), [176, 104]);
+        assert_eq!(paused(Some(50)), [176, 176]);
+    }
+
+    #[test]
+    fn a_touch_stops_coasting() {
+        let coasting_after = |cancel_on_touch, next: &str| {
+            let worker = momentum_worker(cancel_on_touch);
+            flick(&worker, 3.0);
+            worker.settle();
+            worker.send(next);
+            thread::sleep(Duration::from_millis(400));
+            let coasted = vertical_scrolls(&worker.mock.calls()).len() - 1;
+            worker.finish();
+            coasted
+        };
+        let touch = r#"{"type":"TouchDown"}"#;
+        let key = r#"{"type":"KeyPress","key":"a"}"#;
+        let lift = r#"{"type":"TouchUp"}"#;
+        let full = coasting_after(true, lift);
+        assert!(full > 10, "{}", full);
+        assert!(coasting_after(true, touch) < full / 2);
+        assert_eq!(coasting_after(false, touch), full);
+        assert!(coasting_after(false, key) < full / 2);
+    }
+
+    #[test]
+    fn acceleration_per_axis_or_by_overall_distance() {
+        let moved = |config: Config| {
+            let worker = Worker::start(config);
+            worker.send_event(touch_move(1, 0.0, 0.0));
+            worker.send(r#"{"type":"MouseMove","dx":3,"dy":4,"sx":1,"sy":1,"touches":1}"#);
+            worker.send_event(moving_at(3.0, 1.0));
+            worker.settle();
+            let moved = moves(&worker.mock.calls());
+            worker.finish();
+            moved
+        };
+        // Both axes by the 5px moved diagonally, plus 10x speed each.
+        assert_eq!(moved(Config::default()), [(53, 54), (33, 0)]);
+        // Each axis by its own distance, scaled per axis; a straight move
+        // comes out the same.
+        let per_axis = Config {
+            axis_acceleration: Some(config::AxisAcceleration {
+                horizontal: 1.0,
+                vertical: 0.5,
+            }),
+            ..Config::default()
+        };
+        assert_eq!(moved(per_axis), [(33, 24), (33, 0)]);
+    }
+
+    #[test]
+    fn the_first_move_in_a_while_only_places_the_finger() {
+        let worker = Worker::start(Config {
+            resync_after_ms: Some(100),
+            reset_on_finger_change: false,
+            ..Config::default()
+        });
+        // The first on a connection, after a pause and after a touch down.
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        thread::sleep(Duration::from_millis(150));
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.send(TOUCH_DOWN);
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 5 0"; 3]);
+        worker.finish();
+    }
+
+    #[test]
+    fn resync_can_be_turned_off() {
+        let config = Config {
+            resync_after_ms: None,
+            reset_on_finger_change: false,
+            ..Config::default()
+        };
+        let worker = Worker::start(config);
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send(TOUCH_DOWN);
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 90 90", "move Rel 5 0"]);
+        worker.finish();
+    }
+
+    #[test]
+    fn moves_are_resynced_and_reset_by_default() {
+        let config = Config::default();
+        assert_eq!(config.resync_after_ms, Some(1000));
+        assert!(config.reset_on_finger_change);
+        let worker = Worker::start(config);
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.send(TOUCH_DOWN);
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.send_event(touch_move(2, 90.0, 90.0));
+        worker.send_event(touch_move(1, 90.0, 90.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 5 0"; 3]);
+        worker.finish();
+    }
+
+    #[test]
+    fn pointer_is_reconciled_every_few_moves() {
+        let worker = Worker::start(Config {
+            reconcile_every: Some(2),
+            ..Config::default()
+        });
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        for _ in 0..5 {
+            worker.send_event(touch_move(1, 5.0, 0.0));
+        }
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            [
+                "move Rel 5 0",
+                "move Rel 5 0",
+                "move Rel 5 0",
+                "move Abs 15 0",
+                "move Rel 5 0",
+                "move Rel 5 0",
+                "move Abs 25 0",
+            ]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn held_back_clicks_press_on_the_tap_or_on_release() {
+        let held_back = |click_on| Config {
+            click_on,
+            click_drag_window_ms: Some(100),
+            ..Config::default()
+        };
+        let tapped_later = |click_on| Config {
+            click_on,
+            tap_drag: Some(TapDrag {
+                tap_window_ms: 100,
+                hold_ms: 60,
+            }),
+            ..Config::default()
+        };
+        for config in [held_back, tapped_later] {
+            let worker = Worker::start(config(ClickOn::Release));
+            worker.send(TAP);
+            worker.settle();
+            assert!(worker.mock.calls().is_empty());
+            thread::sleep(Duration::from_millis(150));
+            assert_eq!(worker.mock.calls(), ["button Click Left"]);
+            worker.finish();
+
+            let worker = Worker::start(config(ClickOn::Press));
+            worker.send(TAP);
+            worker.settle();
+            assert_eq!(worker.mock.calls(), ["button Press Left"]);
+            thread::sleep(Duration::from_millis(150));
+            assert_eq!(
+                worker.mock.calls(),
+                ["button Press Left", "button Release Left"]
+            );
+            worker.finish();
+        }
+    }
+
+    #[test]
+    fn a_click_pressed_on_the_tap_carries_on_into_a_drag() {
+        let worker = Worker::start(Config {
+            click_on: ClickOn::Press,
+            click_drag_window_ms: Some(100),
+            ..Config::default()
+        });
+        worker.send(TAP);
+        // The first move places the finger, the drag follows.
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.send_event(touch_move(1, 5.0, 0.0));
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["button Press Left", "move Rel 5 0", "move Rel 5 0"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn acceleration_adds_no_more_than_the_cap() {
+        let flicks = [
+            moving_at(16.0, 2.0),
+            moving_at(-16.0, -2.0),
+            moving_at(2.0, 1.0),
+        ];
+        assert_eq!(moved_x(Config::default(), &flicks), [336, -336, 22]);
+        let capped = |axis_acceleration| Config {
+            max_acceleration_px: Some(20.0),
+            axis_acceleration,
+            ..Config::default()
+        };
+        assert_eq!(moved_x(capped(None), &flicks), [36, -36, 22]);
+        let per_axis = Some(config::AxisAcceleration::default());
+        assert_eq!(moved_x(capped(per_axis), &flicks), [36, -36, 22]);
+    }
+
+    #[test]
+    fn key_holds_go_up_on_time_while_other_keys_go_on() {
+        let worker = Worker::start(Config::default());
+        worker.send(r#"{"type":"KeyHold","key":"w","duration_ms":150}"#);
+        worker.send(r#"{"type":"KeyPress","key":"a"}"#);
+        // Held again before the end: kept down until the later one's.
+        worker.send(r#"{"type":"KeyHold","key":"w","duration_ms":250}"#);
+        worker.settle();
+        let pressed = ["key Press Unicode('w')", "key Click Unicode('a')"];
+        assert_eq!(worker.mock.calls(), pressed);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(worker.mock.calls(), pressed);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            worker.mock.calls()[pressed.len()..],
+            ["key Release Unicode('w')"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn key_holds_are_capped_and_let_go_on_disconnect() {
+        let worker = Worker::start(Config {
+            max_key_hold_ms: 100,
+            ..Config::default()
+        });
+        worker.send(r#"{"type":"KeyHold","key":"w","duration_ms":100000}"#);
+        worker.send(r#"{"type":"KeyHold","key":"Return","duration_ms":1000}"#);
+        worker.settle();
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(
+            worker.mock.calls(),
+            [
+                "key Press Unicode('w')",
+                "key Press Return",
+                "key Release Unicode('w')",
+                "key Release Return",
+            ]
+        );
+        worker.finish();
+
+        let worker = Worker::start(Config::default());
+        worker.send(r#"{"type":"KeyHold","key":"w","duration_ms":5000}"#);
+        worker.send_event(ClientEvent::Closed);
+        worker.settle();
+        assert_eq!(
+            worker.mock.calls(),
+            ["key Press Unicode('w')", "key Release Unicode('w')"]
+        );
+        worker.finish();
+    }
+
+    #[test]
+    fn touchpads_of_implausible_size_move_the_pointer() {
+        let worker = Worker::start(Config {
+            edge_swipe: Some(gestures::EdgeSwipe {
+                edge_swipe_px: 12.0,
+                min_distance_px: 60.0,
+                left: Some(Action::Keys("Alt+Left".parse().unwrap())),
+                right: None,
+                top: None,
+                bottom: None,
+            }),
+            ..Config::default()
+        });
+        let at_the_edge = |width: u32| {
+            format!(
+                r#"{{"type":"MouseMove","dx":5,"dy":0,"sx":0,"sy":0,"touches":1,"x":8,"y":5,"width":{},"height":400}}"#,
+                width
+            )
+        };
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send(&at_the_edge(400));
+        worker.send(&at_the_edge(400));
+        worker.settle();
+        assert!(worker.mock.calls().is_empty());
+        worker.send_event(touch_move(0, 0.0, 0.0));
+        worker.send_event(touch_move(1, 0.0, 0.0));
+        worker.send(&at_the_edge(0));
+        worker.send(&at_the_edge(0));
+        worker.settle();
+        assert_eq!(worker.mock.calls(), ["move Rel 5 0"; 2]);
+        worker.finish();
+    }
 }
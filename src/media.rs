@@ -0,0 +1,139 @@
+use crate::error::Error;
+use crate::keys::MediaKey;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+use crate::mpris::Mpris;
+use serde::{Deserialize, Serialize};
+
+/// What to do with a media player.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MediaAction {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Moves forward, or backward for a negative offset.
+    Seek {
+        offset_ms: i64,
+    },
+    SetPosition {
+        position_ms: u64,
+    },
+}
+
+impl MediaAction {
+    /// The media key with the same effect, where there is one.
+    fn to_media_key(self) -> Option<MediaKey> {
+        match self {
+            MediaAction::PlayPause | MediaAction::Play | MediaAction::Pause => {
+                Some(MediaKey::PlayPause)
+            }
+            MediaAction::Stop => Some(MediaKey::Stop),
+            MediaAction::Next => Some(MediaKey::Next),
+            MediaAction::Previous => Some(MediaKey::Previous),
+            MediaAction::Seek { .. } | MediaAction::SetPosition { .. } => None,
+        }
+    }
+}
+
+/// The track a player is on, sent to clients as it changes.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(not(all(feature = "mpris", target_os = "linux")), allow(dead_code))]
+pub struct NowPlaying {
+    pub player: String,
+    pub status: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub length_ms: Option<u64>,
+    pub position_ms: Option<u64>,
+}
+
+impl NowPlaying {
+    /// Whether clients need to hear about this state, given the one they were
+    /// last sent `elapsed_ms` ago. While playing, the position is expected to
+    /// advance on its own; only a jump, such as a seek, is news.
+    pub fn differs_from(&self, previous: &NowPlaying, elapsed_ms: u64) -> bool {
+        let without_position = |now_playing: &NowPlaying| NowPlaying {
+            position_ms: None,
+            ..now_playing.clone()
+        };
+        if without_position(self) != without_position(previous) {
+            return true;
+        }
+        let expected = match self.status.as_str() {
+            "Playing" => previous.position_ms.map(|ms| ms + elapsed_ms),
+            _ => previous.position_ms,
+        };
+        match (self.position_ms, expected) {
+            (Some(position), Some(expected)) => position.abs_diff(expected) > POSITION_TOLERANCE_MS,
+            (position, expected) => position.is_some() != expected.is_some(),
+        }
+    }
+}
+
+/// How far the reported position may stray from the expected one before
+/// clients are told about it.
+const POSITION_TOLERANCE_MS: u64 = 2000;
+
+/// Controls media players directly over MPRIS when the `mpris` feature is
+/// enabled and a session bus is available, and by media keys otherwise.
+#[derive(Clone, Default)]
+pub struct MediaController {
+    #[cfg(all(feature = "mpris", target_os = "linux"))]
+    mpris: Option<Mpris>,
+}
+
+impl MediaController {
+    pub async fn detect() -> Self {
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        match Mpris::connect().await {
+            Ok(mpris) => {
+                println!("Controlling media players over MPRIS.");
+                return MediaController { mpris: Some(mpris) };
+            }
+            Err(e) => eprintln!("MPRIS is unavailable, falling back to media keys: {}", e),
+        }
+        MediaController::default()
+    }
+
+    pub fn has_mpris(&self) -> bool {
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        return self.mpris.is_some();
+        #[cfg(not(all(feature = "mpris", target_os = "linux")))]
+        false
+    }
+
+    /// Performs `action` on the named player, or the active one. Without
+    /// MPRIS, returns the media key to press instead.
+    pub async fn control(
+        &self,
+        action: MediaAction,
+        player: Option<&str>,
+    ) -> Result<Option<MediaKey>, Error> {
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        if let Some(mpris) = &self.mpris {
+            mpris.control(action, player).await?;
+            return Ok(None);
+        }
+        if player.is_some() {
+            return Err(Error::Unsupported(
+                "choosing a media player requires MPRIS support",
+            ));
+        }
+        action
+            .to_media_key()
+            .map(Some)
+            .ok_or(Error::Unsupported("seeking requires MPRIS support"))
+    }
+
+    /// What the active player is playing, if MPRIS is available and a player is running.
+    pub async fn now_playing(&self) -> Option<NowPlaying> {
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        if let Some(mpris) = &self.mpris {
+            return mpris.now_playing().await;
+        }
+        None
+    }
+}
@@ -0,0 +1,116 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Kinetic scrolling: after a two-finger scroll stops sending moves, it keeps
+/// going on its own and slows down until it comes to rest.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Momentum {
+    /// Time between coasting scroll steps, and the pause in moves after
+    /// which coasting starts.
+    pub tick_ms: u64,
+    /// Share of the speed kept from one step to the next.
+    pub decay: f64,
+    /// Speed, in wheel units per step, below which coasting stops.
+    pub min_speed: f64,
+    /// A finger touching the touchpad stops coasting at once, like grabbing
+    /// a spinning scroll wheel. Other input always stops it.
+    pub cancel_on_touch: bool,
+}
+
+impl Default for Momentum {
+    fn default() -> Self {
+        Momentum {
+            tick_ms: 30,
+            decay: 0.9,
+            min_speed: 0.3,
+            cancel_on_touch: true,
+        }
+    }
+}
+
+impl Momentum {
+    pub fn check(&self) -> Result<(), String> {
+        if self.tick_ms == 0 {
+            return Err("tick_ms must be positive".to_string());
+        }
+        if !(0.0..1.0).contains(&self.decay) {
+            return Err("decay must be at least 0 and below 1".to_string());
+        }
+        if self.min_speed <= 0.0 {
+            return Err("min_speed must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn tick(&self) -> Duration {
+        Duration::from_millis(self.tick_ms)
+    }
+}
+
+/// A scroll coasting on after the fingers stopped reporting movement.
+pub struct Coast {
+    /// Wheel units per step, negative scrolling up.
+    speed: f64,
+    /// Fraction of a wheel unit not yet scrolled.
+    remainder: f64,
+    pub deadline: Instant,
+}
+
+impl Coast {
+    /// Arms coasting at `speed` wheel units per millisecond, to start unless
+    /// another move arrives within a tick.
+    pub fn new(momentum: &Momentum, speed: f64) -> Option<Self> {
+        let speed = speed * momentum.tick_ms as f64;
+        if !speed.is_finite() || speed.abs() < momentum.min_speed {
+            return None;
+        }
+        Some(Coast {
+            speed,
+            remainder: 0.0,
+            deadline: Instant::now() + momentum.tick(),
+        })
+    }
+
+    /// The wheel amount of the step now due, and whether coasting goes on.
+    pub fn step(&mut self, momentum: &Momentum) -> (i32, bool) {
+        self.remainder += self.speed;
+        let amount = self.remainder.trunc();
+        self.remainder -= amount;
+        self.speed *= momentum.decay;
+        self.deadline += momentum.tick();
+        (amount as i32, self.speed.abs() >= momentum.min_speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coasting_slows_down_and_stops() {
+        let momentum = Momentum::default();
+        let mut coast = Coast::new(&momentum, 0.1).unwrap();
+        let mut amounts = Vec::new();
+        loop {
+            let (amount, coasting) = coast.step(&momentum);
+            amounts.push(amount);
+            if !coasting {
+                break;
+            }
+        }
+        assert_eq!(amounts[..4], [3, 2, 3, 2]);
+        assert_eq!(amounts.len(), 22);
+        assert_eq!(amounts.iter().sum::<i32>(), 27);
+    }
+
+    #[test]
+    fn slow_scrolls_do_not_coast() {
+        let momentum = Momentum::default();
+        // 0.3 wheel units per 30ms step is just enough.
+        assert!(Coast::new(&momentum, 0.009).is_none());
+        assert!(Coast::new(&momentum, 0.01).is_some());
+        assert!(Coast::new(&momentum, -0.01).is_some());
+        assert!(Coast::new(&momentum, f64::NAN).is_none());
+    }
+}
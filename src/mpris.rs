@@ -0,0 +1,175 @@
+use crate::error::Error;
+use crate::media::{MediaAction, NowPlaying};
+use std::collections::HashMap;
+use zbus::fdo::DBusProxy;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{dbus_proxy, CacheProperties, Connection};
+
+/// Bus names of MPRIS players start with this, followed by the player's name
+/// (e.g. `spotify`, or `vlc.instance1234` when several instances can run).
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[dbus_proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    fn play_pause(&self) -> zbus::Result<()>;
+    fn play(&self) -> zbus::Result<()>;
+    fn pause(&self) -> zbus::Result<()>;
+    fn stop(&self) -> zbus::Result<()>;
+    fn next(&self) -> zbus::Result<()>;
+    fn previous(&self) -> zbus::Result<()>;
+    /// Offset in microseconds.
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+    /// Position in microseconds, only applied while `track_id` is current.
+    fn set_position(
+        &self,
+        track_id: &zbus::zvariant::ObjectPath<'_>,
+        position: i64,
+    ) -> zbus::Result<()>;
+
+    #[dbus_proxy(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+    #[dbus_proxy(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+    #[dbus_proxy(property)]
+    fn position(&self) -> zbus::Result<i64>;
+}
+
+impl From<zbus::Error> for Error {
+    fn from(e: zbus::Error) -> Self {
+        Error::MediaPlayer(e.to_string())
+    }
+}
+
+impl From<zbus::fdo::Error> for Error {
+    fn from(e: zbus::fdo::Error) -> Self {
+        Error::MediaPlayer(e.to_string())
+    }
+}
+
+/// Media players reached over the D-Bus session bus.
+#[derive(Clone)]
+pub struct Mpris {
+    connection: Connection,
+}
+
+impl Mpris {
+    pub async fn connect() -> zbus::Result<Self> {
+        Ok(Mpris {
+            connection: Connection::session().await?,
+        })
+    }
+
+    pub async fn control(&self, action: MediaAction, player: Option<&str>) -> Result<(), Error> {
+        let (name, player) = self.player(player).await?;
+        match action {
+            MediaAction::PlayPause => player.play_pause().await?,
+            MediaAction::Play => player.play().await?,
+            MediaAction::Pause => player.pause().await?,
+            MediaAction::Stop => player.stop().await?,
+            MediaAction::Next => player.next().await?,
+            MediaAction::Previous => player.previous().await?,
+            MediaAction::Seek { offset_ms } => player.seek(offset_ms.saturating_mul(1000)).await?,
+            MediaAction::SetPosition { position_ms } => {
+                let metadata = player.metadata().await?;
+                let track = metadata
+                    .get("mpris:trackid")
+                    .and_then(|value| OwnedObjectPath::try_from(value.clone()).ok())
+                    .ok_or_else(|| {
+                        Error::MediaPlayer(format!("{} did not report the current track", name))
+                    })?;
+                let position = i64::try_from(position_ms.saturating_mul(1000)).unwrap_or(i64::MAX);
+                player.set_position(&track.as_ref(), position).await?;
+            }
+        }
+        println!("Media player {} sent {:?}", name, action);
+        Ok(())
+    }
+
+    pub async fn now_playing(&self) -> Option<NowPlaying> {
+        let (name, player) = self.player(None).await.ok()?;
+        let status = player.playback_status().await.ok()?;
+        let metadata = player.metadata().await.unwrap_or_default();
+        let text = |key: &str| {
+            metadata
+                .get(key)
+                .and_then(|value| <&str>::try_from(value).ok())
+                .map(str::to_string)
+        };
+        let artist = metadata
+            .get("xesam:artist")
+            .and_then(|value| Vec::<String>::try_from(value.clone()).ok())
+            .map(|artists| artists.join(", "))
+            .or_else(|| text("xesam:artist"));
+        // Players disagree on whether the length is signed.
+        let length_us = metadata.get("mpris:length").and_then(|value| {
+            i64::try_from(value)
+                .ok()
+                .and_then(|length| u64::try_from(length).ok())
+                .or_else(|| u64::try_from(value).ok())
+        });
+        let position_us = player
+            .position()
+            .await
+            .ok()
+            .and_then(|position| u64::try_from(position).ok());
+        Some(NowPlaying {
+            player: name,
+            status,
+            title: text("xesam:title"),
+            artist,
+            album: text("xesam:album"),
+            length_ms: length_us.map(|us| us / 1000),
+            position_ms: position_us.map(|us| us / 1000),
+        })
+    }
+
+    /// The player called `name`, or else the one that is playing, or else the
+    /// first one found.
+    async fn player(&self, name: Option<&str>) -> Result<(String, PlayerProxy<'static>), Error> {
+        let players: Vec<String> = DBusProxy::new(&self.connection)
+            .await?
+            .list_names()
+            .await?
+            .into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(PLAYER_PREFIX))
+            .collect();
+
+        let bus_name = match name {
+            Some(name) => players
+                .iter()
+                .find(|bus_name| {
+                    let player = &bus_name[PLAYER_PREFIX.len()..];
+                    player == name || player.starts_with(&format!("{}.", name))
+                })
+                .cloned()
+                .ok_or_else(|| Error::MediaPlayer(format!("no media player called {}", name)))?,
+            None => {
+                let mut active = None;
+                for bus_name in &players {
+                    let proxy = self.proxy(bus_name.clone()).await?;
+                    if proxy.playback_status().await.ok().as_deref() == Some("Playing") {
+                        active = Some(bus_name.clone());
+                        break;
+                    }
+                }
+                active
+                    .or_else(|| players.first().cloned())
+                    .ok_or_else(|| Error::MediaPlayer("no media player is running".to_string()))?
+            }
+        };
+        let proxy = self.proxy(bus_name.clone()).await?;
+        Ok((bus_name[PLAYER_PREFIX.len()..].to_string(), proxy))
+    }
+
+    async fn proxy(&self, bus_name: String) -> Result<PlayerProxy<'static>, Error> {
+        Ok(PlayerProxy::builder(&self.connection)
+            .destination(bus_name)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+}
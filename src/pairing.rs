@@ -0,0 +1,313 @@
+use crate::audit::{AuditEvent, AuditLog};
+use crate::error::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use warp::{Filter, Rejection};
+
+const DEVICES_FILE: &str = "devices.json";
+
+/// How long a pairing code can be used.
+const CODE_LIFETIME: Duration = Duration::from_secs(120);
+
+/// Wrong or expired codes a peer may try before it is locked out.
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(60);
+
+/// A device that entered a valid pairing code, stored by its credential.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TrustedDevice {
+    pub device: String,
+    /// Seconds since the epoch.
+    pub paired_at: u64,
+}
+
+struct PairingCode {
+    code: String,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    code: Option<PairingCode>,
+    devices: HashMap<String, TrustedDevice>,
+    /// Failed attempts per peer, and when the last one happened.
+    failures: HashMap<IpAddr, (u32, Instant)>,
+}
+
+/// Trusted devices and the current one-time pairing code. Everything lives
+/// behind one lock, so two devices racing for the same code cannot both win.
+#[derive(Clone)]
+pub struct Pairing {
+    required: bool,
+    state: Arc<Mutex<State>>,
+}
+
+impl Pairing {
+    /// Loads the trusted devices and, when pairing is required, shows a first code.
+    pub fn load(required: bool) -> Result<Self, Error> {
+        let devices = match fs::read_to_string(DEVICES_FILE) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| Error::Config(format!("{}: {}", DEVICES_FILE, e)))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Error::Config(format!("{}: {}", DEVICES_FILE, e))),
+        };
+        let pairing = Pairing {
+            required,
+            state: Arc::new(Mutex::new(State {
+                devices,
+                ..State::default()
+            })),
+        };
+        if required {
+            pairing.new_code();
+        }
+        Ok(pairing)
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Replaces the pairing code with a fresh one and prints it.
+    pub fn new_code(&self) -> String {
+        let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+        self.state.lock().unwrap().code = Some(PairingCode {
+            code: code.clone(),
+            expires: Instant::now() + CODE_LIFETIME,
+        });
+        println!(
+            "Pairing code: {} (valid for {} seconds)",
+            code,
+            CODE_LIFETIME.as_secs()
+        );
+        code
+    }
+
+    /// The current code and the seconds it remains valid, if there is one.
+    pub fn current_code(&self) -> Option<(String, u64)> {
+        let state = self.state.lock().unwrap();
+        let code = state.code.as_ref()?;
+        let remaining = code.expires.checked_duration_since(Instant::now())?;
+        Some((code.code.clone(), remaining.as_secs()))
+    }
+
+    /// Trades a valid code for a new device credential. The code is used up
+    /// on success; failures count towards the peer's lockout.
+    pub fn pair(&self, peer: Option<IpAddr>, code: &str, device: &str) -> Result<String, Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some(peer) = peer {
+            if let Some((failures, last)) = state.failures.get(&peer) {
+                if *failures >= MAX_FAILURES && now.duration_since(*last) < LOCKOUT {
+                    return Err(Error::TooManyAttempts);
+                }
+            }
+        }
+
+        let valid = state
+            .code
+            .as_ref()
+            .is_some_and(|current| current.code == code && current.expires > now);
+        if !valid {
+            if let Some(peer) = peer {
+                let entry = state.failures.entry(peer).or_insert((0, now));
+                if now.duration_since(entry.1) >= LOCKOUT {
+                    entry.0 = 0;
+                }
+                entry.0 += 1;
+                entry.1 = now;
+            }
+            return Err(Error::Unauthorized("wrong or expired pairing code"));
+        }
+
+        state.code = None;
+        if let Some(peer) = peer {
+            state.failures.remove(&peer);
+        }
+        let token: String = (0..32)
+            .map(|_| format!("{:02x}", rand::thread_rng().gen::<u8>()))
+            .collect();
+        let paired_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        state.devices.insert(
+            token.clone(),
+            TrustedDevice {
+                device: device.to_string(),
+                paired_at,
+            },
+        );
+        save(&state.devices);
+        println!("Device {:?} paired.", device);
+        Ok(token)
+    }
+
+    pub fn trusted_device(&self, token: &str) -> Option<TrustedDevice> {
+        self.state.lock().unwrap().devices.get(token).cloned()
+    }
+
+    /// When pairing is required, checks that `token` is the credential of
+    /// `device` itself, so that one paired device cannot act for another.
+    pub fn check_device(&self, token: Option<&str>, device: &str) -> Result<(), Error> {
+        if !self.required {
+            return Ok(());
+        }
+        match token.and_then(|token| self.trusted_device(token)) {
+            Some(trusted) if trusted.device == device => Ok(()),
+            Some(trusted) => Err(Error::Forbidden(format!(
+                "device {:?} acting for {:?}",
+                trusted.device, device
+            ))),
+            None => Err(Error::Unauthorized("this device is not paired")),
+        }
+    }
+}
+
+fn save(devices: &HashMap<String, TrustedDevice>) {
+    let result = serde_json::to_string_pretty(devices)
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(DEVICES_FILE, text).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("Failed to save {}: {}", DEVICES_FILE, e);
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// When pairing is required, only lets through requests carrying the
+/// credential of a trusted device in the `token` query parameter.
+pub fn trusted(pairing: Pairing) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::query::<TokenQuery>()
+        .and_then(move |query: TokenQuery| {
+            let pairing = pairing.clone();
+            async move {
+                if !pairing.is_required() {
+                    return Ok(());
+                }
+                match query.token.and_then(|token| pairing.trusted_device(&token)) {
+                    Some(_) => Ok(()),
+                    None => Err(warp::reject::custom(Error::Unauthorized(
+                        "this device is not paired",
+                    ))),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[derive(Deserialize)]
+pub struct PairRequest {
+    code: String,
+    device: String,
+}
+
+pub async fn handle_pair(
+    request: PairRequest,
+    addr: Option<SocketAddr>,
+    pairing: Pairing,
+    audit: AuditLog,
+) -> Result<impl warp::Reply, Rejection> {
+    if !pairing.is_required() {
+        return Err(warp::reject::custom(Error::Unsupported(
+            "pairing is not enabled",
+        )));
+    }
+    let peer = addr.map(|addr| addr.ip());
+    match pairing.pair(peer, &request.code, &request.device) {
+        Ok(token) => {
+            audit.record(AuditEvent::DevicePaired {
+                device: request.device,
+                peer: peer.map(|peer| peer.to_string()),
+            });
+            Ok(warp::reply::json(&serde_json::json!({ "token": token })))
+        }
+        Err(e) => {
+            audit.record(AuditEvent::PairingFailed {
+                device: request.device,
+                peer: peer.map(|peer| peer.to_string()),
+                reason: e.to_string(),
+            });
+            Err(warp::reject::custom(e))
+        }
+    }
+}
+
+pub async fn handle_new_code(pairing: Pairing) -> Result<impl warp::Reply, Rejection> {
+    if !pairing.is_required() {
+        return Err(warp::reject::custom(Error::Unsupported(
+            "pairing is not enabled",
+        )));
+    }
+    pairing.new_code();
+    Ok(code_reply(&pairing))
+}
+
+/// Reply with the current pairing code, for the host's own `/qr` page.
+pub fn code_reply(pairing: &Pairing) -> impl warp::Reply {
+    let (code, expires_in) = match pairing.current_code() {
+        Some((code, expires_in)) => (Some(code), expires_in),
+        None => (None, 0),
+    };
+    warp::reply::json(&serde_json::json!({
+        "code": code,
+        "expires_in": expires_in,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairing(required: bool) -> Pairing {
+        let devices = HashMap::from([(
+            "phone-credential".to_string(),
+            TrustedDevice {
+                device: "phone".to_string(),
+                paired_at: 0,
+            },
+        )]);
+        Pairing {
+            required,
+            state: Arc::new(Mutex::new(State {
+                devices,
+                ..State::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn devices_only_act_for_themselves() {
+        let pairing = pairing(true);
+        assert!(pairing
+            .check_device(Some("phone-credential"), "phone")
+            .is_ok());
+        assert!(matches!(
+            pairing.check_device(Some("phone-credential"), "tablet"),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(matches!(
+            pairing.check_device(Some("made-up"), "phone"),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            pairing.check_device(None, "phone"),
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn any_device_goes_without_pairing() {
+        assert!(pairing(false).check_device(None, "tablet").is_ok());
+    }
+}
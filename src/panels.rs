@@ -0,0 +1,183 @@
+use crate::actions::Action;
+use crate::media::MediaAction;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+/// A named group of extra buttons the client pages show, from `panels` in
+/// the config, so that which buttons there are is up to the host.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(try_from = "RawPanel")]
+pub struct Panel {
+    pub name: String,
+    pub buttons: Vec<PanelButton>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PanelButton {
+    /// Sent back in `PanelAction` when the button is pressed.
+    pub id: String,
+    pub label: String,
+    /// A hint for the pages on what to draw, e.g. "undo".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    pub action: ButtonAction,
+}
+
+/// What a panel button does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ButtonAction {
+    /// A key combination or wheel turn, written as for gestures.
+    Input(Action),
+    /// A media player action, written as `{"media": "PlayPause"}`.
+    Media(MediaAction),
+}
+
+impl fmt::Display for ButtonAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ButtonAction::Input(action) => write!(f, "{}", action),
+            ButtonAction::Media(action) => write!(f, "media {:?}", action),
+        }
+    }
+}
+
+/// Clients are told what a button does as text, for a tooltip; they press
+/// it by id.
+impl Serialize for ButtonAction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A panel as written in the config, with its actions still unchecked so
+/// that a mistake can be reported with the panel and button it is in.
+#[derive(Deserialize)]
+struct RawPanel {
+    name: String,
+    buttons: Vec<RawButton>,
+}
+
+#[derive(Deserialize)]
+struct RawButton {
+    id: String,
+    label: String,
+    #[serde(default)]
+    icon: Option<String>,
+    action: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediaButton {
+    media: MediaAction,
+}
+
+impl TryFrom<RawPanel> for Panel {
+    type Error = String;
+
+    fn try_from(raw: RawPanel) -> Result<Self, Self::Error> {
+        let buttons = raw
+            .buttons
+            .into_iter()
+            .map(|button| {
+                // A key combination is parsed here rather than as an `Action`,
+                // whose error would not say what is wrong with it.
+                let action = match button.action {
+                    serde_json::Value::String(combo) => combo
+                        .parse()
+                        .map(|combo| ButtonAction::Input(Action::Keys(combo))),
+                    action if action.get("media").is_some() => {
+                        serde_json::from_value::<MediaButton>(action)
+                            .map(|media| ButtonAction::Media(media.media))
+                            .map_err(|e| e.to_string())
+                    }
+                    action => serde_json::from_value(action)
+                        .map(ButtonAction::Input)
+                        .map_err(|e| e.to_string()),
+                };
+                let action = action.map_err(|e| {
+                    format!(
+                        "panels: panel {:?}, button {:?}: invalid action: {}",
+                        raw.name, button.id, e
+                    )
+                })?;
+                Ok(PanelButton {
+                    id: button.id,
+                    label: button.label,
+                    icon: button.icon,
+                    action,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Panel {
+            name: raw.name,
+            buttons,
+        })
+    }
+}
+
+/// The button with `id` on any of `panels`.
+pub fn find<'a>(panels: &'a [Panel], id: &str) -> Option<&'a PanelButton> {
+    panels
+        .iter()
+        .flat_map(|panel| &panel.buttons)
+        .find(|button| button.id == id)
+}
+
+/// Button ids must tell buttons apart across all panels.
+pub fn check(panels: &[Panel]) -> Result<(), String> {
+    let mut seen = std::collections::HashSet::new();
+    for panel in panels {
+        for button in &panel.buttons {
+            if !seen.insert(button.id.as_str()) {
+                return Err(format!(
+                    "panels: panel {:?}, button {:?}: the id is used by another button",
+                    panel.name, button.id
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panels(json: &str) -> Result<Vec<Panel>, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn invalid_action_is_named_once() {
+        let error = panels(
+            r#"[{ "name": "Edit", "buttons": [
+                { "id": "undo", "label": "Undo", "action": "Ctrl+Zed" }
+            ] }]"#,
+        )
+        .unwrap_err();
+        assert!(
+            error.starts_with("panels: panel \"Edit\", button \"undo\": invalid action: "),
+            "{}",
+            error
+        );
+        assert_eq!(error.matches("panels:").count(), 1);
+    }
+
+    #[test]
+    fn duplicate_ids_are_refused() {
+        let panels = panels(
+            r#"[
+                { "name": "A", "buttons": [{ "id": "x", "label": "X", "action": "Ctrl+x" }] },
+                { "name": "B", "buttons": [{ "id": "x", "label": "Y", "action": "Ctrl+y" }] }
+            ]"#,
+        )
+        .unwrap();
+        let error = check(&panels).unwrap_err();
+        assert_eq!(
+            error,
+            "panels: panel \"B\", button \"x\": the id is used by another button"
+        );
+        assert_eq!(find(&panels, "x").unwrap().label, "X");
+    }
+}
@@ -0,0 +1,154 @@
+use crate::server_info::ServerInfo;
+use crate::version::BUILD;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+
+const PROBE_HISTORY_FILE: &str = "probe_history.json";
+
+/// What the startup checks found, kept from one run to the next so that a
+/// host update breaking input is noticed. Missing fields load as unknown and
+/// unknown ones are ignored, so files from older and newer versions both
+/// load.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+struct ProbeRecord {
+    /// The server version that wrote the record.
+    server_version: String,
+    os_version: Option<String>,
+    input_backend: Option<String>,
+    /// The primary display as "<width>x<height>", if its size was known.
+    display: Option<String>,
+    /// Whether each check passed, by name.
+    checks: BTreeMap<String, bool>,
+}
+
+impl ProbeRecord {
+    fn of(info: &ServerInfo) -> Self {
+        let capabilities = &info.capabilities;
+        let checks = [
+            ("pointer", capabilities.input.pointer),
+            ("keys", capabilities.input.keys),
+            ("scroll", capabilities.input.scroll),
+            ("media_keys", capabilities.media_keys),
+            ("mpris", capabilities.mpris),
+            ("unicode_typing", capabilities.unicode_typing),
+            ("wake_display", capabilities.wake_display),
+        ];
+        ProbeRecord {
+            server_version: BUILD.version.to_string(),
+            os_version: info.os_version.clone(),
+            input_backend: Some(info.input_backend.to_string()),
+            display: info
+                .primary_display
+                .map(|display| format!("{}x{}", display.width, display.height)),
+            checks: checks
+                .into_iter()
+                .map(|(name, passed)| (name.to_string(), passed))
+                .collect(),
+        }
+    }
+}
+
+/// Something that worked in the previous run and does not in this one,
+/// reported as `capability_changes` by `/api/info`.
+#[derive(Serialize, Debug, Clone)]
+pub struct CapabilityChange {
+    pub check: String,
+    pub was: String,
+    pub now: String,
+}
+
+/// What regressed between `previous` and `current`. Checks only one of them
+/// knows about, e.g. from another version, are not compared.
+fn regressions(previous: &ProbeRecord, current: &ProbeRecord) -> Vec<CapabilityChange> {
+    let describe = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".to_string());
+    let mut changes = Vec::new();
+    if previous.input_backend.is_some() && previous.input_backend != current.input_backend {
+        changes.push(CapabilityChange {
+            check: "input_backend".to_string(),
+            was: describe(&previous.input_backend),
+            now: describe(&current.input_backend),
+        });
+    }
+    if previous.display.is_some() && current.display.is_none() {
+        changes.push(CapabilityChange {
+            check: "display".to_string(),
+            was: describe(&previous.display),
+            now: describe(&current.display),
+        });
+    }
+    for (check, passed) in &current.checks {
+        if !passed && previous.checks.get(check) == Some(&true) {
+            changes.push(CapabilityChange {
+                check: check.clone(),
+                was: "pass".to_string(),
+                now: "fail".to_string(),
+            });
+        }
+    }
+    changes
+}
+
+/// Compares what the startup checks found with the previous run, warning
+/// about anything that stopped working. This run's results are kept for the
+/// next unless something regressed, so that the warning repeats until it is
+/// fixed or `reset` clears the history. Problems with the history file are
+/// only logged, as it must never stop the server.
+pub fn compare_with_previous(info: &ServerInfo, reset: bool) -> Vec<CapabilityChange> {
+    if reset {
+        match fs::remove_file(PROBE_HISTORY_FILE) {
+            Ok(()) => println!("Capability check history cleared."),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Warning: {}: {}", PROBE_HISTORY_FILE, e),
+        }
+    }
+    let previous = match fs::read_to_string(PROBE_HISTORY_FILE) {
+        Ok(text) => match serde_json::from_str::<ProbeRecord>(&text) {
+            Ok(previous) => Some(previous),
+            Err(e) => {
+                eprintln!("Warning: {}: {}, starting afresh", PROBE_HISTORY_FILE, e);
+                None
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!("Warning: {}: {}", PROBE_HISTORY_FILE, e);
+            None
+        }
+    };
+    let current = ProbeRecord::of(info);
+    let Some(previous) = previous else {
+        save(&current);
+        return Vec::new();
+    };
+    let changes = regressions(&previous, &current);
+    if changes.is_empty() {
+        save(&current);
+        return changes;
+    }
+    eprintln!("**********************************************************************");
+    eprintln!(
+        "Warning: checks that passed the last time the server ran ({} on OS {}) fail now ({} on OS {}):",
+        previous.server_version,
+        previous.os_version.as_deref().unwrap_or("unknown"),
+        current.server_version,
+        current.os_version.as_deref().unwrap_or("unknown")
+    );
+    for change in &changes {
+        eprintln!("  {}: {} -> {}", change.check, change.was, change.now);
+    }
+    eprintln!("Start once with --reset-probe-history if this is expected.");
+    eprintln!("**********************************************************************");
+    changes
+}
+
+fn save(record: &ProbeRecord) {
+    let saved = serde_json::to_string_pretty(record)
+        .map_err(|e| e.to_string())
+        .and_then(|text| fs::write(PROBE_HISTORY_FILE, text).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        eprintln!("Warning: {}: {}", PROBE_HISTORY_FILE, e);
+    }
+}
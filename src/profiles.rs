@@ -0,0 +1,263 @@
+use crate::error::Error;
+use crate::gestures::{Gesture, GestureSwitches};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+
+const PROFILES_FILE: &str = "profiles.json";
+
+/// How a device wants its gestures translated, remembered across connections.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    /// Multiplier applied to pointer movement.
+    pub sensitivity: f64,
+    /// Extra multipliers for horizontal and vertical pointer movement, on top
+    /// of `sensitivity`, e.g. for very wide screens.
+    pub x_sensitivity: f64,
+    pub y_sensitivity: f64,
+    /// Extra multiplier for pointer movement while a drag holds a button,
+    /// e.g. below 1 for slow, precise drags and fast free movement.
+    pub drag_sensitivity: f64,
+    /// Reverse the pointer's horizontal or vertical direction.
+    pub invert_x: bool,
+    pub invert_y: bool,
+    /// Multiplier applied to two-finger scrolling.
+    pub scroll_factor: f64,
+    pub orientation: Orientation,
+    /// Pointer movement within this many degrees of an axis is straightened
+    /// onto it. 0 turns snapping off.
+    pub snap_degrees: f64,
+    /// Gestures this device wants off, on top of those off in the config.
+    pub gestures: GestureSwitches,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            sensitivity: 1.0,
+            x_sensitivity: 1.0,
+            y_sensitivity: 1.0,
+            drag_sensitivity: 1.0,
+            invert_x: false,
+            invert_y: false,
+            scroll_factor: 1.0,
+            orientation: Orientation::Normal,
+            snap_degrees: 0.0,
+            gestures: GestureSwitches::default(),
+        }
+    }
+}
+
+impl Profile {
+    /// Scales pointer movement (or speed) per axis, applying the inversions.
+    pub fn scale_axes(&self, x: f64, y: f64) -> (f64, f64) {
+        let sign = |invert: bool| if invert { -1.0 } else { 1.0 };
+        (
+            x * self.x_sensitivity * sign(self.invert_x),
+            y * self.y_sensitivity * sign(self.invert_y),
+        )
+    }
+}
+
+/// Which way the device is held, so that moving a finger towards the top of
+/// the screen always moves the pointer up.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Normal,
+    /// Turned a quarter counter-clockwise.
+    Left,
+    /// Turned a quarter clockwise.
+    Right,
+    UpsideDown,
+}
+
+impl Orientation {
+    pub fn rotate(self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::Left => (y, -x),
+            Orientation::Right => (-y, x),
+            Orientation::UpsideDown => (-x, -y),
+        }
+    }
+}
+
+/// Changes requested by a `SetConfig` message; unset fields are kept.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProfileUpdate {
+    pub sensitivity: Option<f64>,
+    pub x_sensitivity: Option<f64>,
+    pub y_sensitivity: Option<f64>,
+    pub drag_sensitivity: Option<f64>,
+    pub invert_x: Option<bool>,
+    pub invert_y: Option<bool>,
+    pub scroll_factor: Option<f64>,
+    pub orientation: Option<Orientation>,
+    pub snap_degrees: Option<f64>,
+    /// Gestures to switch on or off by name; others are kept.
+    pub gestures: Option<BTreeMap<Gesture, bool>>,
+}
+
+impl ProfileUpdate {
+    pub fn apply(&self, profile: &mut Profile) -> Result<(), Error> {
+        for (name, value) in [
+            ("sensitivity", self.sensitivity),
+            ("x_sensitivity", self.x_sensitivity),
+            ("y_sensitivity", self.y_sensitivity),
+            ("drag_sensitivity", self.drag_sensitivity),
+            ("scroll_factor", self.scroll_factor),
+        ] {
+            if let Some(value) = value {
+                if !(value.is_finite() && value > 0.0) {
+                    return Err(Error::InvalidSetting(format!(
+                        "{} must be a positive number, got {}",
+                        name, value
+                    )));
+                }
+            }
+        }
+        if let Some(snap_degrees) = self.snap_degrees {
+            if !(0.0..45.0).contains(&snap_degrees) {
+                return Err(Error::InvalidSetting(format!(
+                    "snap_degrees must be at least 0 and below 45, got {}",
+                    snap_degrees
+                )));
+            }
+        }
+        if let Some(sensitivity) = self.sensitivity {
+            profile.sensitivity = sensitivity;
+        }
+        if let Some(x_sensitivity) = self.x_sensitivity {
+            profile.x_sensitivity = x_sensitivity;
+        }
+        if let Some(y_sensitivity) = self.y_sensitivity {
+            profile.y_sensitivity = y_sensitivity;
+        }
+        if let Some(drag_sensitivity) = self.drag_sensitivity {
+            profile.drag_sensitivity = drag_sensitivity;
+        }
+        if let Some(invert_x) = self.invert_x {
+            profile.invert_x = invert_x;
+        }
+        if let Some(invert_y) = self.invert_y {
+            profile.invert_y = invert_y;
+        }
+        if let Some(scroll_factor) = self.scroll_factor {
+            profile.scroll_factor = scroll_factor;
+        }
+        if let Some(orientation) = self.orientation {
+            profile.orientation = orientation;
+        }
+        if let Some(snap_degrees) = self.snap_degrees {
+            profile.snap_degrees = snap_degrees;
+        }
+        for (gesture, on) in self.gestures.iter().flatten() {
+            profile.gestures.set(*gesture, *on);
+        }
+        Ok(())
+    }
+}
+
+/// Saved profiles by device id, kept in `profiles.json` in the working directory.
+#[derive(Clone, Default)]
+pub struct Profiles {
+    profiles: Arc<Mutex<HashMap<String, Profile>>>,
+    /// Held while the file is written, so that writes land in order.
+    writing: Arc<Mutex<()>>,
+}
+
+impl Profiles {
+    /// Loads the saved profiles, starting empty when the file does not exist.
+    pub fn load() -> Result<Self, Error> {
+        let profiles = match fs::read_to_string(PROFILES_FILE) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| Error::Config(format!("{}: {}", PROFILES_FILE, e)))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(Error::Config(format!("{}: {}", PROFILES_FILE, e))),
+        };
+        Ok(Profiles {
+            profiles: Arc::new(Mutex::new(profiles)),
+            writing: Arc::default(),
+        })
+    }
+
+    /// The saved profile of a device, or the defaults for one never seen before.
+    pub fn get(&self, device: &str) -> Profile {
+        self.profiles
+            .lock()
+            .unwrap()
+            .get(device)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Remembers a device's profile and rewrites the profiles file in the
+    /// background. Must be called from within the Tokio runtime.
+    pub fn save(&self, device: &str, profile: Profile) {
+        self.profiles
+            .lock()
+            .unwrap()
+            .insert(device.to_string(), profile);
+        let profiles = self.clone();
+        tokio::task::spawn_blocking(move || profiles.write());
+    }
+
+    /// Writes the profiles as they are now. The profiles are only locked
+    /// while they are serialized, and a write that waited for an earlier one
+    /// picks up its changes too.
+    fn write(&self) {
+        let _writing = self.writing.lock().unwrap();
+        let text = serde_json::to_string_pretty(&*self.profiles.lock().unwrap());
+        let result = text
+            .map_err(|e| e.to_string())
+            .and_then(|text| fs::write(PROFILES_FILE, text).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to save {}: {}", PROFILES_FILE, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drag_sensitivity_must_be_positive() {
+        let mut profile = Profile::default();
+        for bad in [0.0, -0.5, f64::NAN, f64::INFINITY] {
+            let update = ProfileUpdate {
+                drag_sensitivity: Some(bad),
+                ..ProfileUpdate::default()
+            };
+            assert!(matches!(
+                update.apply(&mut profile),
+                Err(Error::InvalidSetting(_))
+            ));
+        }
+        assert_eq!(profile.drag_sensitivity, 1.0);
+        let update = ProfileUpdate {
+            drag_sensitivity: Some(0.5),
+            ..ProfileUpdate::default()
+        };
+        update.apply(&mut profile).unwrap();
+        assert_eq!(profile.drag_sensitivity, 0.5);
+        // Other updates leave it alone.
+        ProfileUpdate::default().apply(&mut profile).unwrap();
+        assert_eq!(profile.drag_sensitivity, 0.5);
+    }
+
+    #[test]
+    fn axes_scale_and_invert_separately() {
+        let profile = Profile {
+            x_sensitivity: 2.0,
+            y_sensitivity: 0.5,
+            invert_x: true,
+            ..Profile::default()
+        };
+        assert_eq!(profile.scale_axes(3.0, -4.0), (-6.0, -2.0));
+        assert_eq!(Profile::default().scale_axes(3.0, -4.0), (3.0, -4.0));
+    }
+}
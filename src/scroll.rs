@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Two-finger scrolling in `Fast` mode gathered between flushes, in lines,
+/// so that every bit of a slow gesture counts and wheel events go out at a
+/// steady pace, see `scroll_interval_ms`.
+#[derive(Default)]
+pub struct ScrollAccumulator {
+    x: f64,
+    y: f64,
+    /// When the next flush is due; unset while nothing is left to scroll,
+    /// so that a new gesture scrolls straight away.
+    deadline: Option<Instant>,
+}
+
+impl ScrollAccumulator {
+    pub fn add(&mut self, lines_x: f64, lines_y: f64) {
+        self.x += lines_x;
+        self.y += lines_y;
+        self.deadline.get_or_insert_with(Instant::now);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Takes the whole lines gathered, up to `max_lines` on each axis,
+    /// leaving fractions and anything over the cap for later. Once lines
+    /// went out, the next flush is due `interval` later; until then more are
+    /// only gathered.
+    pub fn flush(&mut self, interval: Duration, max_lines: Option<u16>) -> (i32, i32) {
+        let max = max_lines.map_or(f64::from(i32::MAX), f64::from);
+        let lines_x = self.x.trunc().clamp(-max, max);
+        let lines_y = self.y.trunc().clamp(-max, max);
+        self.x -= lines_x;
+        self.y -= lines_y;
+        self.deadline = (lines_x != 0.0 || lines_y != 0.0).then(|| Instant::now() + interval);
+        (lines_x as i32, lines_y as i32)
+    }
+
+    /// Whether whole lines are still waiting to go out.
+    pub fn has_lines(&self) -> bool {
+        self.x.abs() >= 1.0 || self.y.abs() >= 1.0
+    }
+
+    /// The gesture ended: what is left of a line is dropped, rather than
+    /// adding to the next gesture, while whole lines still go out.
+    pub fn end_gesture(&mut self) {
+        self.x = self.x.trunc();
+        self.y = self.y.trunc();
+    }
+}
+
+/// Scrolling that grows less than the finger's speed once it is fast, so
+/// that a quick flick does not overshoot: past `from_speed`, every move
+/// scrolls as if it were made at `from_speed`, but never less than
+/// `min_factor` of what it would scroll otherwise.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScrollDeceleration {
+    /// Finger speed, in pixels per millisecond, up to which scrolling is
+    /// left as it is.
+    pub from_speed: f64,
+    /// The least share of its usual amount a move scrolls, however fast.
+    pub min_factor: f64,
+}
+
+impl Default for ScrollDeceleration {
+    fn default() -> Self {
+        ScrollDeceleration {
+            from_speed: 1.0,
+            min_factor: 0.4,
+        }
+    }
+}
+
+impl ScrollDeceleration {
+    pub fn check(&self) -> Result<(), String> {
+        if !(self.from_speed.is_finite() && self.from_speed > 0.0) {
+            return Err("from_speed must be positive".to_string());
+        }
+        if !(self.min_factor > 0.0 && self.min_factor <= 1.0) {
+            return Err("min_factor must be above 0 and at most 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// What a move at `speed` pixels per millisecond scrolls, relative to
+    /// its usual amount.
+    pub fn factor(&self, speed: f64) -> f64 {
+        if speed <= self.from_speed {
+            return 1.0;
+        }
+        (self.from_speed / speed).max(self.min_factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    /// Adds `amounts` to a new accumulator, flushing after each add for which
+    /// `flush_after` holds and once more when the gesture ends, and returns
+    /// what went out.
+    fn scrolled(
+        amounts: &[f64],
+        flush_after: impl Fn(usize) -> bool,
+        max_lines: Option<u16>,
+    ) -> Vec<i32> {
+        let mut scroll = ScrollAccumulator::default();
+        let mut sent = Vec::new();
+        for (i, &amount) in amounts.iter().enumerate() {
+            scroll.add(0.0, amount);
+            if flush_after(i) {
+                sent.push(scroll.flush(INTERVAL, max_lines).1);
+            }
+        }
+        scroll.end_gesture();
+        while scroll.has_lines() {
+            sent.push(scroll.flush(INTERVAL, max_lines).1);
+        }
+        sent
+    }
+
+    #[test]
+    fn total_scrolled_is_the_total_input_whatever_the_timing() {
+        // Quarters add up exactly, so nothing is lost to rounding.
+        let amounts = [0.25, 0.5, 2.75, 0.25, 0.25, 1.0, 0.75, 3.25, 0.5, 0.5];
+        let total: f64 = amounts.iter().sum();
+        assert_eq!(total, 10.0);
+        let timings: [&dyn Fn(usize) -> bool; 4] =
+            [&|_| true, &|i| i % 3 == 2, &|i| i == 4, &|_| false];
+        for flush_after in timings {
+            let sent = scrolled(&amounts, flush_after, None);
+            assert_eq!(sent.iter().sum::<i32>(), 10, "{:?}", sent);
+        }
+    }
+
+    #[test]
+    fn slow_scrolling_goes_out_evenly() {
+        let sent = scrolled(&[0.5; 8], |_| true, None);
+        assert_eq!(sent, [0, 1, 0, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn flush_is_due_only_after_lines_went_out() {
+        let mut scroll = ScrollAccumulator::default();
+        assert_eq!(scroll.deadline(), None);
+        let start = Instant::now();
+        scroll.add(0.0, 0.5);
+        assert!(scroll
+            .deadline()
+            .is_some_and(|deadline| deadline <= Instant::now()));
+        assert_eq!(scroll.flush(INTERVAL, None), (0, 0));
+        assert_eq!(
+            scroll.deadline(),
+            None,
+            "a new gesture scrolls straight away"
+        );
+        scroll.add(0.0, 0.5);
+        assert_eq!(scroll.flush(INTERVAL, None), (0, 1));
+        assert!(scroll
+            .deadline()
+            .is_some_and(|deadline| deadline >= start + INTERVAL));
+    }
+
+    #[test]
+    fn zero_interval_flushes_every_event() {
+        let mut scroll = ScrollAccumulator::default();
+        for _ in 0..3 {
+            scroll.add(-2.0, 1.5);
+            let sent = scroll.flush(Duration::ZERO, None);
+            assert!(sent.0 <= -2 && sent.1 >= 1);
+            assert!(scroll
+                .deadline()
+                .is_some_and(|deadline| deadline <= Instant::now()));
+        }
+    }
+
+    #[test]
+    fn flush_sends_no_more_than_the_cap() {
+        // Three moves within one interval, each at the cap, go out one
+        // capped flush at a time rather than as one large jump.
+        let sent = scrolled(&[5.0, -5.0, 5.0, 5.0, 5.0], |i| i == 4, Some(5));
+        assert_eq!(sent, [5, 5, 5]);
+        let sent = scrolled(&[-12.5], |_| true, Some(5));
+        assert_eq!(sent, [-5, -5, -2]);
+        // The same total goes out as without the cap.
+        let mut scroll = ScrollAccumulator::default();
+        scroll.add(9.0, -9.0);
+        assert_eq!(scroll.flush(INTERVAL, Some(4)), (4, -4));
+        assert!(scroll.has_lines());
+        assert_eq!(scroll.flush(INTERVAL, Some(4)), (4, -4));
+        assert_eq!(scroll.flush(INTERVAL, Some(4)), (1, -1));
+        assert!(!scroll.has_lines());
+    }
+
+    #[test]
+    fn end_of_a_gesture_drops_only_fractions() {
+        let mut scroll = ScrollAccumulator::default();
+        scroll.add(1.75, -0.75);
+        scroll.end_gesture();
+        assert_eq!(scroll.flush(INTERVAL, None), (1, 0));
+        assert!(!scroll.has_lines());
+        scroll.add(0.0, 0.25);
+        assert_eq!(scroll.flush(INTERVAL, None), (0, 0));
+    }
+
+    #[test]
+    fn slow_moves_scroll_as_usual() {
+        let deceleration = ScrollDeceleration::default();
+        for speed in [0.0, 0.3, 1.0] {
+            assert_eq!(deceleration.factor(speed), 1.0);
+        }
+    }
+
+    #[test]
+    fn fast_moves_scroll_less_per_pixel() {
+        let deceleration = ScrollDeceleration::default();
+        assert_eq!(deceleration.factor(2.0), 0.5);
+        // Past `from_speed`, a move scrolls as if made at `from_speed`...
+        assert_eq!(2.0 * deceleration.factor(2.0), 1.0);
+        // ...down to `min_factor`, so faster moves still scroll further.
+        assert_eq!(deceleration.factor(4.0), 0.4);
+        assert_eq!(deceleration.factor(100.0), 0.4);
+        assert!(8.0 * deceleration.factor(8.0) > 4.0 * deceleration.factor(4.0));
+    }
+
+    #[test]
+    fn check_refuses_bad_settings() {
+        assert!(ScrollDeceleration::default().check().is_ok());
+        for (from_speed, min_factor) in [(0.0, 0.4), (f64::INFINITY, 0.4), (1.0, 0.0), (1.0, 1.5)] {
+            let deceleration = ScrollDeceleration {
+                from_speed,
+                min_factor,
+            };
+            assert!(deceleration.check().is_err());
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::input::Input;
+use std::thread;
+use std::time::Duration;
+
+/// Side of the square traced by the self-test, in pixels.
+const SIDE_PX: i32 = 100;
+const STEPS_PER_SIDE: i32 = 10;
+const STEP_DELAY: Duration = Duration::from_millis(20);
+
+/// Moves the pointer around a small square and back to where it started, so
+/// that users can see input injection works before connecting a device.
+pub fn run() -> Result<(), String> {
+    check_display()?;
+
+    let mut input = Input::new().map_err(|e| e.to_string())?;
+    let start = input.mouse_location();
+    let step = SIDE_PX / STEPS_PER_SIDE;
+    let mut moved = false;
+    for (dx, dy) in [(step, 0), (0, step), (-step, 0), (0, -step)] {
+        for _ in 0..STEPS_PER_SIDE {
+            input.mouse_move_relative(dx, dy);
+            thread::sleep(STEP_DELAY);
+        }
+        if let Some(failure) = input.take_failure() {
+            return Err(failure);
+        }
+        moved |= input.mouse_location() != start;
+    }
+
+    match start {
+        // Where the platform can report the pointer position, the pointer
+        // must have left its starting point at some corner of the square.
+        Some(_) if !moved => Err(
+            "the pointer did not move; check that this program may control the input devices"
+                .to_string(),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// enigo drives X11 on Linux; this says why more plainly than a failed
+/// connection would.
+#[cfg(target_os = "linux")]
+fn check_display() -> Result<(), String> {
+    match std::env::var_os("DISPLAY") {
+        Some(display) if !display.is_empty() => Ok(()),
+        _ => Err("no X11 display found (DISPLAY is not set)".to_string()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_display() -> Result<(), String> {
+    Ok(())
+}
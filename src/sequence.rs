@@ -0,0 +1,217 @@
+use crate::clipboard::{Paste, PASTE_SETTLE};
+use crate::input::Input;
+use enigo::Key;
+use serde::Deserialize;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bounds on the pause between the keystrokes of a `TypeSequence`.
+pub const MIN_INTERVAL_MS: u64 = 10;
+pub const MAX_INTERVAL_MS: u64 = 1000;
+/// Characters typed between two progress reports to the client.
+pub const PROGRESS_EVERY: usize = 20;
+
+/// Text that must stay out of the logs: formatting it only shows its length.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct PrivateText(pub String);
+
+impl fmt::Debug for PrivateText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.chars().count() {
+            1 => write!(f, "<1 character>"),
+            count => write!(f, "<{} characters>", count),
+        }
+    }
+}
+
+/// Text typed one character at a time, paced by the mouse event thread's
+/// deadline so that other events are handled in between.
+pub struct Sequence {
+    pub connection: u64,
+    chars: Vec<char>,
+    typed: usize,
+    interval: Duration,
+    press_enter: bool,
+    pub deadline: Instant,
+}
+
+impl Sequence {
+    /// Starts typing straight away, with the interval clamped to the
+    /// supported range.
+    pub fn new(connection: u64, text: &str, interval_ms: u64, press_enter: bool) -> Self {
+        Sequence {
+            connection,
+            chars: text.chars().collect(),
+            typed: 0,
+            interval: Duration::from_millis(interval_ms.clamp(MIN_INTERVAL_MS, MAX_INTERVAL_MS)),
+            press_enter,
+            deadline: Instant::now(),
+        }
+    }
+
+    /// Types the next character, or Enter after the last one when asked to.
+    /// Returns whether anything is left to type.
+    pub fn step(&mut self, input: &mut Input) -> bool {
+        match self.chars.get(self.typed) {
+            Some(c) => {
+                input.key_sequence(&c.to_string());
+                self.typed += 1;
+            }
+            None if self.press_enter => {
+                input.key_click(Key::Return);
+                self.press_enter = false;
+            }
+            None => {}
+        }
+        self.deadline = Instant::now() + self.interval;
+        self.typed < self.chars.len() || self.press_enter
+    }
+
+    pub fn typed(&self) -> usize {
+        self.typed
+    }
+
+    pub fn total(&self) -> usize {
+        self.chars.len()
+    }
+}
+
+/// The copies of a `TypeString` with `repeat` that are still to come,
+/// `type_delay_ms` apart, paced like a `Sequence`. Pasted copies keep the
+/// text on the clipboard until `PASTE_SETTLE` after the last one.
+pub struct Repeat {
+    pub connection: u64,
+    text: String,
+    left: u32,
+    delay: Duration,
+    paste: Option<Paste>,
+    pub deadline: Instant,
+}
+
+impl Repeat {
+    /// Types `text` once straight away, or pastes it with `paste`, and
+    /// returns what is left to do, if anything. Without a delay every copy
+    /// goes out at once.
+    pub fn start(
+        input: &mut Input,
+        connection: u64,
+        text: &str,
+        times: u32,
+        delay: Duration,
+        paste: Option<Paste>,
+    ) -> Option<Self> {
+        let mut repeat = Repeat {
+            connection,
+            text: text.to_string(),
+            left: times,
+            delay,
+            paste,
+            deadline: Instant::now(),
+        };
+        let more = if delay.is_zero() {
+            while repeat.left > 0 {
+                repeat.type_next(input);
+            }
+            repeat.schedule()
+        } else {
+            repeat.step(input)
+        };
+        more.then_some(repeat)
+    }
+
+    /// Types the next copy, or puts the clipboard back once they are done.
+    /// Returns whether anything is left.
+    pub fn step(&mut self, input: &mut Input) -> bool {
+        if self.left == 0 {
+            self.paste = None;
+            return false;
+        }
+        self.type_next(input);
+        self.schedule()
+    }
+
+    /// Types the copies left without waiting, so that they come before any
+    /// text typed after them.
+    pub fn finish(&mut self, input: &mut Input) {
+        while self.left > 0 {
+            self.type_next(input);
+        }
+        if self.paste.take().is_some() {
+            thread::sleep(PASTE_SETTLE);
+        }
+    }
+
+    pub fn left(&self) -> u32 {
+        self.left
+    }
+
+    fn type_next(&mut self, input: &mut Input) {
+        match &self.paste {
+            Some(paste) => paste.press(input),
+            None => input.key_sequence(&self.text),
+        }
+        self.left -= 1;
+    }
+
+    fn schedule(&mut self) -> bool {
+        let wait = if self.left > 0 {
+            self.delay
+        } else {
+            PASTE_SETTLE
+        };
+        self.deadline = Instant::now() + wait;
+        self.left > 0 || self.paste.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeat_types_the_first_copy_straight_away() {
+        let (mut input, mock) = Input::mock();
+        let delay = Duration::from_millis(50);
+        let mut repeat = Repeat::start(&mut input, 1, "ab", 3, delay, None).unwrap();
+        assert_eq!(mock.calls(), ["text ab"]);
+        assert_eq!(repeat.left(), 2);
+        assert!(repeat.deadline > Instant::now());
+        assert!(repeat.step(&mut input));
+        assert!(!repeat.step(&mut input));
+        assert_eq!(mock.calls(), ["text ab", "text ab", "text ab"]);
+        // Nothing more once the copies are done.
+        assert!(!repeat.step(&mut input));
+        assert_eq!(mock.calls().len(), 3);
+    }
+
+    #[test]
+    fn repeat_without_a_delay_types_everything_at_once() {
+        let (mut input, mock) = Input::mock();
+        assert!(Repeat::start(&mut input, 1, "-", 4, Duration::ZERO, None).is_none());
+        assert_eq!(mock.calls(), ["text -"; 4]);
+        assert!(Repeat::start(&mut input, 1, "+", 1, Duration::from_millis(50), None).is_none());
+        assert_eq!(mock.calls().len(), 5);
+    }
+
+    #[test]
+    fn finish_types_what_is_left() {
+        let (mut input, mock) = Input::mock();
+        let mut repeat =
+            Repeat::start(&mut input, 1, "x", 3, Duration::from_secs(10), None).unwrap();
+        repeat.finish(&mut input);
+        assert_eq!(repeat.left(), 0);
+        assert_eq!(mock.calls(), ["text x"; 3]);
+    }
+
+    #[test]
+    fn private_text_shows_only_its_length() {
+        let text = PrivateText("pässwörd".to_string());
+        assert_eq!(format!("{:?}", text), "<8 characters>");
+        assert_eq!(
+            format!("{:?}", PrivateText("x".to_string())),
+            "<1 character>"
+        );
+    }
+}
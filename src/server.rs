@@ -0,0 +1,33 @@
+use crate::admin::Admin;
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::connection::{Connections, EventSender};
+use crate::input::InputHealth;
+use crate::media::MediaController;
+use crate::pairing::Pairing;
+use crate::profiles::Profiles;
+use crate::server_info::ServerInfo;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+pub mod router;
+
+/// Everything the HTTP routes need, set up once in `main` and cloned into
+/// each request.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub info: Arc<ServerInfo>,
+    pub connections: Connections,
+    /// Where the WebSocket handlers queue input for the worker.
+    pub events: EventSender,
+    pub profiles: Profiles,
+    pub audit: AuditLog,
+    pub media: MediaController,
+    pub pairing: Pairing,
+    pub admin: Admin,
+    /// Retries and failures of the input backend's calls.
+    pub input_health: InputHealth,
+    /// Notified by `/api/shutdown` to stop the server.
+    pub shutdown: Arc<Notify>,
+}
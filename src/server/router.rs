@@ -0,0 +1,727 @@
+use super::AppState;
+use crate::admin::{self, admin_only, Permission};
+use crate::audit::{self, AuditEvent, AuditLog, AuditQuery};
+use crate::calibration::{self, Calibration};
+use crate::capabilities::Capabilities;
+use crate::config::Config;
+use crate::connection::{Connections, Envelope};
+use crate::error::Error;
+use crate::event_log::EventQuery;
+use crate::input::InputHealth;
+use crate::pairing::{self, Pairing};
+use crate::profiles::Profiles;
+use crate::server_info::ServerInfo;
+use crate::{assets, cors, i18n, ClientEvent};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Notify;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::ws::{WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+/// All of the server's routes. They are grouped by who may use them, and
+/// each group checks that once for all of its routes:
+///
+/// - the static files and `/config.js`, for anyone;
+/// - `/ws`, for allowed peers, origins and paired devices, with the admin
+///   permission for holders of the admin secret;
+/// - `/api`, for allowed peers and origins, with CORS;
+/// - within `/api`, the admin routes, for the host or holders of the admin
+///   secret.
+pub fn build_routes(state: AppState) -> BoxedFilter<(Response,)> {
+    static_routes(&state)
+        .or(websocket_route(&state))
+        .or(api_routes(&state))
+        .recover(handle_rejection)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+/// Hands each request its own clone of `value`.
+fn with<T: Clone + Send + Sync>(
+    value: T,
+) -> impl Filter<Extract = (T,), Error = Infallible> + Clone {
+    warp::any().map(move || value.clone())
+}
+
+fn static_routes(state: &AppState) -> BoxedFilter<(Response,)> {
+    let client_config_route = warp::path!("config.js")
+        .and(warp::get())
+        .and(with(state.info.clone()))
+        .and(with(state.pairing.clone()))
+        .and(warp::header::optional::<String>("accept-language"))
+        .map(
+            |info: Arc<ServerInfo>, pairing: Pairing, accept_language: Option<String>| {
+                client_config_script(&info.capabilities, &pairing, accept_language.as_deref())
+                    .into_response()
+            },
+        );
+    assets::static_files(&state.config.static_dirs)
+        .or(client_config_route)
+        .unify()
+        .boxed()
+}
+
+fn websocket_route(state: &AppState) -> BoxedFilter<(Response,)> {
+    warp::path("ws")
+        .and(allowed_peer(state.config.clone()))
+        .and(
+            cors::allowed_origin(state.config.clone())
+                .map(|_| ())
+                .untuple_one(),
+        )
+        .and(pairing::trusted(state.pairing.clone()))
+        .and(admin::permission(state.admin.clone()))
+        .and(warp::ws())
+        .and(warp::addr::remote())
+        .and(warp::query::<DeviceQuery>())
+        .and(warp::header::optional::<String>("accept-language"))
+        .and(with(state.clone()))
+        .map(
+            |permission: Permission,
+             ws: Ws,
+             addr: Option<SocketAddr>,
+             query: DeviceQuery,
+             accept_language: Option<String>,
+             state: AppState| {
+                let limit = state.config.max_message_bytes;
+                let ws = ws.max_message_size(limit).max_frame_size(limit);
+                ws.on_upgrade(move |socket| {
+                    serve_websocket(socket, addr, query, accept_language, permission, state)
+                })
+                .into_response()
+            },
+        )
+        .boxed()
+}
+
+async fn serve_websocket(
+    socket: WebSocket,
+    addr: Option<SocketAddr>,
+    query: DeviceQuery,
+    accept_language: Option<String>,
+    permission: Permission,
+    state: AppState,
+) {
+    let AppState {
+        config,
+        info,
+        connections,
+        events,
+        profiles,
+        audit,
+        media,
+        ..
+    } = state;
+    let profile = query
+        .device
+        .as_deref()
+        .map(|device| profiles.get(device))
+        .unwrap_or_default();
+    let (connection, notices) = connections.register(
+        query.device,
+        profile,
+        i18n::negotiate(query.locale.as_deref(), accept_language.as_deref()),
+    );
+    println!(
+        "WebSocket connection {} opened from {:?} (device {:?}, {:?}).",
+        connection.id, addr, connection.device, permission
+    );
+    audit.record(AuditEvent::ConnectionOpened {
+        connection: connection.id,
+        device: connection.device.clone(),
+        peer: addr.map(|addr| addr.ip().to_string()),
+    });
+    if let Err(e) = crate::handle_websocket(
+        socket,
+        connection.clone(),
+        config,
+        info,
+        profiles,
+        audit.clone(),
+        media,
+        events.clone(),
+        notices,
+        query.token,
+        permission,
+        connections.clone(),
+    )
+    .await
+    {
+        e.log();
+        println!("WebSocket connection terminated.");
+    }
+    // Let go of whatever the connection was still doing.
+    let _ = events.send(Envelope {
+        connection: connection.clone(),
+        event: ClientEvent::Closed,
+        received_at: Instant::now(),
+        logged: None,
+    });
+    audit.record(AuditEvent::ConnectionClosed {
+        connection: connection.id,
+    });
+    connections.remove(connection.id);
+}
+
+fn api_routes(state: &AppState) -> BoxedFilter<(Response,)> {
+    let info = with(state.info.clone());
+    let capabilities_route = warp::path!("capabilities")
+        .and(warp::get())
+        .and(info.clone())
+        .map(|info: Arc<ServerInfo>| warp::reply::json(&info.capabilities));
+
+    let info_route = warp::path!("info")
+        .and(warp::get())
+        .and(info.clone())
+        .map(|info: Arc<ServerInfo>| warp::reply::json(&*info));
+
+    let panels_route = warp::path!("panels")
+        .and(warp::get())
+        .and(with(state.config.clone()))
+        .map(|config: Arc<Config>| warp::reply::json(&config.panels));
+
+    let version_route = warp::path!("version")
+        .and(warp::get())
+        .and(info)
+        .map(|info: Arc<ServerInfo>| warp::reply::json(&info.version));
+
+    let pair_route = warp::path!("pair")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::addr::remote())
+        .and(with(state.pairing.clone()))
+        .and(with(state.audit.clone()))
+        .and_then(pairing::handle_pair);
+
+    // Calibrating acts on the device's own connection, so it is open to the
+    // devices allowed to connect rather than to admins only.
+    let calibrate_start_route = warp::path!("calibrate" / "start")
+        .and(warp::post())
+        .and(pairing::trusted(state.pairing.clone()))
+        .and(warp::query::<CalibrateQuery>())
+        .and(with(state.pairing.clone()))
+        .and(with(state.connections.clone()))
+        .and_then(start_calibration);
+
+    let calibrate_finish_route = warp::path!("calibrate" / "finish")
+        .and(warp::post())
+        .and(pairing::trusted(state.pairing.clone()))
+        .and(warp::query::<CalibrateQuery>())
+        .and(with(state.pairing.clone()))
+        .and(with(state.connections.clone()))
+        .and(with(state.info.clone()))
+        .and(with(state.profiles.clone()))
+        .and(with(state.audit.clone()))
+        .and_then(finish_calibration);
+
+    let routes = capabilities_route
+        .map(Reply::into_response)
+        .or(info_route.map(Reply::into_response))
+        .unify()
+        .or(panels_route.map(Reply::into_response))
+        .unify()
+        .or(version_route.map(Reply::into_response))
+        .unify()
+        .or(pair_route.map(Reply::into_response))
+        .unify()
+        .or(calibrate_start_route.map(Reply::into_response))
+        .unify()
+        .or(calibrate_finish_route.map(Reply::into_response))
+        .unify()
+        .or(admin_routes(state))
+        .unify();
+
+    let config = state.config.clone();
+    warp::path("api")
+        .and(allowed_peer(config.clone()))
+        .and(
+            cors::preflight(config.clone()).or(cors::allowed_origin(config)
+                .and(routes)
+                .map(|origin, reply| cors::with_cors_headers(reply, origin))),
+        )
+        .unify()
+        .boxed()
+}
+
+/// The routes under `/api` for the host or holders of the admin secret. The
+/// check comes after each route's path, so that unknown paths are still 404s
+/// rather than 403s.
+fn admin_routes(state: &AppState) -> BoxedFilter<(Response,)> {
+    let admin = admin_only(state.admin.clone());
+    let connections = with(state.connections.clone());
+    let audit = with(state.audit.clone());
+    let pairing = with(state.pairing.clone());
+
+    let trace_route = warp::path!("clients" / u64 / "trace")
+        .and(warp::post())
+        .and(admin.clone())
+        .and(warp::query::<TraceQuery>())
+        .and(connections.clone())
+        .and(audit.clone())
+        .and_then(set_client_trace);
+
+    let stats_route = warp::path!("clients" / u64 / "stats")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(connections.clone())
+        .and_then(client_stats);
+
+    let clients_route = warp::path!("clients")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(connections.clone())
+        .map(|connections: Connections| {
+            let stats: Vec<_> = connections
+                .all()
+                .iter()
+                .map(|connection| connection.stats())
+                .collect();
+            warp::reply::json(&stats)
+        });
+
+    let audit_route = warp::path!("audit")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(warp::query::<AuditQuery>())
+        .and(audit)
+        .and_then(read_audit_log);
+
+    let events_route = warp::path!("events")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(warp::query::<EventQuery>())
+        .and(connections.clone())
+        .map(|query: EventQuery, connections: Connections| {
+            warp::reply::json(&connections.events().read(&query))
+        });
+
+    let new_code_route = warp::path!("pair" / "new")
+        .and(warp::post())
+        .and(admin.clone())
+        .and(pairing.clone())
+        .and_then(pairing::handle_new_code);
+
+    let code_route = warp::path!("pair" / "code")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(pairing)
+        .map(|pairing: Pairing| pairing::code_reply(&pairing));
+
+    let status_route = warp::path!("status")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(connections)
+        .and(with(state.input_health.clone()))
+        .map(|connections: Connections, input: InputHealth| {
+            warp::reply::json(&serde_json::json!({
+                "connections": connections.all().len(),
+                "power": connections.power(),
+                "input": input.counts(),
+            }))
+        });
+
+    let shutdown_route = warp::path!("shutdown")
+        .and(warp::post())
+        .and(admin)
+        .and(warp::addr::remote())
+        .and(with(state.shutdown.clone()))
+        .map(|peer: Option<SocketAddr>, shutdown: Arc<Notify>| {
+            println!(
+                "Shutting down, as asked by {}.",
+                peer.map_or("unknown peer".to_string(), |peer| peer.ip().to_string())
+            );
+            shutdown.notify_one();
+            StatusCode::ACCEPTED
+        });
+
+    trace_route
+        .map(Reply::into_response)
+        .or(stats_route.map(Reply::into_response))
+        .unify()
+        .or(clients_route.map(Reply::into_response))
+        .unify()
+        .or(status_route.map(Reply::into_response))
+        .unify()
+        .or(audit_route.map(Reply::into_response))
+        .unify()
+        .or(events_route.map(Reply::into_response))
+        .unify()
+        .or(new_code_route.map(Reply::into_response))
+        .unify()
+        .or(code_route.map(Reply::into_response))
+        .unify()
+        .or(shutdown_route.map(Reply::into_response))
+        .unify()
+        .boxed()
+}
+
+fn allowed_peer(config: Arc<Config>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |addr: Option<SocketAddr>| {
+            let config = config.clone();
+            async move {
+                let peer = match addr {
+                    Some(addr) if config.is_peer_allowed(addr.ip()) => return Ok(()),
+                    None if config.allowed_subnets.is_empty() => return Ok(()),
+                    Some(addr) => addr.ip().to_string(),
+                    None => "unknown peer".to_string(),
+                };
+                Err(warp::reject::custom(Error::Forbidden(peer)))
+            }
+        })
+        .untuple_one()
+}
+
+#[derive(Deserialize)]
+struct DeviceQuery {
+    device: Option<String>,
+    /// The pairing credential, already checked by `pairing::trusted`.
+    token: Option<String>,
+    /// The locale the client would like, see `i18n::negotiate`.
+    locale: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TraceQuery {
+    enabled: bool,
+}
+
+async fn set_client_trace(
+    id: u64,
+    query: TraceQuery,
+    connections: Connections,
+    audit: AuditLog,
+) -> Result<impl Reply, Rejection> {
+    let connection = connections
+        .get(id)
+        .ok_or_else(|| warp::reject::custom(Error::UnknownClient(id)))?;
+    connection.set_explain(query.enabled);
+    println!(
+        "Explain mode for connection {} set to: {}",
+        id, query.enabled
+    );
+    audit.record(AuditEvent::SettingChanged {
+        connection: Some(id),
+        setting: "explain",
+        value: serde_json::json!(query.enabled),
+    });
+    Ok(warp::reply::json(&serde_json::json!({
+        "id": id,
+        "explain": query.enabled,
+    })))
+}
+
+async fn client_stats(id: u64, connections: Connections) -> Result<impl Reply, Rejection> {
+    let connection = connections
+        .get(id)
+        .ok_or_else(|| warp::reject::custom(Error::UnknownClient(id)))?;
+    Ok(warp::reply::json(&connection.stats()))
+}
+
+#[derive(Deserialize)]
+struct CalibrateQuery {
+    device: String,
+    /// The pairing credential, which must be the device's own.
+    token: Option<String>,
+    /// Whether to apply the suggested profile settings on finishing.
+    #[serde(default)]
+    apply: bool,
+}
+
+async fn start_calibration(
+    query: CalibrateQuery,
+    pairing: Pairing,
+    connections: Connections,
+) -> Result<impl Reply, Rejection> {
+    pairing
+        .check_device(query.token.as_deref(), &query.device)
+        .map_err(warp::reject::custom)?;
+    let connection = connections
+        .latest_for_device(&query.device)
+        .ok_or_else(|| warp::reject::custom(Error::UnknownDevice(query.device.clone())))?;
+    connection.start_calibration();
+    println!("Calibration started on connection {}.", connection.id);
+    Ok(warp::reply::json(&serde_json::json!({
+        "connection": connection.id,
+        "labels": calibration::LABELS,
+    })))
+}
+
+async fn finish_calibration(
+    query: CalibrateQuery,
+    pairing: Pairing,
+    connections: Connections,
+    info: Arc<ServerInfo>,
+    profiles: Profiles,
+    audit: AuditLog,
+) -> Result<impl Reply, Rejection> {
+    pairing
+        .check_device(query.token.as_deref(), &query.device)
+        .map_err(warp::reject::custom)?;
+    let connection = connections
+        .latest_for_device(&query.device)
+        .ok_or_else(|| warp::reject::custom(Error::UnknownDevice(query.device.clone())))?;
+    let recording = connection
+        .finish_calibration()
+        .ok_or_else(|| warp::reject::custom(Error::NotCalibrating))?;
+    let mut calibration = Calibration::suggest(&recording, info.primary_display);
+    println!(
+        "Calibration finished on connection {}: {:?}",
+        connection.id, calibration
+    );
+    if query.apply && !calibration.profile.is_empty() {
+        crate::update_profile(
+            &connection,
+            &profiles,
+            &audit,
+            &calibration.profile.update(),
+        )
+        .map_err(warp::reject::custom)?;
+        calibration.applied = true;
+    }
+    Ok(warp::reply::json(&calibration))
+}
+
+async fn read_audit_log(query: AuditQuery, audit: AuditLog) -> Result<impl Reply, Rejection> {
+    if !audit.is_enabled() {
+        return Err(warp::reject::custom(Error::Unsupported(
+            "the audit log is disabled",
+        )));
+    }
+    let page = audit::read(&query).map_err(warp::reject::custom)?;
+    Ok(warp::reply::json(&page))
+}
+
+/// Script loaded by every page before its own code, telling it where to
+/// connect, what the server supports and the page texts in the browser's
+/// language.
+fn client_config_script(
+    capabilities: &Capabilities,
+    pairing: &Pairing,
+    accept_language: Option<&str>,
+) -> impl Reply {
+    let locale = i18n::negotiate(None, accept_language);
+    let client_config = serde_json::json!({
+        "wsPath": "/ws",
+        "pairingRequired": pairing.is_required(),
+        "capabilities": capabilities,
+        "locale": locale,
+        "strings": i18n::page_strings(locale),
+    });
+    let reply = warp::reply::with_header(
+        format!("window.TOUCHPAD = {};\n", client_config),
+        "content-type",
+        "application/javascript",
+    );
+    warp::reply::with_header(reply, "vary", "accept-language")
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    match rejection.find::<Error>() {
+        Some(e) => {
+            e.log();
+            Ok(warp::reply::with_status(e.to_string(), e.status()))
+        }
+        None => Err(rejection),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::{Admin, AdminSecret};
+    use crate::capabilities::Capabilities;
+    use crate::connection::event_channel;
+    use crate::input::{BackendKind, InputAbilities, InputReport};
+    use crate::media::MediaController;
+    use crate::version::LatestRelease;
+
+    const SECRET: &str = "0123456789abcdef";
+
+    fn routes(config: Config) -> BoxedFilter<(Response,)> {
+        build_routes(state(config))
+    }
+
+    fn state(config: Config) -> AppState {
+        let report = InputReport {
+            backend: BackendKind::Enigo,
+            abilities: InputAbilities {
+                pointer: true,
+                keys: true,
+                scroll: true,
+            },
+            display: None,
+        };
+        let media = MediaController::default();
+        let capabilities = Capabilities::detect(&config, &media, report);
+        let (events, _) = event_channel(false);
+        AppState {
+            info: Arc::new(ServerInfo::detect(
+                capabilities,
+                report,
+                LatestRelease::default(),
+            )),
+            connections: Connections::default(),
+            events,
+            profiles: Profiles::default(),
+            audit: AuditLog::start(&config).unwrap(),
+            media,
+            pairing: Pairing::load(false).unwrap(),
+            admin: Admin::load(Some(AdminSecret::from(SECRET.to_string())), false).unwrap(),
+            input_health: InputHealth::default(),
+            shutdown: Arc::new(Notify::new()),
+            config: Arc::new(config),
+        }
+    }
+
+    fn on_lan() -> Config {
+        Config {
+            allowed_subnets: serde_json::from_str(r#"["192.168.1.0/24"]"#).unwrap(),
+            ..Config::default()
+        }
+    }
+
+    async fn status(
+        routes: &BoxedFilter<(Response,)>,
+        request: warp::test::RequestBuilder,
+    ) -> StatusCode {
+        request.reply(routes).await.status()
+    }
+
+    #[tokio::test]
+    async fn admin_routes_want_the_secret_from_other_peers() {
+        let routes = routes(on_lan());
+        let peer = "192.168.1.20:5000".parse().unwrap();
+        let request = || warp::test::request().path("/api/clients").remote_addr(peer);
+        assert_eq!(status(&routes, request()).await, StatusCode::FORBIDDEN);
+        let bearer = format!("Bearer {}", SECRET);
+        assert_eq!(
+            status(&routes, request().header("authorization", &bearer)).await,
+            StatusCode::OK
+        );
+        // Routes open to every allowed peer do not ask for it.
+        assert_eq!(
+            status(
+                &routes,
+                warp::test::request().path("/api/version").remote_addr(peer)
+            )
+            .await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_api_paths_are_not_found_rather_than_forbidden() {
+        let routes = routes(on_lan());
+        let request = warp::test::request()
+            .path("/api/nonexistent")
+            .remote_addr("192.168.1.20:5000".parse().unwrap());
+        assert_eq!(status(&routes, request).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn websocket_is_refused_to_other_peers_and_origins() {
+        let routes = routes(on_lan());
+        let upgrade = |peer: &str| {
+            warp::test::request()
+                .path("/ws")
+                .header("connection", "upgrade")
+                .header("upgrade", "websocket")
+                .header("sec-websocket-version", "13")
+                .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .remote_addr(peer.parse().unwrap())
+        };
+        assert_eq!(
+            status(&routes, upgrade("10.0.0.5:5000")).await,
+            StatusCode::FORBIDDEN
+        );
+        let foreign = upgrade("192.168.1.20:5000")
+            .header("host", "192.168.1.2:8080")
+            .header("origin", "https://evil.example");
+        assert_eq!(status(&routes, foreign).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn status_reports_the_input_backend_health() {
+        let routes = routes(Config::default());
+        let response = warp::test::request()
+            .path("/api/status")
+            .header("authorization", format!("Bearer {}", SECRET))
+            .remote_addr("192.168.1.20:5000".parse().unwrap())
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["input"]["failures"], 0);
+    }
+
+    /// The next message on a test WebSocket, as JSON.
+    async fn receive(client: &mut warp::test::WsClient) -> serde_json::Value {
+        let message = client.recv().await.unwrap();
+        serde_json::from_str(message.to_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_admin_secret_grants_the_admin_permission_over_websocket() {
+        // Test WebSockets come from no address, which only an empty
+        // `allowed_subnets` lets in.
+        let state = state(Config::default());
+        let routes = build_routes(state.clone());
+        let connect = |path: String| warp::test::ws().path(&path).handshake(routes.clone());
+        let set_explain = r#"{"type":"SetExplain","active":true}"#;
+
+        let mut device = connect("/ws?device=phone".to_string()).await.unwrap();
+        let hello = receive(&mut device).await;
+        assert_eq!(hello["type"], "Hello");
+        assert_eq!(hello["permission"], "device");
+        device.send_text(set_explain).await;
+        assert_eq!(receive(&mut device).await["code"], "unauthorized");
+
+        let mut admin = connect(format!("/ws?admin={}", SECRET)).await.unwrap();
+        assert_eq!(receive(&mut admin).await["permission"], "admin");
+        let phone = state.connections.all()[0].clone();
+        admin
+            .send_text(format!(
+                r#"{{"type":"SetExplain","active":true,"connection":{}}}"#,
+                phone.id
+            ))
+            .await;
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while !phone.explain() {
+            assert!(Instant::now() < deadline, "explain mode was not set");
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        assert!(connect("/ws?admin=wrong".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn sessions_are_closed_after_max_session_secs() {
+        let routes = routes(Config {
+            max_session_secs: Some(1),
+            ..Config::default()
+        });
+        let start = Instant::now();
+        let mut client = warp::test::ws()
+            .path("/ws")
+            .handshake(routes)
+            .await
+            .unwrap();
+        let mut errors = Vec::new();
+        // The test client reports the close frame as the end of the stream.
+        while let Ok(message) = client.recv().await {
+            let event: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+            if event["type"] == "Error" {
+                errors.push(event["code"].clone());
+            }
+        }
+        assert!(start.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(errors, ["session_expired"]);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::capabilities::Capabilities;
+use crate::input::InputReport;
+use crate::probe_history::CapabilityChange;
+use crate::version::{LatestRelease, VersionInfo, BUILD};
+use serde::Serialize;
+
+/// Version of the messages exchanged over `/ws`, raised whenever they change
+/// in a way older clients would trip over.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Everything a client may want to know about the server up front, sent in
+/// the WebSocket `Hello` and served at `/api/info`.
+///
+/// Nothing identifying the host or its user (hostname, username) is
+/// included, since any allowed peer can read it.
+#[derive(Serialize, Debug, Clone)]
+pub struct ServerInfo {
+    #[serde(flatten)]
+    pub version: VersionInfo,
+    pub protocol_version: u32,
+    pub os: &'static str,
+    /// Kernel release on Linux; left out where it is not known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    pub arch: &'static str,
+    /// How input is injected on this platform.
+    pub input_backend: &'static str,
+    /// Size of the primary display in pixels, where the input backend can
+    /// tell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_display: Option<DisplaySize>,
+    /// Optional Cargo features the server was built with.
+    pub features: Vec<&'static str>,
+    pub capabilities: Capabilities,
+    /// Checks that passed the last time the server ran and fail now, e.g.
+    /// after a host update. Left out when there are none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub capability_changes: Vec<CapabilityChange>,
+    /// Tells this run apart from any other, for the instance lock.
+    pub instance_id: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct DisplaySize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ServerInfo {
+    pub fn detect(
+        capabilities: Capabilities,
+        input: InputReport,
+        latest_version: LatestRelease,
+    ) -> Self {
+        let mut features = Vec::new();
+        if cfg!(feature = "mpris") {
+            features.push("mpris");
+        }
+        ServerInfo {
+            version: VersionInfo {
+                build: BUILD,
+                latest_version,
+            },
+            protocol_version: PROTOCOL_VERSION,
+            os: std::env::consts::OS,
+            os_version: os_version(),
+            arch: std::env::consts::ARCH,
+            input_backend: input.backend.describe(),
+            primary_display: input.display,
+            features,
+            capabilities,
+            capability_changes: Vec::new(),
+            instance_id: String::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn os_version() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|release| release.trim().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn os_version() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::input::{BackendKind, Input, InputAbilities};
+    use crate::media::MediaController;
+
+    fn info(display: bool) -> ServerInfo {
+        let (input, _) = Input::mock();
+        let report = InputReport {
+            backend: BackendKind::Enigo,
+            abilities: InputAbilities {
+                pointer: true,
+                keys: true,
+                scroll: true,
+            },
+            display: input.display_size().filter(|_| display),
+        };
+        let capabilities =
+            Capabilities::detect(&Config::default(), &MediaController::default(), report);
+        ServerInfo::detect(capabilities, report, LatestRelease::default())
+    }
+
+    #[test]
+    fn display_size_comes_from_the_input_report() {
+        let json = serde_json::to_value(info(true)).unwrap();
+        assert_eq!(
+            json["primary_display"],
+            serde_json::json!({ "width": 1920, "height": 1080 })
+        );
+        assert_eq!(json["capabilities"]["absolute_drawing"], true);
+
+        let json = serde_json::to_value(info(false)).unwrap();
+        assert!(json.get("primary_display").is_none());
+        assert_eq!(json["capabilities"]["absolute_drawing"], false);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::Error;
+use rand::Rng;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+const BLOCK_SIZE: usize = 64;
+
+/// HMAC-SHA1 of `message` under `key`, which browsers can compute with
+/// WebCrypto.
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 20] {
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..20].copy_from_slice(&Sha1::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha1::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha1::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Sent in the `Hello` when `signed_events` is on: the nonce the
+/// connection's key is derived from, and which events must be signed.
+#[derive(Serialize, Debug, Clone)]
+pub struct SigningOffer {
+    pub nonce: String,
+    events: &'static [&'static str],
+}
+
+impl SigningOffer {
+    pub fn new(events: &'static [&'static str]) -> Self {
+        SigningOffer {
+            nonce: hex(&rand::thread_rng().gen::<[u8; 16]>()),
+            events,
+        }
+    }
+}
+
+/// Checks the signatures of a connection's sensitive events, see
+/// `signed_events`. The key is derived from the device's pairing credential
+/// and a nonce sent in the `Hello`, so it differs for every connection and
+/// never travels over it.
+pub struct EventSigner {
+    key: [u8; 20],
+    /// Sequence number of the latest accepted event; each must be higher.
+    last_seq: u64,
+}
+
+impl EventSigner {
+    pub fn new(token: &str, nonce: &str) -> Self {
+        let label = format!("web_touchpad events {}", nonce);
+        EventSigner {
+            key: hmac(token.as_bytes(), label.as_bytes()),
+            last_seq: 0,
+        }
+    }
+
+    /// The signature of `event` sent with sequence number `seq`.
+    pub fn signature(&self, seq: u64, event: &str) -> String {
+        hex(&hmac(&self.key, format!("{}.{}", seq, event).as_bytes()))
+    }
+
+    /// Accepts `event` if `sig` is its signature along with `seq`, and it is
+    /// not older than the last accepted event. As the key is the
+    /// connection's own, events captured on another connection never
+    /// verify, so no clock is needed to tell that they are stale.
+    pub fn verify(&mut self, seq: u64, event: &str, sig: &str) -> Result<(), Error> {
+        let expected = self.signature(seq, event);
+        let matches = expected.len() == sig.len()
+            && expected
+                .bytes()
+                .zip(sig.bytes())
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0;
+        if !matches {
+            return Err(Error::Unauthorized("bad event signature"));
+        }
+        if seq <= self.last_seq {
+            return Err(Error::Replayed("sequence number already used"));
+        }
+        self.last_seq = seq;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_matches_rfc_2202() {
+        assert_eq!(
+            hex(&hmac(&[0x0b; 20], b"Hi There")),
+            "b617318655057264e28bc0b6fb378c8ef146be00"
+        );
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        );
+        // A key longer than a block is hashed first.
+        assert_eq!(
+            hex(&hmac(
+                &[0xaa; 80],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "aa4ae5e15272d00e95705637ce8a3b55ed402112"
+        );
+    }
+
+    #[test]
+    fn keys_differ_per_connection() {
+        let event = r#"{"type":"MouseClick","button":"Left"}"#;
+        let signer = EventSigner::new("credential", "nonce-1");
+        let sig = signer.signature(1, event);
+        let mut other = EventSigner::new("credential", "nonce-2");
+        assert!(matches!(
+            other.verify(1, event, &sig),
+            Err(Error::Unauthorized(_))
+        ));
+        let mut other = EventSigner::new("other credential", "nonce-1");
+        assert!(matches!(
+            other.verify(1, event, &sig),
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn sequence_numbers_must_grow() {
+        let event = r#"{"type":"MouseClick","button":"Left"}"#;
+        let mut signer = EventSigner::new("credential", "nonce");
+        let first = signer.signature(1, event);
+        signer.verify(1, event, &first).unwrap();
+        assert!(matches!(
+            signer.verify(1, event, &first),
+            Err(Error::Replayed(_))
+        ));
+        let fifth = signer.signature(5, event);
+        signer.verify(5, event, &fifth).unwrap();
+        let third = signer.signature(3, event);
+        assert!(matches!(
+            signer.verify(3, event, &third),
+            Err(Error::Replayed(_))
+        ));
+        // Nor can the signature of one event be used for another.
+        assert!(matches!(
+            signer.verify(6, r#"{"type":"MouseClick","button":"Right"}"#, &fifth),
+            Err(Error::Unauthorized(_))
+        ));
+    }
+}
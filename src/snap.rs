@@ -0,0 +1,146 @@
+/// Consecutive near-axis movements needed before snapping engages, so that a
+/// single stray event does not lock the pointer to an axis.
+const ENGAGE_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// Straightens pointer movement that keeps close to one axis, for dragging
+/// guides and sliders exactly along it. Movement leaving the cone around the
+/// axis is passed through unchanged straight away.
+#[derive(Default)]
+pub struct AxisSnap {
+    axis: Option<Axis>,
+    streak: u32,
+}
+
+impl AxisSnap {
+    pub fn reset(&mut self) {
+        *self = AxisSnap::default();
+    }
+
+    /// The axis movement is currently snapped to, if any.
+    pub fn snapped(&self) -> Option<Axis> {
+        self.axis.filter(|_| self.streak >= ENGAGE_AFTER)
+    }
+
+    /// Tracks a movement and tells which component to drop, given the
+    /// half-angle of the cone around each axis in degrees (0 disables it).
+    pub fn apply(&mut self, cone_degrees: f64, dx: f64, dy: f64) -> Option<Axis> {
+        if cone_degrees <= 0.0 {
+            self.reset();
+            return None;
+        }
+        if dx == 0.0 && dy == 0.0 {
+            return self.snapped();
+        }
+        let angle = dy.abs().atan2(dx.abs()).to_degrees();
+        let axis = if angle <= cone_degrees {
+            Some(Axis::Horizontal)
+        } else if angle >= 90.0 - cone_degrees {
+            Some(Axis::Vertical)
+        } else {
+            None
+        };
+        if axis != self.axis {
+            self.axis = axis;
+            self.streak = 0;
+        }
+        if self.axis.is_some() {
+            self.streak = self.streak.saturating_add(1);
+        }
+        self.snapped()
+    }
+}
+
+/// Rounds a position on the display to the nearest multiple of `grid`
+/// pixels, without leaving the `size` pixels of the display.
+pub fn to_grid(position: f64, grid: u32, size: usize) -> i32 {
+    let grid = f64::from(grid);
+    let last = ((size.max(1) - 1) as f64 / grid).floor() * grid;
+    ((position / grid).round() * grid).clamp(0.0, last) as i32
+}
+
+/// Two-finger travel, in pixels, after which the scroll axis is decided.
+pub const SCROLL_LOCK_DISTANCE_PX: f64 = 8.0;
+
+/// Keeps a two-finger scroll on the axis it started along, so that a
+/// slightly diagonal gesture does not wobble between the two.
+#[derive(Default)]
+pub struct ScrollLock {
+    dx: f64,
+    dy: f64,
+    axis: Option<Axis>,
+}
+
+impl ScrollLock {
+    pub fn reset(&mut self) {
+        *self = ScrollLock::default();
+    }
+
+    /// Tracks a movement and returns the locked axis, once the gesture has
+    /// travelled far enough to tell which way it goes.
+    pub fn track(&mut self, dx: f64, dy: f64) -> Option<Axis> {
+        if self.axis.is_none() && dx.is_finite() && dy.is_finite() {
+            self.dx += dx;
+            self.dy += dy;
+            if self.dx.hypot(self.dy) >= SCROLL_LOCK_DISTANCE_PX {
+                self.axis = Some(if self.dx.abs() > self.dy.abs() {
+                    Axis::Horizontal
+                } else {
+                    Axis::Vertical
+                });
+            }
+        }
+        self.axis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_grid_rounds_to_the_nearest_line() {
+        assert_eq!(to_grid(14.0, 10, 1920), 10);
+        assert_eq!(to_grid(15.0, 10, 1920), 20);
+        assert_eq!(to_grid(-4.0, 10, 1920), 0);
+        assert_eq!(to_grid(37.0, 25, 1920), 25);
+        assert_eq!(to_grid(38.0, 25, 1920), 50);
+        assert_eq!(to_grid(123.4, 1, 1920), 123);
+    }
+
+    #[test]
+    fn to_grid_stays_on_the_display() {
+        // 1919 is the last pixel; the last line before it is 1910.
+        assert_eq!(to_grid(1918.0, 10, 1920), 1910);
+        assert_eq!(to_grid(5000.0, 64, 1080), 1024);
+        assert_eq!(to_grid(1079.0, 1, 1080), 1079);
+        // A grid coarser than the display leaves only its origin.
+        assert_eq!(to_grid(700.0, 2000, 1080), 0);
+        assert_eq!(to_grid(3.0, 10, 0), 0);
+    }
+
+    #[test]
+    fn scroll_lock_keeps_a_wobbly_vertical_gesture_vertical() {
+        let mut lock = ScrollLock::default();
+        assert_eq!(lock.track(1.0, 3.0), None);
+        assert_eq!(lock.track(-1.0, 6.0), Some(Axis::Vertical));
+        // Sideways wobble, even a move that is mostly sideways, keeps it.
+        for (dx, dy) in [(4.0, 2.0), (-3.0, 5.0), (12.0, 1.0), (f64::NAN, 1.0)] {
+            assert_eq!(lock.track(dx, dy), Some(Axis::Vertical));
+        }
+        lock.reset();
+        assert_eq!(lock.track(9.0, -2.0), Some(Axis::Horizontal));
+    }
+
+    #[test]
+    fn scroll_lock_ignores_non_finite_moves() {
+        let mut lock = ScrollLock::default();
+        assert_eq!(lock.track(f64::INFINITY, 0.0), None);
+        assert_eq!(lock.track(0.0, 8.0), Some(Axis::Vertical));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{ClientEvent, MouseButton};
+use serde::Deserialize;
+use std::mem;
+
+/// A rule from `event_transforms`, changing or dropping client events before
+/// the mouse event thread acts on them. Rules only ever rewrite events into
+/// other events clients could have sent themselves, so none can do more than
+/// a client already can.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTransform {
+    /// Drops events of these types, e.g. "Screenshot".
+    Drop(Vec<String>),
+    /// Swaps horizontal and vertical movement, positions and scrolling, for
+    /// a device held sideways on a stand.
+    SwapAxes,
+    /// Swaps left and right clicks, for left-handed use.
+    SwapButtons,
+}
+
+impl EventTransform {
+    /// The event types this rule drops.
+    pub fn dropped_kinds(&self) -> &[String] {
+        match self {
+            EventTransform::Drop(kinds) => kinds,
+            EventTransform::SwapAxes | EventTransform::SwapButtons => &[],
+        }
+    }
+
+    /// The event after this rule, or `None` if it drops it.
+    fn apply(&self, mut event: ClientEvent) -> Option<ClientEvent> {
+        match self {
+            EventTransform::Drop(kinds) => {
+                return (!kinds.iter().any(|kind| kind == event.kind())).then_some(event);
+            }
+            EventTransform::SwapAxes => {
+                if let ClientEvent::MouseMove {
+                    dx,
+                    dy,
+                    sx,
+                    sy,
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                } = &mut event
+                {
+                    mem::swap(dx, dy);
+                    mem::swap(sx, sy);
+                    mem::swap(x, y);
+                    mem::swap(width, height);
+                }
+            }
+            EventTransform::SwapButtons => {
+                if let ClientEvent::MouseClick { button } = &mut event {
+                    *button = match button {
+                        MouseButton::Left => MouseButton::Right,
+                        MouseButton::Right => MouseButton::Left,
+                    };
+                }
+            }
+        }
+        Some(event)
+    }
+}
+
+/// Runs `event` through `rules` in order, stopping at the first that drops
+/// it. The server's own `Closed` always gets through.
+pub fn apply(rules: &[EventTransform], event: ClientEvent) -> Option<ClientEvent> {
+    if matches!(event, ClientEvent::Closed) {
+        return Some(event);
+    }
+    rules
+        .iter()
+        .try_fold(event, |event, rule| rule.apply(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(json: &str) -> ClientEvent {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn swap_axes_swaps_moves_positions_and_scrolling() {
+        let moved = apply(
+            &[EventTransform::SwapAxes],
+            event(
+                r#"{"type":"MouseMove","dx":3,"dy":-4,"sx":1,"sy":-2,"touches":2,
+                    "x":10,"y":20,"width":300,"height":600}"#,
+            ),
+        );
+        assert_eq!(
+            moved,
+            Some(event(
+                r#"{"type":"MouseMove","dx":-4,"dy":3,"sx":-2,"sy":1,"touches":2,
+                    "x":20,"y":10,"width":600,"height":300}"#,
+            ))
+        );
+        let click = event(r#"{"type":"MouseClick","button":"Left"}"#);
+        assert_eq!(
+            apply(&[EventTransform::SwapAxes], click.clone()),
+            Some(click)
+        );
+    }
+
+    #[test]
+    fn rules_apply_in_order_until_one_drops() {
+        let rules = [
+            EventTransform::SwapButtons,
+            EventTransform::Drop(vec!["Screenshot".to_string()]),
+        ];
+        assert_eq!(
+            apply(&rules, event(r#"{"type":"MouseClick","button":"Left"}"#)),
+            Some(event(r#"{"type":"MouseClick","button":"Right"}"#))
+        );
+        assert_eq!(apply(&rules, event(r#"{"type":"Screenshot"}"#)), None);
+        let drop_all = [EventTransform::Drop(
+            ClientEvent::KINDS
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+        )];
+        assert_eq!(
+            apply(&drop_all, ClientEvent::Closed),
+            Some(ClientEvent::Closed)
+        );
+    }
+}
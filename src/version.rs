@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// What was built, embedded by `build.rs`.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub build_date: &'static str,
+    pub rustc: &'static str,
+}
+
+pub const BUILD: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_hash: env!("BUILD_GIT_HASH"),
+    build_date: env!("BUILD_DATE"),
+    rustc: env!("BUILD_RUSTC_VERSION"),
+};
+
+impl BuildInfo {
+    /// One line for the startup banner.
+    pub fn banner(&self) -> String {
+        format!(
+            "web_touchpad {} ({}, built {} with {})",
+            self.version, self.git_hash, self.build_date, self.rustc
+        )
+    }
+}
+
+/// The build together with the newest release known, served at `/api/version`.
+#[derive(Serialize, Debug, Clone)]
+pub struct VersionInfo {
+    #[serde(flatten)]
+    pub build: BuildInfo,
+    /// A newer release than this build, when `--check-updates` found one.
+    pub latest_version: LatestRelease,
+}
+
+const RELEASES_URL: &str = "https://api.github.com/repos/ycn/web_touchpad/releases/latest";
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The newest release, once `--check-updates` has found one newer than this
+/// build. Serializes as that version, or null.
+#[derive(Clone, Default, Debug)]
+pub struct LatestRelease {
+    newer: Arc<Mutex<Option<String>>>,
+}
+
+impl LatestRelease {
+    pub fn newer(&self) -> Option<String> {
+        self.newer.lock().unwrap().clone()
+    }
+
+    /// Looks up the latest release in the background. Any failure is only
+    /// logged, and the server never waits for the answer.
+    pub fn check_in_background(&self) {
+        let latest = self.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(UPDATE_CHECK_TIMEOUT, fetch_latest_tag()).await {
+                Ok(Ok(tag)) => {
+                    let version = tag.trim_start_matches('v');
+                    if is_newer(version, BUILD.version) {
+                        println!(
+                            "A newer release is available: {} (running {}).",
+                            version, BUILD.version
+                        );
+                        *latest.newer.lock().unwrap() = Some(version.to_string());
+                    } else {
+                        println!("Running the latest release ({}).", BUILD.version);
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Update check failed: {}", e),
+                Err(_) => eprintln!("Update check failed: timed out"),
+            }
+        });
+    }
+}
+
+impl Serialize for LatestRelease {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.newer().serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// Asks the GitHub API through curl, which every supported platform ships,
+/// rather than pulling in an HTTP client and TLS stack for one request.
+async fn fetch_latest_tag() -> Result<String, String> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", &UPDATE_CHECK_TIMEOUT.as_secs().to_string()])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .arg(RELEASES_URL)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let release: Release = serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+    Ok(release.tag_name)
+}
+
+/// Compares dotted version numbers; anything unparsable counts as zero.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .take(3)
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    parts(candidate) > parts(current)
+}
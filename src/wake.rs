@@ -0,0 +1,52 @@
+use crate::input::{BackendKind, InputAbilities};
+
+/// Whether the display can be woken through `backend`. ydotool has no way of
+/// its own and nudges the pointer instead, which needs pointer input.
+pub fn is_supported(backend: BackendKind, abilities: InputAbilities) -> bool {
+    match backend {
+        BackendKind::Enigo => true,
+        BackendKind::Ydotool => abilities.pointer,
+    }
+}
+
+/// Resets the display idle timer, without the continuous flag so that the
+/// display may sleep again later as usual.
+#[cfg(windows)]
+pub fn wake_display() -> Result<(), String> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+    const ES_DISPLAY_REQUIRED: u32 = 0x0000_0002;
+    match unsafe { SetThreadExecutionState(ES_DISPLAY_REQUIRED) } {
+        0 => Err("SetThreadExecutionState failed".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Declares the user active for a second, which turns the display on.
+/// `caffeinate` only exits once that second is up, so it is waited for on a
+/// thread of its own.
+#[cfg(target_os = "macos")]
+pub fn wake_display() -> Result<(), String> {
+    let mut child = std::process::Command::new("caffeinate")
+        .args(["-u", "-t", "1"])
+        .spawn()
+        .map_err(|e| format!("cannot run caffeinate: {}", e))?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Switches the display on through DPMS with `xset`, like `locks::query`.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn wake_display() -> Result<(), String> {
+    let status = std::process::Command::new("xset")
+        .args(["dpms", "force", "on"])
+        .status()
+        .map_err(|e| format!("cannot run xset: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("xset {}", status))
+    }
+}
@@ -0,0 +1,420 @@
+use crate::input::CallError;
+use enigo::{Axis, Button, Direction, Key};
+use std::env;
+use std::mem;
+use std::os::raw::c_long;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Event types and codes, see `linux/input-event-codes.h`.
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const BTN_LEFT: u16 = 0x110;
+const KEY_LEFTSHIFT: u16 = 42;
+
+/// Talks to `ydotoold`, which injects input through uinput and so works
+/// under Wayland as well as X11. Moves, scrolls, buttons and keys are sent
+/// to its socket as the raw input events `ydotool` would send, rather than
+/// running `ydotool` for each of them. Only typing text runs `ydotool`,
+/// which knows which keys make up each character.
+pub struct Ydotool {
+    /// Where the daemon may be listening, tried in turn.
+    paths: Vec<PathBuf>,
+    /// Connected on first use, and again once after the daemon went away.
+    socket: Option<UnixDatagram>,
+}
+
+impl Ydotool {
+    pub fn new() -> Self {
+        Ydotool {
+            paths: socket_paths(),
+            socket: None,
+        }
+    }
+
+    /// Sends `events`, each `(type, code, value)`, to the daemon. Failing
+    /// to reach it may pass, e.g. while it restarts.
+    fn send(&mut self, events: &[(u16, u16, i32)]) -> Result<(), CallError> {
+        let reconnect = self.socket.is_some();
+        match self.try_send(events) {
+            Err(_) if reconnect => {
+                self.socket = None;
+                self.try_send(events)
+            }
+            result => result,
+        }
+        .map_err(CallError::transient)
+    }
+
+    fn try_send(&mut self, events: &[(u16, u16, i32)]) -> Result<(), String> {
+        if self.socket.is_none() {
+            self.socket = Some(connect(&self.paths)?);
+        }
+        let socket = self.socket.as_ref().expect("connected above");
+        for &(kind, code, value) in events {
+            socket
+                .send(&input_event(kind, code, value))
+                .map_err(|e| format!("cannot send to ydotoold: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// An absolute move first goes as far up and left as it can, as the
+    /// daemon's device only moves relatively.
+    pub fn move_mouse(&mut self, x: i32, y: i32, absolute: bool) -> Result<(), CallError> {
+        let mut events = Vec::with_capacity(6);
+        if absolute {
+            events.extend([
+                (EV_REL, REL_X, i32::MIN),
+                (EV_REL, REL_Y, i32::MIN),
+                (EV_SYN, SYN_REPORT, 0),
+            ]);
+        }
+        events.extend([
+            (EV_REL, REL_X, x),
+            (EV_REL, REL_Y, y),
+            (EV_SYN, SYN_REPORT, 0),
+        ]);
+        self.send(&events)
+    }
+
+    /// Positive lengths scroll down and right, as with enigo; the wheel's own
+    /// vertical axis is the other way round.
+    pub fn scroll(&mut self, length: i32, axis: Axis) -> Result<(), CallError> {
+        let event = match axis {
+            Axis::Horizontal => (EV_REL, REL_HWHEEL, length),
+            Axis::Vertical => (EV_REL, REL_WHEEL, -length),
+        };
+        self.send(&[event, (EV_SYN, SYN_REPORT, 0)])
+    }
+
+    pub fn button(&mut self, button: Button, direction: Direction) -> Result<(), CallError> {
+        let offset = match button {
+            Button::Left => 0,
+            Button::Right => 1,
+            Button::Middle => 2,
+            Button::Forward => 5,
+            Button::Back => 6,
+            other => {
+                return Err(CallError::permanent(format!(
+                    "ydotool cannot press {:?}",
+                    other
+                )))
+            }
+        };
+        self.send(&press(BTN_LEFT + offset, direction))
+    }
+
+    pub fn key(&mut self, key: Key, direction: Direction) -> Result<(), CallError> {
+        if let (Key::Unicode(c), Direction::Click) = (key, direction) {
+            return self.character(c);
+        }
+        let code = key_code(key).ok_or_else(|| {
+            CallError::permanent(format!("ydotool has no key code for {:?}", key))
+        })?;
+        self.send(&press(code, direction))
+    }
+
+    /// A character on a US keyboard is pressed, with Shift for a capital;
+    /// any other is typed, which `ydotool` skips when it has no key for it.
+    fn character(&mut self, c: char) -> Result<(), CallError> {
+        let Some(code) = key_code(Key::Unicode(c)) else {
+            return type_text(&c.to_string());
+        };
+        if !c.is_ascii_uppercase() {
+            return self.send(&press(code, Direction::Click));
+        }
+        let mut events = press(KEY_LEFTSHIFT, Direction::Press);
+        events.extend(press(code, Direction::Click));
+        events.extend(press(KEY_LEFTSHIFT, Direction::Release));
+        self.send(&events)
+    }
+
+    pub fn text(&self, text: &str) -> Result<(), CallError> {
+        type_text(text)
+    }
+}
+
+/// The events for pressing, releasing or clicking a key or button.
+fn press(code: u16, direction: Direction) -> Vec<(u16, u16, i32)> {
+    let mut events = Vec::with_capacity(4);
+    if matches!(direction, Direction::Press | Direction::Click) {
+        events.extend([(EV_KEY, code, 1), (EV_SYN, SYN_REPORT, 0)]);
+    }
+    if matches!(direction, Direction::Release | Direction::Click) {
+        events.extend([(EV_KEY, code, 0), (EV_SYN, SYN_REPORT, 0)]);
+    }
+    events
+}
+
+/// Where `ydotoold` may be listening: `YDOTOOL_SOCKET` when set, else the
+/// places its versions have defaulted to.
+fn socket_paths() -> Vec<PathBuf> {
+    if let Some(path) = env::var_os("YDOTOOL_SOCKET") {
+        return vec![PathBuf::from(path)];
+    }
+    let mut paths = Vec::new();
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR") {
+        paths.push(PathBuf::from(runtime).join(".ydotool_socket"));
+    }
+    paths.push(PathBuf::from("/tmp/.ydotool_socket"));
+    paths
+}
+
+fn connect(paths: &[PathBuf]) -> Result<UnixDatagram, String> {
+    let mut failures = Vec::new();
+    for path in paths {
+        let socket = UnixDatagram::unbound().map_err(|e| e.to_string())?;
+        match socket.connect(path) {
+            Ok(()) => return Ok(socket),
+            Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    Err(format!(
+        "cannot reach ydotoold, is it running? ({})",
+        failures.join("; ")
+    ))
+}
+
+/// A `struct input_event` as the kernel lays it out: the time, which the
+/// kernel fills in, then the type, code and value.
+fn input_event(kind: u16, code: u16, value: i32) -> Vec<u8> {
+    let mut event = vec![0; 2 * mem::size_of::<c_long>()];
+    event.extend(kind.to_ne_bytes());
+    event.extend(code.to_ne_bytes());
+    event.extend(value.to_ne_bytes());
+    event
+}
+
+/// Runs `ydotool` with `args`, which goes through the same daemon. Only a
+/// missing `ydotool` fails for good.
+fn run(args: &[&str]) -> Result<(), CallError> {
+    let output = Command::new("ydotool")
+        .args(args)
+        .output()
+        .map_err(|e| CallError::permanent(format!("cannot run ydotool: {}", e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.trim() {
+        "" => Err(CallError::transient(format!("ydotool {}", output.status))),
+        message => Err(CallError::transient(message)),
+    }
+}
+
+fn type_text(text: &str) -> Result<(), CallError> {
+    run(&["type", "--", text])
+}
+
+/// Linux input event code of `key`, see `linux/input-event-codes.h`.
+fn key_code(key: Key) -> Option<u16> {
+    const ROWS: [(&str, u16); 4] = [
+        ("1234567890-=", 2),
+        ("qwertyuiop[]", 16),
+        ("asdfghjkl;'`", 30),
+        ("zxcvbnm,./", 44),
+    ];
+    let code = match key {
+        Key::Unicode(' ') => 57,
+        Key::Unicode('\\') => 43,
+        Key::Unicode(c) => {
+            let c = c.to_ascii_lowercase();
+            return ROWS.iter().find_map(|(row, first)| {
+                let column = row.find(c)?;
+                Some(first + u16::try_from(column).ok()?)
+            });
+        }
+        Key::Escape => 1,
+        Key::Backspace => 14,
+        Key::Tab => 15,
+        Key::Return => 28,
+        Key::Control | Key::LControl => 29,
+        Key::Shift | Key::LShift => 42,
+        Key::RShift => 54,
+        Key::Alt => 56,
+        Key::Space => 57,
+        Key::CapsLock => 58,
+        Key::F1 => 59,
+        Key::F2 => 60,
+        Key::F3 => 61,
+        Key::F4 => 62,
+        Key::F5 => 63,
+        Key::F6 => 64,
+        Key::F7 => 65,
+        Key::F8 => 66,
+        Key::F9 => 67,
+        Key::F10 => 68,
+        Key::Numlock => 69,
+        Key::F11 => 87,
+        Key::F12 => 88,
+        Key::RControl => 97,
+        crate::keys::ALT_GR => 100,
+        Key::Home => 102,
+        Key::UpArrow => 103,
+        Key::PageUp => 104,
+        Key::LeftArrow => 105,
+        Key::RightArrow => 106,
+        Key::End => 107,
+        Key::DownArrow => 108,
+        Key::PageDown => 109,
+        Key::Insert => 110,
+        Key::Delete => 111,
+        Key::VolumeMute => 113,
+        Key::VolumeDown => 114,
+        Key::VolumeUp => 115,
+        Key::Meta => 125,
+        Key::MediaNextTrack => 163,
+        Key::MediaPlayPause => 164,
+        Key::MediaPrevTrack => 165,
+        Key::MediaStop => 166,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client whose daemon is the returned end of a socket pair.
+    fn connected() -> (Ydotool, UnixDatagram) {
+        let (client, daemon) = UnixDatagram::pair().unwrap();
+        daemon.set_nonblocking(true).unwrap();
+        let ydotool = Ydotool {
+            paths: Vec::new(),
+            socket: Some(client),
+        };
+        (ydotool, daemon)
+    }
+
+    /// The events the daemon received, as `(type, code, value)`.
+    fn received(daemon: &UnixDatagram) -> Vec<(u16, u16, i32)> {
+        let size = input_event(0, 0, 0).len();
+        let mut events = Vec::new();
+        let mut buffer = [0; 64];
+        while let Ok(length) = daemon.recv(&mut buffer) {
+            assert_eq!(length, size);
+            let fields = &buffer[size - 8..size];
+            events.push((
+                u16::from_ne_bytes([fields[0], fields[1]]),
+                u16::from_ne_bytes([fields[2], fields[3]]),
+                i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]),
+            ));
+        }
+        events
+    }
+
+    const SYN: (u16, u16, i32) = (EV_SYN, SYN_REPORT, 0);
+
+    #[test]
+    fn input_event_layout() {
+        let event = input_event(EV_REL, REL_Y, -3);
+        assert_eq!(event.len(), 2 * mem::size_of::<c_long>() + 8);
+        assert!(event[..event.len() - 8].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn moves_and_scrolls() {
+        let (mut ydotool, daemon) = connected();
+        ydotool.move_mouse(5, -2, false).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [(EV_REL, REL_X, 5), (EV_REL, REL_Y, -2), SYN]
+        );
+        ydotool.move_mouse(100, 50, true).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [
+                (EV_REL, REL_X, i32::MIN),
+                (EV_REL, REL_Y, i32::MIN),
+                SYN,
+                (EV_REL, REL_X, 100),
+                (EV_REL, REL_Y, 50),
+                SYN
+            ]
+        );
+        ydotool.scroll(2, Axis::Vertical).unwrap();
+        ydotool.scroll(-1, Axis::Horizontal).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [(EV_REL, REL_WHEEL, -2), SYN, (EV_REL, REL_HWHEEL, -1), SYN]
+        );
+    }
+
+    #[test]
+    fn buttons_and_keys() {
+        let (mut ydotool, daemon) = connected();
+        ydotool.button(Button::Right, Direction::Click).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [(EV_KEY, 0x111, 1), SYN, (EV_KEY, 0x111, 0), SYN]
+        );
+        ydotool.key(Key::Shift, Direction::Press).unwrap();
+        assert_eq!(received(&daemon), [(EV_KEY, KEY_LEFTSHIFT, 1), SYN]);
+        ydotool.key(Key::Unicode('q'), Direction::Click).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [(EV_KEY, 16, 1), SYN, (EV_KEY, 16, 0), SYN]
+        );
+        ydotool.key(Key::Unicode('Q'), Direction::Click).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [
+                (EV_KEY, KEY_LEFTSHIFT, 1),
+                SYN,
+                (EV_KEY, 16, 1),
+                SYN,
+                (EV_KEY, 16, 0),
+                SYN,
+                (EV_KEY, KEY_LEFTSHIFT, 0),
+                SYN
+            ]
+        );
+        assert!(
+            !ydotool
+                .key(Key::F20, Direction::Click)
+                .unwrap_err()
+                .transient
+        );
+    }
+
+    #[test]
+    fn reconnects_once_after_the_daemon_restarted() {
+        let dir = env::temp_dir().join(format!("ydotool-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".ydotool_socket");
+        let _ = std::fs::remove_file(&path);
+        let mut ydotool = Ydotool {
+            paths: vec![path.clone()],
+            socket: None,
+        };
+        let error = ydotool.move_mouse(1, 0, false).unwrap_err();
+        assert!(
+            error.to_string().contains("cannot reach ydotoold"),
+            "{}",
+            error
+        );
+        assert!(error.transient);
+
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        ydotool.move_mouse(1, 0, false).unwrap();
+        drop(daemon);
+        std::fs::remove_file(&path).unwrap();
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        daemon.set_nonblocking(true).unwrap();
+        ydotool.move_mouse(0, 1, false).unwrap();
+        assert_eq!(
+            received(&daemon),
+            [(EV_REL, REL_X, 0), (EV_REL, REL_Y, 1), SYN]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}